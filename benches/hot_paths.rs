@@ -0,0 +1,70 @@
+//! Benchmarks of the command path, run with `cargo bench`.
+//!
+//! The sweep benchmark runs against the simulator and is dominated by the transmission delays,
+//! which makes changes to the pacing of the communication measurable.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use usmu::{
+    Current, MicroSmu, Time, Voltage,
+    commands::{
+        IdentityResponse, MeasureRequest, MeasureResponse, SetCurrentLimitRequest, from_wire,
+        to_wire,
+    },
+    milliampere, millisecond,
+    record_iv_curve::IvCurveRecordingParameters,
+    simulator::{Resistor, SimulatedSmu},
+    volt,
+};
+
+fn serialization(c: &mut Criterion) {
+    let measure = MeasureRequest {
+        voltage: Voltage::new::<volt>(-0.123456),
+    };
+    c.bench_function("serialize measure", |b| {
+        b.iter(|| to_wire(black_box(&measure)))
+    });
+
+    let current_limit = SetCurrentLimitRequest::new(Current::new::<milliampere>(12.5));
+    c.bench_function("serialize current limit", |b| {
+        b.iter(|| to_wire(black_box(&current_limit)))
+    });
+}
+
+fn parsing(c: &mut Criterion) {
+    c.bench_function("parse measure", |b| {
+        b.iter(|| from_wire::<MeasureResponse>(black_box("-0.499870,-0.000123\n")).unwrap())
+    });
+
+    c.bench_function("parse identity", |b| {
+        b.iter(|| {
+            from_wire::<IdentityResponse>(black_box("uSMU version 1.0 ID:3473457\n")).unwrap()
+        })
+    });
+}
+
+fn sweep(c: &mut Criterion) {
+    let parameters = IvCurveRecordingParameters {
+        start_voltage: Voltage::new::<volt>(-1.0),
+        end_voltage: Voltage::new::<volt>(1.0),
+        voltage_steps: 5,
+        current_limit: Current::new::<milliampere>(20.0),
+        over_sampling: 1,
+        delay: Time::new::<millisecond>(0.0),
+        adaptive_over_sampling: Default::default(),
+        step_refinement: Default::default(),
+        auto_range: Default::default(),
+    };
+    let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+
+    let mut group = c.benchmark_group("sweep");
+    group.sample_size(10);
+    group.bench_function("5 steps", |b| {
+        b.iter(|| parameters.record(&mut smu).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, serialization, parsing, sweep);
+criterion_main!(benches);
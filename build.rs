@@ -0,0 +1,15 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        // Use a vendored protoc, so the gRPC interface builds without a system wide protobuf installation.
+        let mut config = tonic_prost_build::Config::new();
+        config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+        tonic_prost_build::configure().compile_with_config(
+            config,
+            &["proto/usmu.proto"],
+            &["proto"],
+        )?;
+    }
+
+    Ok(())
+}
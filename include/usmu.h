@@ -0,0 +1,111 @@
+#ifndef USMU_H
+#define USMU_H
+
+/* Generated with cbindgen from src/capi.rs, do not edit manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define USMU_OK 0
+
+// Communication with the device failed.
+#define USMU_ERROR_DEVICE -1
+
+// A passed argument is invalid, e.g. a null pointer, a too small buffer or an out of range parameter.
+#define USMU_ERROR_INVALID_ARGUMENT -2
+
+// The driver panicked.
+#define USMU_ERROR_PANIC -3
+
+// Opaque handle of an opened uSMU.
+typedef struct UsmuDevice UsmuDevice;
+
+// Open a uSMU.
+//
+// `port` is the path of the serial port, or null to use the single attached uSMU.
+// Returns null on failure.
+//
+// # Safety
+// `port` must be null or a valid nul terminated string.
+struct UsmuDevice *usmu_open(const char *port);
+
+// Close the device and release the handle.
+//
+// # Safety
+// `device` must be null or a valid handle obtained from [usmu_open], which is invalid afterwards.
+void usmu_close(struct UsmuDevice *device);
+
+// Enable SMU output.
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open].
+int usmu_enable(struct UsmuDevice *device);
+
+// Disable SMU output (high impedance).
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open].
+int usmu_disable(struct UsmuDevice *device);
+
+// Set the output voltage in volt.
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open].
+int usmu_set_voltage(struct UsmuDevice *device, float voltage);
+
+// Set the sink/source current limit in ampere, at most 40mA.
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open].
+int usmu_set_current_limit(struct UsmuDevice *device, float limit);
+
+// Set the number of samples averaged per measurement.
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open].
+int usmu_set_over_sample_rate(struct UsmuDevice *device, uint16_t samples);
+
+// Set the output to `voltage` and measure voltage (volt) and current (ampere).
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open],
+// `measured_voltage` and `measured_current` must be valid for writes.
+int usmu_measure(struct UsmuDevice *device,
+                 float voltage,
+                 float *measured_voltage,
+                 float *measured_current);
+
+// Record an IV curve with `steps` linearly spaced voltages from `start_voltage` to `end_voltage`.
+//
+// The measured voltages and currents are written to `voltages` and `currents`,
+// which must both hold at least `steps` elements, and their number to `samples`,
+// which is less than `steps` if the sweep ended early. `delay` is the settling time in seconds before each point.
+// The output is enabled for the sweep and disabled afterwards.
+//
+// # Safety
+// `device` must be a valid handle obtained from [usmu_open],
+// `voltages` and `currents` must be valid for writes of `steps` elements, `samples` must be valid for writes.
+int usmu_sweep(struct UsmuDevice *device,
+               float start_voltage,
+               float end_voltage,
+               size_t steps,
+               float current_limit,
+               uint16_t over_sampling,
+               float delay,
+               float *voltages,
+               float *currents,
+               size_t *samples);
+
+// Copy the description of the last failure on this thread as nul terminated string into `buffer`.
+//
+// The message is truncated to fit into `length` bytes.
+// Returns the length of the complete message without the terminator, or 0 if no failure occurred.
+//
+// # Safety
+// `buffer` must be null or valid for writes of `length` bytes.
+size_t usmu_last_error(char *buffer, size_t length);
+
+#endif  /* USMU_H */
@@ -0,0 +1,153 @@
+//! Over-sampling adapted to the measured current of each sweep point.
+//!
+//! The noise of the current measurement is roughly constant in absolute terms, hence the low currents of a curve
+//! spanning many decades, e.g. of a diode, need more averaging than the high currents.
+//! [AdaptiveOverSampling] raises the over-sampling at points measuring less than a current and lowers it at points
+//! measuring more than another current, balancing the noise against the total duration of the sweep.
+//!
+//! Each point is measured at the current rate first. If its current calls for another rate,
+//! the rate is changed and the point is measured again, so only the points at the transitions are measured twice.
+
+use std::{fmt::Display, str::FromStr};
+
+use clap::Parser;
+
+use crate::{Current, ampere};
+
+/// An over-sampling rate applying beyond a current magnitude, e.g. `0.001 mA:100`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverSamplingThreshold {
+    pub current: Current,
+    pub over_sampling: u16,
+}
+
+impl FromStr for OverSamplingThreshold {
+    type Err = String;
+
+    /// Parse `CURRENT:RATE`, e.g. `0.001 mA:100`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (current, over_sampling) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected CURRENT:RATE, got '{s}'"))?;
+        let current = current
+            .trim()
+            .parse::<Current>()
+            .map_err(|e| format!("invalid current '{current}': {e}"))?;
+        let over_sampling = over_sampling
+            .trim()
+            .parse()
+            .ok()
+            .filter(|e| *e > 0)
+            .ok_or_else(|| format!("invalid over-sampling rate '{over_sampling}'"))?;
+        Ok(Self {
+            current: current.abs(),
+            over_sampling,
+        })
+    }
+}
+
+impl Display for OverSamplingThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} A:{}",
+            self.current.get::<ampere>(),
+            self.over_sampling
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Parser)]
+pub struct AdaptiveOverSampling {
+    /// Raise the over-sampling at points measuring less than the current magnitude, e.g. `0.001 mA:100`.
+    #[arg(long, value_name = "CURRENT:RATE")]
+    pub over_sampling_below: Option<OverSamplingThreshold>,
+
+    /// Lower the over-sampling at points measuring more than the current magnitude, e.g. `1 mA:1`.
+    #[arg(long, value_name = "CURRENT:RATE")]
+    pub over_sampling_above: Option<OverSamplingThreshold>,
+}
+
+impl AdaptiveOverSampling {
+    /// The over-sampling rate for a point measuring `current`, `default` between the thresholds.
+    pub fn rate(&self, current: Current, default: u16) -> u16 {
+        let current = current.abs();
+        if let Some(below) = self.over_sampling_below
+            && current < below.current
+        {
+            return below.over_sampling;
+        }
+        if let Some(above) = self.over_sampling_above
+            && current > above.current
+        {
+            return above.over_sampling;
+        }
+        default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MicroSmu, Voltage, milliampere, record_iv_curve::IvCurveRecordingParameters,
+        test_util::FakeSerialPort, volt,
+    };
+
+    use uom::si::electric_current::microampere;
+
+    use super::*;
+
+    #[test]
+    fn adapts_the_over_sampling_to_the_current() {
+        let adaptive = AdaptiveOverSampling {
+            over_sampling_below: Some("0.001 mA:100".parse().unwrap()),
+            over_sampling_above: Some("-1 mA:2".parse().unwrap()),
+        };
+        assert_eq!(adaptive.rate(Current::new::<microampere>(-0.5), 10), 100);
+        assert_eq!(adaptive.rate(Current::new::<microampere>(50.0), 10), 10);
+        assert_eq!(adaptive.rate(Current::new::<milliampere>(-5.0), 10), 2);
+        assert_eq!(
+            AdaptiveOverSampling::default().rate(Current::new::<ampere>(0.0), 10),
+            10
+        );
+        assert!("1 mA".parse::<OverSamplingThreshold>().is_err());
+        assert!("1 mA:0".parse::<OverSamplingThreshold>().is_err());
+        let threshold: OverSamplingThreshold = "1 mA:2".parse().unwrap();
+        assert_eq!(threshold.to_string().parse(), Ok(threshold));
+    }
+
+    #[test]
+    fn remeasures_points_at_transitions() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 0")
+            .expect("CH1:CUR 20")
+            .expect("CH1:ENA")
+            .expect("CH1:OSR 10")
+            .expect("CH1:VOL 0")
+            .expect_query("CH1:MEA:VOL 0", "0.000000,0.000000")
+            .expect("CH1:OSR 100")
+            .expect_query("CH1:MEA:VOL 0", "0.000000,0.000000")
+            .expect("CH1:VOL 1")
+            .expect_query("CH1:MEA:VOL 1", "1.000000,0.001000")
+            .expect("CH1:OSR 10")
+            .expect_query("CH1:MEA:VOL 1", "1.000000,0.001000")
+            .expect("CH1:VOL 2")
+            .expect_query("CH1:MEA:VOL 2", "2.000000,0.002000")
+            .expect("CH1:DIS");
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            end_voltage: Voltage::new::<volt>(2.0),
+            voltage_steps: 3,
+            over_sampling: 10,
+            adaptive_over_sampling: AdaptiveOverSampling {
+                over_sampling_below: Some("0.001 mA:100".parse().unwrap()),
+                over_sampling_above: None,
+            },
+            ..Default::default()
+        };
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let result = parameters.record(&mut smu).unwrap();
+        port.verify();
+        assert_eq!(result.samples.len(), 3);
+    }
+}
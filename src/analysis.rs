@@ -0,0 +1,396 @@
+//! Extraction of key parameters from recorded IV curves.
+//!
+//! All functions expect the samples in sweep order and interpolate linearly between adjacent samples.
+
+use std::{fmt::Display, str::FromStr};
+
+use uom::si::{
+    electrical_conductance::siemens, electrical_resistance::ohm, f32::ElectricalConductance,
+};
+
+use crate::{
+    Current, Power, Resistance, Result, Voltage, ampere,
+    filters::{Filter, filter_curve, savitzky_golay},
+    simulator::THERMAL_VOLTAGE,
+    volt,
+};
+
+/// Interpolate the first zero crossing of `f` along the curve and return the fraction
+/// between the two enclosing samples, or the index of an exact zero.
+fn zero_crossing(
+    samples: &[(Voltage, Current)],
+    f: impl Fn(Voltage, Current) -> f32,
+) -> Option<(usize, f32)> {
+    let values: Vec<f32> = samples.iter().map(|&(v, i)| f(v, i)).collect();
+    if let Some(index) = values.iter().position(|e| *e == 0.0) {
+        return Some((index, 0.0));
+    }
+    values
+        .windows(2)
+        .position(|e| e[0].signum() != e[1].signum())
+        .map(|index| {
+            let (a, b) = (values[index], values[index + 1]);
+            (index, a / (a - b))
+        })
+}
+
+fn interpolate(
+    samples: &[(Voltage, Current)],
+    (index, fraction): (usize, f32),
+) -> (Voltage, Current) {
+    let (v0, i0) = samples[index];
+    if fraction == 0.0 {
+        return (v0, i0);
+    }
+    let (v1, i1) = samples[index + 1];
+    (v0 + (v1 - v0) * fraction, i0 + (i1 - i0) * fraction)
+}
+
+/// Current at 0 V, e.g. of an illuminated solar cell.
+pub fn short_circuit_current(samples: &[(Voltage, Current)]) -> Option<Current> {
+    let crossing = zero_crossing(samples, |v, _| v.get::<volt>())?;
+    Some(interpolate(samples, crossing).1)
+}
+
+/// Voltage at 0 A, e.g. of an illuminated solar cell.
+pub fn open_circuit_voltage(samples: &[(Voltage, Current)]) -> Option<Voltage> {
+    let crossing = zero_crossing(samples, |_, i| i.get::<ampere>())?;
+    Some(interpolate(samples, crossing).0)
+}
+
+/// Voltage at which the current first reaches `current`, e.g. the forward voltage of a diode.
+pub fn forward_voltage(samples: &[(Voltage, Current)], current: Current) -> Option<Voltage> {
+    let crossing = zero_crossing(samples, |_, i| (i - current).get::<ampere>())?;
+    Some(interpolate(samples, crossing).0)
+}
+
+/// Resistance of a least squares line through all samples, e.g. of a resistor.
+pub fn resistance(samples: &[(Voltage, Current)]) -> Option<Resistance> {
+    let points = samples
+        .iter()
+        .map(|(v, i)| (f64::from(i.get::<ampere>()), f64::from(v.get::<volt>())));
+    let (_, slope) = line_fit(points)?;
+    Some(Resistance::new::<ohm>(slope as f32))
+}
+
+/// Resistance of a least squares line through part of a curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResistanceFit {
+    pub resistance: Resistance,
+    /// Coefficient of determination R² of the fit, 1 for samples on a straight line.
+    pub r_squared: f32,
+    /// Number of samples in the fit.
+    pub samples: usize,
+}
+
+fn fit_resistance(samples: impl Iterator<Item = (Voltage, Current)>) -> Option<ResistanceFit> {
+    let points: Vec<(f64, f64)> = samples
+        .map(|(v, i)| (f64::from(i.get::<ampere>()), f64::from(v.get::<volt>())))
+        .collect();
+    let (intercept, slope) = line_fit(points.iter().copied())?;
+    let mean = points.iter().map(|e| e.1).sum::<f64>() / points.len() as f64;
+    let total: f64 = points.iter().map(|(_, v)| (v - mean).powi(2)).sum();
+    let residual: f64 = points
+        .iter()
+        .map(|(i, v)| (v - intercept - slope * i).powi(2))
+        .sum();
+    let r_squared = if total > 0.0 {
+        1.0 - residual / total
+    } else {
+        1.0
+    };
+    Some(ResistanceFit {
+        resistance: Resistance::new::<ohm>(slope as f32),
+        r_squared: r_squared as f32,
+        samples: points.len(),
+    })
+}
+
+/// Shunt resistance, the inverse slope of the samples within `window` around 0 V, e.g. of a solar cell or diode.
+pub fn shunt_resistance(samples: &[(Voltage, Current)], window: Voltage) -> Option<ResistanceFit> {
+    fit_resistance(samples.iter().copied().filter(|(v, _)| v.abs() <= window))
+}
+
+/// Series resistance, the inverse slope of the samples within `window` around the open circuit voltage,
+/// or within `window` below the highest voltage for a curve without positive open circuit voltage, e.g. a diode in the dark.
+///
+/// The slope includes the dynamic resistance of the junction, the estimate is an upper bound improving with the current.
+pub fn series_resistance(samples: &[(Voltage, Current)], window: Voltage) -> Option<ResistanceFit> {
+    let center = match open_circuit_voltage(samples) {
+        Some(voltage) if voltage.value > 0.0 => voltage,
+        _ => {
+            let maximum = samples.iter().map(|e| e.0).reduce(|a, b| a.max(b))?;
+            maximum - window
+        }
+    };
+    fit_resistance(
+        samples
+            .iter()
+            .copied()
+            .filter(|(v, _)| (*v - center).abs() <= window),
+    )
+}
+
+/// Least squares line `y = intercept + slope x`, `None` for less than two distinct `x`.
+pub(crate) fn line_fit(points: impl Iterator<Item = (f64, f64)> + Clone) -> Option<(f64, f64)> {
+    let count = points.clone().count() as f64;
+    let (mean_x, mean_y) = points.clone().fold((0.0, 0.0), |(sx, sy), (x, y)| {
+        (sx + x / count, sy + y / count)
+    });
+    let (covariance, variance) = points.fold((0.0, 0.0), |(c, s), (x, y)| {
+        (c + (x - mean_x) * (y - mean_y), s + (x - mean_x).powi(2))
+    });
+    if count < 2.0 || variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+/// Point of the maximum power delivered by the device, i.e. voltage and current of opposite sign,
+/// e.g. of an illuminated solar cell. `None`, if the device does not deliver power.
+pub fn maximum_power_point(samples: &[(Voltage, Current)]) -> Option<(Voltage, Current)> {
+    samples
+        .iter()
+        .copied()
+        .map(|(v, i)| (v, i, -(v * i)))
+        .filter(|(_, _, power)| power.value > 0.0)
+        .max_by(|a, b| a.2.value.total_cmp(&b.2.value))
+        .map(|(v, i, _)| (v, i))
+}
+
+/// Maximum delivered power, see [maximum_power_point].
+pub fn maximum_power(samples: &[(Voltage, Current)]) -> Option<Power> {
+    let (v, i) = maximum_power_point(samples)?;
+    Some(-(v * i))
+}
+
+/// Ratio of the maximum power to the product of open circuit voltage and short circuit current.
+pub fn fill_factor(samples: &[(Voltage, Current)]) -> Option<f32> {
+    let maximum = maximum_power(samples)?;
+    let ideal = (open_circuit_voltage(samples)? * short_circuit_current(samples)?).abs();
+    if ideal.value == 0.0 {
+        return None;
+    }
+    Some((maximum / ideal).value)
+}
+
+/// Parameters of the Shockley diode equation `I = Is (exp(V / (n Vt)) - 1)` at room temperature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiodeFit {
+    pub saturation_current: Current,
+    pub ideality_factor: f32,
+}
+
+/// Fit the diode equation to the forward biased samples well above the thermal voltage,
+/// by a least squares line through the logarithm of the current.
+pub fn diode_fit(samples: &[(Voltage, Current)]) -> Option<DiodeFit> {
+    let points = samples
+        .iter()
+        .map(|(v, i)| (f64::from(v.get::<volt>()), f64::from(i.get::<ampere>())))
+        .filter(|&(v, i)| v > f64::from(4.0 * THERMAL_VOLTAGE) && i > 0.0)
+        .map(|(v, i)| (v, i.ln()));
+    let (intercept, slope) = line_fit(points)?;
+    if slope <= 0.0 {
+        return None;
+    }
+    Some(DiodeFit {
+        saturation_current: Current::new::<ampere>(intercept.exp() as f32),
+        ideality_factor: (1.0 / (slope * f64::from(THERMAL_VOLTAGE))) as f32,
+    })
+}
+
+/// Differential conductance dI/dV at each sample, by central differences and one-sided at the ends.
+///
+/// Samples without a voltage change to their neighbors are skipped.
+pub fn differential_conductance(
+    samples: &[(Voltage, Current)],
+) -> Vec<(Voltage, ElectricalConductance)> {
+    (0..samples.len())
+        .filter_map(|index| {
+            let (v0, i0) = samples[index.saturating_sub(1)];
+            let (v1, i1) = samples[(index + 1).min(samples.len() - 1)];
+            let dv = (v1 - v0).get::<volt>();
+            if dv == 0.0 {
+                return None;
+            }
+            let conductance = (i1 - i0).get::<ampere>() / dv;
+            Some((
+                samples[index].0,
+                ElectricalConductance::new::<siemens>(conductance),
+            ))
+        })
+        .collect()
+}
+
+/// Savitzky-Golay smoothing of the currents for derivative based analyses, given as `WINDOW:ORDER`, e.g. `7:2`,
+/// see [Filter::SavitzkyGolay]. Noise dominates derivatives of raw samples, in particular of finely stepped sweeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Smoothing {
+    pub window: usize,
+    pub order: usize,
+}
+
+impl Smoothing {
+    /// The samples with smoothed currents, the voltages are kept.
+    pub fn smooth(&self, samples: &[(Voltage, Current)]) -> Result<Vec<(Voltage, Current)>> {
+        filter_curve(
+            &[Filter::SavitzkyGolay {
+                window: self.window,
+                order: self.order,
+            }],
+            samples,
+        )
+    }
+
+    /// Differential conductance dI/dV at each sample, as ratio of the derivatives of the fitted polynomials
+    /// of current and voltage. Fails if there are fewer samples than the window length.
+    ///
+    /// Samples without a voltage change are skipped.
+    pub fn differential_conductance(
+        &self,
+        samples: &[(Voltage, Current)],
+    ) -> Result<Vec<(Voltage, ElectricalConductance)>> {
+        let (voltages, currents): (Vec<f64>, Vec<f64>) = samples
+            .iter()
+            .map(|(v, i)| (f64::from(v.get::<volt>()), f64::from(i.get::<ampere>())))
+            .unzip();
+        let dv = savitzky_golay(&voltages, self.window, self.order, 1)?;
+        let di = savitzky_golay(&currents, self.window, self.order, 1)?;
+        Ok(samples
+            .iter()
+            .zip(dv.into_iter().zip(di))
+            .filter(|(_, (dv, _))| *dv != 0.0)
+            .map(|(&(v, _), (dv, di))| (v, ElectricalConductance::new::<siemens>((di / dv) as f32)))
+            .collect())
+    }
+}
+
+impl Display for Smoothing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.window, self.order)
+    }
+}
+
+impl FromStr for Smoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match format!("savitzky-golay:{s}").parse()? {
+            Filter::SavitzkyGolay { order: 0, .. } => {
+                Err("the order must be at least 1 for derivatives".to_string())
+            }
+            Filter::SavitzkyGolay { window, order } => Ok(Self { window, order }),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of a diode with `photo_current` in parallel, from -0.1 V to 0.7 V.
+    fn diode(ideality_factor: f32, photo_current: f32) -> Vec<(Voltage, Current)> {
+        (0..=80)
+            .map(|e| {
+                let v = -0.1 + e as f32 * 0.01;
+                let i = 1e-12 * (v / (ideality_factor * THERMAL_VOLTAGE)).exp_m1() - photo_current;
+                (Voltage::new::<volt>(v), Current::new::<ampere>(i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_diode() {
+        let fit = diode_fit(&diode(1.5, 0.0)).unwrap();
+        assert!((fit.ideality_factor - 1.5).abs() < 0.01, "{fit:?}");
+        assert!((fit.saturation_current.get::<ampere>() / 1e-12 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn extracts_solar_parameters() {
+        let samples = diode(1.0, 0.01);
+        let (v, i) = maximum_power_point(&samples).unwrap();
+        assert!(v.get::<volt>() > 0.0 && i.get::<ampere>() < 0.0);
+        let fill_factor = fill_factor(&samples).unwrap();
+        assert!(fill_factor > 0.7 && fill_factor < 0.9, "{fill_factor}");
+        assert!(maximum_power_point(&diode(1.0, 0.0)).is_none());
+
+        let conductance = differential_conductance(&samples);
+        assert_eq!(conductance.len(), samples.len());
+        assert!(conductance.iter().all(|(_, g)| g.get::<siemens>() >= 0.0));
+    }
+
+    #[test]
+    fn extracts_series_and_shunt_resistance() {
+        // a diode with 10 Ω in series, up to 100 mA
+        let samples: Vec<_> = (0..=100)
+            .map(|e| {
+                let i = e as f32 * 0.001;
+                let v = THERMAL_VOLTAGE * (i / 1e-12).ln_1p() + 10.0 * i;
+                (Voltage::new::<volt>(v), Current::new::<ampere>(i))
+            })
+            .collect();
+        let window = Voltage::new::<volt>(0.1);
+
+        let series = series_resistance(&samples, window).unwrap();
+        let rs = series.resistance.get::<ohm>();
+        assert!((10.0..11.0).contains(&rs), "{series:?}");
+        assert!(series.r_squared > 0.99);
+
+        // the linear region of an illuminated solar cell with 1 kΩ in parallel
+        let linear: Vec<_> = (-5..=5)
+            .map(|e| {
+                let v = e as f32 * 0.02;
+                (
+                    Voltage::new::<volt>(v),
+                    Current::new::<ampere>(v / 1000.0 - 0.01),
+                )
+            })
+            .collect();
+        let shunt = shunt_resistance(&linear, window).unwrap();
+        assert!(
+            (shunt.resistance.get::<ohm>() - 1000.0).abs() < 1.0,
+            "{shunt:?}"
+        );
+        assert!((shunt.r_squared - 1.0).abs() < 1e-6);
+        assert_eq!(shunt.samples, 11);
+        assert!(shunt_resistance(&linear, Voltage::new::<volt>(0.001)).is_none());
+    }
+
+    #[test]
+    fn smooths_before_derivatives() {
+        // a 100 Ω resistor with periodic noise of 1 mA
+        let samples: Vec<_> = (0..21)
+            .map(|e| {
+                let v = e as f32 * 0.1;
+                let noise = [1e-3, 0.0, -1e-3][e % 3];
+                (
+                    Voltage::new::<volt>(v),
+                    Current::new::<ampere>(v / 100.0 + noise),
+                )
+            })
+            .collect();
+        let smoothing: Smoothing = "5:1".parse().unwrap();
+        assert_eq!(smoothing.to_string(), "5:1");
+        assert!("5:0".parse::<Smoothing>().is_err());
+        assert!("4:1".parse::<Smoothing>().is_err());
+
+        let raw = differential_conductance(&samples);
+        assert!(
+            raw.iter()
+                .any(|(_, g)| (g.get::<siemens>() - 0.01).abs() > 0.005)
+        );
+        let smoothed = smoothing.differential_conductance(&samples).unwrap();
+        assert_eq!(smoothed.len(), samples.len());
+        assert!(
+            smoothed
+                .iter()
+                .all(|(_, g)| (g.get::<siemens>() - 0.01).abs() < 0.003),
+            "{smoothed:?}"
+        );
+        assert_eq!(smoothing.smooth(&samples).unwrap()[10].0, samples[10].0);
+        assert!(smoothing.differential_conductance(&samples[..3]).is_err());
+    }
+}
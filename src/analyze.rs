@@ -0,0 +1,493 @@
+//! Offline analysis of saved sweep results with `usmu analyze`, decoupled from the acquisition.
+//!
+//! Each file is loaded with [crate::sweep_file::load] and summarized by the selected analyses,
+//! as Markdown tables or, with the `json` feature, as JSON array with SI values.
+//! With `--smooth WINDOW:ORDER`, the derivative based analyses, i.e. the diode fit and the differential conductance,
+//! work on Savitzky-Golay smoothed currents, and `--series` writes the raw and smoothed currents side by side.
+
+use std::{fs::File, io::Write, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use uom::si::{electrical_conductance::siemens, electrical_resistance::ohm, power::watt};
+
+use crate::{
+    Result, Voltage, ampere,
+    analysis::{self, Smoothing},
+    millivolt, schema,
+    statistics::{self, Statistics},
+    sweep_file,
+    sweep_result::SweepResult,
+    volt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Analysis {
+    /// Saturation current and ideality factor of the diode equation.
+    Diode,
+    /// Short circuit current, open circuit voltage, maximum power point and fill factor.
+    Solar,
+    /// Resistance of a line through all samples.
+    Resistance,
+    /// Series resistance near the open circuit voltage or the highest forward voltage, and shunt resistance near 0 V.
+    SeriesShunt,
+    /// Differential conductance dI/dV at 0 V and its maximum.
+    Conductance,
+    /// Statistics of the measured voltages and currents.
+    Statistics,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SummaryFormat {
+    Markdown,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiodeSummary {
+    pub saturation_current: Option<f32>,
+    pub ideality_factor: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SolarSummary {
+    pub short_circuit_current: Option<f32>,
+    pub open_circuit_voltage: Option<f32>,
+    pub maximum_power: Option<f32>,
+    pub maximum_power_voltage: Option<f32>,
+    pub fill_factor: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesShuntSummary {
+    pub series_resistance: Option<f32>,
+    /// Coefficient of determination of the series resistance fit.
+    pub series_r_squared: Option<f32>,
+    pub shunt_resistance: Option<f32>,
+    /// Coefficient of determination of the shunt resistance fit.
+    pub shunt_r_squared: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConductanceSummary {
+    /// Conductance at the sample closest to 0 V.
+    pub zero_bias: Option<f32>,
+    pub maximum: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatisticsSummary {
+    pub voltage: Option<Statistics>,
+    pub current: Option<Statistics>,
+}
+
+/// Results of the selected analyses of one file in SI units, unselected analyses are `None`
+/// and values not applicable to the curve are `None` within an analysis.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub file: String,
+    pub samples: usize,
+    /// The smoothing of the derivative based analyses as `WINDOW:ORDER`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diode: Option<DiodeSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub solar: Option<SolarSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resistance: Option<Option<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series_shunt: Option<SeriesShuntSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conductance: Option<ConductanceSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<StatisticsSummary>,
+}
+
+/// Selection and configuration of the analyses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryOptions {
+    pub analyses: Vec<Analysis>,
+    /// Smoothing of the currents for the derivative based analyses.
+    pub smoothing: Option<Smoothing>,
+    /// Voltage range of the series and shunt resistance fits.
+    pub fit_window: Voltage,
+}
+
+impl Default for SummaryOptions {
+    /// All analyses without smoothing.
+    fn default() -> Self {
+        Self {
+            analyses: Analysis::value_variants().to_vec(),
+            smoothing: None,
+            fit_window: Voltage::new::<millivolt>(100.0),
+        }
+    }
+}
+
+impl Summary {
+    /// Fails if the diode or conductance analysis is selected
+    /// and there are fewer samples than the window length of the smoothing.
+    pub fn new(
+        file: impl Into<String>,
+        result: &SweepResult,
+        options: &SummaryOptions,
+    ) -> Result<Self> {
+        let samples = result.points();
+        let selected = |analysis| options.analyses.contains(&analysis);
+        let smoothing = options.smoothing;
+        let diode = if selected(Analysis::Diode) {
+            let smoothed = match smoothing {
+                Some(smoothing) => smoothing.smooth(&samples)?,
+                None => samples.clone(),
+            };
+            let fit = analysis::diode_fit(&smoothed);
+            Some(DiodeSummary {
+                saturation_current: fit.map(|e| e.saturation_current.get::<ampere>()),
+                ideality_factor: fit.map(|e| e.ideality_factor),
+            })
+        } else {
+            None
+        };
+        let conductance = if selected(Analysis::Conductance) {
+            let conductance = match smoothing {
+                Some(smoothing) => smoothing.differential_conductance(&samples)?,
+                None => analysis::differential_conductance(&samples),
+            };
+            Some(ConductanceSummary {
+                zero_bias: conductance
+                    .iter()
+                    .min_by(|a, b| a.0.abs().value.total_cmp(&b.0.abs().value))
+                    .map(|(_, g)| g.get::<siemens>()),
+                maximum: conductance
+                    .iter()
+                    .map(|(_, g)| g.get::<siemens>())
+                    .max_by(f32::total_cmp),
+            })
+        } else {
+            None
+        };
+        Ok(Self {
+            file: file.into(),
+            samples: samples.len(),
+            smoothing: smoothing.map(|e| e.to_string()),
+            diode,
+            solar: selected(Analysis::Solar).then(|| SolarSummary {
+                short_circuit_current: analysis::short_circuit_current(&samples)
+                    .map(|e| e.get::<ampere>()),
+                open_circuit_voltage: analysis::open_circuit_voltage(&samples)
+                    .map(|e| e.get::<volt>()),
+                maximum_power: analysis::maximum_power(&samples).map(|e| e.get::<watt>()),
+                maximum_power_voltage: analysis::maximum_power_point(&samples)
+                    .map(|(v, _)| v.get::<volt>()),
+                fill_factor: analysis::fill_factor(&samples),
+            }),
+            resistance: selected(Analysis::Resistance)
+                .then(|| analysis::resistance(&samples).map(|e| e.get::<ohm>())),
+            series_shunt: selected(Analysis::SeriesShunt).then(|| {
+                let series = analysis::series_resistance(&samples, options.fit_window);
+                let shunt = analysis::shunt_resistance(&samples, options.fit_window);
+                SeriesShuntSummary {
+                    series_resistance: series.map(|e| e.resistance.get::<ohm>()),
+                    series_r_squared: series.map(|e| e.r_squared),
+                    shunt_resistance: shunt.map(|e| e.resistance.get::<ohm>()),
+                    shunt_r_squared: shunt.map(|e| e.r_squared),
+                }
+            }),
+            conductance,
+            statistics: selected(Analysis::Statistics).then(|| {
+                let statistics = statistics::summarize(&samples);
+                StatisticsSummary {
+                    voltage: statistics.map(|e| e.0),
+                    current: statistics.map(|e| e.1),
+                }
+            }),
+        })
+    }
+
+    /// The values as rows of analysis, parameter and value with unit.
+    fn rows(&self) -> Vec<(&'static str, &'static str, String)> {
+        fn value(value: Option<f32>, unit: &str) -> String {
+            match value {
+                Some(value) => format!("{value:.6e} {unit}").trim_end().to_string(),
+                None => "n/a".to_string(),
+            }
+        }
+
+        let mut rows = Vec::new();
+        if let Some(diode) = &self.diode {
+            rows.push((
+                "diode",
+                "saturation current",
+                value(diode.saturation_current, "A"),
+            ));
+            rows.push(("diode", "ideality factor", value(diode.ideality_factor, "")));
+        }
+        if let Some(solar) = &self.solar {
+            rows.push((
+                "solar",
+                "short circuit current",
+                value(solar.short_circuit_current, "A"),
+            ));
+            rows.push((
+                "solar",
+                "open circuit voltage",
+                value(solar.open_circuit_voltage, "V"),
+            ));
+            rows.push(("solar", "maximum power", value(solar.maximum_power, "W")));
+            rows.push((
+                "solar",
+                "maximum power voltage",
+                value(solar.maximum_power_voltage, "V"),
+            ));
+            rows.push(("solar", "fill factor", value(solar.fill_factor, "")));
+        }
+        if let Some(resistance) = self.resistance {
+            rows.push(("resistance", "resistance", value(resistance, "Ω")));
+        }
+        if let Some(series_shunt) = &self.series_shunt {
+            rows.push((
+                "series-shunt",
+                "series resistance",
+                value(series_shunt.series_resistance, "Ω"),
+            ));
+            rows.push((
+                "series-shunt",
+                "series fit R²",
+                value(series_shunt.series_r_squared, ""),
+            ));
+            rows.push((
+                "series-shunt",
+                "shunt resistance",
+                value(series_shunt.shunt_resistance, "Ω"),
+            ));
+            rows.push((
+                "series-shunt",
+                "shunt fit R²",
+                value(series_shunt.shunt_r_squared, ""),
+            ));
+        }
+        if let Some(conductance) = &self.conductance {
+            rows.push((
+                "conductance",
+                "zero bias dI/dV",
+                value(conductance.zero_bias, "S"),
+            ));
+            rows.push((
+                "conductance",
+                "maximum dI/dV",
+                value(conductance.maximum, "S"),
+            ));
+        }
+        if let Some(statistics) = &self.statistics {
+            for (parameter, statistics, unit) in [
+                ("voltage", statistics.voltage, "V"),
+                ("current", statistics.current, "A"),
+            ] {
+                let statistics = statistics.map(|e| {
+                    format!(
+                        "mean {:.6e} {unit}, σ {:.3e} {unit}, min {:.6e} {unit}, max {:.6e} {unit}",
+                        e.mean, e.standard_deviation, e.minimum, e.maximum
+                    )
+                });
+                rows.push((
+                    "statistics",
+                    parameter,
+                    statistics.unwrap_or("n/a".to_string()),
+                ));
+            }
+        }
+        rows
+    }
+}
+
+/// Write the summaries as a Markdown section with a table per file.
+pub fn write_markdown(summaries: &[Summary], mut output: impl Write) -> Result<()> {
+    for summary in summaries {
+        writeln!(output, "## {}\n", summary.file)?;
+        match summary.smoothing.as_ref() {
+            Some(smoothing) => writeln!(
+                output,
+                "{} samples, derivatives smoothed by Savitzky-Golay {smoothing}\n",
+                summary.samples
+            )?,
+            None => writeln!(output, "{} samples\n", summary.samples)?,
+        }
+        writeln!(output, "| Analysis | Parameter | Value |")?;
+        writeln!(output, "|----------|-----------|-------|")?;
+        for (analysis, parameter, value) in summary.rows() {
+            writeln!(output, "| {analysis} | {parameter} | {value} |")?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+/// Write the raw and smoothed currents of the results as CSV with the columns `file`, `voltage`, `current` and `smoothed_current`.
+pub fn write_series(
+    results: &[(String, SweepResult)],
+    smoothing: Option<Smoothing>,
+    output: impl Write,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row<'a> {
+        file: &'a str,
+        voltage: f32,
+        current: f32,
+        smoothed_current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for (file, result) in results {
+        let samples = result.points();
+        let smoothed = match smoothing {
+            Some(smoothing) => smoothing.smooth(&samples)?,
+            None => samples.clone(),
+        };
+        for ((v, i), (_, smoothed)) in samples.into_iter().zip(smoothed) {
+            writer.serialize(Row {
+                file,
+                voltage: v.get::<volt>(),
+                current: i.get::<ampere>(),
+                smoothed_current: smoothed.get::<ampere>(),
+            })?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct AnalyzeArguments {
+    /// Saved sweep results, CSV or, with the `json` feature, JSON files.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Analyses to run, all by default.
+    #[arg(long = "analysis", short = 'a', value_delimiter = ',')]
+    pub analyses: Vec<Analysis>,
+
+    #[arg(long, short = 'f', default_value = "markdown")]
+    pub format: SummaryFormat,
+
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    /// Smooth the currents by a Savitzky-Golay filter before the derivative based analyses,
+    /// given as `WINDOW:ORDER`, e.g. `7:2`.
+    #[arg(long)]
+    pub smooth: Option<Smoothing>,
+
+    /// Voltage range of the series and shunt resistance fits.
+    #[arg(long, default_value = "100 mV")]
+    pub fit_window: Voltage,
+
+    /// Write the raw and the smoothed currents of all files as CSV to this file.
+    #[arg(long)]
+    pub series: Option<PathBuf>,
+}
+
+impl AnalyzeArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut options = SummaryOptions {
+            smoothing: self.smooth,
+            fit_window: self.fit_window,
+            ..Default::default()
+        };
+        if !self.analyses.is_empty() {
+            options.analyses = self.analyses.clone();
+        }
+        let results = self
+            .files
+            .iter()
+            .map(|file| Ok((file.display().to_string(), sweep_file::load(file)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let summaries = results
+            .iter()
+            .map(|(file, result)| Summary::new(file.as_str(), result, &options))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(series) = self.series.as_ref() {
+            write_series(&results, self.smooth, File::create(series)?)?;
+        }
+
+        let output: Box<dyn Write> = match self.output.as_ref() {
+            Some(output) => Box::new(File::create(output)?),
+            None => Box::new(std::io::stdout()),
+        };
+        match self.format {
+            SummaryFormat::Markdown => write_markdown(&summaries, output),
+            #[cfg(feature = "json")]
+            SummaryFormat::Json => {
+                serde_json::to_writer_pretty(output, &summaries)
+                    .map_err(|e| crate::Error::Data(e.to_string()))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MicroSmu,
+        record_iv_curve::IvCurveRecordingParameters,
+        simulator::{Resistor, SimulatedSmu},
+    };
+
+    use super::*;
+
+    #[test]
+    fn summarizes_a_resistor() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let result = IvCurveRecordingParameters {
+            voltage_steps: 5,
+            over_sampling: 1,
+            ..Default::default()
+        }
+        .record(&mut smu)
+        .unwrap();
+        let options = SummaryOptions {
+            analyses: vec![Analysis::Resistance, Analysis::Conductance],
+            ..Default::default()
+        };
+        let summary = Summary::new("resistor.csv", &result, &options).unwrap();
+
+        let resistance = summary.resistance.flatten().unwrap();
+        assert!((resistance - 1000.0).abs() < 1.0, "{resistance}");
+        assert!(summary.diode.is_none());
+
+        let mut markdown = Vec::new();
+        write_markdown(&[summary], &mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.starts_with("## resistor.csv"));
+        assert!(markdown.contains("| resistance | resistance |"));
+        assert!(!markdown.contains("| diode |"));
+    }
+    #[test]
+    fn smooths_only_for_the_derivative_based_analyses() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let result = IvCurveRecordingParameters {
+            voltage_steps: 5,
+            over_sampling: 1,
+            ..Default::default()
+        }
+        .record(&mut smu)
+        .unwrap();
+        let mut options = SummaryOptions {
+            analyses: vec![Analysis::Resistance, Analysis::Statistics],
+            smoothing: Some(Smoothing {
+                window: 7,
+                order: 2,
+            }),
+            ..Default::default()
+        };
+        let summary = Summary::new("resistor.csv", &result, &options).unwrap();
+        assert!(summary.resistance.flatten().is_some());
+
+        for analysis in [Analysis::Diode, Analysis::Conductance] {
+            options.analyses = vec![analysis];
+            assert!(Summary::new("resistor.csv", &result, &options).is_err());
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Annotation of runs with environment information, e.g. readings of a lab climate sensor.
+//!
+//! Hooks run before and after a run and their results, e.g. the output of a shell command,
+//! are collected as run metadata.
+
+use std::{fmt::Display, io::Write, path::PathBuf, process::Command, str::FromStr};
+
+use clap::Parser;
+
+use crate::{Error, Result, schema};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Pre,
+    Post,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Pre => write!(f, "pre"),
+            Stage::Post => write!(f, "post"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    pub stage: Stage,
+    pub name: String,
+    pub value: String,
+}
+
+type Hook = Box<dyn FnMut() -> Result<String>>;
+
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<(Stage, String, Hook)>,
+    annotations: Vec<Annotation>,
+}
+
+impl Hooks {
+    /// Add a hook, whose returned value is recorded as annotation `name`.
+    pub fn add(
+        &mut self,
+        stage: Stage,
+        name: impl Into<String>,
+        hook: impl FnMut() -> Result<String> + 'static,
+    ) -> &mut Self {
+        self.hooks.push((stage, name.into(), Box::new(hook)));
+        self
+    }
+
+    /// Run all hooks of `stage` in the order they were added.
+    pub fn run(&mut self, stage: Stage) -> Result<()> {
+        for (_, name, hook) in self.hooks.iter_mut().filter(|(e, _, _)| *e == stage) {
+            let value = hook()?;
+            self.annotations.push(Annotation {
+                stage,
+                name: name.clone(),
+                value,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Write the annotations as CSV with the columns `stage`, `name` and `value`.
+    pub fn write_csv(&self, output: impl Write) -> Result<()> {
+        let mut writer = schema::csv_writer(output)?;
+        writer.write_record(["stage", "name", "value"])?;
+        for Annotation { stage, name, value } in &self.annotations {
+            writer.write_record([stage.to_string().as_str(), name, value])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A hook running `command` in the system shell, returning its trimmed standard output.
+pub fn shell_command(command: String) -> impl FnMut() -> Result<String> {
+    move || {
+        let output = if cfg!(windows) {
+            Command::new("cmd").arg("/C").arg(&command).output()?
+        } else {
+            Command::new("sh").arg("-c").arg(&command).output()?
+        };
+        if !output.status.success() {
+            Err(Error::External(format!(
+                "Hook '{command}' failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))?;
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// A shell command hook given as `NAME=COMMAND`.
+#[derive(Debug, Clone)]
+pub struct CommandHook {
+    pub name: String,
+    pub command: String,
+}
+
+impl FromStr for CommandHook {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, command) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=COMMAND, got '{s}'"))?;
+        Ok(Self {
+            name: name.to_string(),
+            command: command.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct AnnotationParameter {
+    /// Shell command run before the sweep, its output is recorded as annotation, given as `NAME=COMMAND`.
+    #[arg(long = "pre-hook")]
+    pub pre_hooks: Vec<CommandHook>,
+
+    /// Shell command run after the sweep, its output is recorded as annotation, given as `NAME=COMMAND`.
+    #[arg(long = "post-hook")]
+    pub post_hooks: Vec<CommandHook>,
+
+    /// Write the annotations as CSV to this file, instead of printing them to stderr.
+    #[arg(long)]
+    pub metadata: Option<PathBuf>,
+}
+
+impl AnnotationParameter {
+    pub fn hooks(&self) -> Hooks {
+        let mut hooks = Hooks::default();
+        let stages = [
+            (Stage::Pre, &self.pre_hooks),
+            (Stage::Post, &self.post_hooks),
+        ];
+        for (stage, commands) in stages {
+            for CommandHook { name, command } in commands {
+                hooks.add(stage, name, shell_command(command.clone()));
+            }
+        }
+        hooks
+    }
+
+    pub fn output(&self, hooks: &Hooks) -> Result<()> {
+        if let Some(metadata) = self.metadata.as_ref() {
+            hooks.write_csv(std::fs::File::create(metadata)?)?;
+        } else {
+            for Annotation { stage, name, value } in hooks.annotations() {
+                eprintln!("{stage} {name}: {value}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_the_hooks_of_each_stage() {
+        let mut hooks = Hooks::default();
+        hooks
+            .add(Stage::Post, "after", || Ok("done".to_string()))
+            .add(Stage::Pre, "humidity", || Ok("40 %".to_string()))
+            .add(Stage::Pre, "temperature", || Ok("21.5 C".to_string()));
+
+        hooks.run(Stage::Pre).unwrap();
+        let names = hooks
+            .annotations()
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["humidity", "temperature"]);
+        hooks.run(Stage::Post).unwrap();
+        assert_eq!(hooks.annotations()[2].stage, Stage::Post);
+
+        let mut csv = Vec::new();
+        hooks.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv
+            .lines()
+            .filter(|e| !e.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                "stage,name,value",
+                "pre,humidity,40 %",
+                "pre,temperature,21.5 C",
+                "post,after,done"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_command_hooks() {
+        let hook: CommandHook = "filter=grep -c x=1 log.txt".parse().unwrap();
+        assert_eq!(hook.name, "filter");
+        assert_eq!(hook.command, "grep -c x=1 log.txt");
+        assert!("temperature".parse::<CommandHook>().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn records_the_output_of_shell_commands() {
+        assert_eq!(
+            shell_command("echo '  21.5 C  '".to_string())().unwrap(),
+            "21.5 C"
+        );
+        let failed = shell_command("echo unplugged >&2; exit 3".to_string())();
+        let Err(Error::External(message)) = failed else {
+            panic!("unexpected result {failed:?}");
+        };
+        assert!(message.contains("unplugged"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Sweep results as Arrow [RecordBatch], e.g. to hand them off to Parquet writers or via Arrow IPC.
+//!
+//! The batch has a `voltage`, `current`, `time` and `set_voltage` column of SI values,
+//! their unit is stored in the `unit` metadata of the fields, and a boolean `compliance` column.
+
+use std::{collections::HashMap, sync::Arc};
+
+use arrow_array::{BooleanArray, Float32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::{
+    MicroSmu, Result, ampere, record_iv_curve::IvCurveRecordingParameters, second,
+    sweep_result::SweepResult, volt,
+};
+
+pub const VOLTAGE_COLUMN: &str = "voltage";
+pub const CURRENT_COLUMN: &str = "current";
+pub const TIME_COLUMN: &str = "time";
+pub const SET_VOLTAGE_COLUMN: &str = "set_voltage";
+pub const COMPLIANCE_COLUMN: &str = "compliance";
+
+fn field(name: &str, unit: &str) -> Field {
+    Field::new(name, DataType::Float32, false)
+        .with_metadata(HashMap::from([("unit".to_string(), unit.to_string())]))
+}
+
+/// Schema of the batches created by [to_record_batch].
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        field(VOLTAGE_COLUMN, "V"),
+        field(CURRENT_COLUMN, "A"),
+        field(TIME_COLUMN, "s"),
+        field(SET_VOLTAGE_COLUMN, "V"),
+        Field::new(COMPLIANCE_COLUMN, DataType::Boolean, false),
+    ]))
+}
+
+pub fn to_record_batch(result: &SweepResult) -> RecordBatch {
+    let samples = &result.samples;
+    let voltage: Float32Array = samples.iter().map(|e| e.voltage.get::<volt>()).collect();
+    let current: Float32Array = samples.iter().map(|e| e.current.get::<ampere>()).collect();
+    let time: Float32Array = samples.iter().map(|e| e.time.get::<second>()).collect();
+    let set_voltage: Float32Array = samples
+        .iter()
+        .map(|e| e.set_voltage.get::<volt>())
+        .collect();
+    let compliance: BooleanArray = samples.iter().map(|e| Some(e.compliance)).collect();
+    RecordBatch::try_new(
+        schema(),
+        vec![
+            Arc::new(voltage),
+            Arc::new(current),
+            Arc::new(time),
+            Arc::new(set_voltage),
+            Arc::new(compliance),
+        ],
+    )
+    .expect("columns match the schema")
+}
+
+impl IvCurveRecordingParameters {
+    /// Like [Self::record], but returns the samples as [RecordBatch].
+    pub fn record_batch(&self, smu: &mut MicroSmu) -> Result<RecordBatch> {
+        let result = self.record(smu)?;
+        Ok(to_record_batch(&result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::Array;
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    #[test]
+    fn converts_a_sweep() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let result = IvCurveRecordingParameters {
+            voltage_steps: 5,
+            over_sampling: 1,
+            ..Default::default()
+        }
+        .record(&mut smu)
+        .unwrap();
+        let batch = to_record_batch(&result);
+
+        let fields = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|e| (e.name().clone(), e.data_type().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                (VOLTAGE_COLUMN.to_string(), DataType::Float32),
+                (CURRENT_COLUMN.to_string(), DataType::Float32),
+                (TIME_COLUMN.to_string(), DataType::Float32),
+                (SET_VOLTAGE_COLUMN.to_string(), DataType::Float32),
+                (COMPLIANCE_COLUMN.to_string(), DataType::Boolean),
+            ]
+        );
+        let current = batch
+            .schema()
+            .field_with_name(CURRENT_COLUMN)
+            .unwrap()
+            .clone();
+        assert_eq!(current.metadata()["unit"], "A");
+        assert_eq!(batch.num_rows(), 5);
+
+        let column = |name| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float32Array>()
+                .unwrap()
+                .values()
+                .to_vec()
+        };
+        let points = column(VOLTAGE_COLUMN)
+            .into_iter()
+            .zip(column(CURRENT_COLUMN))
+            .collect::<Vec<_>>();
+        let expected = result
+            .points()
+            .into_iter()
+            .map(|(v, i)| (v.get::<volt>(), i.get::<ampere>()))
+            .collect::<Vec<_>>();
+        assert_eq!(points, expected);
+        let compliance = batch.column_by_name(COMPLIANCE_COLUMN).unwrap();
+        assert_eq!(compliance.null_count(), 0);
+    }
+}
@@ -0,0 +1,432 @@
+//! [AsyncMicroSmu], the commands of [MicroSmu](crate::MicroSmu) on tokio, with the `async` feature.
+//!
+//! Exchanges with high over-sampling take seconds, during which the blocking [MicroSmu](crate::MicroSmu)
+//! occupies its thread. [AsyncMicroSmu] awaits the responses instead, e.g. in GUI or service applications
+//! running on a tokio runtime, and sends every request of [crate::commands] with [AsyncMicroSmu::send_command]
+//! and [AsyncMicroSmu::query].
+//!
+//! Besides the [SafetyLimits], the host-side features of [MicroSmu](crate::MicroSmu), e.g. the interlock, ramps,
+//! reconnecting and corrections of the measurements, are only available on the blocking device.
+//!
+//! ```no_run
+//! # async fn example() -> usmu::Result<()> {
+//! use usmu::{Voltage, async_smu::AsyncMicroSmu, find_serial_ports, volt};
+//!
+//! let port = find_serial_ports()?.into_iter().next().unwrap();
+//! let mut smu = AsyncMicroSmu::open(port)?;
+//! smu.enable().await?;
+//! let measured = smu.measure(Voltage::new::<volt>(0.5)).await?;
+//! smu.disable().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, time::Duration};
+
+use serialport::SerialPortInfo;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::{
+    CURRENT_ADC_CHANNEL, Current, Error, RawMeasurement, Result, VOLTAGE_ADC_CHANNEL, Voltage,
+    builder::{DEFAULT_BAUDRATE, DEFAULT_INTER_COMMAND_DELAY, DEFAULT_TIMEOUT},
+    commands::{
+        self, CurrentRange, DifferentialConversionRequest, DisableRequest, EepromAddress,
+        EnableRequest, EnableVoltageCalibrationModeRequest, IdentityRequest,
+        LockCurrentRangeAndClearCalibrationRequest, MeasureRequest, MeasureResponse,
+        ReadEepromRequest, ResetRequest, SetCurrentLimitDacRequest, SetCurrentLimitRequest,
+        SetOverSampleRateRequest, SetVoltageDacRequest, SetVoltageRequest,
+        WriteCurrentLimitCalibrationRequest, WriteCurrentLimitDacCalibrationRequest,
+        WriteVoltageAdcCalibrationRequest, WriteVoltageDacCalibrationRequest,
+    },
+    safety::SafetyLimits,
+    scpi::{EmptyResponse, ScpiDeserialize, ScpiRequest},
+};
+
+/// The uSMU on any asynchronous byte stream, the serial port of the device by default, see the [module documentation](self).
+pub struct AsyncMicroSmu<P = SerialStream> {
+    port: BufReader<P>,
+    timeout: Duration,
+    inter_command_delay: Duration,
+    safety_limits: SafetyLimits,
+    /// The last voltage and current limit set, for checking the safety limits.
+    voltage: Option<Voltage>,
+    current_limit: Option<Current>,
+    /// Whether a response timed out, which may still arrive and must not be taken for the next one.
+    stale_input: bool,
+}
+
+impl AsyncMicroSmu {
+    /// Open the serial port of the uSMU, requires a tokio runtime with IO enabled.
+    pub fn open(port: SerialPortInfo) -> Result<Self> {
+        let port = tokio_serial::new(port.port_name, DEFAULT_BAUDRATE).open_native_async()?;
+        Ok(Self::new(port))
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite + Unpin> AsyncMicroSmu<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port: BufReader::new(port),
+            timeout: DEFAULT_TIMEOUT,
+            inter_command_delay: DEFAULT_INTER_COMMAND_DELAY,
+            safety_limits: SafetyLimits::default(),
+            voltage: None,
+            current_limit: None,
+            stale_input: false,
+        }
+    }
+
+    /// Fail responses not received within `timeout` like [MicroSmu](crate::MicroSmu), [DEFAULT_TIMEOUT] by default.
+    /// Measurements with high over-sampling need more time.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Pause after each command, [DEFAULT_INTER_COMMAND_DELAY] by default.
+    pub fn set_inter_command_delay(&mut self, delay: Duration) {
+        self.inter_command_delay = delay;
+    }
+
+    /// Validate all further voltages and current limits against `limits`.
+    pub fn set_safety_limits(&mut self, limits: SafetyLimits) {
+        self.safety_limits = limits;
+    }
+
+    pub fn safety_limits(&self) -> &SafetyLimits {
+        &self.safety_limits
+    }
+
+    async fn send(&mut self, command: &str) -> Result<()> {
+        if self.stale_input {
+            self.discard_input().await?;
+        }
+        let port = self.port.get_mut();
+        port.write_all(command.as_bytes()).await?;
+        port.flush().await?;
+        // the device needs a small pause after transmission
+        tokio::time::sleep(self.inter_command_delay).await;
+        Ok(())
+    }
+
+    /// Receive the response line into `data`, which keeps the data read so far on failure.
+    ///
+    /// Timeouts are reported as [io::ErrorKind::TimedOut] like by the serial port of [MicroSmu](crate::MicroSmu),
+    /// i.e. transient and no lost connection, see [Error::is_transient] and [Error::is_disconnect].
+    async fn receive<Response: ScpiDeserialize>(&mut self, data: &mut String) -> Result<Response> {
+        let read = tokio::time::timeout(self.timeout, self.port.read_line(data)).await;
+        match read {
+            Ok(read) => read?,
+            Err(_) => {
+                // reading a line is not cancel safe, the rest of the response may still arrive
+                self.stale_input = true;
+                Err(io::Error::from(io::ErrorKind::TimedOut))?
+            }
+        };
+        commands::from_wire(data)
+    }
+
+    /// Drop the buffered data and the data received so far, e.g. a late response to a query that timed out.
+    async fn discard_input(&mut self) -> Result<()> {
+        let buffered = self.port.buffer().len();
+        std::pin::Pin::new(&mut self.port).consume(buffered);
+        let mut buffer = [0; 256];
+        // a zero timeout polls the read once, i.e. reads only data already received
+        while let Ok(read) =
+            tokio::time::timeout(Duration::ZERO, self.port.get_mut().read(&mut buffer)).await
+        {
+            if read? == 0 {
+                break;
+            }
+        }
+        self.stale_input = false;
+        Ok(())
+    }
+
+    fn wrap(command: String, response: String, source: Error) -> Error {
+        Error::Exchange {
+            command: command.trim_end().to_string(),
+            response,
+            source: Box::new(source),
+        }
+    }
+
+    /// Transmit `request`, failures are reported as [Error::Exchange] like by [MicroSmu](crate::MicroSmu).
+    pub async fn send_command<Request>(&mut self, request: Request) -> Result<()>
+    where
+        Request: ScpiRequest<Response = EmptyResponse>,
+    {
+        let command = commands::to_wire(&request);
+        let sent = self.send(&command).await;
+        sent.map_err(|e| Self::wrap(command, String::new(), e))
+    }
+
+    /// Transmit `request` and receive its response, failures are reported as [Error::Exchange].
+    pub async fn query<Request, Response>(&mut self, request: Request) -> Result<Response>
+    where
+        Request: ScpiRequest<Response = Response>,
+        Response: ScpiDeserialize,
+    {
+        let command = commands::to_wire(&request);
+        let mut response = String::new();
+        let result = match self.send(&command).await {
+            Ok(()) => self.receive(&mut response).await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| Self::wrap(command, response, e))
+    }
+
+    /// Enable SMU output
+    pub async fn enable(&mut self) -> Result<()> {
+        self.send_command(EnableRequest).await
+    }
+
+    /// Disable SMU output (high impedance)
+    pub async fn disable(&mut self) -> Result<()> {
+        self.send_command(DisableRequest).await
+    }
+
+    /// Set the sink/source current limit, see [MicroSmu::set_current_limit](crate::MicroSmu::set_current_limit).
+    ///
+    /// Fails with [Error::InvalidParameter], if limit is below zero or exceeds 40mA
+    /// (the maximum current capability of the SMU).
+    pub async fn set_current_limit(&mut self, limit: Current) -> Result<()> {
+        let request = SetCurrentLimitRequest::try_new(limit)?;
+        self.safety_limits.check(self.voltage, Some(limit))?;
+        self.send_command(request).await?;
+        self.current_limit = Some(limit);
+        Ok(())
+    }
+
+    /// Set the SMU to the requested voltage level in volts
+    ///
+    /// Fails with [Error::SafetyLimit], if the voltage violates the [SafetyLimits].
+    pub async fn set_voltage(&mut self, voltage: Voltage) -> Result<()> {
+        self.safety_limits
+            .check(Some(voltage), self.current_limit)?;
+        self.send_command(SetVoltageRequest { voltage }).await?;
+        self.voltage = Some(voltage);
+        Ok(())
+    }
+
+    /// Set the SMU to the requested voltage level and return the measured voltage and current.
+    ///
+    /// Fails with [Error::SafetyLimit], if the voltage violates the [SafetyLimits].
+    pub async fn measure(&mut self, voltage: Voltage) -> Result<MeasureResponse> {
+        self.safety_limits
+            .check(Some(voltage), self.current_limit)?;
+        let response = self.query(MeasureRequest { voltage }).await?;
+        self.voltage = Some(voltage);
+        Ok(response)
+    }
+
+    /// Set the oversample rate.
+    ///
+    /// This is the number of samples that are averaged for a given measurement
+    pub async fn set_over_sample_rate(&mut self, samples: u16) -> Result<()> {
+        self.send_command(SetOverSampleRateRequest { samples })
+            .await
+    }
+
+    /// Set the voltage DAC to this level.
+    pub async fn set_voltage_dac(&mut self, level: u16) -> Result<()> {
+        self.send_command(SetVoltageDacRequest { level }).await
+    }
+
+    /// Perform a differential conversion between adjacent ADC channels,
+    /// see [MicroSmu::manual_measure_differential_channel](crate::MicroSmu::manual_measure_differential_channel).
+    ///
+    /// Fails with [Error::InvalidParameter], if channel is invalid for differential conversion.
+    pub async fn manual_measure_differential_channel(&mut self, channel: u8) -> Result<u16> {
+        let response = self
+            .query(DifferentialConversionRequest::try_new(channel)?)
+            .await?;
+        Ok(response.value)
+    }
+
+    /// Measure at `voltage` like [Self::measure], then read the raw differential ADC counts
+    /// of the voltage and current, see [RawMeasurement].
+    pub async fn measure_raw(&mut self, voltage: Voltage) -> Result<RawMeasurement> {
+        let calibrated = self.measure(voltage).await?;
+        Ok(RawMeasurement {
+            calibrated,
+            voltage_counts: self
+                .manual_measure_differential_channel(VOLTAGE_ADC_CHANNEL)
+                .await?,
+            current_counts: self
+                .manual_measure_differential_channel(CURRENT_ADC_CHANNEL)
+                .await?,
+        })
+    }
+
+    /// Set the current limit DAC to this level.
+    ///
+    /// Fails with [Error::InvalidParameter], if the level exceeds 12 bit.
+    pub async fn set_current_limit_dac(&mut self, level: u16) -> Result<()> {
+        self.send_command(SetCurrentLimitDacRequest::try_new(level)?)
+            .await
+    }
+
+    /// Enable voltage calibration mode.
+    pub async fn enable_voltage_calibration_mode(&mut self) -> Result<()> {
+        self.send_command(EnableVoltageCalibrationModeRequest).await
+    }
+
+    /// Lock current range and temporarily clear current calibration data.
+    pub async fn lock_current_range_and_clear_calibration(
+        &mut self,
+        range: CurrentRange,
+    ) -> Result<()> {
+        self.send_command(LockCurrentRangeAndClearCalibrationRequest { range })
+            .await
+    }
+
+    /// Read the float stored in the requested EEPROM address.
+    pub async fn read_eeprom(&mut self, address: EepromAddress) -> Result<f32> {
+        let response = self.query(ReadEepromRequest { address }).await?;
+        Ok(response.value)
+    }
+
+    /// Reset the uSMU. This will cause the VCP to disconnect and will require reconnecting.
+    pub async fn reset(mut self) -> Result<()> {
+        self.send_command(ResetRequest).await
+    }
+
+    /// Read the uSMU identification
+    pub async fn get_identity(&mut self) -> Result<u32> {
+        let response = self.query(IdentityRequest).await?;
+        Ok(response.uid)
+    }
+
+    /// Write the voltage DAC calibration to EEPROM.
+    pub async fn write_voltage_dac_calibration(
+        &mut self,
+        slope: f32,
+        intercept: f32,
+    ) -> Result<()> {
+        self.send_command(WriteVoltageDacCalibrationRequest { slope, intercept })
+            .await
+    }
+
+    /// Write the voltage ADC calibration to EEPROM.
+    pub async fn write_voltage_adc_calibration(
+        &mut self,
+        slope: f32,
+        intercept: f32,
+    ) -> Result<()> {
+        self.send_command(WriteVoltageAdcCalibrationRequest { slope, intercept })
+            .await
+    }
+
+    /// Write current ADC calibration for the given current range to EEPROM.
+    pub async fn write_current_limit_calibration(
+        &mut self,
+        range: CurrentRange,
+        slope: f32,
+        intercept: f32,
+    ) -> Result<()> {
+        self.send_command(WriteCurrentLimitCalibrationRequest {
+            range,
+            slope,
+            intercept,
+        })
+        .await
+    }
+
+    /// Write the current limit DAC calibration to EEPROM.
+    pub async fn write_current_limit_dac(&mut self, slope: f32, intercept: f32) -> Result<()> {
+        self.send_command(WriteCurrentLimitDacCalibrationRequest { slope, intercept })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use tokio::io::{AsyncReadExt, DuplexStream};
+
+    use crate::{
+        milliampere,
+        simulator::{Resistor, SimulatedSmu},
+        volt,
+    };
+
+    use super::*;
+
+    /// Answer the requests received on `stream` by `device`.
+    async fn serve(mut stream: DuplexStream, mut device: SimulatedSmu) {
+        let mut buffer = [0; 256];
+        while let Ok(count @ 1..) = stream.read(&mut buffer).await {
+            device.write_all(&buffer[..count]).unwrap();
+            let mut response = Vec::new();
+            // the simulated device times out once all responses are read
+            let _ = device.read_to_end(&mut response);
+            stream.write_all(&response).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn measures_asynchronously() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (host, device) = tokio::io::duplex(1024);
+            let simulated = SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(42);
+            tokio::spawn(serve(device, simulated));
+            let mut smu = AsyncMicroSmu::new(host);
+
+            assert_eq!(smu.get_identity().await.unwrap(), 42);
+            smu.set_current_limit(Current::new::<milliampere>(20.0))
+                .await
+                .unwrap();
+            smu.enable().await.unwrap();
+            let measured = smu.measure(Voltage::new::<volt>(1.0)).await.unwrap();
+            assert!((measured.current.get::<milliampere>() - 1.0).abs() < 1e-3);
+            assert_eq!(
+                smu.read_eeprom(EepromAddress { value: 0 }).await.unwrap(),
+                1.0
+            );
+
+            // nothing answers on this stream
+            let (host, _device) = tokio::io::duplex(1024);
+            let mut smu = AsyncMicroSmu::new(host);
+            smu.set_timeout(Duration::from_millis(100));
+            let error = smu.get_identity().await.unwrap_err();
+            assert!(error.is_transient());
+            assert!(!error.is_disconnect());
+        });
+    }
+
+    #[test]
+    fn discards_late_responses() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (host, mut device) = tokio::io::duplex(1024);
+            let mut smu = AsyncMicroSmu::new(host);
+            smu.set_inter_command_delay(Duration::ZERO);
+            smu.set_timeout(Duration::from_millis(100));
+
+            let mut request = [0; 6];
+            device.write_all(b"uSMU ver").await.unwrap();
+            let error = smu.get_identity().await.unwrap_err();
+            assert!(error.is_transient());
+            device.read_exact(&mut request).await.unwrap();
+            // the late rest of the response, followed by the response to the next query
+            device.write_all(b"sion 1.0 ID:42\n").await.unwrap();
+            let next = tokio::spawn(async move {
+                device.read_exact(&mut request).await.unwrap();
+                assert_eq!(&request, b"*IDN?\n");
+                device.write_all(b"uSMU version 1.0 ID:43\n").await.unwrap();
+                device
+            });
+            assert_eq!(smu.get_identity().await.unwrap(), 43);
+            next.await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,278 @@
+//! Current ranging managed by the host.
+//!
+//! The uSMU selects its current range on its own, but `CH1:RANGE<n>` locks one of the ranges 1, the largest currents,
+//! to 4, the smallest currents, see [LockCurrentRangeAndClearCalibrationRequest](crate::commands::LockCurrentRangeAndClearCalibrationRequest).
+//! Locking clears the current calibration of the firmware, hence [AutoRanging] reads the calibration from the EEPROM
+//! first, corrects the currents of the locked range on the host, see [MicroSmu::lock_current_range],
+//! and writes the calibration back to the firmware when done, see [MicroSmu::restore_current_calibration].
+//!
+//! Each point is measured in the locked range. If the current is outside of it, the range of the current is locked,
+//! the measurement settles for [AutoRange::settling] and [AutoRange::discard] measurements are discarded,
+//! as the first measurements after a change glitch, before the point is measured again.
+//! Currents clipping at the full scale of the locked range switch to range 1, as they tell nothing about the actual current.
+//! Ranges are switched down only below [SWITCH_DOWN] of the full scale of the smaller range, so currents close to
+//! the boundary of two ranges do not toggle between them.
+//!
+//! [MicroSmu::auto_range] ranges once at the voltage set before and keeps the range locked for a scope, see [LockedRange].
+
+use std::{
+    ops::{Deref, DerefMut},
+    thread::sleep,
+    time::Duration,
+};
+
+use clap::Parser;
+
+use crate::{
+    Current, MicroSmu, Result, Time, Voltage, ampere,
+    calibration::{CURRENT_RANGES, Calibration, LinearCalibration},
+    commands::{CurrentRange, MeasureResponse},
+    second,
+};
+
+/// Nominal full scale current of the ranges 1 to 4 in ampere.
+pub const RANGE_FULL_SCALE: [f32; CURRENT_RANGES] = [40e-3, 400e-6, 4e-6, 40e-9];
+
+/// Currents beyond this fraction of the full scale of the locked range clip, see the [module documentation](self).
+pub const SWITCH_UP: f32 = 0.9;
+/// Currents below this fraction of the full scale of a smaller range switch to it.
+pub const SWITCH_DOWN: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Parser)]
+pub struct AutoRange {
+    /// Lock the current range of each point on the host, instead of the automatic ranging of the firmware.
+    #[arg(long)]
+    pub auto_range: bool,
+
+    /// Time to settle after a range change, before measuring.
+    #[arg(long = "range-settling", default_value = "10 ms")]
+    pub settling: Time,
+
+    /// Number of measurements discarded after a range change.
+    #[arg(long = "range-discard", default_value_t = 1)]
+    pub discard: usize,
+}
+
+impl Default for AutoRange {
+    /// The defaults of the command line, without ranging on the host.
+    fn default() -> Self {
+        Self::parse_from(["auto_range"])
+    }
+}
+
+impl AutoRange {
+    /// Read the calibration and start ranging on `smu`, `None` unless [Self::auto_range] is set.
+    pub fn start(&self, smu: &mut MicroSmu) -> Result<Option<AutoRanging>> {
+        if !self.auto_range {
+            return Ok(None);
+        }
+        Ok(Some(AutoRanging {
+            parameters: self.clone(),
+            calibration: Calibration::read(smu)?.current_adc,
+        }))
+    }
+}
+
+/// The range for `current` while `locked` is locked, see the [module documentation](self).
+pub fn select_range(current: Current, locked: Option<CurrentRange>) -> CurrentRange {
+    let current = current.get::<ampere>().abs();
+    let full_scale = |range: u8| RANGE_FULL_SCALE[usize::from(range) - 1];
+    let locked = locked.map_or(1, |e| e.value());
+    if current > full_scale(locked) * SWITCH_UP {
+        // a clipping measurement tells nothing about the actual current, hence to the largest range
+        return CurrentRange::new(1);
+    }
+    let range = (locked + 1..=CURRENT_RANGES as u8)
+        .take_while(|&e| current < full_scale(e) * SWITCH_DOWN)
+        .last()
+        .unwrap_or(locked);
+    CurrentRange::new(range)
+}
+
+/// Ranging of a session, see [AutoRange::start].
+#[derive(Debug, Clone)]
+pub struct AutoRanging {
+    parameters: AutoRange,
+    /// The current ADC calibration of all ranges, as read before locking any.
+    calibration: [LinearCalibration; CURRENT_RANGES],
+}
+
+impl AutoRanging {
+    /// Measure at `voltage` in the range of the current and return the range with the measurement.
+    pub fn measure(
+        &self,
+        smu: &mut MicroSmu,
+        voltage: Voltage,
+    ) -> Result<(MeasureResponse, CurrentRange)> {
+        let mut measured = smu.measure(voltage)?;
+        // each range is locked at most once per point
+        for _ in 0..CURRENT_RANGES {
+            let range = select_range(measured.current, smu.current_range());
+            if smu.current_range() == Some(range) {
+                return Ok((measured, range));
+            }
+            self.lock(smu, range)?;
+            for _ in 0..self.parameters.discard {
+                smu.measure(voltage)?;
+            }
+            measured = smu.measure(voltage)?;
+        }
+        let range = smu
+            .current_range()
+            .expect("a range is locked after the first iteration");
+        Ok((measured, range))
+    }
+
+    fn lock(&self, smu: &mut MicroSmu, range: CurrentRange) -> Result<()> {
+        let calibration = self.calibration[usize::from(range.value()) - 1];
+        smu.lock_current_range(range, calibration)?;
+        sleep(Duration::from_secs_f32(
+            self.parameters.settling.get::<second>().max(0.0),
+        ));
+        Ok(())
+    }
+
+    /// Restore the calibration of the firmware, see [MicroSmu::restore_current_calibration].
+    pub fn finish(&self, smu: &mut MicroSmu) -> Result<()> {
+        smu.restore_current_calibration(&self.calibration)
+    }
+}
+
+/// The current range selected by [MicroSmu::auto_range], locked until the guard is dropped.
+///
+/// Dropping restores the calibration of the firmware, see [AutoRanging::finish],
+/// the range itself stays locked until the device is reset.
+pub struct LockedRange<'a> {
+    smu: &'a mut MicroSmu,
+    ranging: AutoRanging,
+    range: CurrentRange,
+}
+
+impl<'a> LockedRange<'a> {
+    /// Measure at `voltage` and lock the most sensitive range in which the current does not clip.
+    pub(crate) fn select(smu: &'a mut MicroSmu, voltage: Voltage) -> Result<Self> {
+        let ranging = AutoRanging {
+            parameters: AutoRange {
+                auto_range: true,
+                ..Default::default()
+            },
+            calibration: Calibration::read(smu)?.current_adc,
+        };
+        let mut locked = Self {
+            smu,
+            ranging,
+            range: CurrentRange::new(1),
+        };
+        // on failure, dropping the guard restores the calibration of a range locked meanwhile
+        let (_, range) = locked.ranging.measure(locked.smu, voltage)?;
+        locked.range = range;
+        Ok(locked)
+    }
+
+    pub fn range(&self) -> CurrentRange {
+        self.range
+    }
+}
+
+impl Deref for LockedRange<'_> {
+    type Target = MicroSmu;
+
+    fn deref(&self) -> &Self::Target {
+        self.smu
+    }
+}
+
+impl DerefMut for LockedRange<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.smu
+    }
+}
+
+impl Drop for LockedRange<'_> {
+    fn drop(&mut self) {
+        // nothing to report failures to, e.g. if the connection is lost
+        let _ = self.ranging.finish(self.smu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        milliampere,
+        simulator::{Resistor, SimulatedSmu},
+        volt,
+    };
+
+    use super::*;
+
+    fn range(current: f32, locked: Option<u8>) -> u8 {
+        select_range(
+            Current::new::<ampere>(current),
+            locked.map(CurrentRange::new),
+        )
+        .value()
+    }
+
+    #[test]
+    fn selects_ranges_with_hysteresis() {
+        assert_eq!(range(10e-3, None), 1);
+        assert_eq!(range(-100e-6, None), 2);
+        assert_eq!(range(1e-9, None), 4);
+        // close to the boundary of range 2 and 3, the locked range is kept
+        assert_eq!(range(3e-6, Some(2)), 2);
+        assert_eq!(range(3e-6, Some(3)), 3);
+        assert_eq!(range(30e-9, Some(4)), 4);
+        // clipping switches to the largest range
+        assert_eq!(range(39e-9, Some(4)), 1);
+    }
+
+    #[test]
+    fn ranges_and_restores_the_calibration() {
+        let port = SimulatedSmu::new(Resistor::ohm(1000.0));
+        let mut smu = MicroSmu::new(Box::new(port));
+        smu.set_current_limit(Current::new::<milliampere>(20.0))
+            .unwrap();
+        smu.enable().unwrap();
+
+        let ranging = AutoRange {
+            auto_range: true,
+            settling: Time::new::<second>(0.0),
+            discard: 1,
+        }
+        .start(&mut smu)
+        .unwrap()
+        .unwrap();
+        let (measured, range) = ranging
+            .measure(&mut smu, Voltage::new::<volt>(0.001))
+            .unwrap();
+        assert_eq!(range.value(), 3);
+        assert!((measured.current.get::<ampere>() - 1e-6).abs() < 1e-7);
+        // the simulated device clips at the full scale of range 3
+        let (measured, range) = ranging
+            .measure(&mut smu, Voltage::new::<volt>(1.0))
+            .unwrap();
+        assert_eq!(range.value(), 1);
+        assert!((measured.current.get::<milliampere>() - 1.0).abs() < 0.01);
+
+        ranging.finish(&mut smu).unwrap();
+        assert_eq!(Calibration::read(&mut smu).unwrap(), Calibration::nominal());
+    }
+
+    #[test]
+    fn locks_the_range_for_a_scope() {
+        let port = SimulatedSmu::new(Resistor::ohm(1000.0));
+        let mut smu = MicroSmu::new(Box::new(port));
+        smu.set_current_limit(Current::new::<milliampere>(20.0))
+            .unwrap();
+        smu.set_voltage(Voltage::new::<volt>(0.001)).unwrap();
+        smu.enable().unwrap();
+
+        {
+            let mut locked = smu.auto_range().unwrap();
+            assert_eq!(locked.range().value(), 3);
+            let measured = locked.measure_here().unwrap();
+            assert!((measured.current.get::<ampere>() - 1e-6).abs() < 1e-7);
+        }
+        assert_eq!(Calibration::read(&mut smu).unwrap(), Calibration::nominal());
+    }
+}
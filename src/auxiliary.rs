@@ -0,0 +1,256 @@
+//! Auxiliary sensors, e.g. temperature or irradiance, sampled alongside each point of a sweep,
+//! to capture the environmental context with the IV data.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    ops::ControlFlow,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use crate::{
+    Current, Error, MicroSmu, Result, Voltage, ampere, record_iv_curve::IvCurveRecordingParameters,
+    schema, volt,
+};
+
+pub trait AuxiliarySensor {
+    /// Column name of the sensor, preferably including the unit, e.g. `temperature_celsius`.
+    fn name(&self) -> &str;
+
+    fn read(&mut self) -> Result<f64>;
+}
+
+/// A sensor read by calling a function.
+pub struct FnSensor<F> {
+    name: String,
+    read: F,
+}
+
+impl<F: FnMut() -> Result<f64>> FnSensor<F> {
+    pub fn new(name: impl Into<String>, read: F) -> Self {
+        Self {
+            name: name.into(),
+            read,
+        }
+    }
+}
+
+impl<F: FnMut() -> Result<f64>> AuxiliarySensor for FnSensor<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&mut self) -> Result<f64> {
+        (self.read)()
+    }
+}
+
+/// A sensor continuously reporting CSV lines, e.g. via a socket or a pipe.
+///
+/// The feed is read in the background and each read returns the most recent value of the configured column.
+/// Lines without a number in that column, like headers, are skipped.
+pub struct FeedSensor {
+    name: String,
+    latest: Arc<Mutex<Option<f64>>>,
+}
+
+impl FeedSensor {
+    pub fn new(name: impl Into<String>, feed: impl Read + Send + 'static, column: usize) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let update = latest.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(feed).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let value = line
+                    .split(',')
+                    .nth(column)
+                    .and_then(|e| e.trim().parse().ok());
+                if let Some(value) = value {
+                    *update.lock().unwrap_or_else(PoisonError::into_inner) = Some(value);
+                }
+            }
+        });
+        Self {
+            name: name.into(),
+            latest,
+        }
+    }
+
+    /// Read the feed from a TCP server at `address`.
+    pub fn connect(
+        name: impl Into<String>,
+        address: impl ToSocketAddrs,
+        column: usize,
+    ) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        Ok(Self::new(name, stream, column))
+    }
+}
+
+impl AuxiliarySensor for FeedSensor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn read(&mut self) -> Result<f64> {
+        let latest = *self.latest.lock().unwrap_or_else(PoisonError::into_inner);
+        latest.ok_or_else(|| {
+            Error::External(format!(
+                "No value received from sensor '{}' yet.",
+                self.name
+            ))
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct AuxiliarySensors {
+    sensors: Vec<Box<dyn AuxiliarySensor>>,
+}
+
+/// A sample of a sweep with the values of the auxiliary sensors, in the order they were added.
+#[derive(Debug, Clone)]
+pub struct AuxiliarySample {
+    pub voltage: Voltage,
+    pub current: Current,
+    pub values: Vec<f64>,
+}
+
+impl AuxiliarySensors {
+    pub fn add(&mut self, sensor: impl AuxiliarySensor + 'static) -> &mut Self {
+        self.sensors.push(Box::new(sensor));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.sensors.iter().map(|e| e.name()).collect()
+    }
+
+    pub fn read(&mut self) -> Result<Vec<f64>> {
+        self.sensors.iter_mut().map(|e| e.read()).collect()
+    }
+}
+
+impl IvCurveRecordingParameters {
+    /// Like [Self::record], but all `sensors` are read right after each measurement.
+    ///
+    /// A failing sensor ends the sweep with its error.
+    pub fn record_with_sensors(
+        &self,
+        smu: &mut MicroSmu,
+        sensors: &mut AuxiliarySensors,
+    ) -> Result<Vec<AuxiliarySample>> {
+        let mut samples = Vec::with_capacity(self.voltage_steps);
+        let mut error = None;
+        self.record_with(smu, |voltage, current| match sensors.read() {
+            Ok(values) => {
+                samples.push(AuxiliarySample {
+                    voltage,
+                    current,
+                    values,
+                });
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                error = Some(e);
+                ControlFlow::Break(())
+            }
+        })?;
+        match error {
+            Some(e) => Err(e),
+            None => Ok(samples),
+        }
+    }
+}
+
+/// Write the samples as CSV with the columns `voltage`, `current` and one column per sensor.
+pub fn write_csv(names: &[&str], samples: &[AuxiliarySample], output: impl Write) -> Result<()> {
+    let mut writer = schema::csv_writer(output)?;
+    writer.write_record(["voltage", "current"].iter().chain(names))?;
+    for sample in samples {
+        let record = [
+            sample.voltage.get::<volt>().to_string(),
+            sample.current.get::<ampere>().to_string(),
+        ]
+        .into_iter()
+        .chain(sample.values.iter().map(|e| e.to_string()));
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    fn parameters() -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_the_sensors_at_each_point() {
+        let mut readings = 0.0;
+        let mut sensors = AuxiliarySensors::default();
+        sensors
+            .add(FnSensor::new("reading", move || {
+                readings += 1.0;
+                Ok(readings)
+            }))
+            .add(FnSensor::new("temperature_celsius", || Ok(21.5)));
+
+        let samples = parameters()
+            .record_with_sensors(&mut simulated_smu(Resistor::ohm(1000.0)), &mut sensors)
+            .unwrap();
+        let values = samples.iter().map(|e| e.values.clone()).collect::<Vec<_>>();
+        assert_eq!(values, [[1.0, 21.5], [2.0, 21.5], [3.0, 21.5]]);
+
+        let mut csv = Vec::new();
+        write_csv(&sensors.names(), &samples, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut rows = csv.lines().filter(|e| !e.starts_with('#'));
+        assert_eq!(
+            rows.next(),
+            Some("voltage,current,reading,temperature_celsius")
+        );
+        assert_eq!(rows.count(), 3);
+    }
+
+    #[test]
+    fn ends_the_sweep_on_a_failing_sensor() {
+        let mut sensors = AuxiliarySensors::default();
+        sensors.add(FeedSensor::new("irradiance", std::io::empty(), 1));
+
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let result = parameters().record_with_sensors(&mut smu, &mut sensors);
+        assert!(matches!(result, Err(Error::External(_))));
+        assert!(!smu.is_enabled());
+    }
+
+    #[test]
+    fn keeps_the_latest_value_of_the_feed() {
+        let feed = Cursor::new("time,irradiance\n1,980.5\n2,n/a\n3,1002.0\n");
+        let mut sensor = FeedSensor::new("irradiance", feed, 1);
+
+        let latest = (0..100)
+            .map(|_| {
+                thread::sleep(Duration::from_millis(1));
+                sensor.read()
+            })
+            .find(|e| matches!(e, Ok(value) if *value == 1002.0));
+        assert!(latest.is_some());
+    }
+}
@@ -0,0 +1,389 @@
+//! Battery discharge tests, draining a small cell at constant current down to a cutoff voltage
+//! while integrating the delivered capacity and energy,
+//! and constant-current constant-voltage (CC-CV) charging of small cells and supercapacitors.
+//!
+//! The constant current is held by the software [Regulator], so the current is only
+//! corrected a few times per second and limited to the 40 mA of the uSMU.
+
+use std::{io::Write, ops::ControlFlow, thread::sleep, time::Instant};
+
+use serde::Serialize;
+use uom::si::{electric_charge::milliampere_hour, energy::milliwatt_hour};
+
+use crate::{
+    Charge, Current, Energy, Error, MicroSmu, Result, Time, Voltage, ampere,
+    charge::ChargeIntegrator,
+    milliampere,
+    regulation::{RegulationTarget, Regulator},
+    schema, second, timing, volt,
+};
+
+#[derive(Debug, Clone)]
+pub struct DischargeParameters {
+    /// Discharge current drawn from the cell, as positive value.
+    pub current: Current,
+
+    /// The discharge ends, once the cell voltage falls to this level.
+    pub cutoff_voltage: Voltage,
+
+    /// Initial setpoint, preferably the open circuit voltage of the cell, to avoid an initial current surge.
+    pub start_voltage: Voltage,
+
+    /// Time between samples.
+    pub interval: Time,
+
+    /// Abort the discharge after this time, if the cutoff voltage is not reached.
+    pub timeout: Option<Time>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DischargeSample {
+    /// Time since the start of the discharge.
+    pub time: Time,
+    pub voltage: Voltage,
+    /// Measured output current, negative while discharging.
+    pub current: Current,
+    /// Charge drawn from the cell since the start.
+    pub capacity: Charge,
+    /// Energy drawn from the cell since the start.
+    pub energy: Energy,
+}
+
+impl DischargeParameters {
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<DischargeSample>> {
+        self.record_with(smu, |_| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called for each sample and can end the discharge early.
+    ///
+    /// The output is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(&DischargeSample) -> ControlFlow<()>,
+    ) -> Result<Vec<DischargeSample>> {
+        let samples = self.discharge(smu, on_sample);
+        let disabled = smu.disable();
+        let samples = samples?;
+        disabled?;
+        Ok(samples)
+    }
+
+    fn discharge(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(&DischargeSample) -> ControlFlow<()>,
+    ) -> Result<Vec<DischargeSample>> {
+        let current = self.current.abs();
+        let limit = current_limit(current)?;
+        let interval = timing::duration("interval", self.interval)?;
+        let timeout = self
+            .timeout
+            .map(|e| timing::duration("timeout", e))
+            .transpose()?;
+
+        smu.set_voltage(self.start_voltage)?;
+        smu.set_current_limit(limit)?;
+        smu.enable()?;
+
+        let mut regulator = Regulator::new(RegulationTarget::Current(-current), self.start_voltage);
+
+        let start = Instant::now();
+        let mut samples = Vec::new();
+        let mut integrator = ChargeIntegrator::new();
+        loop {
+            let response = regulator.step(smu)?;
+            let elapsed = start.elapsed();
+            integrator.add(elapsed, response.voltage, response.current);
+
+            // the cell delivers the negative output current
+            let sample = DischargeSample {
+                time: Time::new::<second>(elapsed.as_secs_f32()),
+                voltage: response.voltage,
+                current: response.current,
+                capacity: -integrator.charge(),
+                energy: -integrator.energy(),
+            };
+            samples.push(sample);
+
+            if on_sample(&sample).is_break()
+                || sample.voltage <= self.cutoff_voltage
+                || timeout.is_some_and(|timeout| elapsed >= timeout)
+            {
+                break;
+            }
+            sleep(interval);
+        }
+
+        Ok(samples)
+    }
+}
+
+/// The current limit while regulating `current`, leaving headroom for the regulation,
+/// but bounding the surge at the start.
+fn current_limit(current: Current) -> Result<Current> {
+    if current.get::<milliampere>() > 40.0 {
+        Err(Error::Configuration(format!(
+            "Current of {} mA exceeds the 40 mA of the uSMU.",
+            current.get::<milliampere>()
+        )))?;
+    }
+    Ok((current * 1.5).min(Current::new::<milliampere>(40.0)))
+}
+
+#[derive(Debug, Clone)]
+pub struct ChargeParameters {
+    /// Charge current of the constant current phase, as positive value.
+    pub current: Current,
+
+    /// Voltage of the constant voltage phase, which is never exceeded.
+    pub voltage: Voltage,
+
+    /// The charge ends, once the current falls to this level in the constant voltage phase.
+    pub taper_current: Current,
+
+    /// Initial setpoint, preferably the open circuit voltage of the cell, to avoid an initial current surge.
+    pub start_voltage: Voltage,
+
+    /// Time between samples.
+    pub interval: Time,
+
+    /// Abort the charge after this time, if the taper current is not reached.
+    pub timeout: Option<Time>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargePhase {
+    ConstantCurrent,
+    ConstantVoltage,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChargeSample {
+    /// Time since the start of the charge.
+    pub time: Time,
+    pub phase: ChargePhase,
+    pub voltage: Voltage,
+    pub current: Current,
+    /// Charge delivered to the cell since the start.
+    pub charge: Charge,
+    /// Energy delivered to the cell since the start.
+    pub energy: Energy,
+}
+
+impl ChargeParameters {
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<ChargeSample>> {
+        self.record_with(smu, |_| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called for each sample and can end the charge early.
+    ///
+    /// The output is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(&ChargeSample) -> ControlFlow<()>,
+    ) -> Result<Vec<ChargeSample>> {
+        let samples = self.charge(smu, on_sample);
+        let disabled = smu.disable();
+        let samples = samples?;
+        disabled?;
+        Ok(samples)
+    }
+
+    fn charge(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(&ChargeSample) -> ControlFlow<()>,
+    ) -> Result<Vec<ChargeSample>> {
+        let current = self.current.abs();
+        let limit = current_limit(current)?;
+        let start_voltage = self.start_voltage.min(self.voltage);
+        let interval = timing::duration("interval", self.interval)?;
+        let timeout = self
+            .timeout
+            .map(|e| timing::duration("timeout", e))
+            .transpose()?;
+
+        smu.set_voltage(start_voltage)?;
+        smu.set_current_limit(limit)?;
+        smu.enable()?;
+
+        // the constant voltage phase is the constant current regulation clamped at the voltage
+        let mut regulator = Regulator::new(RegulationTarget::Current(current), start_voltage)
+            .with_bounds(Voltage::new::<volt>(-5.0), self.voltage);
+
+        let start = Instant::now();
+        let mut samples = Vec::new();
+        let mut integrator = ChargeIntegrator::new();
+        let mut phase = ChargePhase::ConstantCurrent;
+        loop {
+            let setpoint = regulator.setpoint();
+            let response = regulator.step(smu)?;
+            let elapsed = start.elapsed();
+            integrator.add(elapsed, response.voltage, response.current);
+            if setpoint >= self.voltage {
+                phase = ChargePhase::ConstantVoltage;
+            }
+
+            let sample = ChargeSample {
+                time: Time::new::<second>(elapsed.as_secs_f32()),
+                phase,
+                voltage: response.voltage,
+                current: response.current,
+                charge: integrator.charge(),
+                energy: integrator.energy(),
+            };
+            samples.push(sample);
+
+            if on_sample(&sample).is_break()
+                || (phase == ChargePhase::ConstantVoltage
+                    && sample.current <= self.taper_current.abs())
+                || timeout.is_some_and(|timeout| elapsed >= timeout)
+            {
+                break;
+            }
+            sleep(interval);
+        }
+
+        Ok(samples)
+    }
+}
+
+/// Write the charge samples as CSV with the columns `time` (seconds), `phase`, `voltage`, `current`,
+/// `charge` (mAh) and `energy` (mWh).
+pub fn write_charge_csv(samples: &[ChargeSample], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        time: f32,
+        phase: ChargePhase,
+        voltage: f32,
+        current: f32,
+        charge: f64,
+        energy: f64,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for sample in samples {
+        writer.serialize(Row {
+            time: sample.time.get::<second>(),
+            phase: sample.phase,
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+            charge: sample.charge.get::<milliampere_hour>(),
+            energy: sample.energy.get::<milliwatt_hour>(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write the discharge samples as CSV with the columns `time` (seconds), `voltage`, `current`,
+/// `capacity` (mAh) and `energy` (mWh).
+pub fn write_csv(samples: &[DischargeSample], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        time: f32,
+        voltage: f32,
+        current: f32,
+        capacity: f64,
+        energy: f64,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for sample in samples {
+        writer.serialize(Row {
+            time: sample.time.get::<second>(),
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+            capacity: sample.capacity.get::<milliampere_hour>(),
+            energy: sample.energy.get::<milliwatt_hour>(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{millisecond, simulator::DutModel, test_util::simulated_smu};
+
+    use super::*;
+
+    /// Cell with a fixed open circuit voltage behind an internal resistance.
+    struct Cell {
+        open_circuit_voltage: f32,
+        resistance: f32,
+    }
+
+    impl DutModel for Cell {
+        fn current(&self, voltage: Voltage) -> Current {
+            Current::new::<ampere>(
+                (voltage.get::<volt>() - self.open_circuit_voltage) / self.resistance,
+            )
+        }
+    }
+
+    fn smu() -> MicroSmu {
+        simulated_smu(Cell {
+            open_circuit_voltage: 3.6,
+            resistance: 100.0,
+        })
+    }
+
+    fn parameters() -> ChargeParameters {
+        ChargeParameters {
+            current: Current::new::<milliampere>(5.0),
+            voltage: Voltage::new::<volt>(4.0),
+            taper_current: Current::new::<milliampere>(4.5),
+            start_voltage: Voltage::new::<volt>(3.6),
+            interval: Time::new::<second>(0.0),
+            timeout: Some(Time::new::<second>(5.0)),
+        }
+    }
+
+    #[test]
+    fn switches_to_constant_voltage() {
+        // 5 mA would need 4.1 V, hence the charge is clamped at 4 V and tapers to 4 mA
+        let mut smu = smu();
+        let samples = parameters().record(&mut smu).unwrap();
+
+        assert_eq!(samples[0].phase, ChargePhase::ConstantCurrent);
+        let transition = samples
+            .iter()
+            .position(|e| e.phase == ChargePhase::ConstantVoltage)
+            .unwrap();
+        assert!(
+            samples[transition..]
+                .iter()
+                .all(|e| e.phase == ChargePhase::ConstantVoltage)
+        );
+        assert!(
+            samples
+                .iter()
+                .all(|e| e.voltage.get::<volt>() <= 4.0 + 1e-3)
+        );
+
+        let last = samples.last().unwrap();
+        assert!((last.voltage.get::<volt>() - 4.0).abs() < 1e-3);
+        assert!((last.current.get::<milliampere>() - 4.0).abs() < 0.05);
+        assert!(last.charge.get::<milliampere_hour>() > 0.0);
+        assert!(!smu.is_enabled());
+    }
+
+    #[test]
+    fn rejects_negative_intervals() {
+        let parameters = ChargeParameters {
+            interval: Time::new::<millisecond>(-1.0),
+            ..parameters()
+        };
+        let mut smu = smu();
+        let result = parameters.record(&mut smu);
+        assert!(matches!(result, Err(Error::Configuration(_))));
+        assert_eq!(smu.get_set_voltage(), None);
+    }
+}
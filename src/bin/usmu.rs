@@ -0,0 +1,14 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+use usmu::cli::CommandlineArguments;
+fn main() -> ExitCode {
+    let result = CommandlineArguments::parse().run();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
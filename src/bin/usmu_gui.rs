@@ -0,0 +1,5 @@
+use clap::Parser;
+use usmu::gui::GuiArguments;
+fn main() -> eframe::Result {
+    GuiArguments::parse().run()
+}
@@ -0,0 +1,210 @@
+//! Breakdown and threshold voltage search, ramping the voltage until the current crosses a threshold,
+//! e.g. for Zener/TVS breakdown or gate-threshold style measurements.
+//!
+//! The ramp never exceeds the abort voltage and the output returns to 0 V and is disabled
+//! immediately after the crossing, so the device under test is stressed as little as possible.
+
+use std::{ops::ControlFlow, thread::sleep};
+
+use crate::{
+    Current, Error, MicroSmu, Result, Time, Voltage, ampere,
+    commands::MeasureResponse,
+    sweep_result::{COMPLIANCE_THRESHOLD, SweepSample},
+    timestamp::{Epoch, TimestampSource},
+    timing, volt,
+};
+
+#[derive(Debug, Clone)]
+pub struct BreakdownSearchParameters {
+    pub start_voltage: Voltage,
+
+    /// The ramp runs from the start voltage towards, but never beyond, this voltage.
+    /// Below the start voltage, the ramp searches in reverse direction, e.g. for a Zener breakdown.
+    pub abort_voltage: Voltage,
+
+    /// Voltage increment per point, as positive value.
+    pub step: Voltage,
+
+    /// The search ends, once the magnitude of the current reaches this threshold.
+    pub threshold_current: Current,
+
+    pub current_limit: Current,
+
+    /// Time delay to wait before taking a measurement.
+    pub delay: Time,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakdownSearchResult {
+    /// Voltage at which the current crossed the threshold, interpolated between the last two points,
+    /// or `None` if the abort voltage was reached first.
+    pub crossing_voltage: Option<Voltage>,
+    pub samples: Vec<SweepSample>,
+}
+
+impl BreakdownSearchParameters {
+    pub fn search(&self, smu: &mut MicroSmu) -> Result<BreakdownSearchResult> {
+        self.search_with(smu, |_, _| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::search], but `on_sample` is called for each sample and can abort the ramp early.
+    ///
+    /// The output returns to 0 V and is disabled afterwards, also on failure.
+    pub fn search_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<BreakdownSearchResult> {
+        let result = self.ramp(smu, on_sample);
+        let ramped_down = smu
+            .set_voltage(Voltage::new::<volt>(0.0))
+            .and_then(|_| smu.disable());
+        let result = result?;
+        ramped_down?;
+        Ok(result)
+    }
+
+    fn ramp(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<BreakdownSearchResult> {
+        let start = self.start_voltage.get::<volt>();
+        let abort = self.abort_voltage.get::<volt>();
+        let step = self.step.get::<volt>();
+        if step.is_nan() || step <= 0.0 {
+            Err(Error::Configuration(format!(
+                "The step must be positive, got {step} V."
+            )))?;
+        }
+        let step = step.copysign(abort - start);
+        let threshold = self.threshold_current.abs().get::<ampere>();
+        let delay = timing::duration("delay", self.delay)?;
+
+        smu.set_voltage(self.start_voltage)?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.enable()?;
+
+        let epoch = Epoch::now();
+        let mut samples: Vec<SweepSample> = Vec::new();
+        for index in 0.. {
+            // recompute from the start to not accumulate rounding errors
+            let set_voltage = start + index as f32 * step;
+            if (set_voltage - start).abs() > (abort - start).abs() {
+                break;
+            }
+            let set_voltage = Voltage::new::<volt>(set_voltage);
+            smu.set_voltage(set_voltage)?;
+            sleep(delay);
+            let time = epoch.elapsed(TimestampSource::Monotonic);
+            let MeasureResponse { voltage, current } = smu.measure(set_voltage)?;
+            let previous = samples.last().map(SweepSample::point);
+            samples.push(SweepSample {
+                time,
+                set_voltage,
+                voltage,
+                current,
+                compliance: current.abs() >= self.current_limit * COMPLIANCE_THRESHOLD,
+                range: None,
+            });
+
+            let flow = on_sample(voltage, current);
+
+            if current.abs().get::<ampere>() >= threshold {
+                let crossing_voltage = match previous {
+                    Some((previous_voltage, previous_current)) => {
+                        let (v0, i0) = (previous_voltage, previous_current.abs());
+                        let (v1, i1) = (voltage, current.abs());
+                        let fraction = (threshold - i0.get::<ampere>()) / (i1 - i0).get::<ampere>();
+                        v0 + (v1 - v0) * fraction
+                    }
+                    None => voltage,
+                };
+                return Ok(BreakdownSearchResult {
+                    crossing_voltage: Some(crossing_voltage),
+                    samples,
+                });
+            }
+            if flow.is_break() {
+                break;
+            }
+        }
+
+        Ok(BreakdownSearchResult {
+            crossing_voltage: None,
+            samples,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        milliampere, millisecond, second,
+        simulator::{Diode, Resistor, SimulatedSmu},
+    };
+
+    use super::*;
+
+    fn smu(dut: SimulatedSmu) -> MicroSmu {
+        let mut smu = MicroSmu::new(Box::new(dut));
+        smu.set_inter_command_delay(std::time::Duration::ZERO);
+        smu
+    }
+
+    fn parameters(abort_voltage: f32) -> BreakdownSearchParameters {
+        BreakdownSearchParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            abort_voltage: Voltage::new::<volt>(abort_voltage),
+            step: Voltage::new::<volt>(0.5),
+            threshold_current: Current::new::<milliampere>(2.25),
+            current_limit: Current::new::<milliampere>(20.0),
+            delay: Time::new::<second>(0.0),
+        }
+    }
+
+    #[test]
+    fn interpolates_the_crossing() {
+        let mut smu = smu(SimulatedSmu::new(Resistor::ohm(1000.0)));
+        let result = parameters(5.0).search(&mut smu).unwrap();
+
+        // 1 kΩ crosses 2.25 mA between the points at 2 V and 2.5 V
+        let crossing = result.crossing_voltage.unwrap().get::<volt>();
+        assert!((crossing - 2.25).abs() < 0.01, "crossing at {crossing} V");
+        assert_eq!(result.samples.len(), 6);
+        assert_eq!(
+            result.samples.last().unwrap().set_voltage,
+            Voltage::new::<volt>(2.5)
+        );
+        assert!(result.samples.windows(2).all(|e| e[0].time <= e[1].time));
+        assert!(!smu.is_enabled());
+        assert_eq!(smu.get_set_voltage(), Some(Voltage::new::<volt>(0.0)));
+    }
+
+    #[test]
+    fn stops_at_the_abort_voltage() {
+        // a reverse biased diode only leaks its saturation current
+        let mut smu = smu(SimulatedSmu::new(Diode::default()));
+        let result = parameters(-2.0).search(&mut smu).unwrap();
+
+        assert_eq!(result.crossing_voltage, None);
+        assert_eq!(result.samples.len(), 5);
+        assert_eq!(
+            result.samples.last().unwrap().set_voltage,
+            Voltage::new::<volt>(-2.0)
+        );
+        assert!(!smu.is_enabled());
+    }
+
+    #[test]
+    fn rejects_negative_delays() {
+        let mut smu = smu(SimulatedSmu::new(Resistor::ohm(1000.0)));
+        let parameters = BreakdownSearchParameters {
+            delay: Time::new::<millisecond>(-1.0),
+            ..parameters(5.0)
+        };
+        let result = parameters.search(&mut smu);
+        assert!(matches!(result, Err(Error::Configuration(_))));
+        assert!(!smu.is_enabled());
+    }
+}
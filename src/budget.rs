@@ -0,0 +1,119 @@
+//! Time budgets of acquisitions, preventing runaway unattended runs.
+//!
+//! A run checks its [Budget] after each sample and ends once it is exhausted,
+//! like any other early end by [ControlFlow::Break], i.e. the output is disabled and the samples so far are kept:
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use usmu::{MicroSmu, budget::Budget, record_iv_curve::IvCurveRecordingParameters, simulator::{Resistor, SimulatedSmu}};
+//! # let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+//! let budget = Budget::start(Duration::from_secs(60));
+//! let result = IvCurveRecordingParameters::default()
+//!     .record_with(&mut smu, |_, _| budget.check())
+//!     .unwrap();
+//! ```
+
+use std::{
+    ops::ControlFlow,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+
+use crate::{Time, second, timing::format_duration};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    start: Instant,
+    limit: Duration,
+}
+
+impl Budget {
+    /// Start a budget of `limit` from now.
+    pub fn start(limit: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> Duration {
+        self.limit
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.start.elapsed() >= self.limit
+    }
+
+    /// [ControlFlow::Break] once the budget is exhausted, to be returned from the sample callbacks.
+    pub fn check(&self) -> ControlFlow<()> {
+        if self.exhausted() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Parser)]
+pub struct BudgetParameter {
+    /// Abort the run after this time, e.g. "2 h". The output is disabled and the samples so far are saved.
+    #[arg(long)]
+    pub max_duration: Option<Time>,
+}
+
+impl BudgetParameter {
+    /// Start the budget, if a maximum duration is given.
+    pub fn start(&self) -> Option<Budget> {
+        self.max_duration.map(|e| {
+            Budget::start(Duration::try_from_secs_f32(e.get::<second>()).unwrap_or_default())
+        })
+    }
+}
+
+/// Check the optional `budget`, reporting when it is exhausted.
+pub(crate) fn check(budget: Option<&Budget>) -> ControlFlow<()> {
+    let Some(budget) = budget else {
+        return ControlFlow::Continue(());
+    };
+    let checked = budget.check();
+    if checked.is_break() {
+        eprintln!(
+            "The maximum duration of {} is exceeded, aborting the run.",
+            format_duration(budget.limit())
+        );
+    }
+    checked
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MicroSmu,
+        record_iv_curve::IvCurveRecordingParameters,
+        simulator::{Resistor, SimulatedSmu},
+        sweep_result::SweepStatus,
+    };
+
+    use super::*;
+
+    #[test]
+    fn aborts_exhausted_runs() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let parameters = IvCurveRecordingParameters::default();
+
+        let budget = Budget::start(Duration::from_secs(60));
+        let result = parameters
+            .record_with(&mut smu, |_, _| budget.check())
+            .unwrap();
+        assert_eq!(result.status, SweepStatus::Completed);
+        assert_eq!(result.samples.len(), parameters.voltage_steps);
+
+        let budget = Budget::start(Duration::ZERO);
+        let result = parameters
+            .record_with(&mut smu, |_, _| budget.check())
+            .unwrap();
+        assert_eq!(result.status, SweepStatus::Aborted);
+        assert_eq!(result.samples.len(), 1);
+    }
+}
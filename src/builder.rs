@@ -0,0 +1,158 @@
+//! [MicroSmuBuilder], opening the serial port of the uSMU with custom communication parameters.
+//!
+//! The defaults match the reference firmware, see [MicroSmu::open].
+//! The device does not answer while measuring, hence [MicroSmu] extends the [MicroSmuBuilder::timeout]
+//! by [TIMEOUT_PER_SAMPLE] for each sample of the over-sampling set.
+//! Faster firmware builds may tolerate a shorter [MicroSmuBuilder::inter_command_delay].
+//!
+//! ```no_run
+//! # fn example() -> usmu::Result<()> {
+//! use std::time::Duration;
+//!
+//! use usmu::{MicroSmu, find_serial_ports};
+//!
+//! let port = find_serial_ports()?.into_iter().next().unwrap();
+//! let smu = MicroSmu::builder(port)
+//!     .timeout(Duration::from_secs(10))
+//!     .inter_command_delay(Duration::from_millis(10))
+//!     .open()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use serialport::{SerialPort, SerialPortInfo};
+
+use crate::{MicroSmu, Result};
+
+pub const DEFAULT_BAUDRATE: u32 = 9600;
+/// We need a gracious timeout because the device will not answer
+/// while performing the measurement and stalls the connection.
+/// The value is based on the python reference implementation.
+/// For high over sampling values, it is extended by [TIMEOUT_PER_SAMPLE], see [MicroSmu::set_timeout].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Extension of the timeout per over-sampled ADC sample of a measurement,
+/// twice the [TimingModel::sample_time](crate::timing::TimingModel::sample_time) for some margin.
+pub const TIMEOUT_PER_SAMPLE: Duration = Duration::from_millis(2);
+/// The device needs a small pause after transmission, otherwise we run into IOError timeouts.
+/// The value is based on the python reference implementation, but smaller delays may be acceptable.
+pub const DEFAULT_INTER_COMMAND_DELAY: Duration = Duration::from_millis(50);
+
+/// See the [module documentation](self), created by [MicroSmu::builder].
+#[derive(Debug, Clone)]
+pub struct MicroSmuBuilder {
+    port: SerialPortInfo,
+    baudrate: u32,
+    timeout: Duration,
+    inter_command_delay: Duration,
+}
+
+impl MicroSmuBuilder {
+    pub fn new(port: SerialPortInfo) -> Self {
+        Self {
+            port,
+            baudrate: DEFAULT_BAUDRATE,
+            timeout: DEFAULT_TIMEOUT,
+            inter_command_delay: DEFAULT_INTER_COMMAND_DELAY,
+        }
+    }
+
+    /// [DEFAULT_BAUDRATE] by default.
+    pub fn baudrate(self, baudrate: u32) -> Self {
+        Self { baudrate, ..self }
+    }
+
+    /// Time to wait for a response, [DEFAULT_TIMEOUT] by default.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Pause after each command, [DEFAULT_INTER_COMMAND_DELAY] by default.
+    pub fn inter_command_delay(self, inter_command_delay: Duration) -> Self {
+        Self {
+            inter_command_delay,
+            ..self
+        }
+    }
+
+    /// Open the serial port, e.g. to wrap it before passing it to [MicroSmu::new].
+    /// The inter-command delay is not a property of the port, see [MicroSmu::set_inter_command_delay].
+    pub fn open_port(&self) -> Result<Box<dyn SerialPort>> {
+        let port = serialport::new(&self.port.port_name, self.baudrate)
+            .timeout(self.timeout)
+            .open()?;
+        Ok(port)
+    }
+
+    pub fn open(&self) -> Result<MicroSmu> {
+        let mut smu = MicroSmu::new(self.open_port()?);
+        smu.set_inter_command_delay(self.inter_command_delay);
+        Ok(smu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{Error, test_util::FakeSerialPort};
+
+    use super::*;
+
+    #[test]
+    fn configures_the_communication() {
+        let port = SerialPortInfo {
+            port_name: "/dev/usmu-does-not-exist".to_string(),
+            port_type: serialport::SerialPortType::Unknown,
+        };
+        let builder = MicroSmu::builder(port).baudrate(115200);
+        assert!(matches!(builder.open(), Err(Error::Serialport(_))));
+
+        let port = FakeSerialPort::new();
+        for _ in 0..10 {
+            port.expect("CH1:ENA");
+        }
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        assert_eq!(smu.inter_command_delay(), DEFAULT_INTER_COMMAND_DELAY);
+        smu.set_inter_command_delay(Duration::ZERO);
+        let start = Instant::now();
+        for _ in 0..10 {
+            smu.enable().unwrap();
+        }
+        assert!(start.elapsed() < DEFAULT_INTER_COMMAND_DELAY * 10);
+        port.verify();
+    }
+
+    #[test]
+    fn extends_the_timeout_by_the_over_sampling() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:OSR 1000");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+        smu.set_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(port.timeout(), Duration::from_secs(1));
+
+        smu.set_over_sample_rate(1000).unwrap();
+        assert_eq!(smu.timeout(), Duration::from_secs(1));
+        assert_eq!(
+            port.timeout(),
+            Duration::from_secs(1) + TIMEOUT_PER_SAMPLE * 1000
+        );
+        port.verify();
+    }
+
+    #[test]
+    fn keeps_an_unlimited_timeout() {
+        let mut port = FakeSerialPort::new();
+        port.set_timeout(Duration::MAX).unwrap();
+        port.expect("CH1:OSR 1000");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+
+        smu.set_over_sample_rate(1000).unwrap();
+        assert_eq!(smu.timeout(), Duration::MAX);
+        assert_eq!(port.timeout(), Duration::MAX);
+        port.verify();
+    }
+}
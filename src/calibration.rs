@@ -0,0 +1,438 @@
+//! The calibration coefficients stored in the EEPROM of the uSMU.
+//!
+//! Each conversion is calibrated linearly with a slope and an intercept,
+//! which the calibration commands write to consecutive EEPROM addresses, see [Calibration::address].
+//! [Calibration::read] reads all of them and [Calibration::write] writes them back with the calibration commands,
+//! as the generic EEPROM write is unreliable, see [WriteEepromRequest](crate::commands::WriteEepromRequest).
+//! [Coefficient] addresses a single coefficient by its name, e.g. `voltage_adc_slope`.
+
+#[cfg(any(feature = "config", feature = "json"))]
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(any(feature = "config", feature = "json"))]
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[cfg(any(feature = "config", feature = "json"))]
+use crate::Error;
+use crate::{
+    MicroSmu, Result,
+    commands::{CurrentRange, EepromAddress},
+};
+
+/// Number of EEPROM addresses holding calibration coefficients.
+pub const EEPROM_SIZE: u8 = 14;
+
+/// Number of current ranges, each with its own current ADC calibration.
+pub const CURRENT_RANGES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LinearCalibration {
+    pub slope: f32,
+    pub intercept: f32,
+}
+
+impl LinearCalibration {
+    /// No correction of the nominal conversion.
+    pub const NOMINAL: Self = Self {
+        slope: 1.0,
+        intercept: 0.0,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub voltage_dac: LinearCalibration,
+    pub voltage_adc: LinearCalibration,
+    /// Current ADC per current range, starting at range 1.
+    pub current_adc: [LinearCalibration; CURRENT_RANGES],
+    pub current_limit_dac: LinearCalibration,
+}
+
+/// A calibrated conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coefficients {
+    VoltageDac,
+    VoltageAdc,
+    /// The current ADC in the range 1 to 4.
+    CurrentAdc(u8),
+    CurrentLimitDac,
+}
+
+impl Calibration {
+    /// The nominal calibration, i.e. [LinearCalibration::NOMINAL] for all conversions.
+    /// The device then measures without correction and needs to be calibrated for accurate results.
+    pub fn nominal() -> Self {
+        let nominal = LinearCalibration::NOMINAL;
+        Self {
+            voltage_dac: nominal,
+            voltage_adc: nominal,
+            current_adc: [nominal; CURRENT_RANGES],
+            current_limit_dac: nominal,
+        }
+    }
+
+    /// Write the [Self::nominal] calibration, e.g. to recover a device whose EEPROM was corrupted.
+    /// The previous calibration is lost, consider saving it with [Self::read] first.
+    pub fn restore_defaults(smu: &mut MicroSmu) -> Result<()> {
+        Self::nominal().write(smu)
+    }
+
+    /// EEPROM address of the slope of `coefficients`, the intercept follows at the next address.
+    pub fn address(coefficients: Coefficients) -> EepromAddress {
+        let value = match coefficients {
+            Coefficients::VoltageDac => 0,
+            Coefficients::VoltageAdc => 2,
+            Coefficients::CurrentAdc(range) => 2 + 2 * range,
+            Coefficients::CurrentLimitDac => 12,
+        };
+        EepromAddress { value }
+    }
+
+    /// All conversions, in the order of their EEPROM addresses.
+    pub fn coefficients() -> impl Iterator<Item = Coefficients> {
+        [Coefficients::VoltageDac, Coefficients::VoltageAdc]
+            .into_iter()
+            .chain((1..=CURRENT_RANGES as u8).map(Coefficients::CurrentAdc))
+            .chain([Coefficients::CurrentLimitDac])
+    }
+
+    pub fn get(&self, coefficients: Coefficients) -> LinearCalibration {
+        match coefficients {
+            Coefficients::VoltageDac => self.voltage_dac,
+            Coefficients::VoltageAdc => self.voltage_adc,
+            Coefficients::CurrentAdc(range) => self.current_adc[usize::from(range) - 1],
+            Coefficients::CurrentLimitDac => self.current_limit_dac,
+        }
+    }
+
+    pub fn get_mut(&mut self, coefficients: Coefficients) -> &mut LinearCalibration {
+        match coefficients {
+            Coefficients::VoltageDac => &mut self.voltage_dac,
+            Coefficients::VoltageAdc => &mut self.voltage_adc,
+            Coefficients::CurrentAdc(range) => &mut self.current_adc[usize::from(range) - 1],
+            Coefficients::CurrentLimitDac => &mut self.current_limit_dac,
+        }
+    }
+
+    /// Read all coefficients from the EEPROM.
+    pub fn read(smu: &mut MicroSmu) -> Result<Self> {
+        let zero = LinearCalibration {
+            slope: 0.0,
+            intercept: 0.0,
+        };
+        let mut calibration = Calibration {
+            voltage_dac: zero,
+            voltage_adc: zero,
+            current_adc: [zero; CURRENT_RANGES],
+            current_limit_dac: zero,
+        };
+        for coefficients in Self::coefficients() {
+            let address = Self::address(coefficients).value;
+            *calibration.get_mut(coefficients) = LinearCalibration {
+                slope: smu.read_eeprom(EepromAddress { value: address })?,
+                intercept: smu.read_eeprom(EepromAddress { value: address + 1 })?,
+            };
+        }
+        Ok(calibration)
+    }
+
+    /// Write all coefficients to the EEPROM with the calibration commands.
+    pub fn write(&self, smu: &mut MicroSmu) -> Result<()> {
+        for coefficients in Self::coefficients() {
+            Self::write_coefficients(smu, coefficients, self.get(coefficients))?;
+        }
+        Ok(())
+    }
+
+    /// Write the slope and intercept of `coefficients` with its calibration command.
+    pub fn write_coefficients(
+        smu: &mut MicroSmu,
+        coefficients: Coefficients,
+        calibration: LinearCalibration,
+    ) -> Result<()> {
+        let LinearCalibration { slope, intercept } = calibration;
+        match coefficients {
+            Coefficients::VoltageDac => smu.write_voltage_dac_calibration(slope, intercept),
+            Coefficients::VoltageAdc => smu.write_voltage_adc_calibration(slope, intercept),
+            Coefficients::CurrentAdc(range) => {
+                smu.write_current_limit_calibration(CurrentRange::new(range), slope, intercept)
+            }
+            Coefficients::CurrentLimitDac => smu.write_current_limit_dac(slope, intercept),
+        }
+    }
+
+    /// The coefficients changed from `self` to `other`.
+    pub fn diff(&self, other: &Calibration) -> Vec<Difference> {
+        Self::coefficients()
+            .flat_map(|coefficients| {
+                let (before, after) = (self.get(coefficients), other.get(coefficients));
+                [
+                    ("slope", before.slope, after.slope),
+                    ("intercept", before.intercept, after.intercept),
+                ]
+                .map(|(coefficient, before, after)| Difference {
+                    coefficients,
+                    coefficient,
+                    before,
+                    after,
+                })
+            })
+            .filter(|e| e.before.to_bits() != e.after.to_bits())
+            .collect()
+    }
+}
+
+/// A coefficient differing between two calibrations, see [Calibration::diff].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Difference {
+    pub coefficients: Coefficients,
+    /// `slope` or `intercept`.
+    pub coefficient: &'static str,
+    pub before: f32,
+    pub after: f32,
+}
+
+impl Difference {
+    /// Relative change in percent, `None` if the coefficient was 0 before.
+    pub fn percentage(&self) -> Option<f32> {
+        (self.before != 0.0).then(|| (self.after - self.before) / self.before.abs() * 100.0)
+    }
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {} -> {}",
+            self.coefficients, self.coefficient, self.before, self.after
+        )?;
+        match self.percentage() {
+            Some(percentage) => write!(f, " ({percentage:+.3} %)"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Coefficients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Coefficients::VoltageDac => write!(f, "voltage DAC"),
+            Coefficients::VoltageAdc => write!(f, "voltage ADC"),
+            Coefficients::CurrentAdc(range) => write!(f, "current ADC range {range}"),
+            Coefficients::CurrentLimitDac => write!(f, "current limit DAC"),
+        }
+    }
+}
+
+/// A single coefficient at its own EEPROM address, named after the conversion and the coefficient,
+/// e.g. `voltage_adc_slope`, `current_adc_range2_intercept` or `current_limit_dac_slope`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coefficient {
+    pub coefficients: Coefficients,
+    /// The intercept, otherwise the slope.
+    pub intercept: bool,
+}
+
+impl Coefficient {
+    /// All coefficients, in the order of their EEPROM addresses.
+    pub fn all() -> impl Iterator<Item = Self> {
+        Calibration::coefficients().flat_map(|coefficients| {
+            [false, true].map(|intercept| Self {
+                coefficients,
+                intercept,
+            })
+        })
+    }
+
+    pub fn address(&self) -> EepromAddress {
+        let slope = Calibration::address(self.coefficients).value;
+        EepromAddress {
+            value: slope + u8::from(self.intercept),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        let conversion = match self.coefficients {
+            Coefficients::VoltageDac => "voltage_dac".to_string(),
+            Coefficients::VoltageAdc => "voltage_adc".to_string(),
+            Coefficients::CurrentAdc(range) => format!("current_adc_range{range}"),
+            Coefficients::CurrentLimitDac => "current_limit_dac".to_string(),
+        };
+        let coefficient = if self.intercept { "intercept" } else { "slope" };
+        format!("{conversion}_{coefficient}")
+    }
+
+    pub fn read(&self, smu: &mut MicroSmu) -> Result<f32> {
+        smu.read_eeprom(self.address())
+    }
+
+    /// Write `value` with the calibration command of the conversion, which also writes the other coefficient of the
+    /// conversion, so it is read first and written unchanged.
+    pub fn write(&self, smu: &mut MicroSmu, value: f32) -> Result<()> {
+        let slope = Calibration::address(self.coefficients);
+        let mut calibration = LinearCalibration {
+            slope: smu.read_eeprom(slope)?,
+            intercept: smu.read_eeprom(EepromAddress {
+                value: slope.value + 1,
+            })?,
+        };
+        match self.intercept {
+            true => calibration.intercept = value,
+            false => calibration.slope = value,
+        }
+        Calibration::write_coefficients(smu, self.coefficients, calibration)
+    }
+}
+
+impl std::fmt::Display for Coefficient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Coefficient {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::all().find(|e| e.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Self::all().map(|e| e.name()).collect();
+            format!(
+                "unknown coefficient '{s}', expected one of {}",
+                names.join(", ")
+            )
+        })
+    }
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+enum Format {
+    #[cfg(feature = "config")]
+    Toml,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// Fail for files other than `.toml` with the `config` feature or `.json` with the `json` feature.
+#[cfg(all(feature = "cli", any(feature = "config", feature = "json")))]
+pub(crate) fn check_file(path: &Path) -> Result<()> {
+    format(path).map(|_| ())
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+fn format(path: &Path) -> Result<Format> {
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "config")]
+        Some("toml") => Ok(Format::Toml),
+        #[cfg(feature = "json")]
+        Some("json") => Ok(Format::Json),
+        _ => Err(Error::Configuration(format!(
+            "Unsupported file {}, expected a .toml file with the `config` feature or a .json file with the `json` feature.",
+            path.display()
+        )))?,
+    }
+}
+
+/// Load the TOML or JSON file `path`, the `kind` of the file is reported on errors.
+#[cfg(any(feature = "config", feature = "json"))]
+pub(crate) fn load_file<T: DeserializeOwned>(path: &Path, kind: &str) -> Result<T> {
+    let format = format(path)?;
+    let content = std::fs::read_to_string(path)?;
+    let value = match format {
+        #[cfg(feature = "config")]
+        Format::Toml => toml::from_str(&content).map_err(|e| e.to_string()),
+        #[cfg(feature = "json")]
+        Format::Json => serde_json::from_str(&content).map_err(|e| e.to_string()),
+    };
+    value.map_err(|e| Error::Data(format!("Invalid {kind} {}: {e}", path.display())))
+}
+
+/// Save `value` as TOML or JSON, chosen by the extension of `path`.
+#[cfg(all(feature = "cli", any(feature = "config", feature = "json")))]
+pub(crate) fn save_file(value: &impl Serialize, path: &Path) -> Result<()> {
+    let content = match format(path)? {
+        #[cfg(feature = "config")]
+        Format::Toml => toml::to_string_pretty(value).map_err(|e| Error::Data(e.to_string()))?,
+        #[cfg(feature = "json")]
+        Format::Json => {
+            serde_json::to_string_pretty(value).map_err(|e| Error::Data(e.to_string()))?
+        }
+    };
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::FakeSerialPort;
+
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_all_coefficients() {
+        let port = FakeSerialPort::new();
+        for address in 0..EEPROM_SIZE {
+            port.expect_query(&format!("*READ {address}"), &format!("{address}.5"));
+        }
+        port.expect("CAL:DAC 0.5 1.5")
+            .expect("CAL:VOL 2.5 3.5")
+            .expect("CAL:CUR:RANGE 1 4.5 5.5")
+            .expect("CAL:CUR:RANGE 2 6.5 7.5")
+            .expect("CAL:CUR:RANGE 3 8.5 9.5")
+            .expect("CAL:CUR:RANGE 4 10.5 11.5")
+            .expect("CAL:ILIM 12.5 13.5");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+
+        let calibration = Calibration::read(&mut smu).unwrap();
+        assert_eq!(
+            calibration.get(Coefficients::CurrentAdc(2)),
+            LinearCalibration {
+                slope: 6.5,
+                intercept: 7.5
+            }
+        );
+        calibration.write(&mut smu).unwrap();
+        port.verify();
+    }
+
+    #[test]
+    fn diffs_coefficients() {
+        let before = Calibration::nominal();
+        let mut after = before;
+        after.current_adc[1].slope = 1.02;
+        after.current_limit_dac.intercept = 0.1;
+
+        let differences = before.diff(&after);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(
+            differences[0].to_string(),
+            "current ADC range 2 slope: 1 -> 1.02 (+2.000 %)"
+        );
+        assert_eq!(
+            differences[1].to_string(),
+            "current limit DAC intercept: 0 -> 0.1"
+        );
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn writes_single_coefficients() {
+        let addresses: Vec<_> = Coefficient::all().map(|e| e.address().value).collect();
+        assert_eq!(addresses, (0..EEPROM_SIZE).collect::<Vec<_>>());
+        for coefficient in Coefficient::all() {
+            assert_eq!(coefficient.name().parse(), Ok(coefficient));
+        }
+        assert!("voltage_adc".parse::<Coefficient>().is_err());
+
+        let coefficient: Coefficient = "current_adc_range3_intercept".parse().unwrap();
+        assert_eq!(coefficient.address().value, 9);
+        let port = FakeSerialPort::new();
+        port.expect_query("*READ 8", "1.5")
+            .expect_query("*READ 9", "-0.5")
+            .expect("CAL:CUR:RANGE 3 1.5 0.25");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        coefficient.write(&mut smu, 0.25).unwrap();
+        port.verify();
+    }
+}
@@ -0,0 +1,179 @@
+//! Host-side record of when the uSMUs were calibrated, keyed by their UID.
+//!
+//! The uSMU does not store the date of its calibration, hence a [MicroSmu] with a [CalibrationLog],
+//! see [MicroSmu::set_calibration_log], appends the UID and the time to the log
+//! whenever it writes calibration coefficients.
+//! The log is a CSV file with the columns `uid` and `calibrated_at` in seconds since the UNIX epoch,
+//! by default `usmu/calibrations.csv` in the user's data directory.
+//!
+//! The command line tools warn when measuring with a calibration older than `--max-calibration-age`.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use clap::Parser;
+use serde::Deserialize;
+
+use crate::{Error, MicroSmu, Result, Time, second};
+
+const HEADER: &str = "uid,calibrated_at";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalibrationLog {
+    path: PathBuf,
+}
+
+impl CalibrationLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `usmu/calibrations.csv` in `$XDG_DATA_HOME`, `~/.local/share` or `%APPDATA%`.
+    pub fn default_path() -> Option<PathBuf> {
+        let env = |name| std::env::var_os(name).filter(|e| !e.is_empty());
+        let data = env("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|e| PathBuf::from(e).join(".local").join("share")))
+            .or_else(|| env("APPDATA").map(PathBuf::from))?;
+        Some(data.join("usmu").join("calibrations.csv"))
+    }
+
+    /// Record that uSMU `uid` was calibrated at `time`, the log and its directory are created if missing.
+    pub fn record(&self, uid: u32, time: SystemTime) -> Result<()> {
+        if let Some(directory) = self.path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let header = if file.metadata()?.len() == 0 {
+            format!("{HEADER}\n")
+        } else {
+            String::new()
+        };
+        let timestamp = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        file.write_all(format!("{header}{uid},{timestamp}\n").as_bytes())?;
+        Ok(())
+    }
+
+    /// The time uSMU `uid` was last calibrated, `None` if it is not in the log or the log does not exist.
+    pub fn last_calibration(&self, uid: u32) -> Result<Option<SystemTime>> {
+        #[derive(Deserialize)]
+        struct Row {
+            uid: u32,
+            calibrated_at: f64,
+        }
+
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let mut last = None;
+        for row in csv::Reader::from_path(&self.path)?.deserialize() {
+            let row: Row = row.map_err(|e| Error::Data(format!("Invalid calibration log: {e}")))?;
+            if row.uid == uid
+                && let Ok(since_epoch) = Duration::try_from_secs_f64(row.calibrated_at)
+            {
+                last = last.max(Some(SystemTime::UNIX_EPOCH + since_epoch));
+            }
+        }
+        Ok(last)
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct CalibrationAgeParameter {
+    /// Warn when measuring with a calibration older than this, e.g. "180 d".
+    #[arg(long, default_value = "365 d")]
+    pub max_calibration_age: Time,
+
+    /// The log of the calibration dates, by default `usmu/calibrations.csv` in the user's data directory.
+    #[arg(long)]
+    pub calibration_log: Option<PathBuf>,
+}
+
+impl Default for CalibrationAgeParameter {
+    /// The defaults of the command line.
+    fn default() -> Self {
+        Self::parse_from(["usmu"])
+    }
+}
+
+impl CalibrationAgeParameter {
+    pub fn log(&self) -> Option<CalibrationLog> {
+        self.calibration_log
+            .clone()
+            .or_else(CalibrationLog::default_path)
+            .map(CalibrationLog::new)
+    }
+
+    /// Record the calibrations written by `smu` and warn, if its calibration is older than the maximum age.
+    /// The device is only identified if the log exists, i.e. after a calibration was recorded.
+    pub fn configure(&self, smu: &mut MicroSmu) -> Result<()> {
+        let Some(log) = self.log() else {
+            return Ok(());
+        };
+        smu.set_calibration_log(Some(log.clone()));
+        if !log.path.exists() {
+            return Ok(());
+        }
+        let uid = smu.get_identity()?;
+        let Some(age) = log
+            .last_calibration(uid)?
+            .and_then(|e| SystemTime::now().duration_since(e).ok())
+        else {
+            return Ok(());
+        };
+        let max_age = Duration::try_from_secs_f32(self.max_calibration_age.get::<second>())
+            .unwrap_or_default();
+        if age > max_age {
+            eprintln!(
+                "Warning: uSMU {uid} was calibrated {} days ago, the measurements may be inaccurate. \
+                 Consider calibrating it again.",
+                age.as_secs() / 86400
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::FakeSerialPort;
+
+    use super::*;
+
+    #[test]
+    fn records_calibrations() {
+        let path = std::env::temp_dir()
+            .join(format!("usmu-calibration-log-{}", std::process::id()))
+            .join("calibrations.csv");
+        let log = CalibrationLog::new(&path);
+        let day = Duration::from_secs(86400);
+        log.record(7, SystemTime::UNIX_EPOCH + day).unwrap();
+        assert_eq!(log.last_calibration(42).unwrap(), None);
+
+        let port = FakeSerialPort::new();
+        port.expect("CAL:VOL 1 0")
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_calibration_log(Some(log.clone()));
+        smu.write_voltage_adc_calibration(1.0, 0.0).unwrap();
+        port.verify();
+
+        let calibrated = log.last_calibration(42).unwrap().unwrap();
+        assert!(calibrated.elapsed().unwrap() < day);
+        assert_eq!(
+            log.last_calibration(7).unwrap(),
+            Some(SystemTime::UNIX_EPOCH + day)
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
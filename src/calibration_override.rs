@@ -0,0 +1,224 @@
+//! Host-side corrections of the measured values, leaving the calibration in the EEPROM untouched.
+//!
+//! A [CalibrationOverride], see [MicroSmu::set_calibration_override](crate::MicroSmu::set_calibration_override),
+//! corrects the values calibrated by the device linearly, `slope × value + intercept` in volt and ampere,
+//! e.g. to try a recalibration before writing it or to apply per-experiment corrections.
+//! The current is corrected per range of its magnitude, the first range containing it applies:
+//!
+//! ```toml
+//! [voltage]
+//! slope = 1.0005
+//! intercept = -0.0002
+//!
+//! [[current]]
+//! up_to = 1e-6
+//! slope = 1.02
+//! intercept = 0.0
+//!
+//! # no `up_to` applies to all larger currents
+//! [[current]]
+//! slope = 0.998
+//! intercept = 0.0
+//! ```
+//!
+//! Overrides stored as profiles `<UID>.toml` or `<UID>.json` in `usmu/profiles` of the user's configuration directory
+//! are applied automatically to the device with that UID by the command line tools, see [CalibrationOverrideParameter],
+//! e.g. to correct known quirks of the devices of a fleet on every machine.
+
+#[cfg(any(feature = "config", feature = "json"))]
+use std::path::{Path, PathBuf};
+
+#[cfg(any(feature = "config", feature = "json"))]
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Current, Voltage, ampere, calibration::LinearCalibration, commands::MeasureResponse, volt,
+};
+#[cfg(any(feature = "config", feature = "json"))]
+use crate::{MicroSmu, Result, calibration::load_file};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationOverride {
+    #[serde(default = "nominal")]
+    pub voltage: LinearCalibration,
+    /// Corrections of the current by magnitude, currents outside all ranges are not corrected.
+    #[serde(default)]
+    pub current: Vec<CurrentCorrection>,
+}
+
+fn nominal() -> LinearCalibration {
+    LinearCalibration::NOMINAL
+}
+
+/// Correction of the currents up to a magnitude.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CurrentCorrection {
+    /// Largest magnitude corrected in ampere, all larger currents if not given.
+    pub up_to: Option<f32>,
+    #[serde(flatten)]
+    pub correction: LinearCalibration,
+}
+
+impl LinearCalibration {
+    pub fn apply(&self, value: f32) -> f32 {
+        self.slope * value + self.intercept
+    }
+}
+
+impl CalibrationOverride {
+    #[cfg(any(feature = "config", feature = "json"))]
+    pub fn load(path: &Path) -> Result<Self> {
+        load_file(path, "calibration override")
+    }
+
+    /// The profile of uSMU `uid` in `directory` and its path, `None` if there is none.
+    #[cfg(any(feature = "config", feature = "json"))]
+    pub fn profile(directory: &Path, uid: u32) -> Result<Option<(PathBuf, Self)>> {
+        for extension in ["toml", "json"] {
+            let path = directory.join(format!("{uid}.{extension}"));
+            if path.is_file() {
+                let profile = Self::load(&path)?;
+                return Ok(Some((path, profile)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn apply(&self, response: MeasureResponse) -> MeasureResponse {
+        let current = response.current.get::<ampere>();
+        let current = match self
+            .current
+            .iter()
+            .find(|e| e.up_to.is_none_or(|up_to| current.abs() <= up_to))
+        {
+            Some(range) => range.correction.apply(current),
+            None => current,
+        };
+        MeasureResponse {
+            voltage: Voltage::new::<volt>(self.voltage.apply(response.voltage.get::<volt>())),
+            current: Current::new::<ampere>(current),
+        }
+    }
+}
+
+/// `usmu/profiles` in `$XDG_CONFIG_HOME`, `~/.config` or `%APPDATA%`.
+#[cfg(any(feature = "config", feature = "json"))]
+pub fn profile_directory() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|e| !e.is_empty());
+    let config = env("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|e| PathBuf::from(e).join(".config")))
+        .or_else(|| env("APPDATA").map(PathBuf::from))?;
+    Some(config.join("usmu").join("profiles"))
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+#[derive(Debug, Clone, Default, Parser)]
+pub struct CalibrationOverrideParameter {
+    /// Correct the measurements on the host by the calibration override in this TOML or JSON file
+    /// instead of the profile of the device, leaving the calibration in the EEPROM untouched.
+    #[arg(long)]
+    pub calibration_override: Option<PathBuf>,
+
+    /// Directory of the calibration profiles `<UID>.toml` or `<UID>.json`,
+    /// by default `usmu/profiles` in the user's configuration directory.
+    #[arg(long)]
+    pub profile_directory: Option<PathBuf>,
+
+    /// Do not apply the calibration profile of the device.
+    #[arg(long, conflicts_with = "calibration_override")]
+    pub no_profile: bool,
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+impl CalibrationOverrideParameter {
+    /// The override given explicitly.
+    pub fn load(&self) -> Result<Option<CalibrationOverride>> {
+        self.calibration_override
+            .as_deref()
+            .map(CalibrationOverride::load)
+            .transpose()
+    }
+
+    /// Apply the override given explicitly or else the profile of `smu`.
+    /// The device is only identified if the profile directory exists.
+    pub fn configure(&self, smu: &mut MicroSmu) -> Result<()> {
+        if let Some(calibration_override) = self.load()? {
+            smu.set_calibration_override(Some(calibration_override));
+            return Ok(());
+        }
+        let directory = self.profile_directory.clone().or_else(profile_directory);
+        let Some(directory) = directory.filter(|e| !self.no_profile && e.is_dir()) else {
+            return Ok(());
+        };
+        let uid = smu.get_identity()?;
+        if let Some((path, profile)) = CalibrationOverride::profile(&directory, uid)? {
+            eprintln!(
+                "Applying the calibration profile {} of uSMU {uid}.",
+                path.display()
+            );
+            smu.set_calibration_override(Some(profile));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use crate::simulator::{Resistor, SimulatedSmu};
+
+    use super::*;
+
+    #[test]
+    fn corrects_measurements() {
+        let path = std::env::temp_dir().join(format!("usmu-override-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[voltage]\nslope = 2.0\nintercept = 0.5\n\n\
+             [[current]]\nup_to = 1e-3\nslope = 1.0\nintercept = 1.0\n",
+        )
+        .unwrap();
+        let calibration_override = CalibrationOverride::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        smu.set_calibration_override(Some(calibration_override));
+        smu.enable().unwrap();
+        let corrected = smu.measure(Voltage::new::<volt>(0.5)).unwrap();
+        assert_eq!(corrected.voltage, Voltage::new::<volt>(1.5));
+        assert_eq!(corrected.current, Current::new::<ampere>(1.0005));
+
+        // beyond all ranges of the current
+        let corrected = smu.measure(Voltage::new::<volt>(2.0)).unwrap();
+        assert_eq!(corrected.current, Current::new::<ampere>(0.002));
+    }
+
+    #[test]
+    fn applies_profiles() {
+        let directory = std::env::temp_dir().join(format!("usmu-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("42.toml"),
+            "[voltage]\nslope = 1.0\nintercept = 0.25\n",
+        )
+        .unwrap();
+        let parameter = CalibrationOverrideParameter {
+            profile_directory: Some(directory.clone()),
+            ..Default::default()
+        };
+
+        let profiled = SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(42);
+        let mut smu = MicroSmu::new(Box::new(profiled));
+        parameter.configure(&mut smu).unwrap();
+        let corrected = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert_eq!(corrected.voltage, Voltage::new::<volt>(1.25));
+
+        let other = SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(7);
+        let mut smu = MicroSmu::new(Box::new(other));
+        parameter.configure(&mut smu).unwrap();
+        let uncorrected = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert_eq!(uncorrected.voltage, Voltage::new::<volt>(1.0));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,437 @@
+//! C API to link the driver from C/C++ applications, see `include/usmu.h`.
+//!
+//! All functions returning `int` report [USMU_OK] on success or one of the negative `USMU_ERROR_*` codes.
+//! A description of the last failure on the calling thread is available through [usmu_last_error].
+//! Panics are caught at the boundary and reported as [USMU_ERROR_PANIC].
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr, slice,
+};
+
+use crate::{
+    Current, Error, MicroSmu, Time, Voltage, ampere, record_iv_curve::IvCurveRecordingParameters,
+    record_iv_curve::SmuConnectionParameter, second, volt,
+};
+
+pub const USMU_OK: c_int = 0;
+/// Communication with the device failed.
+pub const USMU_ERROR_DEVICE: c_int = -1;
+/// A passed argument is invalid, e.g. a null pointer, a too small buffer or an out of range parameter.
+pub const USMU_ERROR_INVALID_ARGUMENT: c_int = -2;
+/// The driver panicked.
+pub const USMU_ERROR_PANIC: c_int = -3;
+
+/// Opaque handle of an opened uSMU.
+pub struct UsmuDevice(MicroSmu);
+
+struct CapiError {
+    code: c_int,
+    message: String,
+}
+
+impl From<Error> for CapiError {
+    fn from(value: Error) -> Self {
+        let code = match value {
+            Error::InvalidParameter(_) => USMU_ERROR_INVALID_ARGUMENT,
+            _ => USMU_ERROR_DEVICE,
+        };
+        Self {
+            code,
+            message: value.to_string(),
+        }
+    }
+}
+
+fn invalid_argument(message: &str) -> CapiError {
+    CapiError {
+        code: USMU_ERROR_INVALID_ARGUMENT,
+        message: message.to_string(),
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).expect("nul bytes are replaced");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run `f` and translate its result and panics into a status code.
+fn call(f: impl FnOnce() -> Result<(), CapiError>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => USMU_OK,
+        Ok(Err(e)) => {
+            set_last_error(e.message);
+            e.code
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|e| e.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {message}"));
+            USMU_ERROR_PANIC
+        }
+    }
+}
+
+/// # Safety
+/// `device` must be null or a valid handle obtained from [usmu_open].
+unsafe fn device<'a>(device: *mut UsmuDevice) -> Result<&'a mut MicroSmu, CapiError> {
+    // SAFETY: guaranteed by the caller
+    unsafe { device.as_mut() }
+        .map(|e| &mut e.0)
+        .ok_or_else(|| invalid_argument("device handle is null"))
+}
+
+/// Open a uSMU.
+///
+/// `port` is the path of the serial port, or null to use the single attached uSMU.
+/// Returns null on failure.
+///
+/// # Safety
+/// `port` must be null or a valid nul terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_open(port: *const c_char) -> *mut UsmuDevice {
+    let mut device = ptr::null_mut();
+    call(|| {
+        let port = if port.is_null() {
+            None
+        } else {
+            // SAFETY: guaranteed by the caller
+            let port = unsafe { CStr::from_ptr(port) };
+            let port = port
+                .to_str()
+                .map_err(|_| invalid_argument("port is not valid UTF-8"))?;
+            Some(PathBuf::from(port))
+        };
+        let connection = SmuConnectionParameter {
+            port,
+            ..Default::default()
+        };
+        let smu = connection.connect()?;
+        device = Box::into_raw(Box::new(UsmuDevice(smu)));
+        Ok(())
+    });
+    device
+}
+
+/// Close the device and release the handle.
+///
+/// # Safety
+/// `device` must be null or a valid handle obtained from [usmu_open], which is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_close(device: *mut UsmuDevice) {
+    if !device.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { Box::from_raw(device) });
+    }
+}
+
+/// Enable SMU output.
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_enable(device: *mut UsmuDevice) -> c_int {
+    // SAFETY: guaranteed by the caller
+    call(|| Ok(unsafe { self::device(device) }?.enable()?))
+}
+
+/// Disable SMU output (high impedance).
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_disable(device: *mut UsmuDevice) -> c_int {
+    // SAFETY: guaranteed by the caller
+    call(|| Ok(unsafe { self::device(device) }?.disable()?))
+}
+
+/// Set the output voltage in volt.
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_set_voltage(device: *mut UsmuDevice, voltage: f32) -> c_int {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let smu = unsafe { self::device(device) }?;
+        Ok(smu.set_voltage(Voltage::new::<volt>(voltage))?)
+    })
+}
+
+/// Set the sink/source current limit in ampere, at most 40mA.
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_set_current_limit(device: *mut UsmuDevice, limit: f32) -> c_int {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let smu = unsafe { self::device(device) }?;
+        Ok(smu.set_current_limit(Current::new::<ampere>(limit))?)
+    })
+}
+
+/// Set the number of samples averaged per measurement.
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_set_over_sample_rate(device: *mut UsmuDevice, samples: u16) -> c_int {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let smu = unsafe { self::device(device) }?;
+        Ok(smu.set_over_sample_rate(samples)?)
+    })
+}
+
+/// Set the output to `voltage` and measure voltage (volt) and current (ampere).
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open],
+/// `measured_voltage` and `measured_current` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_measure(
+    device: *mut UsmuDevice,
+    voltage: f32,
+    measured_voltage: *mut f32,
+    measured_current: *mut f32,
+) -> c_int {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let smu = unsafe { self::device(device) }?;
+        if measured_voltage.is_null() || measured_current.is_null() {
+            return Err(invalid_argument("output pointer is null"));
+        }
+        let measurement = smu.measure(Voltage::new::<volt>(voltage))?;
+        // SAFETY: guaranteed by the caller and checked for null
+        unsafe {
+            *measured_voltage = measurement.voltage.get::<volt>();
+            *measured_current = measurement.current.get::<ampere>();
+        }
+        Ok(())
+    })
+}
+
+/// Record an IV curve with `steps` linearly spaced voltages from `start_voltage` to `end_voltage`.
+///
+/// The measured voltages and currents are written to `voltages` and `currents`,
+/// which must both hold at least `steps` elements, and their number to `samples`,
+/// which is less than `steps` if the sweep ended early. `delay` is the settling time in seconds before each point.
+/// The output is enabled for the sweep and disabled afterwards.
+///
+/// # Safety
+/// `device` must be a valid handle obtained from [usmu_open],
+/// `voltages` and `currents` must be valid for writes of `steps` elements, `samples` must be valid for writes.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn usmu_sweep(
+    device: *mut UsmuDevice,
+    start_voltage: f32,
+    end_voltage: f32,
+    steps: usize,
+    current_limit: f32,
+    over_sampling: u16,
+    delay: f32,
+    voltages: *mut f32,
+    currents: *mut f32,
+    samples: *mut usize,
+) -> c_int {
+    call(|| {
+        // SAFETY: guaranteed by the caller
+        let smu = unsafe { self::device(device) }?;
+        if samples.is_null() || steps > 0 && (voltages.is_null() || currents.is_null()) {
+            return Err(invalid_argument("output buffer is null"));
+        }
+        if !delay.is_finite() || delay < 0.0 {
+            return Err(invalid_argument("delay must be a non-negative time"));
+        }
+
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(start_voltage),
+            end_voltage: Voltage::new::<volt>(end_voltage),
+            voltage_steps: steps,
+            current_limit: Current::new::<ampere>(current_limit),
+            over_sampling,
+            delay: Time::new::<second>(delay),
+            adaptive_over_sampling: Default::default(),
+            step_refinement: Default::default(),
+            auto_range: Default::default(),
+        };
+        let result = parameters.record(smu)?;
+
+        // SAFETY: guaranteed by the caller and checked for null
+        unsafe { *samples = result.samples.len().min(steps) };
+        if steps == 0 {
+            return Ok(());
+        }
+        // SAFETY: guaranteed by the caller and checked for null
+        let (voltages, currents) = unsafe {
+            (
+                slice::from_raw_parts_mut(voltages, steps),
+                slice::from_raw_parts_mut(currents, steps),
+            )
+        };
+        for (sample, (v, i)) in result
+            .samples
+            .iter()
+            .zip(voltages.iter_mut().zip(currents.iter_mut()))
+        {
+            *v = sample.voltage.get::<volt>();
+            *i = sample.current.get::<ampere>();
+        }
+        Ok(())
+    })
+}
+
+/// Copy the description of the last failure on this thread as nul terminated string into `buffer`.
+///
+/// The message is truncated to fit into `length` bytes.
+/// Returns the length of the complete message without the terminator, or 0 if no failure occurred.
+///
+/// # Safety
+/// `buffer` must be null or valid for writes of `length` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn usmu_last_error(buffer: *mut c_char, length: usize) -> usize {
+    LAST_ERROR.with(|e| {
+        let e = e.borrow();
+        let Some(message) = e.as_ref() else {
+            return 0;
+        };
+        let message = message.as_bytes();
+        if !buffer.is_null() && length > 0 {
+            let copied = message.len().min(length - 1);
+            // SAFETY: guaranteed by the caller, `copied + 1` does not exceed `length`
+            unsafe {
+                ptr::copy_nonoverlapping(message.as_ptr().cast(), buffer, copied);
+                *buffer.add(copied) = 0;
+            }
+        }
+        message.len()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    fn last_error() -> String {
+        let mut buffer = [0 as c_char; 128];
+        // SAFETY: the buffer is valid for its length
+        unsafe { usmu_last_error(buffer.as_mut_ptr(), buffer.len()) };
+        // SAFETY: terminated by usmu_last_error
+        unsafe { CStr::from_ptr(buffer.as_ptr()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn reports_the_last_error() {
+        // no failure occurred on a new thread yet, SAFETY: a null buffer is only measured
+        let fresh = thread::spawn(|| unsafe { usmu_last_error(ptr::null_mut(), 0) });
+        assert_eq!(fresh.join().unwrap(), 0);
+
+        // SAFETY: null handles are rejected
+        let status = unsafe { usmu_enable(ptr::null_mut()) };
+        assert_eq!(status, USMU_ERROR_INVALID_ARGUMENT);
+        assert_eq!(last_error(), "device handle is null");
+
+        // SAFETY: a null buffer is only measured, the buffer is valid for its length
+        let length = unsafe { usmu_last_error(ptr::null_mut(), 0) };
+        assert_eq!(length, "device handle is null".len());
+        let mut buffer = [1 as c_char; 7];
+        let length = unsafe { usmu_last_error(buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(length, "device handle is null".len());
+        // SAFETY: terminated by usmu_last_error
+        let truncated = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+        assert_eq!(truncated.to_str().unwrap(), "device");
+
+        assert_eq!(call(|| panic!("driver bug")), USMU_ERROR_PANIC);
+        assert_eq!(last_error(), "panic: driver bug");
+    }
+
+    #[test]
+    fn measures_through_the_handle() {
+        let smu = simulated_smu(Resistor::ohm(1000.0));
+        let device = Box::into_raw(Box::new(UsmuDevice(smu)));
+
+        // SAFETY: `device` is a valid handle until closed, the outputs are valid for writes
+        unsafe {
+            assert_eq!(usmu_set_current_limit(device, 0.02), USMU_OK);
+            assert_eq!(usmu_enable(device), USMU_OK);
+            let (mut voltage, mut current) = (0.0, 0.0);
+            assert_eq!(
+                usmu_measure(device, 1.0, &mut voltage, &mut current),
+                USMU_OK
+            );
+            assert!((current - 1e-3).abs() < 1e-5);
+            let status = usmu_measure(device, 1.0, ptr::null_mut(), &mut current);
+            assert_eq!(status, USMU_ERROR_INVALID_ARGUMENT);
+            assert_eq!(last_error(), "output pointer is null");
+
+            let (mut voltages, mut currents, mut samples) = ([0.0; 3], [0.0; 3], 0);
+            let status = usmu_sweep(
+                device,
+                0.0,
+                1.0,
+                3,
+                0.02,
+                1,
+                0.0,
+                voltages.as_mut_ptr(),
+                currents.as_mut_ptr(),
+                &mut samples,
+            );
+            assert_eq!(status, USMU_OK);
+            assert_eq!(samples, 3);
+            assert!((voltages[1] - 0.5).abs() < 1e-3);
+            assert!((currents[2] - 1e-3).abs() < 1e-5);
+            let status = usmu_sweep(
+                device,
+                0.0,
+                1.0,
+                3,
+                0.02,
+                1,
+                0.0,
+                ptr::null_mut(),
+                currents.as_mut_ptr(),
+                &mut samples,
+            );
+            assert_eq!(status, USMU_ERROR_INVALID_ARGUMENT);
+            for delay in [-1.0, f32::NAN] {
+                let status = usmu_sweep(
+                    device,
+                    0.0,
+                    1.0,
+                    3,
+                    0.02,
+                    1,
+                    delay,
+                    voltages.as_mut_ptr(),
+                    currents.as_mut_ptr(),
+                    &mut samples,
+                );
+                assert_eq!(status, USMU_ERROR_INVALID_ARGUMENT);
+                assert_eq!(last_error(), "delay must be a non-negative time");
+            }
+
+            usmu_close(device);
+            usmu_close(ptr::null_mut());
+        }
+    }
+}
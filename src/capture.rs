@@ -0,0 +1,329 @@
+//! Threshold-triggered capture of transient events, e.g. intermittent shorts.
+//!
+//! The device is monitored while a ring buffer keeps the samples of the pre-trigger window.
+//! Once the [TriggerCondition] is met, the samples of the post-trigger window are recorded and form a [Capture]
+//! together with the buffered ones. Afterwards the trigger is re-armed.
+
+use std::{
+    collections::VecDeque, fs::File, io::Write, ops::ControlFlow, path::PathBuf, str::FromStr,
+};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::{
+    Current, MicroSmu, Result, Time, Voltage, ampere,
+    monitor::{MonitorParameters, MonitorSample},
+    record_iv_curve::SmuConnectionParameter,
+    schema, second,
+    timestamp::TimestampSource,
+    volt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerCondition {
+    CurrentAbove(Current),
+    CurrentBelow(Current),
+    /// The voltage changes by more than this between two consecutive samples.
+    VoltageChange(Voltage),
+}
+
+impl TriggerCondition {
+    pub fn is_met(&self, previous: Option<&MonitorSample>, sample: &MonitorSample) -> bool {
+        match *self {
+            TriggerCondition::CurrentAbove(threshold) => sample.current > threshold,
+            TriggerCondition::CurrentBelow(threshold) => sample.current < threshold,
+            TriggerCondition::VoltageChange(threshold) => {
+                previous.is_some_and(|e| (sample.voltage - e.voltage).abs() > threshold)
+            }
+        }
+    }
+}
+
+impl FromStr for TriggerCondition {
+    type Err = String;
+
+    /// Parse `current-above:QUANTITY`, `current-below:QUANTITY` or `voltage-change:QUANTITY`, e.g. `current-above:5 mA`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, threshold) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected CONDITION:THRESHOLD, got '{s}'"))?;
+        let threshold = threshold.trim();
+        let invalid = |e| format!("invalid threshold '{threshold}': {e}");
+        match name {
+            "current-above" => Ok(TriggerCondition::CurrentAbove(
+                threshold.parse().map_err(invalid)?,
+            )),
+            "current-below" => Ok(TriggerCondition::CurrentBelow(
+                threshold.parse().map_err(invalid)?,
+            )),
+            "voltage-change" => Ok(TriggerCondition::VoltageChange(
+                threshold.parse().map_err(invalid)?,
+            )),
+            name => Err(format!(
+                "unknown condition '{name}', expected current-above, current-below or voltage-change"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureParameters {
+    pub monitor: MonitorParameters,
+    pub condition: TriggerCondition,
+
+    /// Time before the trigger kept in the capture.
+    pub pre_trigger: Time,
+
+    /// Time after the trigger recorded in the capture.
+    pub post_trigger: Time,
+}
+
+#[derive(Debug, Clone)]
+pub struct Capture {
+    /// Index of the sample meeting the trigger condition.
+    pub trigger_index: usize,
+    pub samples: Vec<MonitorSample>,
+}
+
+impl Capture {
+    pub fn trigger(&self) -> &MonitorSample {
+        &self.samples[self.trigger_index]
+    }
+}
+
+impl CaptureParameters {
+    /// Monitor until the first capture is complete, `None` if the monitoring duration ended before a trigger.
+    pub fn capture(&self, smu: &mut MicroSmu) -> Result<Option<Capture>> {
+        let captures = self.capture_with(smu, |_| ControlFlow::Break(()))?;
+        Ok(captures.into_iter().next())
+    }
+
+    /// Like [Self::capture], but re-arms after each capture until `on_capture` breaks or the monitoring duration ended.
+    ///
+    /// A capture still recording its post-trigger window when the duration ended is returned truncated.
+    /// The output is disabled afterwards, also on failure.
+    pub fn capture_with(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_capture: impl FnMut(&Capture) -> ControlFlow<()>,
+    ) -> Result<Vec<Capture>> {
+        let mut captures = Vec::new();
+        let mut ring: VecDeque<MonitorSample> = VecDeque::new();
+        let mut previous: Option<MonitorSample> = None;
+        let mut active: Option<Capture> = None;
+
+        self.monitor.monitor_with(smu, |sample| {
+            let flow = match active.as_mut() {
+                Some(capture) => {
+                    capture.samples.push(*sample);
+                    if sample.time - capture.trigger().time >= self.post_trigger {
+                        let capture = active.take().expect("capture is active");
+                        let flow = on_capture(&capture);
+                        captures.push(capture);
+                        flow
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                }
+                None if self.condition.is_met(previous.as_ref(), sample) => {
+                    let mut samples: Vec<_> = ring.drain(..).collect();
+                    samples.push(*sample);
+                    let capture = Capture {
+                        trigger_index: samples.len() - 1,
+                        samples,
+                    };
+                    if self.post_trigger.get::<second>() <= 0.0 {
+                        let flow = on_capture(&capture);
+                        captures.push(capture);
+                        flow
+                    } else {
+                        active = Some(capture);
+                        ControlFlow::Continue(())
+                    }
+                }
+                None => {
+                    ring.push_back(*sample);
+                    while ring
+                        .front()
+                        .is_some_and(|e| sample.time - e.time > self.pre_trigger)
+                    {
+                        ring.pop_front();
+                    }
+                    ControlFlow::Continue(())
+                }
+            };
+            previous = Some(*sample);
+            flow
+        })?;
+
+        captures.extend(active);
+        Ok(captures)
+    }
+}
+
+/// Write the captures as CSV with the columns `capture` (index), `time` (seconds relative to the trigger),
+/// `voltage` and `current`.
+pub fn write_csv(captures: &[Capture], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        capture: usize,
+        time: f32,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for (index, capture) in captures.iter().enumerate() {
+        let trigger = capture.trigger().time;
+        for sample in &capture.samples {
+            writer.serialize(Row {
+                capture: index,
+                time: (sample.time - trigger).get::<second>(),
+                voltage: sample.voltage.get::<volt>(),
+                current: sample.current.get::<ampere>(),
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct CaptureArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Bias voltage applied while monitoring.
+    #[arg(long, short = 'v', default_value = "0 V")]
+    pub voltage: Voltage,
+
+    #[arg(long, short = 'c', default_value = "20 mA")]
+    pub current_limit: Current,
+
+    /// Number of samples averaged per measurement.
+    #[arg(long, short = 'r', default_value_t = 1)]
+    pub over_sampling: u16,
+
+    /// Time between measurements.
+    #[arg(long, short = 'i', default_value = "10 ms")]
+    pub interval: Time,
+
+    /// Total monitoring time, monitors until the captures are complete if not given.
+    #[arg(long, short = 't')]
+    pub duration: Option<Time>,
+
+    /// Trigger condition, `current-above:THRESHOLD`, `current-below:THRESHOLD` or `voltage-change:THRESHOLD`,
+    /// e.g. `current-above:5 mA`.
+    #[arg(long)]
+    pub trigger: TriggerCondition,
+
+    /// Time before the trigger kept in the capture.
+    #[arg(long, default_value = "1 s")]
+    pub pre_trigger: Time,
+
+    /// Time after the trigger recorded in the capture.
+    #[arg(long, default_value = "1 s")]
+    pub post_trigger: Time,
+
+    /// Number of captures before stopping.
+    #[arg(long, short = 'n', default_value_t = 1)]
+    pub count: usize,
+
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    #[cfg(feature = "checksum")]
+    #[command(flatten)]
+    pub checksum_parameter: crate::checksum::ChecksumParameter,
+}
+
+impl CaptureArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut smu = self.connection_parameter.connect()?;
+        let parameters = CaptureParameters {
+            monitor: MonitorParameters {
+                voltage: self.voltage,
+                current_limit: self.current_limit,
+                over_sampling: self.over_sampling,
+                interval: self.interval,
+                duration: self.duration,
+                auto_zero: None,
+                timestamps: TimestampSource::default(),
+                epoch: None,
+            },
+            condition: self.trigger,
+            pre_trigger: self.pre_trigger,
+            post_trigger: self.post_trigger,
+        };
+
+        let mut remaining = self.count;
+        let captures = parameters.capture_with(&mut smu, |capture| {
+            eprintln!("Triggered at {} s", capture.trigger().time.get::<second>());
+            remaining = remaining.saturating_sub(1);
+            if remaining == 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        if captures.is_empty() {
+            eprintln!("Not triggered.");
+        }
+
+        match self.output.as_ref() {
+            Some(output) => write_csv(&captures, File::create(output)?)?,
+            None => write_csv(&captures, std::io::stdout())?,
+        }
+        #[cfg(feature = "checksum")]
+        self.checksum_parameter.seal(self.output.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::electric_current::milliampere;
+
+    use super::*;
+
+    fn sample(time: f32, voltage: f32, current: f32) -> MonitorSample {
+        MonitorSample {
+            time: Time::new::<second>(time),
+            voltage: Voltage::new::<volt>(voltage),
+            current: Current::new::<ampere>(current),
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!(
+            "current-above:5 mA".parse::<TriggerCondition>().unwrap(),
+            TriggerCondition::CurrentAbove(Current::new::<milliampere>(5.0))
+        );
+        assert_eq!(
+            "voltage-change:0.1 V".parse::<TriggerCondition>(),
+            Ok(TriggerCondition::VoltageChange(Voltage::new::<volt>(0.1)))
+        );
+        assert!("current-above:5".parse::<TriggerCondition>().is_err());
+        assert!("power-above:5 W".parse::<TriggerCondition>().is_err());
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let low = sample(0.0, 1.0, 0.001);
+        let high = sample(1.0, 1.5, 0.01);
+        let above = TriggerCondition::CurrentAbove(Current::new::<ampere>(0.005));
+        assert!(!above.is_met(None, &low));
+        assert!(above.is_met(Some(&low), &high));
+        let below = TriggerCondition::CurrentBelow(Current::new::<ampere>(0.005));
+        assert!(below.is_met(None, &low));
+        let change = TriggerCondition::VoltageChange(Voltage::new::<volt>(0.2));
+        assert!(!change.is_met(None, &high));
+        assert!(change.is_met(Some(&low), &high));
+        assert!(!change.is_met(Some(&high), &high));
+    }
+}
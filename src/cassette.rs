@@ -0,0 +1,611 @@
+//! Recording of the serial communication with a device into cassettes, to replay it later without hardware.
+//!
+//! A cassette is a text file with one line per transmitted command, prefixed with `> `,
+//! each followed by the response line, if any, prefixed with `< `:
+//!
+//! ```text
+//! > CH1:MEA:VOL 0.5
+//! < 0.4998,0.00123
+//! ```
+//!
+//! Recordings by [RecordingPort] and [RecordingTransport] prefix each line with the seconds since the start
+//! of the recording, and note failed reads as comments starting with `# `, e.g. for bug reports against the firmware.
+//! Both are ignored when loading a cassette, so recordings serve as regression fixtures:
+//!
+//! ```text
+//! 0.000021 > CH1:MEA:VOL 0.5
+//! 0.061877 < 0.4998,0.00123
+//! 1.062410 # read failed: timed out, received "0.49"
+//! ```
+//!
+//! [ReplayPort] feeds the responses of a cassette back, e.g. to develop sweep logic and output formatting
+//! on machines without a uSMU attached, see `--replay-cassette` of the CLI.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::{Error, Result, transport::Transport};
+
+const COMMAND_PREFIX: &str = "> ";
+const RESPONSE_PREFIX: &str = "< ";
+const COMMENT_PREFIX: &str = "# ";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub command: String,
+    pub response: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cassette {
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// A fake serial port replaying the cassette, which panics on deviating commands, see [ReplayPort] otherwise.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn replay(&self) -> crate::test_util::FakeSerialPort {
+        let port = crate::test_util::FakeSerialPort::new();
+        for Exchange { command, response } in &self.exchanges {
+            match response {
+                Some(response) => port.expect_query(command, response),
+                None => port.expect(command),
+            };
+        }
+        port
+    }
+}
+
+impl FromStr for Cassette {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut exchanges: Vec<Exchange> = Vec::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = strip_timestamp(line);
+            if line.starts_with(COMMENT_PREFIX) {
+                continue;
+            } else if let Some(command) = line.strip_prefix(COMMAND_PREFIX) {
+                exchanges.push(Exchange {
+                    command: command.to_string(),
+                    response: None,
+                });
+            } else if let Some(response) = line.strip_prefix(RESPONSE_PREFIX) {
+                let exchange = exchanges
+                    .last_mut()
+                    .filter(|e| e.response.is_none())
+                    .ok_or_else(|| {
+                        Error::Data(format!("Response without command in line {}.", number + 1))
+                    })?;
+                exchange.response = Some(response.to_string());
+            } else if !line.is_empty() {
+                Err(Error::Data(format!(
+                    "Invalid cassette line {}: '{line}'",
+                    number + 1
+                )))?;
+            }
+        }
+        Ok(Self { exchanges })
+    }
+}
+
+/// `line` without the leading timestamp of recordings, if any.
+fn strip_timestamp(line: &str) -> &str {
+    match line.split_once(' ') {
+        Some((time, rest)) if time.parse::<f64>().is_ok() => rest,
+        _ => line,
+    }
+}
+
+/// Writes timestamped lines to a cassette file, see the [module documentation](self).
+struct Recorder {
+    cassette: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    fn create(cassette: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            cassette: BufWriter::new(File::create(cassette)?),
+            start: Instant::now(),
+        })
+    }
+
+    fn record(&mut self, prefix: &str, line: &str) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(self.cassette, "{time:.6} {prefix}{line}")?;
+        self.cassette.flush()
+    }
+}
+
+impl Display for Cassette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for Exchange { command, response } in &self.exchanges {
+            writeln!(f, "{COMMAND_PREFIX}{command}")?;
+            if let Some(response) = response {
+                writeln!(f, "{RESPONSE_PREFIX}{response}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A serial port writing all transmitted and received lines to a cassette file, with timestamps.
+pub struct RecordingPort {
+    port: Box<dyn SerialPort>,
+    cassette: Recorder,
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+impl RecordingPort {
+    pub fn create(port: Box<dyn SerialPort>, cassette: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            port,
+            cassette: Recorder::create(cassette)?,
+            sent: Vec::new(),
+            received: Vec::new(),
+        })
+    }
+
+    /// Append complete lines of `data` to `buffer` and write them to the cassette.
+    fn record(
+        cassette: &mut Recorder,
+        prefix: &str,
+        buffer: &mut Vec<u8>,
+        data: &[u8],
+    ) -> io::Result<()> {
+        for &byte in data {
+            if byte == b'\n' {
+                let line = std::mem::take(buffer);
+                cassette.record(prefix, &String::from_utf8_lossy(&line))?;
+            } else {
+                buffer.push(byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for RecordingPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.port.read(buf)?;
+        Self::record(
+            &mut self.cassette,
+            RESPONSE_PREFIX,
+            &mut self.received,
+            &buf[..count],
+        )?;
+        Ok(count)
+    }
+}
+
+impl Write for RecordingPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.port.write(buf)?;
+        Self::record(
+            &mut self.cassette,
+            COMMAND_PREFIX,
+            &mut self.sent,
+            &buf[..count],
+        )?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+impl SerialPort for RecordingPort {
+    fn name(&self) -> Option<String> {
+        self.port.name()
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        self.port.baud_rate()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        self.port.data_bits()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        self.port.flow_control()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        self.port.parity()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        self.port.stop_bits()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.port.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.port.set_baud_rate(baud_rate)
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.port.set_data_bits(data_bits)
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.port.set_flow_control(flow_control)
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.port.set_parity(parity)
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.port.set_stop_bits(stop_bits)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        SerialPort::set_timeout(self.port.as_mut(), timeout)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.port.write_request_to_send(level)
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.port.write_data_terminal_ready(level)
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.port.read_clear_to_send()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.port.read_data_set_ready()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.port.read_ring_indicator()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.port.read_carrier_detect()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        self.port.bytes_to_read()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        self.port.bytes_to_write()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        self.port.clear(buffer_to_clear)
+    }
+
+    /// The clone is not recorded.
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        self.port.try_clone()
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.port.set_break()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.port.clear_break()
+    }
+}
+
+/// A serial port answering the commands recorded in a cassette with the recorded responses.
+///
+/// Commands deviating from the cassette fail with [io::ErrorKind::InvalidData] and are skipped,
+/// reads without a pending response time out like on a real port.
+pub struct ReplayPort {
+    exchanges: VecDeque<Exchange>,
+    /// The incomplete line written so far.
+    line: Vec<u8>,
+    readable: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl ReplayPort {
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            exchanges: cassette.exchanges.into(),
+            line: Vec::new(),
+            readable: VecDeque::new(),
+            timeout: Duration::from_millis(1000),
+        }
+    }
+
+    fn receive(&mut self, command: &str) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let exchange = self.exchanges.front().ok_or_else(|| {
+            invalid(format!(
+                "Unexpected command '{command}', the cassette ended."
+            ))
+        })?;
+        if exchange.command != command {
+            Err(invalid(format!(
+                "Unexpected command '{command}', the cassette continues with '{}'.",
+                exchange.command
+            )))?;
+        }
+        if let Some(response) = self.exchanges.pop_front().and_then(|e| e.response) {
+            self.readable.extend(response.bytes());
+            self.readable.push_back(b'\n');
+        }
+        Ok(())
+    }
+
+    /// Whether all exchanges of the cassette were replayed.
+    pub fn is_finished(&self) -> bool {
+        self.exchanges.is_empty()
+    }
+}
+
+impl Read for ReplayPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.readable.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no response"));
+        }
+        let count = buf.len().min(self.readable.len());
+        for (target, source) in buf.iter_mut().zip(self.readable.drain(..count)) {
+            *target = source;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for ReplayPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.line);
+                self.receive(&String::from_utf8_lossy(&line))?;
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for ReplayPort {
+    fn name(&self) -> Option<String> {
+        Some("replay".to_string())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(crate::builder::DEFAULT_BAUDRATE)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.readable.len() as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    /// Replayed data is only buffered by the port itself, hence there is nothing to clear.
+    fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(serialport::Error::new(
+            serialport::ErrorKind::Unknown,
+            "A replay port cannot be cloned.",
+        ))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [Transport] writing all transmitted and received lines to a cassette file, with timestamps,
+/// e.g. for a [MicroSmu](crate::MicroSmu) on a TCP bridge, see [MicroSmu::with_transport](crate::MicroSmu::with_transport).
+///
+/// Failed reads are noted with the data received so far.
+pub struct RecordingTransport<T> {
+    transport: T,
+    cassette: Recorder,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    pub fn create(transport: T, cassette: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            transport,
+            cassette: Recorder::create(cassette)?,
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        self.transport.write_line(line)?;
+        self.cassette.record(COMMAND_PREFIX, line.trim_end())?;
+        Ok(())
+    }
+
+    fn read_line(&mut self, line: &mut String) -> Result<()> {
+        let read = self.transport.read_line(line);
+        match &read {
+            Ok(()) => self.cassette.record(RESPONSE_PREFIX, line.trim_end())?,
+            Err(e) => self.cassette.record(
+                COMMENT_PREFIX,
+                &format!("read failed: {e}, received {line:?}"),
+            )?,
+        }
+        read
+    }
+
+    fn timeout(&self) -> Duration {
+        self.transport.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.transport.set_timeout(timeout)
+    }
+
+    fn name(&self) -> Option<String> {
+        self.transport.name()
+    }
+
+    fn discard_input(&mut self) -> Result<()> {
+        self.transport.discard_input()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MicroSmu, test_util::FakeSerialPort};
+
+    use super::*;
+
+    #[test]
+    fn recordings_load_as_cassettes() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:ENA")
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .expect("*IDN?");
+        let path =
+            std::env::temp_dir().join(format!("usmu-recording-{}.cassette", std::process::id()));
+        let transport = RecordingTransport::create(Box::new(port.clone()), &path).unwrap();
+        let mut smu = MicroSmu::with_transport(transport);
+        smu.set_inter_command_delay(Duration::ZERO);
+        smu.set_timeout(Duration::from_millis(10)).unwrap();
+
+        smu.enable().unwrap();
+        assert_eq!(smu.get_identity().unwrap(), 42);
+        assert!(smu.get_identity().is_err());
+
+        let recording = std::fs::read_to_string(&path).unwrap();
+        let timestamped = |line: &str| line.split(' ').next().unwrap().parse::<f64>().is_ok();
+        assert!(recording.lines().all(timestamped));
+        assert!(recording.contains("# read failed"));
+        let cassette: Cassette = recording.parse().unwrap();
+        let expected = "> CH1:ENA\n> *IDN?\n< uSMU version 1.0 ID:42\n> *IDN?\n";
+        assert_eq!(cassette.to_string(), expected);
+        std::fs::remove_file(path).unwrap();
+        port.verify();
+    }
+
+    #[test]
+    fn replays_the_responses() {
+        let cassette: Cassette = "0.1 > CH1:ENA\n0.2 > *IDN?\n0.3 < uSMU version 1.0 ID:42\n"
+            .parse()
+            .unwrap();
+        let port = ReplayPort::new(cassette);
+        let mut smu = MicroSmu::new(Box::new(port));
+        smu.set_inter_command_delay(Duration::ZERO);
+
+        smu.enable().unwrap();
+        assert!(smu.disable().is_err());
+        assert_eq!(smu.get_identity().unwrap(), 42);
+        let Error::Exchange { source, .. } = smu.get_identity().unwrap_err() else {
+            panic!("the cassette ended");
+        };
+        assert!(matches!(*source, Error::IoError(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+}
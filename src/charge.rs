@@ -0,0 +1,96 @@
+//! Integration of the output current over time into the accumulated charge and energy,
+//! e.g. for electrochemistry or leakage budgets.
+
+use std::time::Duration;
+
+use uom::si::{electric_charge::coulomb, energy::joule};
+
+use crate::{Charge, Current, Energy, Voltage, ampere, volt};
+
+/// Trapezoidal integration of consecutive samples, accumulated in double precision
+/// to not lose the small increments of long runs.
+///
+/// Charge and energy are signed like the output current, i.e. negative if the device under test sources current.
+#[derive(Debug, Clone, Default)]
+pub struct ChargeIntegrator {
+    charge: f64,
+    energy: f64,
+    /// Time, current and power of the previous sample.
+    previous: Option<(Duration, f64, f64)>,
+}
+
+impl ChargeIntegrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample taken at `time`, relative to an arbitrary but fixed start.
+    pub fn add(&mut self, time: Duration, voltage: Voltage, current: Current) {
+        let current = f64::from(current.get::<ampere>());
+        let power = f64::from(voltage.get::<volt>()) * current;
+        if let Some((previous_time, previous_current, previous_power)) = self.previous {
+            let dt = time.saturating_sub(previous_time).as_secs_f64();
+            self.charge += (previous_current + current) / 2.0 * dt;
+            self.energy += (previous_power + power) / 2.0 * dt;
+        }
+        self.previous = Some((time, current, power));
+    }
+
+    pub fn charge(&self) -> Charge {
+        Charge::new::<coulomb>(self.charge)
+    }
+
+    pub fn energy(&self) -> Energy {
+        Energy::new::<joule>(self.energy)
+    }
+
+    /// Restart the integration from zero.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integrate `current(t)` at `voltage` from 0 s to 10 s in steps of 100 ms.
+    fn integrate(voltage: f32, current: impl Fn(f32) -> f32) -> ChargeIntegrator {
+        let mut integrator = ChargeIntegrator::new();
+        for step in 0..=100 {
+            let time = Duration::from_millis(100 * step);
+            integrator.add(
+                time,
+                Voltage::new::<volt>(voltage),
+                Current::new::<ampere>(current(time.as_secs_f32())),
+            );
+        }
+        integrator
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= 1e-6 * expected.abs(),
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn integrates_a_constant_current() {
+        // Q = I t, E = U I t
+        let integrator = integrate(1.5, |_| -2e-3);
+        assert_close(integrator.charge().get::<coulomb>(), -2e-3 * 10.0);
+        assert_close(integrator.energy().get::<joule>(), 1.5 * -2e-3 * 10.0);
+    }
+
+    #[test]
+    fn integrates_a_linear_current() {
+        // I = k t, hence Q = k t² / 2 and E = U k t² / 2, which the trapezoids match exactly
+        let mut integrator = integrate(2.0, |t| 1e-3 * t);
+        assert_close(integrator.charge().get::<coulomb>(), 1e-3 * 100.0 / 2.0);
+        assert_close(integrator.energy().get::<joule>(), 2.0 * 1e-3 * 100.0 / 2.0);
+
+        integrator.reset();
+        assert_eq!(integrator.charge().get::<coulomb>(), 0.0);
+    }
+}
@@ -0,0 +1,130 @@
+//! SHA-256 checksums of output files, as tamper-evidence for raw measurement data.
+//!
+//! A sealed file ends with the comment line `# sha256: DIGEST`, the hex encoded digest of all preceding bytes.
+//! CSV readers skip it as comment, see [crate::schema]. `usmu verify FILE...` checks the digests.
+
+use std::{
+    fmt::Write as _,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+const FOOTER: &str = "# sha256:";
+
+/// Hex encoded SHA-256 digest of `data`.
+pub fn digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut out, e| {
+            write!(out, "{e:02x}").unwrap();
+            out
+        })
+}
+
+/// Append the checksum footer to the file at `path`.
+pub fn seal(path: &Path) -> Result<()> {
+    let mut data = std::fs::read(path)?;
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    // the footer starts on a line of its own
+    if !data.is_empty() && !data.ends_with(b"\n") {
+        writeln!(file)?;
+        data.push(b'\n');
+    }
+    writeln!(file, "{FOOTER} {}", digest(&data))?;
+    Ok(())
+}
+
+/// Check the checksum footer of `data`, fails if it is missing or does not match.
+pub fn verify(data: &[u8]) -> Result<()> {
+    let content = data.strip_suffix(b"\n").unwrap_or(data);
+    let start = content
+        .iter()
+        .rposition(|&e| e == b'\n')
+        .map_or(0, |e| e + 1);
+    let footer = std::str::from_utf8(&content[start..])
+        .ok()
+        .and_then(|e| e.trim_end_matches('\r').strip_prefix(FOOTER))
+        .ok_or_else(|| Error::Data("The file has no checksum.".to_string()))?;
+    let expected = footer.trim();
+    let actual = digest(&data[..start]);
+    if expected != actual {
+        Err(Error::Data(format!(
+            "Checksum mismatch, the file states {expected}, but its data has {actual}."
+        )))?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ChecksumParameter {
+    /// Append a SHA-256 checksum of the data to the output file, check it with `usmu verify`.
+    #[arg(long, requires = "output")]
+    pub checksum: bool,
+}
+
+impl ChecksumParameter {
+    /// Seal `output`, if requested.
+    pub fn seal(&self, output: Option<&PathBuf>) -> Result<()> {
+        match output {
+            Some(output) if self.checksum => seal(output),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct VerifyArguments {
+    /// Files with a checksum footer.
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+impl VerifyArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut failed = 0;
+        for file in &self.files {
+            let result: Result<()> = std::fs::read(file)
+                .map_err(Into::into)
+                .and_then(|e| verify(&e));
+            match result {
+                Ok(()) => println!("{}: OK", file.display()),
+                Err(e) => {
+                    println!("{}: FAILED, {e}", file.display());
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            Err(Error::Data(format!(
+                "{failed} of {} files failed.",
+                self.files.len()
+            )))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_modifications() {
+        let path = std::env::temp_dir().join(format!("usmu-checksum-{}.csv", std::process::id()));
+        std::fs::write(&path, "voltage,current\n1.0,0.001\n").unwrap();
+        seal(&path).unwrap();
+        let sealed = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        verify(&sealed).unwrap();
+        let tampered = String::from_utf8(sealed).unwrap().replace("0.001", "0.002");
+        assert!(verify(tampered.as_bytes()).is_err());
+        assert!(verify(b"voltage,current\n").is_err());
+    }
+}
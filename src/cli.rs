@@ -0,0 +1,83 @@
+use clap::{Parser, Subcommand};
+
+use crate::Result;
+
+#[derive(Debug, Parser)]
+pub struct CommandlineArguments {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run analyses on saved sweep results and summarize them as Markdown or JSON.
+    Analyze(crate::analyze::AnalyzeArguments),
+
+    /// Monitor the device and capture the samples around a trigger condition, e.g. intermittent shorts.
+    Capture(crate::capture::CaptureArguments),
+
+    /// Send raw DAC and ADC commands for hardware bring-up, requires --i-know-what-im-doing.
+    Debug(crate::debug::DebugArguments),
+
+    /// Back up and restore the calibration stored in the EEPROM.
+    #[cfg(any(feature = "config", feature = "json"))]
+    Eeprom(crate::eeprom::EepromArguments),
+
+    /// Test the device against known fixtures and report the results as JUnit XML.
+    HilTest(crate::hil_test::HilTestArguments),
+
+    /// Sweep the current limit DAC code against a load and measure the clamped current.
+    IlimSweep(crate::ilim_sweep::IlimSweepArguments),
+
+    /// Take repeated readings at a fixed bias and summarize their distribution.
+    Histogram(crate::statistics::HistogramArguments),
+
+    /// Sample the device at a fixed bias in uniform intervals.
+    Monitor(crate::monitor::MonitorArguments),
+
+    /// Append an operator note to the event log of a measurement campaign.
+    #[cfg(feature = "event-log")]
+    Note(crate::event_log::NoteArguments),
+
+    /// Serve a connected uSMU to remote clients.
+    #[cfg(feature = "server")]
+    Serve(crate::server::ServeArguments),
+
+    /// Continuously sample a connected uSMU and serve the results as Prometheus metrics.
+    #[cfg(feature = "exporter")]
+    Exporter(crate::exporter::ExporterArguments),
+
+    /// Track the key parameters of a device under test across the runs in a results store.
+    #[cfg(feature = "sqlite")]
+    Trend(crate::store::TrendArguments),
+
+    /// Check the checksums of output files.
+    #[cfg(feature = "checksum")]
+    Verify(crate::checksum::VerifyArguments),
+}
+
+impl CommandlineArguments {
+    pub fn run(&self) -> Result<()> {
+        match &self.command {
+            Command::Analyze(arguments) => arguments.run(),
+            Command::Capture(arguments) => arguments.run(),
+            Command::Debug(arguments) => arguments.run(),
+            #[cfg(any(feature = "config", feature = "json"))]
+            Command::Eeprom(arguments) => arguments.run(),
+            Command::Histogram(arguments) => arguments.run(),
+            Command::HilTest(arguments) => arguments.run(),
+            Command::IlimSweep(arguments) => arguments.run(),
+            Command::Monitor(arguments) => arguments.run(),
+            #[cfg(feature = "event-log")]
+            Command::Note(arguments) => arguments.run(),
+            #[cfg(feature = "server")]
+            Command::Serve(arguments) => arguments.run(),
+            #[cfg(feature = "exporter")]
+            Command::Exporter(arguments) => arguments.run(),
+            #[cfg(feature = "sqlite")]
+            Command::Trend(arguments) => arguments.run(),
+            #[cfg(feature = "checksum")]
+            Command::Verify(arguments) => arguments.run(),
+        }
+    }
+}
@@ -1,10 +1,90 @@
-use scpi_client::{
-    EmptyResponse, Result, ScpiDeserialize, ScpiSerialize, impl_scpi_request, impl_scpi_serialize,
-    match_literal,
-};
+use std::str::FromStr;
+
+use scpi_client::{impl_scpi_request, impl_scpi_serialize};
 use uom::si::electric_current::{ampere, milliampere};
 
-use crate::{Current, Voltage, volt};
+use crate::{
+    Current, Error, Voltage,
+    scpi::{EmptyResponse, Result, ScpiDeserialize, ScpiSerialize, check_empty, match_literal},
+    volt,
+};
+
+/// The line transmitted to the device for `command`, including the terminating newline.
+pub fn to_wire(command: &impl ScpiSerialize) -> String {
+    let mut out = String::new();
+    out.reserve(32);
+
+    command.serialize(&mut out);
+    out.push('\n');
+
+    assert!(out.is_ascii());
+    out
+}
+
+/// Parse a complete response `line` received from the device, including the terminating newline.
+///
+/// Fails with [Error::InvalidResponse] at the data that could not be parsed.
+pub fn from_wire<Response: ScpiDeserialize>(line: &str) -> crate::Result<Response> {
+    let mut data = line;
+    let parse = |data: &mut &str| {
+        let response = Response::deserialize(data)?;
+        match_literal(data, "\n")?;
+        check_empty(data)?;
+        Ok(response)
+    };
+    parse(&mut data).map_err(|source| Error::InvalidResponse {
+        token: token(data).to_string(),
+        source,
+    })
+}
+
+/// The leading characters of `input` that may be part of a number.
+fn token(input: &str) -> &str {
+    let end = input
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+        .unwrap_or(input.len());
+    &input[..end]
+}
+
+/// SCPI's representation of positive infinity, negative infinity is its negation.
+const SCPI_INFINITY: f32 = 9.9e37;
+/// SCPI's representation of not-a-number.
+const SCPI_NAN: f32 = 9.91e37;
+
+/// Parse a floating point number as formatted by the firmware builds, e.g. `0.5`, `+5.0E-1`, `.5`, `nan` or `-inf`,
+/// including SCPI's `9.9E37` for infinity and `9.91E37` for not-a-number.
+///
+/// On failure, `input` is left at the number.
+pub fn float(input: &mut &str) -> Result<f32> {
+    let token = token(input);
+    match token.parse::<f32>() {
+        Ok(value) => {
+            *input = &input[token.len()..];
+            Ok(match value {
+                SCPI_NAN => f32::NAN,
+                SCPI_INFINITY => f32::INFINITY,
+                value if value == -SCPI_INFINITY => f32::NEG_INFINITY,
+                value => value,
+            })
+        }
+        // the parser of `scpi-client` reports the failure
+        Err(_) => f32::deserialize(&mut &**input),
+    }
+}
+
+/// Parse an integer, optionally with a leading `+`.
+///
+/// On failure, `input` is left at the number.
+pub fn integer<T: FromStr + ScpiDeserialize>(input: &mut &str) -> Result<T> {
+    let token = token(input);
+    match token.strip_prefix('+').unwrap_or(token).parse::<T>() {
+        Ok(value) => {
+            *input = &input[token.len()..];
+            Ok(value)
+        }
+        Err(_) => T::deserialize(&mut &**input),
+    }
+}
 
 struct FormatVolt(Voltage);
 
@@ -38,14 +118,17 @@ impl From<Current> for FormatMilliAmpere {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct EnableRequest;
 impl_scpi_serialize!(EnableRequest, ["CH1:ENA"]);
 impl_scpi_request!(EnableRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct DisableRequest;
 impl_scpi_serialize!(DisableRequest, ["CH1:DIS"]);
 impl_scpi_request!(DisableRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetCurrentLimitRequest {
     limit: Current,
 }
@@ -57,9 +140,19 @@ impl SetCurrentLimitRequest {
     ///
     /// Panics, if limit is below zero or exceeds 40mA (the maximum current capability of the SMU).
     pub fn new(limit: Current) -> Self {
-        assert!(limit.is_sign_positive());
-        assert!(limit.get::<milliampere>() <= 40.0);
-        Self { limit }
+        Self::try_new(limit).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [Self::new], but fails with [Error::InvalidParameter] instead of panicking.
+    pub fn try_new(limit: Current) -> crate::Result<Self> {
+        let valid = limit.is_sign_positive() && limit.get::<milliampere>() <= 40.0;
+        if !valid {
+            Err(Error::InvalidParameter(format!(
+                "Invalid current limit {} mA, only 0 - 40 mA are valid.",
+                limit.get::<milliampere>()
+            )))?;
+        }
+        Ok(Self { limit })
     }
 }
 impl_scpi_serialize!(
@@ -68,57 +161,70 @@ impl_scpi_serialize!(
 );
 impl_scpi_request!(SetCurrentLimitRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetVoltageRequest {
     pub voltage: Voltage,
 }
 impl_scpi_serialize!(SetVoltageRequest, ["CH1:VOL ", voltage as FormatVolt]);
 impl_scpi_request!(SetVoltageRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct MeasureRequest {
     pub voltage: Voltage,
 }
 impl_scpi_serialize!(MeasureRequest, ["CH1:MEA:VOL ", voltage as FormatVolt]);
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MeasureResponse {
     pub voltage: Voltage,
     pub current: Current,
 }
 impl ScpiDeserialize for MeasureResponse {
     fn deserialize(input: &mut &str) -> Result<Self> {
-        let voltage = f32::deserialize(input)?;
+        let voltage = float(input)?;
         let voltage = Voltage::new::<volt>(voltage);
         match_literal(input, ",")?;
-        let current = f32::deserialize(input)?;
+        let current = float(input)?;
         let current = Current::new::<ampere>(current);
         Ok(Self { voltage, current })
     }
 }
 impl_scpi_request!(MeasureRequest, MeasureResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetOverSampleRateRequest {
     pub samples: u16,
 }
 impl_scpi_serialize!(SetOverSampleRateRequest, ["CH1:OSR ", samples]);
 impl_scpi_request!(SetOverSampleRateRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetVoltageDacRequest {
     pub level: u16,
 }
 impl_scpi_serialize!(SetVoltageDacRequest, ["DAC ", level]);
 impl_scpi_request!(SetVoltageDacRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct DifferentialConversionRequest {
     channel: u8,
 }
 impl_scpi_serialize!(DifferentialConversionRequest, ["ADC ", channel]);
 
 impl DifferentialConversionRequest {
+    /// Panics, if `channel` is not 0 or 2.
     pub fn new(channel: u8) -> Self {
-        assert!(
-            channel == 0 || channel == 2,
-            "Differential measurements can only be performed on channel zero or channel two."
-        );
-        Self { channel }
+        Self::try_new(channel).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [Self::new], but fails with [Error::InvalidParameter] instead of panicking.
+    pub fn try_new(channel: u8) -> crate::Result<Self> {
+        if channel != 0 && channel != 2 {
+            Err(Error::InvalidParameter(format!(
+                "Invalid channel '{channel}', differential measurements can only be performed on channel zero or channel two."
+            )))?;
+        }
+        Ok(Self { channel })
     }
 
     pub fn channel_zero() -> Self {
@@ -130,13 +236,14 @@ impl DifferentialConversionRequest {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct DifferentialConversionResponse {
     pub value: u16,
 }
 
 impl ScpiDeserialize for DifferentialConversionResponse {
     fn deserialize(input: &mut &str) -> Result<Self> {
-        let value = u16::deserialize(input)?;
+        let value = integer(input)?;
         Ok(Self { value })
     }
 }
@@ -146,23 +253,35 @@ impl_scpi_request!(
     DifferentialConversionResponse
 );
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetCurrentLimitDacRequest {
     pub level: u16,
 }
 impl SetCurrentLimitDacRequest {
     /// Panics if the value exceeds the 12 least significant bits.
     pub fn new(level: u16) -> Self {
-        assert!(level >> 12 == 0);
-        Self { level }
+        Self::try_new(level).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [Self::new], but fails with [Error::InvalidParameter] instead of panicking.
+    pub fn try_new(level: u16) -> crate::Result<Self> {
+        if level >> 12 != 0 {
+            Err(Error::InvalidParameter(format!(
+                "Invalid current limit DAC level {level}, only 12 bit are valid."
+            )))?;
+        }
+        Ok(Self { level })
     }
 }
 impl_scpi_serialize!(SetCurrentLimitDacRequest, ["ILIM ", level]);
 impl_scpi_request!(SetCurrentLimitDacRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct EnableVoltageCalibrationModeRequest;
 impl_scpi_serialize!(EnableVoltageCalibrationModeRequest, ["CH1:VCAL"]);
 impl_scpi_request!(EnableVoltageCalibrationModeRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LockCurrentRangeAndClearCalibrationRequest {
     pub range: CurrentRange,
 }
@@ -172,7 +291,7 @@ impl_scpi_serialize!(
 );
 impl_scpi_request!(LockCurrentRangeAndClearCalibrationRequest, EmptyResponse);
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct EepromAddress {
     pub value: u8,
 }
@@ -190,6 +309,7 @@ impl_scpi_serialize!(EepromAddress, [value]);
 ///
 /// [firmware]: https://github.com/joeltroughton/uSMU/blob/3fdb82477a9f5ed1c374189c9d4eb9d7cdb289f6/Firmware/For%20HW%20version%2010/Core/Src/main.c#L727
 /// [doc]: https://github.com/joeltroughton/uSMU/tree/main/Firmware/For%20HW%20version%2010
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteEepromRequest {
     pub address: EepromAddress,
     pub value: f32,
@@ -197,41 +317,47 @@ pub struct WriteEepromRequest {
 impl_scpi_serialize!(WriteEepromRequest, ["WRITE ", address, " ", value]);
 impl_scpi_request!(WriteEepromRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ReadEepromRequest {
     pub address: EepromAddress,
 }
 impl_scpi_serialize!(ReadEepromRequest, ["*READ ", address]);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ReadEepromResponse {
     pub value: f32,
 }
 impl ScpiDeserialize for ReadEepromResponse {
     fn deserialize(input: &mut &str) -> Result<Self> {
-        let value = f32::deserialize(input)?;
+        let value = float(input)?;
         Ok(Self { value })
     }
 }
 impl_scpi_request!(ReadEepromRequest, ReadEepromResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ResetRequest;
 impl_scpi_serialize!(ResetRequest, ["*RST"]);
 impl_scpi_request!(ResetRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct IdentityRequest;
 impl_scpi_serialize!(IdentityRequest, ["*IDN?"]);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct IdentityResponse {
     pub uid: u32,
 }
 impl ScpiDeserialize for IdentityResponse {
     fn deserialize(input: &mut &str) -> Result<Self> {
         match_literal(input, "uSMU version 1.0 ID:")?;
-        let uid = u32::deserialize(input)?;
+        let uid = integer(input)?;
         Ok(IdentityResponse { uid })
     }
 }
 impl_scpi_request!(IdentityRequest, IdentityResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteVoltageDacCalibrationRequest {
     pub slope: f32,
     pub intercept: f32,
@@ -242,6 +368,7 @@ impl_scpi_serialize!(
 );
 impl_scpi_request!(WriteVoltageDacCalibrationRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteVoltageAdcCalibrationRequest {
     pub slope: f32,
     pub intercept: f32,
@@ -252,6 +379,7 @@ impl_scpi_serialize!(
 );
 impl_scpi_request!(WriteVoltageAdcCalibrationRequest, EmptyResponse);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CurrentRange {
     value: u8,
 }
@@ -260,14 +388,25 @@ impl_scpi_serialize!(CurrentRange, [value]);
 impl CurrentRange {
     /// Panics, if `value` is not a valid current range (1, 2, 3 or 4).
     pub fn new(value: u8) -> Self {
-        assert!(
-            (1..=4).contains(&value),
-            "Invalid current range '{value}', only 1 - 4 are valid."
-        );
-        Self { value }
+        Self::try_new(value).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [Self::new], but fails with [Error::InvalidParameter] instead of panicking.
+    pub fn try_new(value: u8) -> crate::Result<Self> {
+        if !(1..=4).contains(&value) {
+            Err(Error::InvalidParameter(format!(
+                "Invalid current range '{value}', only 1 - 4 are valid."
+            )))?;
+        }
+        Ok(Self { value })
+    }
+
+    pub fn value(&self) -> u8 {
+        self.value
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteCurrentLimitCalibrationRequest {
     pub range: CurrentRange,
     pub slope: f32,
@@ -279,6 +418,7 @@ impl_scpi_serialize!(
 );
 impl_scpi_request!(WriteCurrentLimitCalibrationRequest, EmptyResponse);
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteCurrentLimitDacCalibrationRequest {
     pub slope: f32,
     pub intercept: f32,
@@ -291,14 +431,38 @@ impl_scpi_request!(WriteCurrentLimitDacCalibrationRequest, EmptyResponse);
 
 #[cfg(test)]
 mod tests {
-    use scpi_client::{ScpiDeserialize, ScpiSerialize, check_empty};
+    use proptest::prelude::*;
 
     use crate::{
-        Current,
-        commands::{SetCurrentLimitDacRequest, SetCurrentLimitRequest},
+        Current, Error, Voltage, ampere,
+        commands::{
+            CurrentRange, DifferentialConversionRequest, DifferentialConversionResponse,
+            IdentityResponse, MeasureResponse, ReadEepromResponse, SetCurrentLimitDacRequest,
+            SetCurrentLimitRequest, SetVoltageRequest, from_wire,
+        },
         milliampere,
+        scpi::{ScpiDeserialize, ScpiSerialize, check_empty},
+        volt,
     };
 
+    fn serialize(value: &impl ScpiSerialize) -> String {
+        let mut buffer = String::new();
+        value.serialize(&mut buffer);
+        buffer
+    }
+
+    fn deserialize<T: ScpiDeserialize>(data: &str) -> T {
+        let mut data = data;
+        let value = T::deserialize(&mut data).unwrap();
+        check_empty(data).unwrap();
+        value
+    }
+
+    /// Finite values across the whole range of `f32`, excluding subnormals.
+    fn finite() -> impl Strategy<Value = f32> {
+        prop::num::f32::NORMAL | prop::num::f32::ZERO
+    }
+
     #[test]
     fn float_conversion_is_reasonably_lossless() {
         // The finest measurement resolution of the uSMU is 10 nanoamps.
@@ -312,6 +476,38 @@ mod tests {
         assert_eq!(decoded, one_nano_amp);
     }
 
+    #[test]
+    fn parses_varying_number_formats() {
+        let response: MeasureResponse = from_wire("+5.0E-1,1.23e-3\n").unwrap();
+        assert_eq!(response.voltage, Voltage::new::<volt>(0.5));
+        assert_eq!(response.current, Current::new::<ampere>(0.00123));
+
+        let response: MeasureResponse = from_wire("-.5,5.\n").unwrap();
+        assert_eq!(response.voltage, Voltage::new::<volt>(-0.5));
+        assert_eq!(response.current, Current::new::<ampere>(5.0));
+
+        let response: MeasureResponse = from_wire("NaN,-inf\n").unwrap();
+        assert!(response.voltage.is_nan());
+        assert_eq!(response.current.value, f32::NEG_INFINITY);
+
+        let response: MeasureResponse = from_wire("9.91E37,-9.9E37\n").unwrap();
+        assert!(response.voltage.is_nan());
+        assert_eq!(response.current.value, f32::NEG_INFINITY);
+
+        let response: IdentityResponse = from_wire("uSMU version 1.0 ID:+42\n").unwrap();
+        assert_eq!(response.uid, 42);
+        let response: DifferentialConversionResponse = from_wire("+2047\n").unwrap();
+        assert_eq!(response.value, 2047);
+    }
+
+    #[test]
+    fn malformed_numbers_are_reported() {
+        let error = from_wire::<MeasureResponse>("0.5,1.2.3\n").unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse { token, .. } if token == "1.2.3"));
+        let error = from_wire::<DifferentialConversionResponse>("2047.5\n").unwrap_err();
+        assert!(matches!(error, Error::InvalidResponse { token, .. } if token == "2047.5"));
+    }
+
     #[test]
     #[should_panic]
     fn current_limit_panics_for_values_below_zero() {
@@ -329,4 +525,72 @@ mod tests {
     fn current_limit_dac_cannot_exceed_12_bit() {
         SetCurrentLimitDacRequest::new(0b0001_0000_0000_0000);
     }
+
+    #[test]
+    fn invalid_parameters_are_reported() {
+        fn invalid<T>(result: crate::Result<T>) -> bool {
+            matches!(result, Err(Error::InvalidParameter(_)))
+        }
+        assert!(invalid(SetCurrentLimitRequest::try_new(Current::new::<
+            milliampere,
+        >(-1.0))));
+        assert!(invalid(SetCurrentLimitRequest::try_new(Current::new::<
+            milliampere,
+        >(f32::NAN))));
+        assert!(invalid(SetCurrentLimitDacRequest::try_new(4096)));
+        assert!(invalid(DifferentialConversionRequest::try_new(1)));
+        assert!(invalid(CurrentRange::try_new(0)));
+        assert!(CurrentRange::try_new(4).is_ok());
+    }
+
+    proptest! {
+        #[test]
+        fn float_round_trip(value in finite()) {
+            prop_assert_eq!(deserialize::<f32>(&serialize(&value)), value);
+        }
+
+        #[test]
+        fn voltage_serialization_round_trip(value in finite()) {
+            let request = SetVoltageRequest { voltage: Voltage::new::<volt>(value) };
+            let serialized = serialize(&request);
+            let argument = serialized.strip_prefix("CH1:VOL ").unwrap();
+            prop_assert_eq!(deserialize::<f32>(argument), value);
+        }
+
+        #[test]
+        fn current_limit_serialization_round_trip(value in 0.0f32..=40.0) {
+            let request = SetCurrentLimitRequest::new(Current::new::<milliampere>(value));
+            let serialized = serialize(&request);
+            let argument = serialized.strip_prefix("CH1:CUR ").unwrap();
+            prop_assert_eq!(deserialize::<f32>(argument), request.limit.get::<milliampere>());
+        }
+
+        #[test]
+        fn measure_response_round_trip(voltage in finite(), current in finite()) {
+            let response = format!("{},{}", serialize(&voltage), serialize(&current));
+            let expected = MeasureResponse {
+                voltage: Voltage::new::<volt>(voltage),
+                current: Current::new::<ampere>(current),
+            };
+            prop_assert_eq!(deserialize::<MeasureResponse>(&response), expected);
+        }
+
+        #[test]
+        fn identity_response_round_trip(uid: u32) {
+            let response = format!("uSMU version 1.0 ID:{}", serialize(&uid));
+            prop_assert_eq!(deserialize::<IdentityResponse>(&response), IdentityResponse { uid });
+        }
+
+        #[test]
+        fn read_eeprom_response_round_trip(value in finite()) {
+            let response = deserialize::<ReadEepromResponse>(&serialize(&value));
+            prop_assert_eq!(response, ReadEepromResponse { value });
+        }
+
+        #[test]
+        fn differential_conversion_response_round_trip(value: u16) {
+            let response = deserialize::<DifferentialConversionResponse>(&serialize(&value));
+            prop_assert_eq!(response, DifferentialConversionResponse { value });
+        }
+    }
 }
@@ -0,0 +1,131 @@
+//! `usmu debug`, the raw DAC and ADC commands for hardware bring-up and firmware development.
+//!
+//! The commands bypass the calibration, the safety limits and the interlock,
+//! hence they require `--i-know-what-im-doing`.
+
+use clap::{
+    Parser, Subcommand,
+    builder::{PossibleValuesParser, TypedValueParser},
+};
+
+use crate::{Error, MicroSmu, Result, record_iv_curve::SmuConnectionParameter};
+
+#[derive(Debug, Parser)]
+pub struct DebugArguments {
+    /// Confirm sending raw commands, which bypass the calibration, the safety limits and the interlock.
+    #[arg(long, global = true)]
+    pub i_know_what_im_doing: bool,
+
+    #[command(subcommand)]
+    pub command: DebugCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DebugCommand {
+    /// Set the voltage DAC to a raw code.
+    Dac(DacArguments),
+
+    /// Set the current limit DAC to a raw 12 bit code.
+    Ilim(IlimArguments),
+
+    /// Read a raw differential conversion of the ADC channels 0 and 1, or 2 and 3.
+    Adc(AdcArguments),
+}
+
+#[derive(Debug, Parser)]
+pub struct DacArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    pub code: u16,
+}
+
+#[derive(Debug, Parser)]
+pub struct IlimArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    #[arg(value_parser = clap::value_parser!(u16).range(0..4096))]
+    pub code: u16,
+}
+
+#[derive(Debug, Parser)]
+pub struct AdcArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The first channel of the differential pair, 0 or 2.
+    #[arg(value_parser = PossibleValuesParser::new(["0", "2"]).map(|e| e.parse::<u8>().unwrap()))]
+    pub channel: u8,
+}
+
+impl DebugCommand {
+    fn connection_parameter(&self) -> &SmuConnectionParameter {
+        match self {
+            DebugCommand::Dac(arguments) => &arguments.connection_parameter,
+            DebugCommand::Ilim(arguments) => &arguments.connection_parameter,
+            DebugCommand::Adc(arguments) => &arguments.connection_parameter,
+        }
+    }
+
+    /// Send the raw command to `smu`, returning the conversion result of [DebugCommand::Adc].
+    pub fn execute(&self, smu: &mut MicroSmu) -> Result<Option<u16>> {
+        match self {
+            DebugCommand::Dac(arguments) => smu.set_voltage_dac(arguments.code)?,
+            DebugCommand::Ilim(arguments) => smu.set_current_limit_dac(arguments.code)?,
+            DebugCommand::Adc(arguments) => {
+                return Ok(Some(
+                    smu.manual_measure_differential_channel(arguments.channel)?,
+                ));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl DebugArguments {
+    pub fn run(&self) -> Result<()> {
+        if !self.i_know_what_im_doing {
+            Err(Error::Configuration("Raw commands bypass the calibration, the safety limits and the interlock \
+                 and may damage the device or the device under test. Pass --i-know-what-im-doing to send them.".to_string()))?;
+        }
+        let mut smu = self.command.connection_parameter().connect()?;
+        match self.command.execute(&mut smu)? {
+            Some(value) => println!("{value}"),
+            None => eprintln!("Sent."),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::FakeSerialPort;
+
+    use super::*;
+
+    #[test]
+    fn sends_raw_commands() {
+        let parse =
+            |arguments: &[&str]| DebugArguments::try_parse_from(["debug"].iter().chain(arguments));
+        assert!(parse(&["ilim", "4096"]).is_err());
+        assert!(parse(&["adc", "1"]).is_err());
+        let unconfirmed = parse(&["dac", "1000", "--simulate", "resistor"]).unwrap();
+        assert!(unconfirmed.run().is_err());
+
+        let port = FakeSerialPort::new();
+        port.expect("DAC 1000")
+            .expect("ILIM 4095")
+            .expect_query("ADC 2", "1234");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        for (arguments, expected) in [
+            (["dac", "1000"], None),
+            (["ilim", "4095"], None),
+            (["adc", "2"], Some(1234)),
+        ] {
+            let arguments = parse(&arguments).unwrap();
+            assert_eq!(arguments.command.execute(&mut smu).unwrap(), expected);
+        }
+        port.verify();
+    }
+}
@@ -0,0 +1,275 @@
+//! On-the-fly decimation of monitored samples, so long acquisitions produce manageable files.
+//!
+//! Either every Nth sample is kept, or the samples are aggregated per time bucket to their minimum,
+//! mean and maximum, which preserves the envelope, e.g. short spikes, unlike keeping single samples.
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{
+    Current, Result, Time, Voltage, ampere, commands::MeasureResponse, monitor::MonitorSample,
+    schema, second, volt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decimation {
+    /// Keep the first and then every Nth sample.
+    KeepEvery(usize),
+    /// Aggregate the samples per time bucket of this width, aligned to the start of the monitoring.
+    Bucket(Time),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope<T> {
+    pub minimum: T,
+    pub mean: T,
+    pub maximum: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorBucket {
+    /// Start of the time bucket.
+    pub start: Time,
+    /// Number of samples in the bucket.
+    pub count: usize,
+    pub voltage: Envelope<Voltage>,
+    pub current: Envelope<Current>,
+    /// Offsets of the last auto-zero within the bucket, `None` without auto-zero.
+    pub offset: Option<MeasureResponse>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decimated {
+    Sample(MonitorSample),
+    Bucket(MonitorBucket),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    index: u64,
+    count: usize,
+    voltage: (f32, f64, f32),
+    current: (f32, f64, f32),
+    offset: Option<MeasureResponse>,
+}
+
+impl Accumulator {
+    fn new(index: u64) -> Self {
+        Self {
+            index,
+            count: 0,
+            voltage: (f32::INFINITY, 0.0, f32::NEG_INFINITY),
+            current: (f32::INFINITY, 0.0, f32::NEG_INFINITY),
+            offset: None,
+        }
+    }
+
+    fn add(&mut self, sample: &MonitorSample) {
+        fn add((minimum, sum, maximum): &mut (f32, f64, f32), value: f32) {
+            *minimum = minimum.min(value);
+            *sum += f64::from(value);
+            *maximum = maximum.max(value);
+        }
+
+        self.count += 1;
+        add(&mut self.voltage, sample.voltage.get::<volt>());
+        add(&mut self.current, sample.current.get::<ampere>());
+        self.offset = sample.offset.or(self.offset);
+    }
+
+    fn bucket(&self, width: Time) -> MonitorBucket {
+        fn envelope<T>(
+            (minimum, sum, maximum): (f32, f64, f32),
+            count: usize,
+            new: impl Fn(f32) -> T,
+        ) -> Envelope<T> {
+            Envelope {
+                minimum: new(minimum),
+                mean: new((sum / count as f64) as f32),
+                maximum: new(maximum),
+            }
+        }
+
+        MonitorBucket {
+            start: width * self.index as f32,
+            count: self.count,
+            voltage: envelope(self.voltage, self.count, Voltage::new::<volt>),
+            current: envelope(self.current, self.count, Current::new::<ampere>),
+            offset: self.offset,
+        }
+    }
+}
+
+/// Decimates a stream of samples, see [Decimation].
+#[derive(Debug, Clone)]
+pub struct Decimator {
+    decimation: Decimation,
+    seen: usize,
+    bucket: Option<Accumulator>,
+}
+
+impl Decimator {
+    /// Panics, if the decimation keeps every 0th sample or the bucket width is not positive.
+    pub fn new(decimation: Decimation) -> Self {
+        match decimation {
+            Decimation::KeepEvery(n) => assert!(n > 0, "Cannot keep every 0th sample."),
+            Decimation::Bucket(width) => {
+                assert!(
+                    width.get::<second>() > 0.0,
+                    "The bucket width must be positive."
+                )
+            }
+        }
+        Self {
+            decimation,
+            seen: 0,
+            bucket: None,
+        }
+    }
+
+    /// Add the next sample, returns the decimated sample or the completed bucket, if any.
+    pub fn push(&mut self, sample: &MonitorSample) -> Option<Decimated> {
+        match self.decimation {
+            Decimation::KeepEvery(n) => {
+                let keep = self.seen.is_multiple_of(n);
+                self.seen += 1;
+                keep.then_some(Decimated::Sample(*sample))
+            }
+            Decimation::Bucket(width) => {
+                let index = (sample.time.get::<second>() / width.get::<second>()).max(0.0) as u64;
+                let completed = match self.bucket {
+                    Some(bucket) if bucket.index != index => {
+                        self.bucket = Some(Accumulator::new(index));
+                        Some(Decimated::Bucket(bucket.bucket(width)))
+                    }
+                    Some(_) => None,
+                    None => {
+                        self.bucket = Some(Accumulator::new(index));
+                        None
+                    }
+                };
+                if let Some(bucket) = self.bucket.as_mut() {
+                    bucket.add(sample);
+                }
+                completed
+            }
+        }
+    }
+
+    /// The last incomplete bucket, if any.
+    pub fn finish(self) -> Option<Decimated> {
+        match self.decimation {
+            Decimation::KeepEvery(_) => None,
+            Decimation::Bucket(width) => self
+                .bucket
+                .map(|bucket| Decimated::Bucket(bucket.bucket(width))),
+        }
+    }
+}
+
+/// Decimate all `samples` at once.
+pub fn decimate(decimation: Decimation, samples: &[MonitorSample]) -> Vec<Decimated> {
+    let mut decimator = Decimator::new(decimation);
+    let mut decimated: Vec<_> = samples.iter().filter_map(|e| decimator.push(e)).collect();
+    decimated.extend(decimator.finish());
+    decimated
+}
+
+#[derive(Serialize)]
+pub(crate) struct BucketRow {
+    time: f32,
+    count: usize,
+    voltage_min: f32,
+    voltage_mean: f32,
+    voltage_max: f32,
+    current_min: f32,
+    current_mean: f32,
+    current_max: f32,
+    voltage_offset: Option<f32>,
+    current_offset: Option<f32>,
+}
+
+impl From<&MonitorBucket> for BucketRow {
+    fn from(bucket: &MonitorBucket) -> Self {
+        Self {
+            time: bucket.start.get::<second>(),
+            count: bucket.count,
+            voltage_min: bucket.voltage.minimum.get::<volt>(),
+            voltage_mean: bucket.voltage.mean.get::<volt>(),
+            voltage_max: bucket.voltage.maximum.get::<volt>(),
+            current_min: bucket.current.minimum.get::<ampere>(),
+            current_mean: bucket.current.mean.get::<ampere>(),
+            current_max: bucket.current.maximum.get::<ampere>(),
+            voltage_offset: bucket.offset.map(|e| e.voltage.get::<volt>()),
+            current_offset: bucket.offset.map(|e| e.current.get::<ampere>()),
+        }
+    }
+}
+
+/// Write the buckets as CSV with the columns `time` (start of the bucket in seconds), `count`,
+/// the `_min`, `_mean` and `_max` of `voltage` and `current`, and the `voltage_offset` and `current_offset`.
+pub fn write_csv(buckets: &[MonitorBucket], output: impl Write) -> Result<()> {
+    let mut writer = schema::csv_writer(output)?;
+    for bucket in buckets {
+        writer.serialize(BucketRow::from(bucket))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: f32, current: f32) -> MonitorSample {
+        MonitorSample {
+            time: Time::new::<second>(time),
+            voltage: Voltage::new::<volt>(1.0),
+            current: Current::new::<ampere>(current),
+            offset: None,
+        }
+    }
+
+    #[test]
+    fn keeps_every_nth_sample() {
+        let samples: Vec<_> = (0..7).map(|e| sample(e as f32, 0.0)).collect();
+        let kept: Vec<_> = decimate(Decimation::KeepEvery(3), &samples)
+            .into_iter()
+            .map(|e| match e {
+                Decimated::Sample(sample) => sample.time.get::<second>(),
+                Decimated::Bucket(_) => panic!("unexpected bucket"),
+            })
+            .collect();
+        assert_eq!(kept, [0.0, 3.0, 6.0]);
+    }
+
+    #[test]
+    fn aggregates_buckets() {
+        let samples = [
+            sample(0.1, 1.0),
+            sample(0.5, 3.0),
+            sample(0.9, 2.0),
+            sample(2.2, 5.0),
+        ];
+        let buckets: Vec<_> = decimate(Decimation::Bucket(Time::new::<second>(1.0)), &samples)
+            .into_iter()
+            .map(|e| match e {
+                Decimated::Bucket(bucket) => bucket,
+                Decimated::Sample(_) => panic!("unexpected sample"),
+            })
+            .collect();
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].start.get::<second>(), 0.0);
+        assert_eq!(buckets[0].count, 3);
+        let current = buckets[0].current;
+        assert_eq!(
+            [current.minimum, current.mean, current.maximum].map(|e| e.get::<ampere>()),
+            [1.0, 2.0, 3.0]
+        );
+        assert_eq!(buckets[1].start.get::<second>(), 2.0);
+        assert_eq!(buckets[1].count, 1);
+    }
+}
@@ -0,0 +1,145 @@
+//! Enumerating the attached uSMUs by their UID, e.g. for setups with several devices.
+//!
+//! [identify_attached] lists the serial ports of the attached uSMUs with their UIDs,
+//! [DeviceManager] keeps all of them open and hands out the handles by UID:
+//!
+//! ```no_run
+//! # fn example() -> usmu::Result<()> {
+//! use usmu::devices::DeviceManager;
+//!
+//! let mut devices = DeviceManager::open_all()?;
+//! for uid in devices.uids().collect::<Vec<_>>() {
+//!     devices.get(uid)?.enable()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serialport::{SerialPort, SerialPortInfo};
+
+use crate::{Error, MicroSmu, Result, find_serial_ports, transport::Transport};
+
+/// The serial port of an attached uSMU.
+#[derive(Debug, Clone)]
+pub struct AttachedDevice {
+    pub port: SerialPortInfo,
+    /// The UID of the device, `None` if it could not be read, e.g. because the port is in use.
+    pub uid: Option<u32>,
+}
+
+/// Find the serial ports of all attached uSMUs and read their UIDs, closing the ports again.
+pub fn identify_attached() -> Result<Vec<AttachedDevice>> {
+    let devices = find_serial_ports()?
+        .into_iter()
+        .map(|port| AttachedDevice {
+            uid: MicroSmu::open(port.clone())
+                .and_then(|mut e| e.get_identity())
+                .ok(),
+            port,
+        })
+        .collect();
+    Ok(devices)
+}
+
+/// Open devices indexed by their UID, see the [module documentation](self).
+pub struct DeviceManager<T = Box<dyn SerialPort>> {
+    devices: BTreeMap<u32, MicroSmu<T>>,
+}
+
+impl DeviceManager {
+    /// Open all attached uSMUs, skipping ports which cannot be opened or identified,
+    /// e.g. because they are in use.
+    pub fn open_all() -> Result<Self> {
+        let devices = find_serial_ports()?
+            .into_iter()
+            .filter_map(|port| MicroSmu::open(port).ok());
+        Self::identify(devices.filter_map(|mut e| Some((e.get_identity().ok()?, e))))
+    }
+}
+
+impl<T: Transport> DeviceManager<T> {
+    /// Manage `devices`, e.g. connected by another [Transport], each identified by its UID.
+    pub fn new(devices: impl IntoIterator<Item = MicroSmu<T>>) -> Result<Self> {
+        let devices = devices
+            .into_iter()
+            .map(|mut e| Ok((e.get_identity()?, e)))
+            .collect::<Result<Vec<_>>>()?;
+        Self::identify(devices)
+    }
+
+    /// Index `devices` by their UID, failing with [Error::Ambiguous] for duplicate UIDs.
+    fn identify(devices: impl IntoIterator<Item = (u32, MicroSmu<T>)>) -> Result<Self> {
+        let mut indexed = BTreeMap::new();
+        for (uid, smu) in devices {
+            if indexed.insert(uid, smu).is_some() {
+                Err(Error::Ambiguous(format!(
+                    "Multiple uSMUs report the UID {uid}."
+                )))?;
+            }
+        }
+        Ok(Self { devices: indexed })
+    }
+
+    /// The UIDs of the managed devices, in ascending order.
+    pub fn uids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.devices.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// The device with the UID `uid`, fails with [Error::NotFound] if it is not managed.
+    pub fn get(&mut self, uid: u32) -> Result<&mut MicroSmu<T>> {
+        self.devices
+            .get_mut(&uid)
+            .ok_or_else(|| Error::NotFound(format!("Could not find uSMU {uid}.")))
+    }
+
+    /// Remove the device with the UID `uid` from the manager, e.g. to move it to another thread.
+    pub fn take(&mut self, uid: u32) -> Result<MicroSmu<T>> {
+        self.devices
+            .remove(&uid)
+            .ok_or_else(|| Error::NotFound(format!("Could not find uSMU {uid}.")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::FakeSerialPort;
+
+    use super::*;
+
+    fn device(uid: u32) -> (FakeSerialPort, MicroSmu) {
+        let port = FakeSerialPort::new();
+        port.expect_query("*IDN?", &format!("uSMU version 1.0 ID:{uid}"));
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(std::time::Duration::ZERO);
+        (port, smu)
+    }
+
+    #[test]
+    fn indexes_the_devices_by_uid() {
+        let (first, first_smu) = device(43);
+        let (second, second_smu) = device(42);
+        second.expect("CH1:ENA");
+        let mut devices = DeviceManager::new([first_smu, second_smu]).unwrap();
+
+        assert_eq!(devices.uids().collect::<Vec<_>>(), [42, 43]);
+        devices.get(42).unwrap().enable().unwrap();
+        assert!(matches!(devices.get(44), Err(Error::NotFound(_))));
+        devices.take(43).unwrap();
+        assert_eq!(devices.len(), 1);
+        first.verify();
+        second.verify();
+
+        let duplicates = DeviceManager::new([device(42).1, device(42).1]);
+        assert!(matches!(duplicates, Err(Error::Ambiguous(_))));
+    }
+}
@@ -0,0 +1,338 @@
+//! `usmu eeprom`, backing up, restoring, comparing and resetting the calibration of a uSMU,
+//! and reading and changing single coefficients by name, see [Coefficient].
+//!
+//! Dumps hold the UID of the device, the time of the dump in seconds since the UNIX epoch and the
+//! [Calibration], as TOML with the `config` feature or as JSON with the `json` feature, chosen by the file extension, e.g.
+//!
+//! ```toml
+//! uid = 42
+//! timestamp = 1755600000.12
+//!
+//! [calibration.voltage_dac]
+//! slope = 1.0
+//! intercept = 0.0
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, MicroSmu, Result,
+    calibration::{Calibration, Coefficient, check_file, load_file, save_file},
+    record_iv_curve::SmuConnectionParameter,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EepromDump {
+    pub uid: u32,
+    /// Time of the dump in seconds since the UNIX epoch.
+    pub timestamp: f64,
+    pub calibration: Calibration,
+}
+
+impl EepromDump {
+    /// Read the calibration of `smu`.
+    pub fn read(smu: &mut MicroSmu) -> Result<Self> {
+        Ok(Self {
+            uid: smu.get_identity()?,
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            calibration: Calibration::read(smu)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        load_file(path, "EEPROM dump")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_file(self, path)
+    }
+
+    /// Write the calibration to `smu`, which must be the device of the dump unless `force`d.
+    pub fn restore(&self, smu: &mut MicroSmu, force: bool) -> Result<()> {
+        let uid = smu.get_identity()?;
+        if uid != self.uid && !force {
+            Err(Error::Configuration(format!(
+                "The dump is of uSMU {}, but uSMU {uid} is connected. Restoring the calibration of another device requires --force.",
+                self.uid
+            )))?;
+        }
+        self.calibration.write(smu)
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct EepromArguments {
+    #[command(subcommand)]
+    pub command: EepromCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum EepromCommand {
+    /// Save the calibration coefficients of the device with its UID to a TOML or JSON file.
+    Dump(DumpArguments),
+
+    /// Write the calibration coefficients of a dump back to the device.
+    Restore(RestoreArguments),
+
+    /// Compare the calibration coefficients of two dumps, or of a dump and the device.
+    Diff(DiffArguments),
+
+    /// Overwrite the calibration with the nominal coefficients, e.g. to recover a corrupted EEPROM.
+    RestoreDefaults(RestoreDefaultsArguments),
+
+    /// Print a single coefficient, e.g. `voltage_adc_slope`.
+    Get(GetArguments),
+
+    /// Change a single coefficient and verify it by reading it back.
+    Set(SetArguments),
+}
+
+#[derive(Debug, Parser)]
+pub struct DumpArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The dump file, `.toml` or `.json`.
+    #[arg(long, short = 'o')]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Parser)]
+pub struct RestoreArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The dump file, `.toml` or `.json`.
+    pub dump: PathBuf,
+
+    /// Restore the dump also to a device with another UID.
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct DiffArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The dump compared against.
+    pub a: PathBuf,
+
+    /// The dump compared, the device if not given.
+    pub b: Option<PathBuf>,
+}
+
+impl DiffArguments {
+    pub fn run(&self) -> Result<()> {
+        let a = EepromDump::load(&self.a)?;
+        let (b, b_name) = match self.b.as_ref() {
+            Some(path) => (EepromDump::load(path)?, path.display().to_string()),
+            None => {
+                let mut smu = self.connection_parameter.connect()?;
+                (EepromDump::read(&mut smu)?, "the device".to_string())
+            }
+        };
+        println!("a: {} (uSMU {})", self.a.display(), a.uid);
+        println!("b: {b_name} (uSMU {})", b.uid);
+
+        let differences = a.calibration.diff(&b.calibration);
+        if differences.is_empty() {
+            println!("The calibrations are identical.");
+        }
+        for difference in differences {
+            println!("{difference}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct RestoreDefaultsArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Do not ask for confirmation.
+    #[arg(long)]
+    pub yes: bool,
+}
+
+impl RestoreDefaultsArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut smu = self.connection_parameter.connect()?;
+        let uid = smu.get_identity()?;
+        if !self.yes && !confirm(uid)? {
+            Err(Error::Aborted(
+                "Not confirmed, the calibration is unchanged.".to_string(),
+            ))?;
+        }
+        Calibration::restore_defaults(&mut smu)?;
+        eprintln!(
+            "Restored the nominal calibration of uSMU {uid}, it needs to be calibrated again."
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct GetArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The coefficient, e.g. `voltage_adc_slope` or `current_adc_range2_intercept`.
+    pub name: Coefficient,
+}
+
+impl GetArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut smu = self.connection_parameter.connect()?;
+        println!("{}", self.name.read(&mut smu)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct SetArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// The coefficient, e.g. `voltage_adc_slope` or `current_adc_range2_intercept`.
+    pub name: Coefficient,
+
+    #[arg(allow_hyphen_values = true)]
+    pub value: f32,
+
+    /// Do not ask for confirmation.
+    #[arg(long)]
+    pub yes: bool,
+}
+
+impl SetArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut smu = self.connection_parameter.connect()?;
+        let uid = smu.get_identity()?;
+        let before = self.name.read(&mut smu)?;
+        eprintln!("{} of uSMU {uid}: {before} -> {}", self.name, self.value);
+        if !self.yes && !confirm(uid)? {
+            Err(Error::Aborted(
+                "Not confirmed, the calibration is unchanged.".to_string(),
+            ))?;
+        }
+        set_coefficient(&mut smu, self.name, self.value)?;
+        eprintln!("Changed {} of uSMU {uid} to {}.", self.name, self.value);
+        Ok(())
+    }
+}
+
+/// Write `value` to `coefficient` and verify it by reading it back,
+/// within the 6 decimal places the firmware reports.
+pub fn set_coefficient(smu: &mut MicroSmu, coefficient: Coefficient, value: f32) -> Result<()> {
+    coefficient.write(smu, value)?;
+    let read = coefficient.read(smu)?;
+    if (read - value).abs() > f32::max(1e-6, value.abs() * 1e-6) {
+        Err(Error::Data(format!(
+            "Verifying {coefficient} failed, wrote {value} but read back {read}."
+        )))?;
+    }
+    Ok(())
+}
+
+/// Ask the operator to confirm overwriting the calibration of uSMU `uid` by typing the UID.
+fn confirm(uid: u32) -> Result<bool> {
+    eprintln!(
+        "This overwrites the calibration of uSMU {uid}, consider saving it with `usmu eeprom dump` first."
+    );
+    eprint!("Type the UID {uid} to confirm: ");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim() == uid.to_string())
+}
+
+impl EepromArguments {
+    pub fn run(&self) -> Result<()> {
+        match &self.command {
+            EepromCommand::Dump(arguments) => {
+                // fail on an unsupported file before talking to the device
+                check_file(&arguments.output)?;
+                let mut smu = arguments.connection_parameter.connect()?;
+                let dump = EepromDump::read(&mut smu)?;
+                dump.save(&arguments.output)?;
+                eprintln!(
+                    "Saved the calibration of uSMU {} to {}.",
+                    dump.uid,
+                    arguments.output.display()
+                );
+            }
+            EepromCommand::Restore(arguments) => {
+                let dump = EepromDump::load(&arguments.dump)?;
+                let mut smu = arguments.connection_parameter.connect()?;
+                dump.restore(&mut smu, arguments.force)?;
+                eprintln!("Restored the calibration of uSMU {}.", dump.uid);
+            }
+            EepromCommand::Diff(arguments) => arguments.run()?,
+            EepromCommand::RestoreDefaults(arguments) => arguments.run()?,
+            EepromCommand::Get(arguments) => arguments.run()?,
+            EepromCommand::Set(arguments) => arguments.run()?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "config"))]
+mod tests {
+    use crate::simulator::{Resistor, SimulatedSmu};
+
+    use super::*;
+
+    #[test]
+    fn dumps_and_restores() {
+        let port = SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(42);
+        let mut smu = MicroSmu::new(Box::new(port));
+        let mut dump = EepromDump::read(&mut smu).unwrap();
+        dump.calibration.voltage_adc.slope = 1.25;
+        dump.calibration.current_adc[3].intercept = -0.5;
+
+        let path = std::env::temp_dir().join(format!("usmu-eeprom-{}.toml", std::process::id()));
+        dump.save(&path).unwrap();
+        let loaded = EepromDump::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, dump);
+
+        loaded.restore(&mut smu, false).unwrap();
+        assert_eq!(Calibration::read(&mut smu).unwrap(), dump.calibration);
+
+        let other = SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(7);
+        let mut other = MicroSmu::new(Box::new(other));
+        assert!(loaded.restore(&mut other, false).is_err());
+        loaded.restore(&mut other, true).unwrap();
+
+        Calibration::restore_defaults(&mut other).unwrap();
+        assert_eq!(
+            Calibration::read(&mut other).unwrap(),
+            Calibration::nominal()
+        );
+    }
+
+    #[test]
+    fn sets_single_coefficients() {
+        let port = SimulatedSmu::new(Resistor::ohm(1000.0));
+        let mut smu = MicroSmu::new(Box::new(port));
+        let coefficient: Coefficient = "voltage_adc_intercept".parse().unwrap();
+        set_coefficient(&mut smu, coefficient, -0.125).unwrap();
+        assert_eq!(coefficient.read(&mut smu).unwrap(), -0.125);
+
+        let mut expected = Calibration::read(&mut smu).unwrap();
+        assert_eq!(expected.voltage_adc.intercept, -0.125);
+        expected.current_limit_dac.slope = 1.5;
+        set_coefficient(&mut smu, "current_limit_dac_slope".parse().unwrap(), 1.5).unwrap();
+        assert_eq!(Calibration::read(&mut smu).unwrap(), expected);
+    }
+}
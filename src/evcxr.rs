@@ -0,0 +1,172 @@
+//! Inline display of sweep results in [evcxr] Jupyter kernels.
+//!
+//! With `use usmu::evcxr::EvcxrDisplay;` in scope,
+//! evaluating sweep results in a notebook cell renders an IV plot and a table of the samples.
+//!
+//! [evcxr]: https://github.com/evcxr/evcxr
+
+use std::fmt::Write;
+
+use crate::{Current, Voltage, ampere, sweep_result::SweepResult, volt};
+
+const WIDTH: f32 = 480.0;
+const HEIGHT: f32 = 320.0;
+const MARGIN: f32 = 64.0;
+
+/// Display hook picked up by evcxr for the result of a cell.
+pub trait EvcxrDisplay {
+    fn evcxr_display(&self);
+}
+
+impl EvcxrDisplay for [(Voltage, Current)] {
+    fn evcxr_display(&self) {
+        println!(
+            "EVCXR_BEGIN_CONTENT text/html\n{}{}\nEVCXR_END_CONTENT",
+            svg_plot(self),
+            html_table(self)
+        );
+    }
+}
+
+impl EvcxrDisplay for Vec<(Voltage, Current)> {
+    fn evcxr_display(&self) {
+        self.as_slice().evcxr_display();
+    }
+}
+
+impl EvcxrDisplay for SweepResult {
+    fn evcxr_display(&self) {
+        self.points().evcxr_display();
+    }
+}
+
+/// Table of the samples, voltage in volt and current in ampere.
+pub fn html_table(samples: &[(Voltage, Current)]) -> String {
+    let mut out = String::from(
+        "<table><thead><tr><th>Voltage (V)</th><th>Current (A)</th></tr></thead><tbody>",
+    );
+    for (voltage, current) in samples {
+        write!(
+            out,
+            "<tr><td>{}</td><td>{:e}</td></tr>",
+            voltage.get::<volt>(),
+            current.get::<ampere>()
+        )
+        .unwrap();
+    }
+    out.push_str("</tbody></table>");
+    out
+}
+
+/// Linear mapping of `range` onto the pixel range `from..to`.
+fn scale((min, max): (f32, f32), from: f32, to: f32) -> impl Fn(f32) -> f32 {
+    // a single distinct value is drawn in the center
+    let span = if max > min { max - min } else { 1.0 };
+    let offset = if max > min { min } else { min - 0.5 };
+    move |value| from + (value - offset) / span * (to - from)
+}
+
+fn range(values: impl Iterator<Item = f32>) -> (f32, f32) {
+    values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), e| {
+        (min.min(e), max.max(e))
+    })
+}
+
+/// IV plot of the samples as SVG.
+pub fn svg_plot(samples: &[(Voltage, Current)]) -> String {
+    let mut out = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="11">"#
+    );
+    let (left, right) = (MARGIN, WIDTH - MARGIN / 2.0);
+    let (top, bottom) = (MARGIN / 2.0, HEIGHT - MARGIN);
+    write!(
+        out,
+        r#"<rect x="{left}" y="{top}" width="{}" height="{}" fill="none" stroke="gray"/>"#,
+        right - left,
+        bottom - top
+    )
+    .unwrap();
+
+    if !samples.is_empty() {
+        let voltages = range(samples.iter().map(|(v, _)| v.get::<volt>()));
+        let currents = range(samples.iter().map(|(_, i)| i.get::<ampere>()));
+        let x = scale(voltages, left, right);
+        let y = scale(currents, bottom, top);
+
+        out.push_str(r#"<polyline fill="none" stroke="steelblue" stroke-width="1.5" points=""#);
+        for (voltage, current) in samples {
+            write!(
+                out,
+                "{:.1},{:.1} ",
+                x(voltage.get::<volt>()),
+                y(current.get::<ampere>())
+            )
+            .unwrap();
+        }
+        out.push_str(r#""/>"#);
+
+        let (label_y, label_x) = (bottom + 14.0, left - 4.0);
+        write!(
+            out,
+            r#"<text x="{left}" y="{label_y}">{} V</text><text x="{right}" y="{label_y}" text-anchor="end">{} V</text>"#,
+            voltages.0, voltages.1
+        )
+        .unwrap();
+        write!(
+            out,
+            r#"<text x="{label_x}" y="{bottom}" text-anchor="end">{:.2e} A</text><text x="{label_x}" y="{}" text-anchor="end">{:.2e} A</text>"#,
+            currents.0,
+            top + 10.0,
+            currents.1
+        )
+        .unwrap();
+    }
+
+    out.push_str("</svg>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<(Voltage, Current)> {
+        (0..3)
+            .map(|e| {
+                (
+                    Voltage::new::<volt>(e as f32),
+                    Current::new::<ampere>(e as f32 * 1e-3),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tabulates_the_samples() {
+        let table = html_table(&samples());
+        assert!(table.starts_with("<table>"));
+        assert_eq!(table.matches("<tr><td>").count(), 3);
+        assert!(table.contains("<tr><td>2</td><td>2e-3</td></tr>"));
+    }
+
+    #[test]
+    fn plots_the_samples() {
+        let plot = svg_plot(&samples());
+        // the curve spans the plot area from the bottom left to the top right corner
+        let points = format!(
+            "{:.1},{:.1} {:.1},{:.1} {:.1},{:.1} ",
+            MARGIN,
+            HEIGHT - MARGIN,
+            (MARGIN + WIDTH - MARGIN / 2.0) / 2.0,
+            (HEIGHT - MARGIN + MARGIN / 2.0) / 2.0,
+            WIDTH - MARGIN / 2.0,
+            MARGIN / 2.0
+        );
+        assert!(plot.contains(&format!(r#"points="{points}""#)), "{plot}");
+        assert!(plot.ends_with("</svg>"));
+
+        assert!(!svg_plot(&[]).contains("polyline"));
+        let constant = svg_plot(&[(Voltage::new::<volt>(1.0), Current::new::<ampere>(0.0)); 2]);
+        assert!(!constant.contains("NaN"), "{constant}");
+    }
+}
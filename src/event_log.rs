@@ -0,0 +1,244 @@
+//! Append-only event log of a measurement campaign, as machine-readable audit trail next to the sample data.
+//!
+//! Each line of the log is a JSON object with the `timestamp` in seconds since the UNIX epoch,
+//! the `run` it belongs to, i.e. the start time of the run in milliseconds since the UNIX epoch,
+//! and the `event` with its fields, e.g.
+//! `{"timestamp": 1755600000.12, "run": 1755600000120, "event": "compliance", "voltage": 1.8, "current": 0.02}`.
+//! Several runs, also concurrent ones, append to the same log.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::{
+    Current, Error, Result, Voltage, ampere,
+    protection::{ProtectionEvent, ProtectionRecord},
+    schema::SCHEMA_VERSION,
+    sweep_result::{SweepResult, SweepStatus},
+    volt,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    RunStarted {
+        /// The command line of the run.
+        command: String,
+        schema_version: u32,
+    },
+    RunFinished {
+        samples: usize,
+        aborted: bool,
+        compliance: bool,
+    },
+    DeviceError {
+        message: String,
+    },
+    /// The current reached the current limit.
+    Compliance {
+        voltage: f32,
+        current: f32,
+    },
+    Note {
+        text: String,
+    },
+    /// A setting was rejected by the safety limits.
+    SafetyLimit {
+        message: String,
+    },
+    /// The interlock opened and the output was disabled.
+    Interlock {
+        message: String,
+    },
+}
+
+impl Event {
+    pub fn run_started(command: impl Into<String>) -> Self {
+        Event::RunStarted {
+            command: command.into(),
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+
+    pub fn run_finished(result: &SweepResult) -> Self {
+        Event::RunFinished {
+            samples: result.samples.len(),
+            aborted: result.status == SweepStatus::Aborted,
+            compliance: result.compliance(),
+        }
+    }
+
+    pub fn compliance(voltage: Voltage, current: Current) -> Self {
+        Event::Compliance {
+            voltage: voltage.get::<volt>(),
+            current: current.get::<ampere>(),
+        }
+    }
+}
+
+impl From<&ProtectionEvent> for Event {
+    fn from(event: &ProtectionEvent) -> Self {
+        match event {
+            ProtectionEvent::Compliance { voltage, current } => {
+                Event::compliance(*voltage, *current)
+            }
+            ProtectionEvent::SafetyLimit { message } => Event::SafetyLimit {
+                message: message.clone(),
+            },
+            ProtectionEvent::Interlock { message } => Event::Interlock {
+                message: message.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: f64,
+    run: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+fn since_epoch(time: SystemTime) -> std::time::Duration {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+pub struct EventLog {
+    file: File,
+    run: u64,
+}
+
+impl EventLog {
+    /// Open the log at `path` for appending the events of a new run, the file is created if missing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            run: since_epoch(SystemTime::now()).as_millis() as u64,
+        })
+    }
+
+    /// Append `event`, each event is written with a single write, so lines of concurrent runs do not interleave.
+    pub fn log(&mut self, event: &Event) -> Result<()> {
+        self.log_at(SystemTime::now(), event)
+    }
+
+    /// Like [Self::log], for an event which happened at `time`.
+    pub fn log_at(&mut self, time: SystemTime, event: &Event) -> Result<()> {
+        let entry = Entry {
+            timestamp: since_epoch(time).as_secs_f64(),
+            run: self.run,
+            event,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(|e| Error::Data(e.to_string()))?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// Log the protection events of the sweep, e.g. compliance and interlock trips, at the time they happened,
+    /// followed by the end of the sweep or its failure as device error.
+    pub fn finish_sweep(
+        &mut self,
+        result: &Result<SweepResult>,
+        protection: &[ProtectionRecord],
+    ) -> Result<()> {
+        for record in protection {
+            self.log_at(record.time, &Event::from(&record.event))?;
+        }
+        match result {
+            Ok(result) => self.log(&Event::run_finished(result)),
+            Err(e) => self.log(&Event::DeviceError {
+                message: e.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct EventLogParameter {
+    /// Append the events of the run, e.g. its start, end, compliance and interlock trips, as JSON lines to this file.
+    #[arg(long)]
+    pub event_log: Option<PathBuf>,
+
+    /// Operator note recorded in the event log at the start of the run.
+    #[arg(long, requires = "event_log")]
+    pub note: Option<String>,
+}
+
+impl EventLogParameter {
+    /// Open the event log, if one is configured, and log the start of the run.
+    pub fn start(&self) -> Result<Option<EventLog>> {
+        let Some(path) = self.event_log.as_ref() else {
+            return Ok(None);
+        };
+        let mut log = EventLog::open(path)?;
+        let command: Vec<String> = std::env::args().collect();
+        log.log(&Event::run_started(command.join(" ")))?;
+        if let Some(text) = self.note.as_ref() {
+            log.log(&Event::Note { text: text.clone() })?;
+        }
+        Ok(Some(log))
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct NoteArguments {
+    /// The event log of the campaign.
+    #[arg(long)]
+    pub event_log: PathBuf,
+
+    pub text: String,
+}
+
+impl NoteArguments {
+    pub fn run(&self) -> Result<()> {
+        EventLog::open(&self.event_log)?.log(&Event::Note {
+            text: self.text.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("usmu-events-{}.jsonl", std::process::id()));
+        let mut log = EventLog::open(&path).unwrap();
+        log.log(&Event::run_started("record_iv_curve")).unwrap();
+        log.log(&Event::compliance(
+            Voltage::new::<volt>(1.5),
+            Current::new::<ampere>(0.02),
+        ))
+        .unwrap();
+        EventLog::open(&path)
+            .unwrap()
+            .log(&Event::Note {
+                text: "swapped probe".to_string(),
+            })
+            .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|e| serde_json::from_str(e).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "run_started");
+        assert_eq!(lines[0]["run"], lines[1]["run"]);
+        assert_eq!(lines[1]["event"], "compliance");
+        assert_eq!(lines[1]["voltage"], 1.5);
+        assert_eq!(lines[2]["text"], "swapped probe");
+    }
+}
@@ -0,0 +1,280 @@
+//! Prometheus exporter, continuously sampling the device at a fixed bias voltage.
+//!
+//! The metrics are served in the Prometheus text format on `/metrics`:
+//!
+//! | Metric                        | Description                                               |
+//! |-------------------------------|-----------------------------------------------------------|
+//! | `usmu_voltage_volts`          | Last measured voltage                                     |
+//! | `usmu_current_amperes`        | Last measured current                                     |
+//! | `usmu_power_watts`            | Last measured power                                       |
+//! | `usmu_compliance`             | 1 if the last current reached the current limit, else 0   |
+//! | `usmu_current_limit_amperes`  | Configured current limit                                  |
+//! | `usmu_samples_total`          | Number of successful measurements                         |
+//! | `usmu_errors_total`           | Number of failed measurements                             |
+//! | `usmu_charge_coulombs`        | Charge since the start, with `--integrate-charge`         |
+//!
+//! All metrics are labeled with the `uid` of the device.
+
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use axum::{Router, extract::State, routing::get};
+use clap::Parser;
+use uom::si::electric_charge::coulomb;
+
+use crate::{
+    Current, MicroSmu, Result, Time, Voltage, ampere, charge::ChargeIntegrator,
+    commands::MeasureResponse, record_iv_curve::SmuConnectionParameter,
+    sweep_result::COMPLIANCE_THRESHOLD, timing, volt,
+};
+
+#[derive(Debug, Parser)]
+pub struct ExporterArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Address serving the `/metrics` endpoint.
+    #[arg(long, default_value = "127.0.0.1:9184")]
+    pub listen: SocketAddr,
+
+    /// Bias voltage applied while sampling.
+    #[arg(long, short = 'v', default_value = "0 V")]
+    pub voltage: Voltage,
+
+    #[arg(long, short = 'c', default_value = "20 mA")]
+    pub current_limit: Current,
+
+    /// Number of samples averaged per measurement.
+    #[arg(long, short = 'r', default_value_t = 10)]
+    pub over_sampling: u16,
+
+    /// Time between measurements.
+    #[arg(long, short = 'i', default_value = "1 s")]
+    pub interval: Time,
+
+    /// Integrate the current over time and export the accumulated charge.
+    #[arg(long)]
+    pub integrate_charge: bool,
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    last: Option<MeasureResponse>,
+    samples: u64,
+    errors: u64,
+    charge: Option<ChargeIntegrator>,
+}
+
+#[derive(Clone)]
+struct Exporter {
+    uid: u32,
+    current_limit: Current,
+    metrics: Arc<Mutex<Metrics>>,
+}
+
+impl ExporterArguments {
+    pub fn run(&self) -> Result<()> {
+        let mut smu = self.connection_parameter.connect()?;
+        let uid = smu.get_identity()?;
+
+        smu.set_current_limit(self.current_limit)?;
+        smu.set_over_sample_rate(self.over_sampling)?;
+        smu.set_voltage(self.voltage)?;
+        smu.enable()?;
+
+        let state = Metrics {
+            charge: self.integrate_charge.then(ChargeIntegrator::new),
+            ..Default::default()
+        };
+        let exporter = Exporter {
+            uid,
+            current_limit: self.current_limit,
+            metrics: Arc::new(Mutex::new(state)),
+        };
+
+        let sampler = exporter.clone();
+        let voltage = self.voltage;
+        let interval = timing::duration("interval", self.interval)?;
+        thread::spawn(move || sampler.sample(smu, voltage, interval));
+
+        let router = Router::new()
+            .route("/metrics", get(metrics))
+            .with_state(exporter);
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind(self.listen).await?;
+            axum::serve(listener, router).await
+        })?;
+        Ok(())
+    }
+}
+
+impl Exporter {
+    fn sample(&self, mut smu: MicroSmu, voltage: Voltage, interval: Duration) {
+        let start = Instant::now();
+        loop {
+            let measurement = smu.measure(voltage);
+            self.record(start.elapsed(), measurement);
+            thread::sleep(interval);
+        }
+    }
+
+    /// Update the metrics with a `measurement` taken `elapsed` after the start.
+    fn record(&self, elapsed: Duration, measurement: Result<MeasureResponse>) {
+        let mut metrics = self.metrics.lock().unwrap_or_else(PoisonError::into_inner);
+        match measurement {
+            Ok(measurement) => {
+                if let Some(charge) = metrics.charge.as_mut() {
+                    charge.add(elapsed, measurement.voltage, measurement.current);
+                }
+                metrics.last = Some(measurement);
+                metrics.samples += 1;
+            }
+            Err(e) => {
+                eprintln!("Measurement failed: {e}");
+                metrics.errors += 1;
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let metrics = self.metrics.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+            writeln!(out, "# HELP {name} {help}").unwrap();
+            writeln!(out, "# TYPE {name} {kind}").unwrap();
+            writeln!(out, "{name}{{uid=\"{}\"}} {value}", self.uid).unwrap();
+        };
+
+        if let Some(MeasureResponse { voltage, current }) = metrics.last {
+            let compliance = current.abs() >= self.current_limit * COMPLIANCE_THRESHOLD;
+            metric(
+                "usmu_voltage_volts",
+                "gauge",
+                "Last measured voltage.",
+                voltage.get::<volt>().into(),
+            );
+            metric(
+                "usmu_current_amperes",
+                "gauge",
+                "Last measured current.",
+                current.get::<ampere>().into(),
+            );
+            metric(
+                "usmu_power_watts",
+                "gauge",
+                "Last measured power.",
+                (voltage.get::<volt>() * current.get::<ampere>()).into(),
+            );
+            metric(
+                "usmu_compliance",
+                "gauge",
+                "Whether the last measured current reached the current limit.",
+                u8::from(compliance).into(),
+            );
+        }
+        metric(
+            "usmu_current_limit_amperes",
+            "gauge",
+            "Configured current limit.",
+            self.current_limit.get::<ampere>().into(),
+        );
+        metric(
+            "usmu_samples_total",
+            "counter",
+            "Number of successful measurements.",
+            metrics.samples as f64,
+        );
+        metric(
+            "usmu_errors_total",
+            "counter",
+            "Number of failed measurements.",
+            metrics.errors as f64,
+        );
+        if let Some(charge) = metrics.charge.as_ref() {
+            metric(
+                "usmu_charge_coulombs",
+                "gauge",
+                "Charge accumulated since the start.",
+                charge.charge().get::<coulomb>(),
+            );
+        }
+
+        out
+    }
+}
+
+async fn metrics(State(exporter): State<Exporter>) -> String {
+    exporter.render()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, milliampere};
+
+    use super::*;
+
+    fn exporter(integrate_charge: bool) -> Exporter {
+        Exporter {
+            uid: 42,
+            current_limit: Current::new::<milliampere>(20.0),
+            metrics: Arc::new(Mutex::new(Metrics {
+                charge: integrate_charge.then(ChargeIntegrator::new),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn measurement(current: f32) -> Result<MeasureResponse> {
+        Ok(MeasureResponse {
+            voltage: Voltage::new::<volt>(1.0),
+            current: Current::new::<milliampere>(current),
+        })
+    }
+
+    /// The value of the metric `name`, `None` if it is not rendered.
+    fn value(rendered: &str, name: &str) -> Option<f64> {
+        let line = rendered
+            .lines()
+            .find(|e| e.starts_with(&format!("{name}{{uid=\"42\"}} ")))?;
+        Some(line.rsplit_once(' ')?.1.parse().unwrap())
+    }
+
+    #[test]
+    fn renders_the_last_measurement() {
+        let exporter = exporter(false);
+        let rendered = exporter.render();
+        assert_eq!(value(&rendered, "usmu_current_amperes"), None);
+        assert_eq!(value(&rendered, "usmu_samples_total"), Some(0.0));
+
+        exporter.record(Duration::ZERO, measurement(1.0));
+        exporter.record(
+            Duration::from_secs(1),
+            Err(Error::Internal("Device vanished.".to_string())),
+        );
+        exporter.record(Duration::from_secs(2), measurement(20.0));
+        let rendered = exporter.render();
+        assert!(rendered.contains("# TYPE usmu_samples_total counter"));
+        let current = value(&rendered, "usmu_current_amperes").unwrap();
+        assert!((current - 0.02).abs() < 1e-7);
+        assert_eq!(value(&rendered, "usmu_compliance"), Some(1.0));
+        assert_eq!(value(&rendered, "usmu_samples_total"), Some(2.0));
+        assert_eq!(value(&rendered, "usmu_errors_total"), Some(1.0));
+        assert_eq!(value(&rendered, "usmu_charge_coulombs"), None);
+    }
+
+    #[test]
+    fn integrates_the_charge() {
+        let exporter = exporter(true);
+        exporter.record(Duration::ZERO, measurement(1.0));
+        exporter.record(Duration::from_secs(2), measurement(1.0));
+        let charge = value(&exporter.render(), "usmu_charge_coulombs").unwrap();
+        assert!((charge - 2e-3).abs() < 1e-7);
+    }
+}
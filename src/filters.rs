@@ -0,0 +1,384 @@
+//! Post-processing filters for sweep and time series results.
+//!
+//! Filters are given on the command line as `NAME:PARAMETERS`, e.g. `--filter median:5 --filter savitzky-golay:7:2`,
+//! and applied in the given order. The same notation is recorded in the run metadata.
+
+use std::{fmt::Display, str::FromStr};
+
+use clap::Parser;
+
+use crate::{
+    Current, Error, Result, Voltage, ampere,
+    annotation::{Hooks, Stage},
+    monitor::MonitorSample,
+    sweep_result::SweepResult,
+    volt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Mean over a centered window of odd length, truncated at the ends.
+    MovingAverage { window: usize },
+    /// Median over a centered window of odd length, truncated at the ends, removing outliers while preserving steps.
+    Median { window: usize },
+    /// First order low-pass `y[k] = alpha x[k] + (1 - alpha) y[k - 1]`, with `0 < alpha <= 1`.
+    Exponential { alpha: f64 },
+    /// Least squares polynomial of `order` over a centered window of odd length,
+    /// smoothing noise while preserving peaks and slopes better than a moving average.
+    /// At the ends, the polynomial of the first and last full window is evaluated.
+    SavitzkyGolay { window: usize, order: usize },
+}
+
+impl Filter {
+    /// Filter uniformly spaced `values`, fails if they are too short for a Savitzky-Golay window.
+    pub fn apply(&self, values: &[f64]) -> Result<Vec<f64>> {
+        Ok(match *self {
+            Filter::MovingAverage { window } => {
+                centered(values, window, |e| e.iter().sum::<f64>() / e.len() as f64)
+            }
+            Filter::Median { window } => centered(values, window, |e| {
+                let mut e = e.to_vec();
+                e.sort_by(f64::total_cmp);
+                let middle = e.len() / 2;
+                if e.len() % 2 == 0 {
+                    (e[middle - 1] + e[middle]) / 2.0
+                } else {
+                    e[middle]
+                }
+            }),
+            Filter::Exponential { alpha } => values
+                .iter()
+                .scan(None, |state: &mut Option<f64>, &value| {
+                    let filtered = state.map_or(value, |e| alpha * value + (1.0 - alpha) * e);
+                    *state = Some(filtered);
+                    Some(filtered)
+                })
+                .collect(),
+            Filter::SavitzkyGolay { window, order } => savitzky_golay(values, window, order, 0)?,
+        })
+    }
+
+    fn validate(self) -> std::result::Result<Self, String> {
+        match self {
+            Filter::MovingAverage { window }
+            | Filter::Median { window }
+            | Filter::SavitzkyGolay { window, .. }
+                if window % 2 == 0 =>
+            {
+                Err(format!("the window length must be odd, got {window}"))
+            }
+            Filter::Exponential { alpha } if !(alpha > 0.0 && alpha <= 1.0) => {
+                Err(format!("alpha must be in (0, 1], got {alpha}"))
+            }
+            Filter::SavitzkyGolay { window, order } if order >= window => Err(format!(
+                "the order {order} must be below the window length {window}"
+            )),
+            filter => Ok(filter),
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Filter::MovingAverage { window } => write!(f, "moving-average:{window}"),
+            Filter::Median { window } => write!(f, "median:{window}"),
+            Filter::Exponential { alpha } => write!(f, "exponential:{alpha}"),
+            Filter::SavitzkyGolay { window, order } => {
+                write!(f, "savitzky-golay:{window}:{order}")
+            }
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        fn parse<T: FromStr>(value: Option<&str>, name: &str) -> std::result::Result<T, String> {
+            let value = value.ok_or_else(|| format!("missing {name}"))?;
+            value
+                .parse()
+                .map_err(|_| format!("invalid {name} '{value}'"))
+        }
+
+        let mut parts = s.split(':');
+        let filter = match parts.next().unwrap_or_default() {
+            "moving-average" => Filter::MovingAverage {
+                window: parse(parts.next(), "window length")?,
+            },
+            "median" => Filter::Median {
+                window: parse(parts.next(), "window length")?,
+            },
+            "exponential" => Filter::Exponential {
+                alpha: parse(parts.next(), "alpha")?,
+            },
+            "savitzky-golay" => Filter::SavitzkyGolay {
+                window: parse(parts.next(), "window length")?,
+                order: parse(parts.next(), "order")?,
+            },
+            name => Err(format!(
+                "unknown filter '{name}', expected moving-average, median, exponential or savitzky-golay"
+            ))?,
+        };
+        if let Some(extra) = parts.next() {
+            Err(format!("unexpected parameter '{extra}' of '{s}'"))?;
+        }
+        filter.validate()
+    }
+}
+
+/// Apply `reduce` to the centered window around each value, truncated at the ends.
+fn centered(values: &[f64], window: usize, reduce: impl Fn(&[f64]) -> f64) -> Vec<f64> {
+    let half = window / 2;
+    (0..values.len())
+        .map(|k| reduce(&values[k.saturating_sub(half)..(k + half + 1).min(values.len())]))
+        .collect()
+}
+
+/// Savitzky-Golay filter returning the `derivative` of the fitted polynomials, per sample spacing.
+pub(crate) fn savitzky_golay(
+    values: &[f64],
+    window: usize,
+    order: usize,
+    derivative: usize,
+) -> Result<Vec<f64>> {
+    if values.len() < window {
+        Err(Error::Configuration(format!(
+            "{} values are too few for a Savitzky-Golay window of {window}.",
+            values.len()
+        )))?;
+    }
+    let half = window / 2;
+    // the weights evaluating the fit at each position within the window
+    let weights: Vec<Vec<f64>> = (0..window)
+        .map(|position| fit_weights(window, order, position as f64 - half as f64, derivative))
+        .collect();
+
+    Ok((0..values.len())
+        .map(|k| {
+            let start = k.saturating_sub(half).min(values.len() - window);
+            values[start..start + window]
+                .iter()
+                .zip(&weights[k - start])
+                .map(|(value, weight)| value * weight)
+                .sum()
+        })
+        .collect())
+}
+
+/// Weights of the samples at `-half..=half` yielding the `derivative` at `at` of the least squares polynomial of `order`.
+fn fit_weights(window: usize, order: usize, at: f64, derivative: usize) -> Vec<f64> {
+    let half = (window / 2) as f64;
+    let positions: Vec<f64> = (0..window).map(|e| e as f64 - half).collect();
+
+    // normal equations J^T J c = J^T y with the Vandermonde matrix J
+    let size = order + 1;
+    let mut normal = vec![vec![0.0; size]; size];
+    for (i, row) in normal.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = positions.iter().map(|x| x.powi((i + j) as i32)).sum();
+        }
+    }
+
+    // the derivative of the polynomial at `at` is a linear combination b of its coefficients
+    let mut b: Vec<f64> = (0..size)
+        .map(|k| {
+            if k < derivative {
+                0.0
+            } else {
+                let factor: f64 = ((k - derivative + 1)..=k).map(|e| e as f64).product();
+                factor * at.powi((k - derivative) as i32)
+            }
+        })
+        .collect();
+
+    // the weights are J (J^T J)^-1 b, solve the symmetric system by Gaussian elimination
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| normal[a][column].abs().total_cmp(&normal[b][column].abs()))
+            .unwrap_or(column);
+        normal.swap(column, pivot);
+        b.swap(column, pivot);
+        let (upper, lower) = normal.split_at_mut(column + 1);
+        let pivot_row = &upper[column];
+        for (offset, row) in lower.iter_mut().enumerate() {
+            let factor = row[column] / pivot_row[column];
+            for (entry, pivot) in row.iter_mut().zip(pivot_row).skip(column) {
+                *entry -= factor * pivot;
+            }
+            b[column + 1 + offset] -= factor * b[column];
+        }
+    }
+    let mut solution = vec![0.0; size];
+    for row in (0..size).rev() {
+        let rest: f64 = (row + 1..size).map(|k| normal[row][k] * solution[k]).sum();
+        solution[row] = (b[row] - rest) / normal[row][row];
+    }
+
+    positions
+        .iter()
+        .map(|x| {
+            solution
+                .iter()
+                .enumerate()
+                .map(|(k, s)| s * x.powi(k as i32))
+                .sum()
+        })
+        .collect()
+}
+
+/// Apply the filters in order to the currents of a sweep, the voltages are kept.
+pub fn filter_curve(
+    filters: &[Filter],
+    samples: &[(Voltage, Current)],
+) -> Result<Vec<(Voltage, Current)>> {
+    let mut currents: Vec<f64> = samples
+        .iter()
+        .map(|(_, i)| i.get::<ampere>().into())
+        .collect();
+    for filter in filters {
+        currents = filter.apply(&currents)?;
+    }
+    Ok(samples
+        .iter()
+        .zip(currents)
+        .map(|(&(v, _), i)| (v, Current::new::<ampere>(i as f32)))
+        .collect())
+}
+
+/// Apply the filters in order to the voltages and currents of a time series, the times and offsets are kept.
+pub fn filter_series(filters: &[Filter], samples: &[MonitorSample]) -> Result<Vec<MonitorSample>> {
+    let mut voltages: Vec<f64> = samples
+        .iter()
+        .map(|e| e.voltage.get::<volt>().into())
+        .collect();
+    let mut currents: Vec<f64> = samples
+        .iter()
+        .map(|e| e.current.get::<ampere>().into())
+        .collect();
+    for filter in filters {
+        voltages = filter.apply(&voltages)?;
+        currents = filter.apply(&currents)?;
+    }
+    Ok(samples
+        .iter()
+        .zip(voltages.into_iter().zip(currents))
+        .map(|(sample, (v, i))| MonitorSample {
+            voltage: Voltage::new::<volt>(v as f32),
+            current: Current::new::<ampere>(i as f32),
+            ..*sample
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct FilterParameter {
+    /// Filter applied to the measured currents before the output, given as `NAME:PARAMETERS`,
+    /// e.g. `moving-average:5`, `median:5`, `exponential:0.2` or `savitzky-golay:7:2` (window length and order).
+    /// Repeat to apply several filters in order.
+    #[arg(long = "filter")]
+    pub filters: Vec<Filter>,
+}
+
+impl FilterParameter {
+    /// Filter the currents of the sweep, the remaining sample data is kept.
+    pub fn apply(&self, mut result: SweepResult) -> Result<SweepResult> {
+        if self.filters.is_empty() {
+            return Ok(result);
+        }
+        let filtered = filter_curve(&self.filters, &result.points())?;
+        for (sample, (_, current)) in result.samples.iter_mut().zip(filtered) {
+            sample.current = current;
+        }
+        Ok(result)
+    }
+
+    /// Record the filter configuration as `filter` annotation of the run.
+    pub fn annotate(&self, hooks: &mut Hooks) {
+        if self.filters.is_empty() {
+            return;
+        }
+        let configuration = self
+            .filters
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        hooks.add(Stage::Post, "filter", move || Ok(configuration.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn parses_and_displays_filters() {
+        for filter in [
+            "moving-average:5",
+            "median:3",
+            "exponential:0.25",
+            "savitzky-golay:7:2",
+        ] {
+            assert_eq!(filter.parse::<Filter>().unwrap().to_string(), filter);
+        }
+        for invalid in [
+            "median",
+            "median:4",
+            "exponential:0",
+            "savitzky-golay:5:5",
+            "gauss:3",
+            "median:3:1",
+        ] {
+            assert!(invalid.parse::<Filter>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn moving_average_and_median() {
+        let values = [1.0, 2.0, 9.0, 4.0, 5.0];
+        let average = Filter::MovingAverage { window: 3 }.apply(&values).unwrap();
+        assert_close(&average, &[1.5, 4.0, 5.0, 6.0, 4.5]);
+        let median = Filter::Median { window: 3 }.apply(&values).unwrap();
+        assert_close(&median, &[1.5, 2.0, 4.0, 5.0, 4.5]);
+    }
+
+    #[test]
+    fn exponential_smoothing() {
+        let smoothed = Filter::Exponential { alpha: 0.5 }
+            .apply(&[0.0, 2.0, 2.0])
+            .unwrap();
+        assert_close(&smoothed, &[0.0, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn savitzky_golay_preserves_polynomials() {
+        let values: Vec<f64> = (0..12)
+            .map(|e| {
+                let x = e as f64;
+                0.5 * x * x - 3.0 * x + 1.0
+            })
+            .collect();
+        let smoothed = Filter::SavitzkyGolay {
+            window: 5,
+            order: 2,
+        }
+        .apply(&values)
+        .unwrap();
+        assert_close(&smoothed, &values);
+
+        let slope = savitzky_golay(&values, 5, 2, 1).unwrap();
+        let expected: Vec<f64> = (0..12).map(|e| e as f64 - 3.0).collect();
+        assert_close(&slope, &expected);
+
+        assert!(savitzky_golay(&values[..3], 5, 2, 0).is_err());
+    }
+}
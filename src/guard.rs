@@ -0,0 +1,126 @@
+//! [EnabledGuard], the output of the uSMU enabled for a scope.
+//!
+//! The guard disables the output when it is dropped, also on early returns and panics,
+//! so a failed sweep never leaves the device under test powered.
+//!
+//! ```no_run
+//! # fn example(smu: &mut usmu::MicroSmu) -> usmu::Result<()> {
+//! use usmu::{Voltage, volt};
+//!
+//! let mut output = smu.enabled()?;
+//! let measured = output.measure(Voltage::new::<volt>(0.5))?;
+//! // the output is disabled here, or if the measurement fails
+//! # Ok(())
+//! # }
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use crate::{MicroSmu, Result, transport::Transport};
+
+/// See the [module documentation](self), created by [MicroSmu::enabled].
+pub struct EnabledGuard<'a, T: Transport> {
+    smu: &'a mut MicroSmu<T>,
+}
+
+impl<'a, T: Transport> EnabledGuard<'a, T> {
+    /// Take over the already enabled output of `smu`.
+    pub(crate) fn new(smu: &'a mut MicroSmu<T>) -> Self {
+        Self { smu }
+    }
+
+    /// Disable the output like dropping the guard, but report failures, see [MicroSmu::disable].
+    pub fn disable(self) -> Result<()> {
+        self.smu.disable()
+    }
+}
+
+impl<T: Transport> Deref for EnabledGuard<'_, T> {
+    type Target = MicroSmu<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.smu
+    }
+}
+
+impl<T: Transport> DerefMut for EnabledGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.smu
+    }
+}
+
+impl<T: Transport> Drop for EnabledGuard<'_, T> {
+    /// Disable the output, with the ramp-down, if any, see [MicroSmu::disable].
+    fn drop(&mut self) {
+        // nothing to report failures to, e.g. if the connection is lost
+        if self.smu.is_enabled() {
+            let _ = self.smu.disable();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    use crate::{
+        Current, Voltage, milliampere,
+        record_iv_curve::IvCurveRecordingParameters,
+        test_util::{FakeSerialPort, Fault},
+        volt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn disables_the_output_on_drop() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:ENA")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .expect("CH1:DIS")
+            .expect("CH1:ENA")
+            .expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+
+        {
+            let mut output = smu.enabled().unwrap();
+            output.measure(Voltage::new::<volt>(1.0)).unwrap();
+            assert!(output.is_enabled());
+        }
+        assert!(!smu.is_enabled());
+        assert_eq!(smu.get_set_voltage(), Some(Voltage::new::<volt>(1.0)));
+        let panicked = catch_unwind(AssertUnwindSafe(|| {
+            let _output = smu.enabled().unwrap();
+            panic!("the sweep failed");
+        }));
+        assert!(panicked.is_err());
+        port.verify();
+    }
+
+    #[test]
+    fn disables_the_output_of_a_failed_sweep() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL -1")
+            .expect("CH1:CUR 20")
+            .expect("CH1:ENA")
+            .expect("CH1:OSR 1")
+            .expect("CH1:VOL -1")
+            .expect_query("CH1:MEA:VOL -1", "-1.0,-0.001")
+            .fault(Fault::Garbage(b"#".to_vec()))
+            .expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(std::time::Duration::ZERO);
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(-1.0),
+            end_voltage: Voltage::new::<volt>(1.0),
+            voltage_steps: 2,
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 1,
+            ..Default::default()
+        };
+
+        assert!(parameters.record(&mut smu).is_err());
+        assert!(!smu.is_enabled());
+        port.verify();
+    }
+}
@@ -0,0 +1,449 @@
+//! `usmu-gui`, a desktop application to select a device, watch its live readout,
+//! set up and plot sweeps as they are recorded and export their results.
+//!
+//! The connection options of the command line, e.g. `--safety-limits`, apply to all devices connected in the window.
+
+mod worker;
+
+use std::{
+    fmt::Display,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use clap::{Parser, ValueEnum};
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+
+use crate::{
+    Current, Result, Time, Voltage,
+    commands::MeasureResponse,
+    find_serial_ports, milliampere, millisecond,
+    record_iv_curve::{IvCurveRecordingParameters, SmuConnectionParameter},
+    simulator::SimulatedDut,
+    sweep_file,
+    sweep_result::{SweepResult, SweepStatus},
+    timing::{TimingModel, format_duration},
+    volt,
+};
+use worker::{Command, Event, Worker};
+
+/// Output range of the uSMU.
+const VOLTAGE_RANGE: RangeInclusive<f32> = -5.0..=5.0;
+/// Range of the current limit in milliampere.
+const CURRENT_LIMIT_RANGE: RangeInclusive<f32> = 0.0..=40.0;
+
+#[derive(Debug, Parser)]
+pub struct GuiArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+}
+
+impl GuiArguments {
+    pub fn run(self) -> eframe::Result {
+        let options = eframe::NativeOptions {
+            viewport: egui::ViewportBuilder::default().with_inner_size([1100.0, 700.0]),
+            ..Default::default()
+        };
+        eframe::run_native(
+            "μSMU",
+            options,
+            Box::new(|context| Ok(Box::new(App::new(context.egui_ctx.clone(), self)))),
+        )
+    }
+}
+
+/// A device to connect to.
+#[derive(Debug, Clone, PartialEq)]
+enum Device {
+    Port(String),
+    Simulated(SimulatedDut),
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Device::Port(name) => write!(f, "{name}"),
+            Device::Simulated(dut) => match dut.to_possible_value() {
+                Some(value) => write!(f, "Simulated {}", value.get_name()),
+                None => write!(f, "Simulated {dut:?}"),
+            },
+        }
+    }
+}
+
+struct App {
+    worker: Worker,
+    /// Options of all connections, the device is replaced by the selection.
+    connection_parameter: SmuConnectionParameter,
+    devices: Vec<Device>,
+    device: Option<Device>,
+    /// Description of the connected device.
+    connected: Option<String>,
+    /// A readout or sweep is running.
+    busy: bool,
+    bias: Voltage,
+    sweep: IvCurveRecordingParameters,
+    reading: Option<MeasureResponse>,
+    /// The samples of the current sweep, as they are recorded.
+    samples: Vec<(Voltage, Current)>,
+    result: Option<SweepResult>,
+    export_path: String,
+    status: String,
+}
+
+impl App {
+    fn new(context: egui::Context, arguments: GuiArguments) -> Self {
+        let GuiArguments {
+            mut connection_parameter,
+        } = arguments;
+        let simulated = connection_parameter.simulation_parameter.simulate.take();
+        let port = connection_parameter.port.take();
+        let device = match (simulated, port) {
+            (Some(dut), _) => Some(Device::Simulated(dut)),
+            (None, Some(port)) => Some(Device::Port(port.display().to_string())),
+            (None, None) => None,
+        };
+
+        let mut app = Self {
+            worker: Worker::spawn(move || context.request_repaint()),
+            connection_parameter,
+            devices: Vec::new(),
+            device,
+            connected: None,
+            busy: false,
+            bias: Voltage::new::<volt>(0.0),
+            sweep: IvCurveRecordingParameters::default(),
+            reading: None,
+            samples: Vec::new(),
+            result: None,
+            export_path: "sweep.csv".to_string(),
+            status: String::new(),
+        };
+        app.refresh_devices();
+        if app.device.is_some() {
+            app.connect();
+        }
+        app
+    }
+
+    fn refresh_devices(&mut self) {
+        let ports = find_serial_ports().unwrap_or_else(|e| {
+            self.status = format!("Searching for devices failed: {e}");
+            Vec::new()
+        });
+        self.devices = ports
+            .into_iter()
+            .map(|e| Device::Port(e.port_name))
+            .chain(
+                SimulatedDut::value_variants()
+                    .iter()
+                    .copied()
+                    .map(Device::Simulated),
+            )
+            .collect();
+        if self.device.is_none() {
+            self.device = self.devices.first().cloned();
+        }
+    }
+
+    fn connect(&mut self) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let mut parameter = self.connection_parameter.clone();
+        match device {
+            Device::Port(name) => parameter.port = Some(PathBuf::from(name)),
+            Device::Simulated(dut) => parameter.simulation_parameter.simulate = Some(dut),
+        }
+        self.connected = None;
+        self.reading = None;
+        self.status = "Connecting...".to_string();
+        self.worker.send(Command::Connect(Box::new(parameter)));
+    }
+
+    fn start_readout(&mut self) {
+        self.busy = true;
+        self.status = "Reading...".to_string();
+        self.worker.send(Command::Readout {
+            voltage: self.bias,
+            current_limit: self.sweep.current_limit,
+            over_sampling: self.sweep.over_sampling,
+        });
+    }
+
+    fn start_sweep(&mut self) {
+        self.busy = true;
+        self.samples.clear();
+        self.result = None;
+        self.status = "Sweeping...".to_string();
+        self.worker.send(Command::Sweep(self.sweep.clone()));
+    }
+
+    fn export(&mut self) {
+        let Some(result) = self.result.as_ref() else {
+            return;
+        };
+        let path = PathBuf::from(&self.export_path);
+        self.status = match export(result, &path) {
+            Ok(()) => format!("Saved {}.", path.display()),
+            Err(e) => format!("Saving {} failed: {e}", path.display()),
+        };
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Connected { uid, port } => {
+                let connected = match port {
+                    Some(port) => format!("uSMU {uid} on {port}"),
+                    None => format!("uSMU {uid}"),
+                };
+                self.status = format!("Connected to {connected}.");
+                self.connected = Some(connected);
+            }
+            Event::Disconnected => {
+                self.connected = None;
+                self.reading = None;
+            }
+            Event::Reading(reading) => self.reading = Some(reading),
+            Event::Sample(voltage, current) => {
+                self.samples.push((voltage, current));
+                self.reading = Some(MeasureResponse { voltage, current });
+            }
+            Event::Finished(result) => {
+                self.busy = false;
+                self.status = match result.as_ref() {
+                    Some(result) if result.status == SweepStatus::Aborted => {
+                        format!("Sweep stopped after {} samples.", result.samples.len())
+                    }
+                    Some(result) => {
+                        format!("Sweep completed with {} samples.", result.samples.len())
+                    }
+                    None => "Readout stopped.".to_string(),
+                };
+                self.result = result;
+            }
+            Event::Failed(e) => {
+                self.busy = false;
+                self.status = e;
+            }
+        }
+    }
+
+    fn device_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Device");
+        ui.horizontal(|ui| {
+            let selected = match self.device.as_ref() {
+                Some(device) => device.to_string(),
+                None => "Select a device".to_string(),
+            };
+            egui::ComboBox::from_id_salt("device")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for device in &self.devices {
+                        ui.selectable_value(
+                            &mut self.device,
+                            Some(device.clone()),
+                            device.to_string(),
+                        );
+                    }
+                });
+            if ui.button("⟳").on_hover_text("Search for devices").clicked() {
+                self.refresh_devices();
+            }
+        });
+        ui.horizontal(|ui| {
+            let connectable = self.device.is_some() && !self.busy;
+            if ui
+                .add_enabled(connectable, egui::Button::new("Connect"))
+                .clicked()
+            {
+                self.connect();
+            }
+            let connected = self.connected.is_some() && !self.busy;
+            if ui
+                .add_enabled(connected, egui::Button::new("Disconnect"))
+                .clicked()
+            {
+                self.worker.send(Command::Disconnect);
+                self.status = "Disconnected.".to_string();
+            }
+        });
+        ui.label(self.connected.as_deref().unwrap_or("Not connected"));
+    }
+
+    fn readout_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Readout");
+        egui::Grid::new("readout").num_columns(2).show(ui, |ui| {
+            self.bias = Voltage::new::<volt>(quantity(
+                ui,
+                "Bias",
+                self.bias.get::<volt>(),
+                "V",
+                0.01,
+                VOLTAGE_RANGE,
+            ));
+        });
+        let idle = self.connected.is_some() && !self.busy;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(idle, egui::Button::new("Start")).clicked() {
+                self.start_readout();
+            }
+            if ui
+                .add_enabled(self.busy, egui::Button::new("Stop"))
+                .clicked()
+            {
+                self.worker.stop();
+            }
+        });
+        let (voltage, current) = match self.reading {
+            Some(reading) => (
+                format!("{:+.4} V", reading.voltage.get::<volt>()),
+                format!("{:+.4} mA", reading.current.get::<milliampere>()),
+            ),
+            None => ("--- V".to_string(), "--- mA".to_string()),
+        };
+        ui.label(egui::RichText::new(voltage).monospace().size(24.0));
+        ui.label(egui::RichText::new(current).monospace().size(24.0));
+    }
+
+    fn sweep_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Sweep");
+        egui::Grid::new("sweep").num_columns(2).show(ui, |ui| {
+            let sweep = &mut self.sweep;
+            sweep.start_voltage = Voltage::new::<volt>(quantity(
+                ui,
+                "Start",
+                sweep.start_voltage.get::<volt>(),
+                "V",
+                0.01,
+                VOLTAGE_RANGE,
+            ));
+            sweep.end_voltage = Voltage::new::<volt>(quantity(
+                ui,
+                "End",
+                sweep.end_voltage.get::<volt>(),
+                "V",
+                0.01,
+                VOLTAGE_RANGE,
+            ));
+            ui.label("Steps");
+            ui.add(egui::DragValue::new(&mut sweep.voltage_steps).range(1..=10_000));
+            ui.end_row();
+            sweep.current_limit = Current::new::<milliampere>(quantity(
+                ui,
+                "Current limit",
+                sweep.current_limit.get::<milliampere>(),
+                "mA",
+                0.1,
+                CURRENT_LIMIT_RANGE,
+            ));
+            ui.label("Over-sampling");
+            ui.add(egui::DragValue::new(&mut sweep.over_sampling).range(1..=u16::MAX));
+            ui.end_row();
+            sweep.delay = Time::new::<millisecond>(quantity(
+                ui,
+                "Delay",
+                sweep.delay.get::<millisecond>(),
+                "ms",
+                1.0,
+                0.0..=10_000.0,
+            ));
+        });
+        let duration = self.sweep.estimated_duration(&TimingModel::default());
+        ui.label(format!("Estimated duration {}", format_duration(duration)));
+        let idle = self.connected.is_some() && !self.busy;
+        ui.horizontal(|ui| {
+            if ui.add_enabled(idle, egui::Button::new("Run")).clicked() {
+                self.start_sweep();
+            }
+            if ui
+                .add_enabled(self.busy, egui::Button::new("Stop"))
+                .clicked()
+            {
+                self.worker.stop();
+            }
+        });
+    }
+
+    fn export_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Export");
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.export_path);
+            if ui
+                .add_enabled(self.result.is_some(), egui::Button::new("Save"))
+                .clicked()
+            {
+                self.export();
+            }
+        });
+        #[cfg(feature = "json")]
+        ui.label("Saved as CSV, or as JSON with the extension .json.");
+    }
+
+    fn plot_ui(&self, ui: &mut egui::Ui) {
+        let points = PlotPoints::from_iter(self.samples.iter().map(|(voltage, current)| {
+            [
+                f64::from(voltage.get::<volt>()),
+                f64::from(current.get::<milliampere>()),
+            ]
+        }));
+        Plot::new("iv_curve")
+            .x_axis_label("Voltage (V)")
+            .y_axis_label("Current (mA)")
+            .legend(Legend::default())
+            .show(ui, |plot| plot.line(Line::new(points).name("IV curve")));
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Some(event) = self.worker.try_event() {
+            self.handle(event);
+        }
+
+        egui::TopBottomPanel::bottom("status").show(context, |ui| ui.label(&self.status));
+        egui::SidePanel::left("setup")
+            .resizable(false)
+            .show(context, |ui| {
+                self.device_ui(ui);
+                ui.separator();
+                self.readout_ui(ui);
+                ui.separator();
+                self.sweep_ui(ui);
+                ui.separator();
+                self.export_ui(ui);
+            });
+        egui::CentralPanel::default().show(context, |ui| self.plot_ui(ui));
+    }
+}
+
+/// A row of a two column grid, editing `value` in `unit`.
+fn quantity(
+    ui: &mut egui::Ui,
+    label: &str,
+    mut value: f32,
+    unit: &str,
+    speed: f64,
+    range: RangeInclusive<f32>,
+) -> f32 {
+    ui.label(label);
+    ui.add(
+        egui::DragValue::new(&mut value)
+            .speed(speed)
+            .range(range)
+            .suffix(format!(" {unit}")),
+    );
+    ui.end_row();
+    value
+}
+
+/// Save `result` as CSV, or as JSON if `path` has the extension `.json`.
+fn export(result: &SweepResult, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "json")]
+        Some("json") => sweep_file::json::write_json(result, file),
+        _ => sweep_file::write_csv(result, file),
+    }
+}
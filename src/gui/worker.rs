@@ -0,0 +1,283 @@
+//! The device session of the GUI on a background thread, so the window stays responsive during measurements.
+
+use std::{
+    ops::ControlFlow,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    Current, Error, MicroSmu, Result, Voltage,
+    commands::MeasureResponse,
+    record_iv_curve::{IvCurveRecordingParameters, SmuConnectionParameter},
+    sweep_result::SweepResult,
+};
+
+/// Time between the measurements of the live readout.
+const READOUT_INTERVAL: Duration = Duration::from_millis(200);
+
+pub enum Command {
+    /// Connect to the device, closing the previous connection.
+    Connect(Box<SmuConnectionParameter>),
+    /// Close the connection, the output is disabled.
+    Disconnect,
+    /// Measure continuously at a bias voltage, until stopped by [Worker::stop].
+    Readout {
+        voltage: Voltage,
+        current_limit: Current,
+        over_sampling: u16,
+    },
+    /// Record a sweep, reporting every sample. Stopping it by [Worker::stop] keeps the samples so far.
+    Sweep(IvCurveRecordingParameters),
+    /// Close the connection and end the session.
+    Exit,
+}
+
+pub enum Event {
+    Connected {
+        uid: u32,
+        port: Option<String>,
+    },
+    Disconnected,
+    /// A measurement of the live readout.
+    Reading(MeasureResponse),
+    /// A sample of the running sweep.
+    Sample(Voltage, Current),
+    /// A readout or sweep ended, with the result of a sweep.
+    Finished(Option<SweepResult>),
+    Failed(String),
+}
+
+pub struct Worker {
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Start the session, `notify` is called after every event, e.g. to repaint the window.
+    pub fn spawn(notify: impl Fn() + Send + 'static) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let session = Session {
+            smu: None,
+            events: Events {
+                sender: event_sender,
+                notify: Box::new(notify),
+            },
+            stop: stop.clone(),
+        };
+        let thread = thread::spawn(move || session.run(command_receiver));
+        Self {
+            commands,
+            events,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue `command`, it runs after the current readout or sweep.
+    pub fn send(&self, command: Command) {
+        // the session only ends on `Command::Exit`
+        let _ = self.commands.send(command);
+    }
+
+    /// Stop the running readout or sweep.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// The next event, if any.
+    pub fn try_event(&self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for Worker {
+    /// Wait for the output to be disabled, before the application exits.
+    fn drop(&mut self) {
+        self.stop();
+        self.send(Command::Exit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Events {
+    sender: Sender<Event>,
+    notify: Box<dyn Fn() + Send>,
+}
+
+impl Events {
+    fn emit(&self, event: Event) {
+        // nobody listens anymore, if the window closed
+        if self.sender.send(event).is_ok() {
+            (self.notify)();
+        }
+    }
+}
+
+struct Session {
+    smu: Option<MicroSmu>,
+    events: Events,
+    stop: Arc<AtomicBool>,
+}
+
+impl Session {
+    fn run(mut self, commands: Receiver<Command>) {
+        for command in commands {
+            let exit = matches!(command, Command::Exit);
+            if let Err(e) = self.execute(command) {
+                self.events.emit(Event::Failed(e.to_string()));
+            }
+            if exit {
+                break;
+            }
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Connect(parameter) => {
+                self.disconnect()?;
+                let mut smu = parameter.connect()?;
+                let uid = smu.get_identity()?;
+                self.events.emit(Event::Connected {
+                    uid,
+                    port: smu.port_name(),
+                });
+                self.smu = Some(smu);
+            }
+            Command::Disconnect | Command::Exit => self.disconnect()?,
+            Command::Readout {
+                voltage,
+                current_limit,
+                over_sampling,
+            } => {
+                self.readout(voltage, current_limit, over_sampling)?;
+                self.events.emit(Event::Finished(None));
+            }
+            Command::Sweep(parameters) => {
+                let result = self.sweep(&parameters)?;
+                self.events.emit(Event::Finished(Some(result)));
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        if let Some(mut smu) = self.smu.take() {
+            self.events.emit(Event::Disconnected);
+            smu.disable()?;
+        }
+        Ok(())
+    }
+
+    fn readout(
+        &mut self,
+        voltage: Voltage,
+        current_limit: Current,
+        over_sampling: u16,
+    ) -> Result<()> {
+        self.stop.store(false, Ordering::Relaxed);
+        let smu = connected(&mut self.smu)?;
+        smu.set_current_limit(current_limit)?;
+        smu.set_over_sample_rate(over_sampling)?;
+        smu.set_voltage(voltage)?;
+        smu.enable()?;
+
+        let result = loop {
+            if self.stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            match smu.measure(voltage) {
+                Ok(reading) => self.events.emit(Event::Reading(reading)),
+                Err(e) => break Err(e),
+            }
+            thread::sleep(READOUT_INTERVAL);
+        };
+        let disabled = smu.disable();
+        result?;
+        disabled
+    }
+
+    fn sweep(&mut self, parameters: &IvCurveRecordingParameters) -> Result<SweepResult> {
+        self.stop.store(false, Ordering::Relaxed);
+        let smu = connected(&mut self.smu)?;
+        let (events, stop) = (&self.events, &self.stop);
+        let result = parameters.record_with(smu, |voltage, current| {
+            events.emit(Event::Sample(voltage, current));
+            match stop.load(Ordering::Relaxed) {
+                true => ControlFlow::Break(()),
+                false => ControlFlow::Continue(()),
+            }
+        });
+        if result.is_err() {
+            // the sweep only disables the output on success
+            let _ = smu.disable();
+        }
+        result
+    }
+}
+
+fn connected(smu: &mut Option<MicroSmu>) -> Result<&mut MicroSmu> {
+    smu.as_mut()
+        .ok_or_else(|| Error::InvalidState("Not connected to a uSMU.".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::{simulator::SimulatedDut, sweep_result::SweepStatus, volt};
+
+    use super::*;
+
+    fn wait(worker: &Worker) -> Event {
+        loop {
+            if let Some(event) = worker.try_event() {
+                return event;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn runs_sweeps_in_background() {
+        let worker = Worker::spawn(|| {});
+        worker.send(Command::Sweep(IvCurveRecordingParameters::default()));
+        assert!(matches!(wait(&worker), Event::Failed(_)));
+
+        let mut parameter = SmuConnectionParameter::parse_from(["usmu-gui"]);
+        parameter.simulation_parameter.simulate = Some(SimulatedDut::Resistor);
+        worker.send(Command::Connect(Box::new(parameter)));
+        assert!(matches!(wait(&worker), Event::Connected { .. }));
+
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            end_voltage: Voltage::new::<volt>(1.0),
+            voltage_steps: 5,
+            ..Default::default()
+        };
+        worker.send(Command::Sweep(parameters));
+        let mut samples = 0;
+        let result = loop {
+            match wait(&worker) {
+                Event::Sample(..) => samples += 1,
+                Event::Finished(Some(result)) => break result,
+                _ => panic!("unexpected event"),
+            }
+        };
+        assert_eq!(samples, 5);
+        assert_eq!(result.samples.len(), 5);
+        assert_eq!(result.status, SweepStatus::Completed);
+    }
+}
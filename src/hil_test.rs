@@ -0,0 +1,421 @@
+//! Hardware-in-the-loop self-test of a device and this driver against known test fixtures,
+//! e.g. to validate a lab's devices after every update.
+//!
+//! Each [Fixture] attached to the output is measured at a set of voltages, every voltage is a test case
+//! passing if all values are within their tolerances. The results are reported as JUnit XML,
+//! one test suite per fixture, to be collected by the usual CI tooling.
+
+use std::{
+    fmt::Display,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+use uom::si::electrical_resistance::ohm;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Voltage, ampere, analysis, commands::MeasureResponse,
+    record_iv_curve::SmuConnectionParameter, volt,
+};
+
+/// A known load attached to the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Fixture {
+    /// Nothing attached, the output voltage follows the set voltage without current.
+    Open,
+    /// Output shorted, the current is clamped at the current limit.
+    Short,
+    /// The reference resistor, the current follows Ohm's law.
+    Resistor,
+}
+
+impl Display for Fixture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Fixture::Open => "open",
+            Fixture::Short => "short",
+            Fixture::Resistor => "resistor",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HilTestParameters {
+    /// Voltages tested with each fixture, the short skips 0 V.
+    pub voltages: Vec<Voltage>,
+    pub current_limit: Current,
+    /// Resistance of the reference resistor in ohm.
+    pub resistance: f32,
+    pub voltage_tolerance: Voltage,
+    /// Tolerance of the current through the open fixture, and in addition to the relative tolerance at the resistor.
+    pub current_tolerance: Current,
+    /// Relative tolerance of the current limit at the short, the current at the resistor and its resistance.
+    pub relative_tolerance: f64,
+    /// Number of samples averaged per measurement.
+    pub over_sampling: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// A value is out of tolerance.
+    Failed(String),
+    /// The measurement failed, e.g. by a communication error.
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub time: Duration,
+    pub outcome: Outcome,
+}
+
+/// The test cases of one fixture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestSuite {
+    pub fixture: Fixture,
+    /// UID of the device tested.
+    pub uid: u32,
+    pub cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    pub fn failures(&self) -> usize {
+        self.count(|e| matches!(e, Outcome::Failed(_)))
+    }
+
+    pub fn errors(&self) -> usize {
+        self.count(|e| matches!(e, Outcome::Error(_)))
+    }
+
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases.iter().filter(|e| f(&e.outcome)).count()
+    }
+
+    fn time(&self) -> Duration {
+        self.cases.iter().map(|e| e.time).sum()
+    }
+}
+
+impl HilTestParameters {
+    /// Test `smu` with `fixture` attached, the output is disabled afterwards.
+    pub fn run(&self, smu: &mut MicroSmu, fixture: Fixture) -> Result<TestSuite> {
+        let uid = smu.get_identity()?;
+        let cases = self.cases(smu, fixture);
+        let disabled = smu.disable();
+        let cases = cases?;
+        disabled?;
+        Ok(TestSuite {
+            fixture,
+            uid,
+            cases,
+        })
+    }
+
+    fn cases(&self, smu: &mut MicroSmu, fixture: Fixture) -> Result<Vec<TestCase>> {
+        smu.set_over_sample_rate(self.over_sampling)?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.set_voltage(Voltage::new::<volt>(0.0))?;
+        smu.enable()?;
+
+        let voltages = self
+            .voltages
+            .iter()
+            .filter(|e| fixture != Fixture::Short || e.value != 0.0);
+        let mut points = Vec::new();
+        let mut cases: Vec<TestCase> = voltages
+            .map(|&voltage| {
+                case(format!("{fixture} at {} V", voltage.get::<volt>()), || {
+                    let measured = smu.measure(voltage)?;
+                    points.push((measured.voltage, measured.current));
+                    Ok(self.check(fixture, voltage, measured))
+                })
+            })
+            .collect();
+        if fixture == Fixture::Resistor {
+            cases.push(case("resistance".to_string(), || {
+                let expected = f64::from(self.resistance);
+                let failure = match analysis::resistance(&points) {
+                    Some(resistance) => within(
+                        "resistance",
+                        f64::from(resistance.get::<ohm>()),
+                        expected,
+                        self.relative_tolerance * expected,
+                        "Ω",
+                    ),
+                    None => Some("too few points to fit the resistance".to_string()),
+                };
+                Ok(failure.into_iter().collect())
+            }));
+        }
+        Ok(cases)
+    }
+
+    /// The values of `measured` out of tolerance.
+    fn check(&self, fixture: Fixture, voltage: Voltage, measured: MeasureResponse) -> Vec<String> {
+        let set = f64::from(voltage.get::<volt>());
+        let voltage = f64::from(measured.voltage.get::<volt>());
+        let current = f64::from(measured.current.get::<ampere>());
+        let voltage_tolerance = f64::from(self.voltage_tolerance.get::<volt>());
+        let current_tolerance = f64::from(self.current_tolerance.get::<ampere>());
+        let limit = f64::from(self.current_limit.get::<ampere>());
+        let failures = match fixture {
+            Fixture::Open => [
+                within("voltage", voltage, set, voltage_tolerance, "V"),
+                within("current", current, 0.0, current_tolerance, "A"),
+            ],
+            Fixture::Short => [
+                within("voltage", voltage, 0.0, voltage_tolerance, "V"),
+                within(
+                    "current",
+                    current,
+                    limit.copysign(set),
+                    self.relative_tolerance * limit,
+                    "A",
+                ),
+            ],
+            Fixture::Resistor => {
+                let expected = voltage / f64::from(self.resistance);
+                let tolerance = self.relative_tolerance * expected.abs() + current_tolerance;
+                [
+                    within("voltage", voltage, set, voltage_tolerance, "V"),
+                    within("current", current, expected, tolerance, "A"),
+                ]
+            }
+        };
+        failures.into_iter().flatten().collect()
+    }
+}
+
+/// Run a test case, `f` returns the failures.
+fn case(name: String, f: impl FnOnce() -> Result<Vec<String>>) -> TestCase {
+    let start = Instant::now();
+    let outcome = match f() {
+        Ok(failures) if failures.is_empty() => Outcome::Passed,
+        Ok(failures) => Outcome::Failed(failures.join(", ")),
+        Err(e) => Outcome::Error(e.to_string()),
+    };
+    TestCase {
+        name,
+        time: start.elapsed(),
+        outcome,
+    }
+}
+
+/// The failure, if `value` is not within `tolerance` of `expected`.
+fn within(quantity: &str, value: f64, expected: f64, tolerance: f64, unit: &str) -> Option<String> {
+    ((value - expected).abs() > tolerance).then(|| {
+        format!(
+            "{quantity} {value:.6e} {unit} is not within {expected:.6e} ± {tolerance:.1e} {unit}"
+        )
+    })
+}
+
+/// Write the suites as JUnit XML report.
+pub fn write_junit(suites: &[TestSuite], mut output: impl Write) -> Result<()> {
+    let sum = |f: fn(&TestSuite) -> usize| suites.iter().map(f).sum::<usize>();
+    let time: Duration = suites.iter().map(TestSuite::time).sum();
+    writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        output,
+        r#"<testsuites name="usmu hil-test" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+        sum(|e| e.cases.len()),
+        sum(TestSuite::failures),
+        sum(TestSuite::errors),
+        time.as_secs_f64()
+    )?;
+    for suite in suites {
+        writeln!(
+            output,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+            suite.fixture,
+            suite.cases.len(),
+            suite.failures(),
+            suite.errors(),
+            suite.time().as_secs_f64()
+        )?;
+        writeln!(output, "    <properties>")?;
+        writeln!(
+            output,
+            r#"      <property name="uid" value="{}"/>"#,
+            suite.uid
+        )?;
+        writeln!(output, "    </properties>")?;
+        for case in &suite.cases {
+            let testcase = format!(
+                r#"    <testcase classname="usmu.hil.{}" name="{}" time="{:.3}""#,
+                suite.fixture,
+                escape(&case.name),
+                case.time.as_secs_f64()
+            );
+            match &case.outcome {
+                Outcome::Passed => writeln!(output, "{testcase}/>")?,
+                Outcome::Failed(message) => writeln!(
+                    output,
+                    "{testcase}>\n      <failure message=\"{}\"/>\n    </testcase>",
+                    escape(message)
+                )?,
+                Outcome::Error(message) => writeln!(
+                    output,
+                    "{testcase}>\n      <error message=\"{}\"/>\n    </testcase>",
+                    escape(message)
+                )?,
+            }
+        }
+        writeln!(output, "  </testsuite>")?;
+    }
+    writeln!(output, "</testsuites>")?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[derive(Debug, Parser)]
+pub struct HilTestArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Fixtures to test in sequence, e.g. `open,short,resistor`.
+    /// The operator is asked to attach each one, unless only one is given.
+    #[arg(long, short = 'f', value_delimiter = ',', required = true)]
+    pub fixture: Vec<Fixture>,
+
+    /// Resistance of the reference resistor fixture in ohm.
+    #[arg(long, default_value_t = 1000.0)]
+    pub resistance: f32,
+
+    /// Voltages tested with each fixture.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        default_value = "-2 V,-1 V,0 V,1 V,2 V"
+    )]
+    pub voltages: Vec<Voltage>,
+
+    #[arg(long, short = 'c', default_value = "10 mA")]
+    pub current_limit: Current,
+
+    #[arg(long, default_value = "10 mV")]
+    pub voltage_tolerance: Voltage,
+
+    /// Tolerance of the current through the open fixture, and in addition to the relative tolerance at the resistor.
+    #[arg(long, default_value = "0.001 mA")]
+    pub current_tolerance: Current,
+
+    /// Relative tolerance of the current limit at the short, the current at the resistor and its resistance.
+    #[arg(long, default_value_t = 0.01)]
+    pub relative_tolerance: f64,
+
+    /// Number of samples averaged per measurement.
+    #[arg(long, short = 'r', default_value_t = 10)]
+    pub over_sampling: u16,
+
+    /// Write the JUnit XML report to this file instead of stdout.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+impl HilTestArguments {
+    pub fn run(&self) -> Result<()> {
+        let parameters = HilTestParameters {
+            voltages: self.voltages.clone(),
+            current_limit: self.current_limit,
+            resistance: self.resistance,
+            voltage_tolerance: self.voltage_tolerance,
+            current_tolerance: self.current_tolerance,
+            relative_tolerance: self.relative_tolerance,
+            over_sampling: self.over_sampling,
+        };
+        let mut smu = self.connection_parameter.connect()?;
+        let mut suites = Vec::new();
+        for &fixture in &self.fixture {
+            if self.fixture.len() > 1 {
+                eprint!("Attach the {fixture} fixture to the output and press Enter: ");
+                std::io::stdin().read_line(&mut String::new())?;
+            }
+            let suite = parameters.run(&mut smu, fixture)?;
+            eprintln!(
+                "{fixture}: {} tests, {} failures, {} errors",
+                suite.cases.len(),
+                suite.failures(),
+                suite.errors()
+            );
+            suites.push(suite);
+        }
+
+        match self.output.as_ref() {
+            Some(output) => write_junit(&suites, std::fs::File::create(output)?)?,
+            None => write_junit(&suites, std::io::stdout().lock())?,
+        }
+        let failed: usize = suites.iter().map(|e| e.failures() + e.errors()).sum();
+        if failed > 0 {
+            Err(Error::Data(format!(
+                "{failed} of {} hardware tests failed.",
+                suites.iter().map(|e| e.cases.len()).sum::<usize>()
+            )))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        milliampere,
+        simulator::{Open, Resistor, Short, SimulatedSmu},
+    };
+
+    use super::*;
+
+    #[test]
+    fn tests_fixtures() {
+        let parameters = HilTestParameters {
+            voltages: [-1.0, 0.0, 1.0].map(Voltage::new::<volt>).to_vec(),
+            current_limit: Current::new::<milliampere>(10.0),
+            resistance: 1000.0,
+            voltage_tolerance: Voltage::new::<volt>(0.01),
+            current_tolerance: Current::new::<milliampere>(0.001),
+            relative_tolerance: 0.01,
+            over_sampling: 1,
+        };
+        let port = SimulatedSmu::new(Open).with_uid(42);
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let mut suites = vec![parameters.run(&mut smu, Fixture::Open).unwrap()];
+        port.attach(Short);
+        suites.push(parameters.run(&mut smu, Fixture::Short).unwrap());
+        port.attach(Resistor::ohm(1000.0));
+        suites.push(parameters.run(&mut smu, Fixture::Resistor).unwrap());
+        assert_eq!(
+            suites.iter().map(|e| e.cases.len()).collect::<Vec<_>>(),
+            vec![3, 2, 4]
+        );
+        assert!(suites.iter().all(|e| e.failures() == 0 && e.errors() == 0));
+
+        // the resistor draws current at the open fixture
+        let wrong = parameters.run(&mut smu, Fixture::Open).unwrap();
+        assert_eq!(wrong.failures(), 2);
+        suites.push(wrong);
+
+        let mut report = Vec::new();
+        write_junit(&suites, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(
+            report
+                .contains(r#"<testsuites name="usmu hil-test" tests="12" failures="2" errors="0""#)
+        );
+        assert!(report.contains(r#"<testcase classname="usmu.hil.resistor" name="resistance""#));
+        assert!(report.contains(r#"<property name="uid" value="42"/>"#));
+        assert!(report.contains("<failure message=\"current 1.000000e-3 A is not within"));
+    }
+}
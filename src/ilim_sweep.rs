@@ -0,0 +1,234 @@
+//! Characterization of the current limit DAC, to derive its calibration empirically.
+//!
+//! The output drives a known load, e.g. a resistor, with a voltage high enough for the current limit to clamp
+//! the current at every code of the sweep. The measured current over the raw DAC code is the transfer curve
+//! of the current limit, its least squares line the basis of the coefficients written with
+//! [MicroSmu::write_current_limit_dac].
+
+use std::{io::Write, path::PathBuf};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Voltage, ampere, analysis::line_fit,
+    commands::MeasureResponse, milliampere, record_iv_curve::SmuConnectionParameter, schema, volt,
+};
+
+/// Highest code of the 12 bit current limit DAC.
+pub const MAX_CODE: u16 = 4095;
+
+#[derive(Debug, Clone)]
+pub struct IlimSweepParameters {
+    /// Voltage driving the load, high enough to reach the current limit at all codes.
+    pub voltage: Voltage,
+    pub start_code: u16,
+    pub end_code: u16,
+    /// Number of codes, evenly spaced from the start to the end code.
+    pub steps: usize,
+    /// Number of samples averaged per reading.
+    pub over_sampling: u16,
+}
+
+/// The current measured with the current limit DAC at `code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IlimSample {
+    pub code: u16,
+    pub voltage: Voltage,
+    pub current: Current,
+}
+
+/// Least squares line of the current over the code, in milliampere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferFit {
+    /// Current per code in milliampere.
+    pub slope: f64,
+    /// Current at code 0 in milliampere.
+    pub intercept: f64,
+}
+
+impl IlimSweepParameters {
+    /// The codes of the sweep.
+    pub fn codes(&self) -> Vec<u16> {
+        let (start, end) = (i64::from(self.start_code), i64::from(self.end_code));
+        let intervals = self.steps.saturating_sub(1).max(1) as i64;
+        let mut codes: Vec<u16> = (0..self.steps as i64)
+            .map(|step| (start + (end - start) * step / intervals) as u16)
+            .collect();
+        codes.dedup();
+        codes
+    }
+
+    /// Sweep the current limit DAC, measuring the clamped current at each code.
+    ///
+    /// The output is disabled afterwards, also on failure.
+    /// The current limit DAC keeps the last code until the next [MicroSmu::set_current_limit].
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<IlimSample>> {
+        if self.start_code.max(self.end_code) > MAX_CODE {
+            Err(Error::Configuration(format!(
+                "The current limit DAC codes range from 0 to {MAX_CODE}."
+            )))?;
+        }
+        let samples = self.sweep(smu);
+        let disabled = smu.disable();
+        let samples = samples?;
+        disabled?;
+        Ok(samples)
+    }
+
+    fn sweep(&self, smu: &mut MicroSmu) -> Result<Vec<IlimSample>> {
+        smu.set_over_sample_rate(self.over_sampling)?;
+        smu.set_current_limit_dac(self.start_code)?;
+        smu.set_voltage(self.voltage)?;
+        smu.enable()?;
+
+        self.codes()
+            .into_iter()
+            .map(|code| {
+                smu.set_current_limit_dac(code)?;
+                let MeasureResponse { voltage, current } = smu.measure(self.voltage)?;
+                Ok(IlimSample {
+                    code,
+                    voltage,
+                    current,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Fit the transfer curve, `None` for less than two codes.
+///
+/// Codes at which the load draws less than the limit, i.e. the output voltage reaches the driving voltage,
+/// should be excluded before.
+pub fn transfer_fit(samples: &[IlimSample]) -> Option<TransferFit> {
+    let (intercept, slope) = line_fit(
+        samples
+            .iter()
+            .map(|e| (f64::from(e.code), f64::from(e.current.get::<milliampere>()))),
+    )?;
+    Some(TransferFit { slope, intercept })
+}
+
+/// Write the samples as CSV with the columns `code`, `voltage` and `current`.
+pub fn write_csv(samples: &[IlimSample], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        code: u16,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for sample in samples {
+        writer.serialize(Row {
+            code: sample.code,
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct IlimSweepArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Voltage driving the load, high enough to reach the current limit at all codes.
+    #[arg(long, short = 'v', default_value = "4 V")]
+    pub voltage: Voltage,
+
+    #[arg(long, default_value_t = 0)]
+    pub start_code: u16,
+
+    #[arg(long, default_value_t = MAX_CODE)]
+    pub end_code: u16,
+
+    /// Number of codes, evenly spaced from the start to the end code.
+    #[arg(long, short = 'n', default_value_t = 32)]
+    pub steps: usize,
+
+    /// Number of samples averaged per reading.
+    #[arg(long, short = 'r', default_value_t = 10)]
+    pub over_sampling: u16,
+
+    /// Write the transfer curve as CSV to this file instead of stdout.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+impl IlimSweepArguments {
+    pub fn run(&self) -> Result<()> {
+        let parameters = IlimSweepParameters {
+            voltage: self.voltage,
+            start_code: self.start_code,
+            end_code: self.end_code,
+            steps: self.steps,
+            over_sampling: self.over_sampling,
+        };
+        let mut smu = self.connection_parameter.connect()?;
+        let samples = parameters.record(&mut smu)?;
+
+        // the current is only limited while the output voltage is below the driving voltage
+        let clamped: Vec<IlimSample> = samples
+            .iter()
+            .copied()
+            .filter(|e| (e.voltage - self.voltage).abs() > self.voltage.abs() * 0.01)
+            .collect();
+        if clamped.len() < samples.len() {
+            eprintln!(
+                "{} of {} codes did not limit the current, increase the voltage or lower the load resistance.",
+                samples.len() - clamped.len(),
+                samples.len()
+            );
+        }
+        match transfer_fit(&clamped) {
+            Some(TransferFit { slope, intercept }) => eprintln!(
+                "Transfer: I = {slope:.6e} mA × code {intercept:+.6e} mA ({} codes)",
+                clamped.len()
+            ),
+            None => eprintln!("Too few codes limited the current to fit the transfer."),
+        }
+
+        match self.output.as_ref() {
+            Some(output) => write_csv(&samples, std::fs::File::create(output)?),
+            None => write_csv(&samples, std::io::stdout().lock()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulator::{Resistor, SimulatedSmu};
+
+    use super::*;
+
+    #[test]
+    fn sweeps_current_limit_codes() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(100.0))));
+        let parameters = IlimSweepParameters {
+            voltage: Voltage::new::<volt>(4.0),
+            start_code: 0,
+            end_code: 3000,
+            steps: 4,
+            over_sampling: 1,
+        };
+        assert_eq!(parameters.codes(), vec![0, 1000, 2000, 3000]);
+
+        let samples = parameters.record(&mut smu).unwrap();
+        assert_eq!(samples.len(), 4);
+        assert!(samples[3].voltage < parameters.voltage);
+        let fit = transfer_fit(&samples).unwrap();
+        assert!((fit.slope - 40.0 / 4095.0).abs() < 1e-6);
+        assert!(fit.intercept.abs() < 1e-3);
+
+        let invalid = IlimSweepParameters {
+            end_code: MAX_CODE + 1,
+            ..parameters
+        };
+        assert!(invalid.record(&mut smu).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! External interlocks permitting the operation of the uSMU, e.g. a closed enclosure lid or a supervising process.
+//!
+//! [MicroSmu](crate::MicroSmu) checks its [Interlock] before enabling the output and, while the output is enabled,
+//! before each voltage setting and measurement, i.e. between the points of a sweep.
+//! If the interlock is open, the output is disabled immediately, without a ramp-down,
+//! and the operation fails with [Error::Interlock].
+//!
+//! Interlocks are available as a file ([FileInterlock]), TCP heartbeats ([TcpHeartbeat]) and,
+//! with the `gpio` feature, a Linux GPIO input line ([GpioInterlock]).
+
+use std::{
+    fmt::Display,
+    io::{ErrorKind, Read},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{Error, Result};
+
+pub trait Interlock: Send {
+    /// Fails with [Error::Interlock], if the interlock is open.
+    fn check(&mut self) -> Result<()>;
+}
+
+/// Closed while the file exists, e.g. created by a lid switch daemon or removed by an operator to stop.
+pub struct FileInterlock {
+    path: PathBuf,
+}
+
+impl FileInterlock {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Interlock for FileInterlock {
+    fn check(&mut self) -> Result<()> {
+        if !self.path.exists() {
+            return Err(Error::Interlock(format!(
+                "{} does not exist",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Closed while data is received on a TCP connection at least once per timeout,
+/// e.g. from a supervising process sending heartbeats. Once lost, the connection is not reestablished.
+pub struct TcpHeartbeat {
+    stream: Option<TcpStream>,
+    timeout: Duration,
+    last_heartbeat: Instant,
+}
+
+impl TcpHeartbeat {
+    pub fn new(stream: TcpStream, timeout: Duration) -> Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream: Some(stream),
+            timeout,
+            last_heartbeat: Instant::now(),
+        })
+    }
+
+    /// Connect to a heartbeat source listening on `address`.
+    pub fn connect(address: impl ToSocketAddrs, timeout: Duration) -> Result<Self> {
+        Self::new(TcpStream::connect(address)?, timeout)
+    }
+}
+
+impl Interlock for TcpHeartbeat {
+    fn check(&mut self) -> Result<()> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Err(Error::Interlock("heartbeat connection lost".to_string()));
+        };
+        let mut buffer = [0; 64];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.stream = None;
+                    return Err(Error::Interlock("heartbeat connection closed".to_string()));
+                }
+                Ok(_) => self.last_heartbeat = Instant::now(),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.stream = None;
+                    return Err(Error::Interlock(format!(
+                        "heartbeat connection failed: {e}"
+                    )));
+                }
+            }
+        }
+        let elapsed = self.last_heartbeat.elapsed();
+        if elapsed > self.timeout {
+            return Err(Error::Interlock(format!(
+                "no heartbeat for {:.1} s",
+                elapsed.as_secs_f32()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Closed while a GPIO input line of the Linux character device interface is high.
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+pub struct GpioInterlock {
+    handle: gpio_cdev::LineHandle,
+    line: u32,
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl GpioInterlock {
+    pub fn new(chip: impl AsRef<std::path::Path>, line: u32) -> Result<Self> {
+        use gpio_cdev::{Chip, LineRequestFlags};
+
+        let handle = Chip::new(chip)
+            .and_then(|mut e| e.get_line(line))
+            .and_then(|e| e.request(LineRequestFlags::INPUT, 0, "usmu"))
+            .map_err(|e| {
+                Error::External(format!("Failed to request GPIO input line {line}: {e}"))
+            })?;
+        Ok(Self { handle, line })
+    }
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl Interlock for GpioInterlock {
+    fn check(&mut self) -> Result<()> {
+        match self.handle.get_value() {
+            Ok(1) => Ok(()),
+            Ok(_) => Err(Error::Interlock(format!("GPIO line {} is low", self.line))),
+            Err(e) => Err(Error::Interlock(format!(
+                "failed to read GPIO line {}: {e}",
+                self.line
+            ))),
+        }
+    }
+}
+
+/// Command line notation of an interlock, `file:PATH`, `tcp:ADDRESS` or `gpio:CHIP:LINE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterlockSpec {
+    File(PathBuf),
+    Tcp(String),
+    Gpio { chip: PathBuf, line: u32 },
+}
+
+impl InterlockSpec {
+    /// Open the interlock, `timeout` is the maximum interval of TCP heartbeats.
+    pub fn open(&self, timeout: Duration) -> Result<Box<dyn Interlock>> {
+        Ok(match self {
+            InterlockSpec::File(path) => Box::new(FileInterlock::new(path)),
+            InterlockSpec::Tcp(address) => {
+                Box::new(TcpHeartbeat::connect(address.as_str(), timeout)?)
+            }
+            #[cfg(all(feature = "gpio", target_os = "linux"))]
+            InterlockSpec::Gpio { chip, line } => Box::new(GpioInterlock::new(chip, *line)?),
+            #[cfg(not(all(feature = "gpio", target_os = "linux")))]
+            InterlockSpec::Gpio { .. } => Err(Error::Configuration(
+                "GPIO interlocks require the `gpio` feature and Linux.".to_string(),
+            ))?,
+        })
+    }
+}
+
+impl Display for InterlockSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterlockSpec::File(path) => write!(f, "file:{}", path.display()),
+            InterlockSpec::Tcp(address) => write!(f, "tcp:{address}"),
+            InterlockSpec::Gpio { chip, line } => write!(f, "gpio:{}:{line}", chip.display()),
+        }
+    }
+}
+
+impl FromStr for InterlockSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, rest) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected KIND:PARAMETERS, got '{s}'"))?;
+        match kind {
+            "file" => Ok(InterlockSpec::File(rest.into())),
+            "tcp" => Ok(InterlockSpec::Tcp(rest.to_string())),
+            "gpio" => {
+                let (chip, line) = rest
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("expected gpio:CHIP:LINE, got '{s}'"))?;
+                let line = line
+                    .parse()
+                    .map_err(|_| format!("invalid GPIO line '{line}'"))?;
+                Ok(InterlockSpec::Gpio {
+                    chip: chip.into(),
+                    line,
+                })
+            }
+            kind => Err(format!(
+                "unknown interlock '{kind}', expected file, tcp or gpio"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, net::TcpListener};
+
+    use crate::{MicroSmu, Voltage, test_util::FakeSerialPort, volt};
+
+    use super::*;
+
+    #[test]
+    fn disables_when_opened() {
+        let path = std::env::temp_dir().join(format!("usmu-interlock-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let port = FakeSerialPort::new();
+        port.expect("CH1:ENA")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let spec: InterlockSpec = format!("file:{}", path.display()).parse().unwrap();
+        smu.set_interlock(Some(spec.open(Duration::ZERO).unwrap()));
+
+        smu.enable().unwrap();
+        smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let result = smu.measure(Voltage::new::<volt>(1.0));
+        assert!(matches!(result, Err(Error::Interlock(_))), "{result:?}");
+        assert!(matches!(smu.enable(), Err(Error::Interlock(_))));
+        port.verify();
+    }
+
+    #[test]
+    fn expects_heartbeats() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut interlock =
+            TcpHeartbeat::connect(listener.local_addr().unwrap(), Duration::from_millis(200))
+                .unwrap();
+        let (mut source, _) = listener.accept().unwrap();
+
+        interlock.check().unwrap();
+        std::thread::sleep(Duration::from_millis(150));
+        source.write_all(b"\n").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        interlock.check().unwrap();
+        std::thread::sleep(Duration::from_millis(250));
+        assert!(interlock.check().is_err());
+        drop(source);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(interlock.check().is_err());
+
+        assert_eq!(
+            "gpio:/dev/gpiochip0:17".parse(),
+            Ok(InterlockSpec::Gpio {
+                chip: "/dev/gpiochip0".into(),
+                line: 17
+            })
+        );
+        assert!("usb:1".parse::<InterlockSpec>().is_err());
+    }
+}
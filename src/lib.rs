@@ -1,14 +1,29 @@
 use std::{
-    io::{BufRead, BufReader},
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use scpi_client::{
-    EmptyResponse, ScpiDeserialize, ScpiRequest, ScpiSerialize, check_empty, match_literal,
-};
 use serialport::{SerialPort, SerialPortInfo};
 
+use crate::{
+    auto_range::LockedRange,
+    builder::{DEFAULT_INTER_COMMAND_DELAY, MicroSmuBuilder, TIMEOUT_PER_SAMPLE},
+    calibration::{CURRENT_RANGES, LinearCalibration},
+    calibration_log::CalibrationLog,
+    calibration_override::CalibrationOverride,
+    guard::EnabledGuard,
+    interlock::Interlock,
+    protection::{ProtectionCounters, ProtectionEvent, ProtectionLog, ProtectionRecord},
+    reconnect::ReconnectPolicy,
+    retry::RetryPolicy,
+    safety::SafetyLimits,
+    scpi::{EmptyResponse, ScpiDeserialize, ScpiRequest},
+    stream::MeasurementStream,
+    sweep_result::COMPLIANCE_THRESHOLD,
+    temperature_compensation::TemperatureCompensation,
+    transport::Transport,
+};
+
 use crate::commands::{
     CurrentRange, DifferentialConversionRequest, DisableRequest, EepromAddress, EnableRequest,
     EnableVoltageCalibrationModeRequest, IdentityRequest,
@@ -22,76 +37,583 @@ use crate::commands::{
 pub type Current = uom::si::f32::ElectricCurrent;
 pub type Voltage = uom::si::f32::ElectricPotential;
 pub type Time = uom::si::f32::Time;
+pub type Power = uom::si::f32::Power;
+pub type Resistance = uom::si::f32::ElectricalResistance;
+pub type Temperature = uom::si::f32::ThermodynamicTemperature;
+pub type TemperatureInterval = uom::si::f32::TemperatureInterval;
+// Integrated quantities accumulate many small increments, hence double precision.
+pub type Charge = uom::si::f64::ElectricCharge;
+pub type Energy = uom::si::f64::Energy;
 
 pub use uom::si::electric_current::{ampere, milliampere};
 pub use uom::si::electric_potential::{millivolt, volt};
 pub use uom::si::time::{millisecond, second};
 
+pub mod adaptive_over_sampling;
+#[cfg(feature = "alerts")]
+pub mod alert;
+pub mod analysis;
+#[cfg(feature = "cli")]
+pub mod analyze;
+pub mod annotation;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "async")]
+pub mod async_smu;
+pub mod auto_range;
+pub mod auxiliary;
+pub mod battery;
+pub mod breakdown;
+pub mod budget;
+pub mod builder;
+pub mod calibration;
+pub mod calibration_log;
+pub mod calibration_override;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capture;
+pub mod cassette;
+pub mod charge;
+#[cfg(feature = "checksum")]
+pub mod checksum;
+#[cfg(feature = "cli")]
+pub mod cli;
 pub mod commands;
+pub mod debug;
+pub mod decimation;
+pub mod devices;
+#[cfg(all(feature = "cli", any(feature = "config", feature = "json")))]
+pub mod eeprom;
+#[cfg(feature = "evcxr")]
+pub mod evcxr;
+#[cfg(feature = "event-log")]
+pub mod event_log;
+#[cfg(feature = "exporter")]
+pub mod exporter;
+pub mod filters;
+pub mod guard;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod hil_test;
+pub mod ilim_sweep;
+pub mod interlock;
+pub mod monitor;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod notify;
+#[cfg(feature = "polars")]
+pub mod polars;
+pub mod protection;
+pub mod pulsed;
+pub mod reconnect;
 pub mod record_iv_curve;
+pub mod regulation;
+pub mod resample;
+pub mod retry;
+pub mod rolling_buffer;
+pub mod safety;
+pub mod schema;
+pub mod scpi;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod shared;
+pub mod simulator;
+#[cfg(feature = "spectrum")]
+pub mod spectrum;
+pub mod statistics;
+pub mod step_refinement;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod stream;
+pub mod stress;
+pub mod sweep_file;
+pub mod sweep_result;
+pub mod switch;
+#[cfg(feature = "opentelemetry")]
+mod telemetry;
+pub mod temperature_compensation;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod thermal;
+pub mod timestamp;
+pub mod timing;
+pub mod transport;
+pub mod trigger;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("{0}")]
-    ScpiClient(#[from] scpi_client::Error),
+    ScpiClient(#[from] scpi::Error),
+    /// A response could not be parsed, `token` is the data at which parsing failed, e.g. a malformed number.
+    #[error("Invalid response at {token:?}: {source}")]
+    InvalidResponse { token: String, source: scpi::Error },
     #[error("IOError: {0}")]
     IoError(#[from] std::io::Error),
     #[error("serialport error: {0}")]
     Serialport(#[from] serialport::Error),
+    #[error("Safety limit: {0}")]
+    SafetyLimit(String),
+    #[error("Interlock open: {0}")]
+    Interlock(String),
+    /// An exchange with the device failed, with the command sent and the raw response read until the failure.
+    #[error("{source} (command {command:?}, response {response:?})")]
+    Exchange {
+        command: String,
+        response: String,
+        source: Box<Error>,
+    },
+    /// No device or resource was found, e.g. no uSMU with the requested UID.
+    #[error("{0}")]
+    NotFound(String),
+    /// The device to connect to is ambiguous, e.g. multiple uSMUs are attached.
+    #[error("{0}")]
+    Ambiguous(String),
+    /// The connection was lost and could not be recovered, see [MicroSmu::recover].
+    #[error("{0}")]
+    Disconnected(String),
+    /// A parameter is out of the range of the device, e.g. a current limit above 40 mA.
+    #[error("{0}")]
+    InvalidParameter(String),
+    /// Invalid parameters or configuration, e.g. a non-positive step or an unsupported file type.
+    #[error("{0}")]
+    Configuration(String),
+    /// Invalid or unsupported data, e.g. a malformed sweep file or a checksum mismatch.
+    #[error("{0}")]
+    Data(String),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    /// An external resource failed, e.g. a GPIO line, a sensor, a hook or a notification service.
     #[error("{0}")]
-    Other(#[from] anyhow::Error),
+    External(String),
+    /// An operation did not complete in time, e.g. a temperature did not stabilize.
+    #[error("{0}")]
+    Timeout(String),
+    /// An operation is not possible in the current state, e.g. fetching an acquisition that was not triggered.
+    #[error("{0}")]
+    InvalidState(String),
+    /// The operator aborted the operation.
+    #[error("{0}")]
+    Aborted(String),
+    /// A background thread panicked or a shared handle is poisoned.
+    #[error("{0}")]
+    Internal(String),
+    /// All attempts of a retried query failed, with the failure of each, see [retry].
+    #[error("All {} attempts failed: {}", .0.len(), join_errors(.0))]
+    Retried(Vec<Error>),
+}
+
+fn join_errors(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-pub struct MicroSmu {
-    port: Box<dyn SerialPort>,
+/// The uSMU connected by a [Transport], by default its serial port.
+pub struct MicroSmu<T = Box<dyn SerialPort>> {
+    port: T,
+    /// The timeout set, the port waits longer with over-sampling, see [Self::set_timeout].
+    timeout: Duration,
+    inter_command_delay: Duration,
+    safety_limits: SafetyLimits,
+    /// The last voltage and current limit set, for checking the safety limits.
+    voltage: Option<Voltage>,
+    current_limit: Option<Current>,
+    /// Whether the output was enabled by [Self::enable].
+    enabled: bool,
+    soft_start: Option<Time>,
+    ramp_down: Option<Time>,
+    interlock: Option<Box<dyn Interlock>>,
+    protection: ProtectionLog,
+    over_sample_rate: Option<u16>,
+    /// The UID of the device to reconnect to and the policy.
+    reconnect: Option<(u32, ReconnectPolicy<T>)>,
+    reconnects: u32,
+    retry: Option<RetryPolicy>,
+    calibration_log: Option<CalibrationLog>,
+    calibration_override: Option<CalibrationOverride>,
+    temperature_compensation: Option<TemperatureCompensation>,
+    /// The range locked by [Self::lock_current_range].
+    current_range: Option<CurrentRange>,
+    /// The calibration of the locked range applied on the host, until [Self::restore_current_calibration].
+    current_range_calibration: Option<LinearCalibration>,
 }
 
 impl MicroSmu {
+    /// Open the serial port of the uSMU with the default communication parameters, see [builder].
     pub fn open(port: SerialPortInfo) -> Result<MicroSmu> {
-        const BAUDRATE: u32 = 9600;
-        let port = serialport::new(port.port_name, BAUDRATE)
-            // We need a gracious timeout because the device will not answer
-            // while performing the measurement and stalls the connection.
-            // The value is based on the python reference implementation.
-            // Note, that for high over sampling values this is still not sufficient.
-            .timeout(Duration::from_millis(1000))
-            .open()?;
-        let smu = Self::new(port);
-        Ok(smu)
+        Self::builder(port).open()
+    }
+
+    /// Open the serial port configured for the uSMU, e.g. to wrap it before passing it to [Self::new].
+    pub fn open_port(port: SerialPortInfo) -> Result<Box<dyn SerialPort>> {
+        Self::builder(port).open_port()
+    }
+
+    /// Open the serial port of the uSMU with custom communication parameters, see [builder].
+    pub fn builder(port: SerialPortInfo) -> MicroSmuBuilder {
+        MicroSmuBuilder::new(port)
     }
 
     pub fn new(port: Box<dyn SerialPort>) -> MicroSmu {
-        Self { port }
+        Self::with_transport(port)
+    }
+
+    /// Reset the uSMU like [Self::reset], wait up to `timeout` for its VCP to enumerate again
+    /// and reopen it by its UID, see [reconnect::wait_for_device].
+    ///
+    /// The returned handle keeps the communication parameters, i.e. the baudrate, timeout and inter-command delay,
+    /// see [builder]. The device and all other settings, e.g. the safety limits, start from their defaults.
+    pub fn reset_and_reconnect(mut self, timeout: Duration) -> Result<MicroSmu> {
+        let uid = self.get_identity()?;
+        let baudrate = self.port.baud_rate()?;
+        let port_timeout = self.timeout();
+        let inter_command_delay = self.inter_command_delay;
+        self.reset()?;
+        reconnect::wait_for_device(uid, timeout, |uid| {
+            Self::builder(reconnect::find_by_uid(uid)?)
+                .baudrate(baudrate)
+                .timeout(port_timeout)
+                .inter_command_delay(inter_command_delay)
+                .open()
+        })
+    }
+
+    /// Lock the most sensitive current range that does not clip at the voltage set before,
+    /// until the returned guard is dropped, see [auto_range::LockedRange].
+    ///
+    /// Fails with [Error::InvalidState], if no voltage was set yet.
+    pub fn auto_range(&mut self) -> Result<LockedRange<'_>> {
+        let Some(voltage) = self.voltage else {
+            Err(Error::InvalidState(
+                "No voltage set to range at.".to_string(),
+            ))?
+        };
+        LockedRange::select(self, voltage)
+    }
+}
+
+impl<T: Transport> MicroSmu<T> {
+    /// Talk to the device through `port`, e.g. a TCP bridge or a mock, see [transport].
+    pub fn with_transport(port: T) -> Self {
+        Self {
+            timeout: port.timeout(),
+            port,
+            inter_command_delay: DEFAULT_INTER_COMMAND_DELAY,
+            safety_limits: SafetyLimits::default(),
+            voltage: None,
+            current_limit: None,
+            enabled: false,
+            soft_start: None,
+            ramp_down: None,
+            interlock: None,
+            protection: ProtectionLog::default(),
+            over_sample_rate: None,
+            reconnect: None,
+            reconnects: 0,
+            retry: None,
+            calibration_log: None,
+            calibration_override: None,
+            temperature_compensation: None,
+            current_range: None,
+            current_range_calibration: None,
+        }
+    }
+
+    /// The voltage set last, `None` before the first one.
+    ///
+    /// The firmware cannot be queried for its settings, hence this and the following getters
+    /// return the settings sent by this handle, which are lost on a reset of the device.
+    pub fn get_set_voltage(&self) -> Option<Voltage> {
+        self.voltage
+    }
+
+    /// The current limit set last, `None` before the first one.
+    pub fn get_current_limit(&self) -> Option<Current> {
+        self.current_limit
+    }
+
+    /// The oversample rate set last, `None` before the first one.
+    pub fn get_over_sample_rate(&self) -> Option<u16> {
+        self.over_sample_rate
+    }
+
+    /// Whether the output is enabled by [Self::enable].
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Validate all further voltages and current limits against `limits`.
+    pub fn set_safety_limits(&mut self, limits: SafetyLimits) {
+        self.safety_limits = limits;
+    }
+
+    pub fn safety_limits(&self) -> &SafetyLimits {
+        &self.safety_limits
+    }
+
+    /// Ramp the output from 0 V to the voltage set before within `duration` on [Self::enable],
+    /// protecting sensitive devices from turn-on transients. `None` switches the output on at once.
+    pub fn set_soft_start(&mut self, duration: Option<Time>) {
+        self.soft_start = duration;
+    }
+
+    /// Ramp the output from the voltage set to 0 V within `duration` on [Self::disable], before disabling it.
+    /// `None` switches the output off at once.
+    pub fn set_ramp_down(&mut self, duration: Option<Time>) {
+        self.ramp_down = duration;
+    }
+
+    /// Check `interlock` before enabling the output and, while it is enabled, before each voltage setting
+    /// and measurement. An open interlock disables the output immediately and fails with [Error::Interlock].
+    pub fn set_interlock(&mut self, interlock: Option<Box<dyn Interlock>>) {
+        self.interlock = interlock;
+    }
+
+    fn check_interlock(&mut self) -> Result<()> {
+        let Some(interlock) = self.interlock.as_mut() else {
+            return Ok(());
+        };
+        let Err(open) = interlock.check() else {
+            return Ok(());
+        };
+        self.protection.record(ProtectionEvent::Interlock {
+            message: open.to_string(),
+        });
+        if self.enabled {
+            self.send_command(DisableRequest)?;
+            self.enabled = false;
+        }
+        Err(open)
+    }
+
+    fn check_safety_limits(
+        &mut self,
+        voltage: Option<Voltage>,
+        current_limit: Option<Current>,
+    ) -> Result<()> {
+        let checked = self.safety_limits.check(voltage, current_limit);
+        if let Err(Error::SafetyLimit(message)) = &checked {
+            self.protection.record(ProtectionEvent::SafetyLimit {
+                message: message.clone(),
+            });
+        }
+        checked
+    }
+
+    /// Reconnect to the device according to `policy`, if the connection is lost, see [reconnect].
+    /// The UID of the device is queried to find it again.
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy<T>>) -> Result<()> {
+        self.reconnect = match policy {
+            Some(policy) => Some((self.get_identity()?, policy)),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Retry queries failing by a timeout or a garbled response according to `policy`, see [retry].
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry = policy;
+    }
+
+    /// Record the time of each calibration written in `log`, see [calibration_log].
+    pub fn set_calibration_log(&mut self, log: Option<CalibrationLog>) {
+        self.calibration_log = log;
+    }
+
+    /// Correct all further measurements on the host by `calibration_override`, see [calibration_override].
+    pub fn set_calibration_override(&mut self, calibration_override: Option<CalibrationOverride>) {
+        self.calibration_override = calibration_override;
+    }
+
+    /// Remove the thermal drift from all further measurements, see [temperature_compensation].
+    /// The drift is removed before the [CalibrationOverride] is applied.
+    pub fn set_temperature_compensation(&mut self, compensation: Option<TemperatureCompensation>) {
+        self.temperature_compensation = compensation;
+    }
+
+    fn record_calibration(&mut self) -> Result<()> {
+        let Some(log) = self.calibration_log.clone() else {
+            return Ok(());
+        };
+        let uid = self.get_identity()?;
+        log.record(uid, std::time::SystemTime::now())
+    }
+
+    /// Continue on `port` after the connection was lost, restoring the current limit, over-sampling, locked current range
+    /// and voltage set before and enabling the output, if it was enabled.
+    pub fn reconnect(&mut self, port: T) -> Result<()> {
+        self.port = port;
+        self.apply_timeout()?;
+        if let Some(limit) = self.current_limit {
+            self.set_current_limit(limit)?;
+        }
+        if let Some(samples) = self.over_sample_rate {
+            self.set_over_sample_rate(samples)?;
+        }
+        // a restored calibration applies in any range, hence only ranges corrected on the host are locked again
+        if let (Some(range), Some(_)) = (self.current_range, self.current_range_calibration) {
+            self.lock_current_range_and_clear_calibration(range)?;
+        }
+        if let Some(voltage) = self.voltage {
+            self.set_voltage(voltage)?;
+        }
+        if std::mem::take(&mut self.enabled) {
+            self.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Recover from `error`, if it is a lost connection and a [ReconnectPolicy] is set,
+    /// by reconnecting to the device. Otherwise, or if reconnecting fails, `error` is returned.
+    pub fn recover(&mut self, error: Error) -> Result<()> {
+        let Some((uid, policy)) = self.reconnect.clone() else {
+            return Err(error);
+        };
+        if !error.is_disconnect() || self.reconnects >= policy.max_reconnects {
+            return Err(error);
+        }
+        self.reconnects += 1;
+        for attempt in 1..=policy.attempts {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(uid, attempt, attempts = policy.attempts, error = %error, "Reconnecting");
+            if let Some(on_attempt) = &policy.on_attempt {
+                on_attempt(uid, attempt, &error);
+            }
+            sleep(policy.delay);
+            if let Ok(port) = (policy.connector)(uid)
+                && self.reconnect(port).is_ok()
+            {
+                return Ok(());
+            }
+        }
+        Err(Error::Disconnected(format!(
+            "Lost the connection to uSMU {uid} and failed to reconnect: {error}"
+        )))?
+    }
+
+    /// Number of compliance events, safety limit rejections and interlock trips so far.
+    pub fn protection_counters(&self) -> ProtectionCounters {
+        self.protection.counters
+    }
+
+    /// The protection events since the last call, see [protection].
+    pub fn take_protection_events(&mut self) -> Vec<ProtectionRecord> {
+        std::mem::take(&mut self.protection.records)
+    }
+
+    /// Step the voltage linearly from `from` to `to` within `duration`, in steps of about [RAMP_STEP].
+    fn ramp(&mut self, from: Voltage, to: Voltage, duration: Time) -> Result<()> {
+        let duration = Duration::from_secs_f32(duration.get::<second>().max(0.0));
+        let steps = duration.div_duration_f32(RAMP_STEP).ceil().max(1.0) as u32;
+        let start = Instant::now();
+        for step in 1..=steps {
+            let due = start + duration * step / steps;
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                sleep(wait);
+            }
+            self.set_voltage(from + (to - from) * (step as f32 / steps as f32))?;
+        }
+        Ok(())
     }
 
-    fn send(&mut self, request: impl ScpiSerialize) -> Result<()> {
-        let mut out = String::new();
-        out.reserve(32);
+    /// Name of the underlying serial port or other [Transport], if available.
+    pub fn port_name(&self) -> Option<String> {
+        self.port.name()
+    }
 
-        request.serialize(&mut out);
-        out.push('\n');
+    /// Fail responses not received within `timeout`, see [Transport::set_timeout].
+    ///
+    /// The device does not answer while measuring, hence the timeout is extended by
+    /// [TIMEOUT_PER_SAMPLE] for each sample of the over-sampling, see [Self::set_over_sample_rate].
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = timeout;
+        self.apply_timeout()
+    }
 
-        assert!(out.is_ascii());
+    /// The timeout set, without the extension for the over-sampling.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
 
-        self.port.write_all(out.as_bytes())?;
+    fn apply_timeout(&mut self) -> Result<()> {
+        let samples = u32::from(self.over_sample_rate.unwrap_or(0));
+        // saturating, transports without a timeout report `Duration::MAX`, see [Transport::timeout]
+        self.port.set_timeout(
+            self.timeout
+                .saturating_add(TIMEOUT_PER_SAMPLE.saturating_mul(samples)),
+        )
+    }
+
+    /// Pause after each command, see [builder::DEFAULT_INTER_COMMAND_DELAY].
+    pub fn set_inter_command_delay(&mut self, delay: Duration) {
+        self.inter_command_delay = delay;
+    }
 
-        // The device needs a small pause after transmission,
-        // otherwise we run into IOError timeouts.
-        // The value is based on the python reference implementation,
-        // but smaller delays may be acceptable.
-        sleep(Duration::from_millis(50));
+    pub fn inter_command_delay(&self) -> Duration {
+        self.inter_command_delay
+    }
+
+    fn send(&mut self, command: &str) -> Result<()> {
+        self.port.write_line(command)?;
+
+        // The device needs a small pause after transmission, see [builder::DEFAULT_INTER_COMMAND_DELAY].
+        sleep(self.inter_command_delay);
 
         Ok(())
     }
 
+    /// Receive the response line into `data`, which keeps the data read so far on failure.
+    fn receive<Response: ScpiDeserialize>(&mut self, data: &mut String) -> Result<Response> {
+        self.port.read_line(data)?;
+        let response = commands::from_wire(data)?;
+        Ok(response)
+    }
+
+    /// Perform a single exchange with the device, i.e. transmitting `command` and receiving the response, if any.
+    ///
+    /// Failures are reported as [Error::Exchange] with the command and the response received.
+    /// With the `tracing` feature, each exchange is a span with the raw command, with an event of the raw response.
+    fn exchange<R>(
+        &mut self,
+        command: &str,
+        f: impl FnOnce(&mut Self, &str, &mut String) -> Result<R>,
+    ) -> Result<R> {
+        #[cfg(feature = "opentelemetry")]
+        let span = telemetry::scpi_span(command);
+        #[cfg(feature = "tracing")]
+        let _entered = tracing::debug_span!("scpi", command = command.trim_end()).entered();
+
+        let mut response = String::new();
+        let result = f(self, command, &mut response);
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(_) => tracing::debug!(response = response.trim_end(), "SCPI exchange"),
+            Err(e) => {
+                tracing::warn!(response = response.as_str(), error = %e, "SCPI exchange failed")
+            }
+        }
+        let result = result.map_err(|source| Error::Exchange {
+            command: command.trim_end().to_string(),
+            response,
+            source: Box::new(source),
+        });
+
+        #[cfg(feature = "opentelemetry")]
+        span.finish_exchange(&result);
+
+        result
+    }
+
     pub fn send_command<Request>(&mut self, request: Request) -> Result<()>
     where
         Request: ScpiRequest<Response = EmptyResponse>,
     {
-        self.send(request)?;
-        Ok(())
+        let command = commands::to_wire(&request);
+        self.exchange(&command, |smu, command, _| smu.send(command))
     }
 
     pub fn query<Request, Response>(&mut self, request: Request) -> Result<Response>
@@ -99,27 +621,94 @@ impl MicroSmu {
         Request: ScpiRequest<Response = Response>,
         Response: ScpiDeserialize,
     {
-        self.send(request)?;
-
-        let mut reader = BufReader::new(&mut self.port);
-        let mut data = String::new();
-        reader.read_line(&mut data)?;
-        let mut data = data.as_str();
-        let response = Response::deserialize(&mut data)?;
-        match_literal(&mut data, "\n")?;
-        check_empty(data)?;
-        Ok(response)
+        let command = commands::to_wire(&request);
+        let query = |smu: &mut Self| {
+            smu.exchange(&command, |smu, command, response| {
+                smu.send(command)?;
+                smu.receive(response)
+            })
+        };
+        let Some(policy) = self.retry else {
+            return query(self);
+        };
+        #[cfg(feature = "opentelemetry")]
+        let span = telemetry::query_span(&command);
+        let mut attempts = 0;
+        let mut failures = Vec::new();
+        let result = loop {
+            attempts += 1;
+            let error = match query(self) {
+                Ok(response) => break Ok(response),
+                Err(e) => e,
+            };
+            let transient = error.is_transient();
+            failures.push(error);
+            if !transient || attempts >= policy.attempts {
+                // only report the attempts if the query was actually retried
+                break Err(match failures.len() {
+                    1 => failures.remove(0),
+                    _ => Error::Retried(failures),
+                });
+            }
+            sleep(policy.delay(attempts));
+            // a late response to the failed attempt would be taken for the response to the next one
+            if let Err(e) = self.port.discard_input() {
+                break Err(e);
+            }
+        };
+
+        #[cfg(feature = "opentelemetry")]
+        span.finish_query(attempts, &result);
+
+        result
     }
 
     /// Enable SMU output
+    ///
+    /// With [Self::set_soft_start], the output is enabled at 0 V and ramped to the voltage set before.
     pub fn enable(&mut self) -> Result<()> {
-        self.send_command(EnableRequest)?;
-        Ok(())
+        self.check_interlock()?;
+        match (self.soft_start, self.voltage) {
+            (Some(duration), Some(target)) if target.value != 0.0 => {
+                let zero = Voltage::new::<volt>(0.0);
+                self.set_voltage(zero)?;
+                self.send_command(EnableRequest)?;
+                self.enabled = true;
+                self.ramp(zero, target, duration)
+            }
+            _ => {
+                self.send_command(EnableRequest)?;
+                self.enabled = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Enable SMU output like [Self::enable] until the returned guard is dropped, see [guard].
+    pub fn enabled(&mut self) -> Result<EnabledGuard<'_, T>> {
+        self.enable()?;
+        Ok(EnabledGuard::new(self))
     }
 
     /// Disable SMU output (high impedance)
+    ///
+    /// With [Self::set_ramp_down], the voltage of an output enabled by [Self::enable] is ramped to 0 V first.
+    /// The output is disabled also if the ramp fails. Afterwards, the voltage set before is restored,
+    /// so the next [Self::enable] continues at it.
     pub fn disable(&mut self) -> Result<()> {
-        self.send_command(DisableRequest)?;
+        match (self.ramp_down, self.voltage) {
+            (Some(duration), Some(setpoint)) if self.enabled && setpoint.value != 0.0 => {
+                let ramped = self.ramp(setpoint, Voltage::new::<volt>(0.0), duration);
+                self.send_command(DisableRequest)?;
+                self.enabled = false;
+                ramped?;
+                self.set_voltage(setpoint)?;
+            }
+            _ => {
+                self.send_command(DisableRequest)?;
+                self.enabled = false;
+            }
+        }
         Ok(())
     }
 
@@ -128,30 +717,94 @@ impl MicroSmu {
     /// `limit` is the absolute value and is applied as limit to both source and sink current,
     /// although sink induces a negative sign in the measurements.
     ///
-    /// Panics, if limit is below zero or exceeds 40mA (the maximum current capability of the SMU).
+    /// Fails with [Error::InvalidParameter], if limit is below zero or exceeds 40mA
+    /// (the maximum current capability of the SMU), and with [Error::SafetyLimit], if the limit violates the [SafetyLimits].
     pub fn set_current_limit(&mut self, limit: Current) -> Result<()> {
-        self.send_command(SetCurrentLimitRequest::new(limit))?;
+        let request = SetCurrentLimitRequest::try_new(limit)?;
+        self.check_safety_limits(self.voltage, Some(limit))?;
+        self.send_command(request)?;
+        self.current_limit = Some(limit);
         Ok(())
     }
 
     /// Set the SMU to the requested voltage level in volts
+    ///
+    /// Fails with [Error::SafetyLimit], if the voltage violates the [SafetyLimits],
+    /// and with [Error::Interlock], if the interlock opened.
     pub fn set_voltage(&mut self, voltage: Voltage) -> Result<()> {
+        self.check_safety_limits(Some(voltage), self.current_limit)?;
+        if self.enabled {
+            self.check_interlock()?;
+        }
         self.send_command(SetVoltageRequest { voltage })?;
+        self.voltage = Some(voltage);
         Ok(())
     }
 
-    /// Set the SMU to the requested voltage level and return the measured voltage and current
+    /// Set the SMU to the requested voltage level and return the measured voltage and current,
+    /// compensated by the [TemperatureCompensation] and corrected by the [CalibrationOverride], if set.
+    ///
+    /// Fails with [Error::SafetyLimit], if the voltage violates the [SafetyLimits],
+    /// and with [Error::Interlock], if the interlock opened.
     pub fn measure(&mut self, voltage: Voltage) -> Result<MeasureResponse> {
+        self.check_safety_limits(Some(voltage), self.current_limit)?;
+        if self.enabled {
+            self.check_interlock()?;
+        }
         let response = self.query(MeasureRequest { voltage })?;
-        Ok(response)
+        let response = match self.current_range_calibration {
+            Some(calibration) => MeasureResponse {
+                current: Current::new::<ampere>(
+                    calibration.apply(response.current.get::<ampere>()),
+                ),
+                ..response
+            },
+            None => response,
+        };
+        self.voltage = Some(voltage);
+        if let Some(limit) = self.current_limit {
+            let compliance = response.current.abs() >= limit * COMPLIANCE_THRESHOLD;
+            self.protection
+                .measurement(response.voltage, response.current, compliance);
+        }
+        let response = match self.temperature_compensation.as_mut() {
+            Some(compensation) => compensation.apply(response)?,
+            None => response,
+        };
+        Ok(match self.calibration_override.as_ref() {
+            Some(calibration_override) => calibration_override.apply(response),
+            None => response,
+        })
+    }
+
+    /// Measure at the voltage set before, like [Self::measure], e.g. in monitoring loops of custom applications.
+    ///
+    /// The firmware only measures together with setting the voltage, hence the last voltage set is sent again,
+    /// which leaves the output unchanged.
+    /// Fails with [Error::InvalidState], if no voltage was set yet.
+    pub fn measure_here(&mut self) -> Result<MeasureResponse> {
+        let Some(voltage) = self.voltage else {
+            Err(Error::InvalidState(
+                "No voltage set to measure at.".to_string(),
+            ))?
+        };
+        self.measure(voltage)
+    }
+
+    /// Measure like [Self::measure_here] every `interval`, with timestamps, see [stream].
+    pub fn stream_measurements(&mut self, interval: Duration) -> MeasurementStream<'_, T> {
+        MeasurementStream::new(self, interval)
     }
 
     /// Set the oversample rate.
     ///
     /// This is the number of samples that are averaged for a given measurement
+    ///
+    /// The timeout is extended accordingly, see [Self::set_timeout].
     pub fn set_over_sample_rate(&mut self, samples: u16) -> Result<()> {
         self.send_command(SetOverSampleRateRequest { samples })?;
-        Ok(())
+        self.over_sample_rate = Some(samples);
+        self.apply_timeout()
     }
 
     /// Set the voltage DAC to this level.
@@ -164,15 +817,28 @@ impl MicroSmu {
     /// Only channel 0 and 2 can be used for differential conversion.
     /// The differential measurement is sampled with the next adjacent channel, so 0 with 1 and 2 with 3.
     ///
-    /// Panics if channel is invalid for differential conversion.
+    /// Fails with [Error::InvalidParameter], if channel is invalid for differential conversion.
     pub fn manual_measure_differential_channel(&mut self, channel: u8) -> Result<u16> {
-        let response = self.query(DifferentialConversionRequest::new(channel))?;
+        let response = self.query(DifferentialConversionRequest::try_new(channel)?)?;
         Ok(response.value)
     }
 
+    /// Measure at `voltage` like [Self::measure], then read the raw differential ADC counts
+    /// of the voltage and current, see [RawMeasurement].
+    pub fn measure_raw(&mut self, voltage: Voltage) -> Result<RawMeasurement> {
+        let calibrated = self.measure(voltage)?;
+        Ok(RawMeasurement {
+            calibrated,
+            voltage_counts: self.manual_measure_differential_channel(VOLTAGE_ADC_CHANNEL)?,
+            current_counts: self.manual_measure_differential_channel(CURRENT_ADC_CHANNEL)?,
+        })
+    }
+
     /// Set the current limit DAC to this level.
+    ///
+    /// Fails with [Error::InvalidParameter], if the level exceeds 12 bit.
     pub fn set_current_limit_dac(&mut self, level: u16) -> Result<()> {
-        self.send_command(SetCurrentLimitDacRequest { level })?;
+        self.send_command(SetCurrentLimitDacRequest::try_new(level)?)?;
         Ok(())
     }
 
@@ -188,6 +854,44 @@ impl MicroSmu {
         Ok(())
     }
 
+    /// Lock the current `range` and correct the currents measured in it by its `calibration` on the host,
+    /// as locking clears the current calibration of the firmware, see [auto_range].
+    pub fn lock_current_range(
+        &mut self,
+        range: CurrentRange,
+        calibration: LinearCalibration,
+    ) -> Result<()> {
+        self.lock_current_range_and_clear_calibration(range)?;
+        self.current_range = Some(range);
+        self.current_range_calibration = Some(calibration);
+        Ok(())
+    }
+
+    /// The range locked by [Self::lock_current_range], if any.
+    pub fn current_range(&self) -> Option<CurrentRange> {
+        self.current_range
+    }
+
+    /// Write the current ADC `calibration` of all ranges, as read before [Self::lock_current_range],
+    /// back to the firmware and stop correcting the currents on the host.
+    ///
+    /// The coefficients are unchanged, hence not recorded in the calibration log.
+    /// The range stays locked until the device is reset, as the firmware has no command to unlock it.
+    pub fn restore_current_calibration(
+        &mut self,
+        calibration: &[LinearCalibration; CURRENT_RANGES],
+    ) -> Result<()> {
+        for (range, calibration) in (1..).zip(calibration) {
+            self.send_command(WriteCurrentLimitCalibrationRequest {
+                range: CurrentRange::try_new(range)?,
+                slope: calibration.slope,
+                intercept: calibration.intercept,
+            })?;
+        }
+        self.current_range_calibration = None;
+        Ok(())
+    }
+
     /// Write a float to the EEPROM address of int.
     ///
     /// Always panics as unimplemented.
@@ -202,7 +906,8 @@ impl MicroSmu {
         Ok(response.value)
     }
 
-    /// Reset the uSMU. This will cause the VCP to disconnect and will require reconnecting.
+    /// Reset the uSMU. This will cause the VCP to disconnect and will require reconnecting,
+    /// see [MicroSmu::reset_and_reconnect].
     pub fn reset(mut self) -> Result<()> {
         self.send_command(ResetRequest)?;
         Ok(())
@@ -217,13 +922,13 @@ impl MicroSmu {
     /// Write the voltage DAC calibration to EEPROM.
     pub fn write_voltage_dac_calibration(&mut self, slope: f32, intercept: f32) -> Result<()> {
         self.send_command(WriteVoltageDacCalibrationRequest { slope, intercept })?;
-        Ok(())
+        self.record_calibration()
     }
 
     /// Write the voltage ADC calibration to EEPROM.
     pub fn write_voltage_adc_calibration(&mut self, slope: f32, intercept: f32) -> Result<()> {
         self.send_command(WriteVoltageAdcCalibrationRequest { slope, intercept })?;
-        Ok(())
+        self.record_calibration()
     }
 
     /// Write current ADC calibration for the given current range to EEPROM.
@@ -238,16 +943,34 @@ impl MicroSmu {
             slope,
             intercept,
         })?;
-        Ok(())
+        self.record_calibration()
     }
 
     /// Write the current limit DAC calibration to EEPROM.
     pub fn write_current_limit_dac(&mut self, slope: f32, intercept: f32) -> Result<()> {
         self.send_command(WriteCurrentLimitDacCalibrationRequest { slope, intercept })?;
-        Ok(())
+        self.record_calibration()
     }
 }
 
+/// Interval of the voltage steps of ramps, i.e. of [MicroSmu::set_soft_start] and [MicroSmu::set_ramp_down].
+pub const RAMP_STEP: Duration = Duration::from_millis(100);
+
+/// First ADC channel of the differential pair sensing the output voltage, see [MicroSmu::measure_raw].
+pub const VOLTAGE_ADC_CHANNEL: u8 = 0;
+/// First ADC channel of the differential pair sensing the output current, see [MicroSmu::measure_raw].
+pub const CURRENT_ADC_CHANNEL: u8 = 2;
+
+/// A calibrated measurement with the uncalibrated ADC counts read right after it,
+/// to separate the behavior of the ADC from the effects of the calibration coefficients.
+/// The counts are a separate conversion, hence they include their own noise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawMeasurement {
+    pub calibrated: MeasureResponse,
+    pub voltage_counts: u16,
+    pub current_counts: u16,
+}
+
 pub const USB_VID: u16 = 1155;
 pub const USB_PID: u16 = 22336;
 
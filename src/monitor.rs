@@ -0,0 +1,534 @@
+//! Monitoring a device at a fixed bias, sampling at a uniform interval, e.g. for leakage or noise measurements.
+//!
+//! In long runs the offsets of the instrument drift, e.g. with the temperature of the lab over a day.
+//! With [AutoZeroParameters], the bias is periodically switched to 0 V to measure the offsets,
+//! and the samples carry both the raw and the offset-corrected values.
+//! This assumes the device under test conducts no current at 0 V, as for the leakage of passive devices.
+
+use std::{
+    fs::File,
+    io::Write,
+    ops::ControlFlow,
+    path::PathBuf,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime},
+};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Time, Voltage, ampere,
+    budget::{self, BudgetParameter},
+    commands::MeasureResponse,
+    decimation::{BucketRow, Decimated, Decimation, Decimator},
+    notify::NotifyParameter,
+    record_iv_curve::SmuConnectionParameter,
+    schema, second,
+    timestamp::{Epoch, TimestampSource},
+    timing, volt,
+};
+
+/// Name of the run in notifications.
+const RUN: &str = "Monitoring";
+
+#[derive(Debug, Clone)]
+pub struct MonitorParameters {
+    /// Bias applied while monitoring.
+    pub voltage: Voltage,
+    pub current_limit: Current,
+
+    /// Number of samples averaged per measurement.
+    pub over_sampling: u16,
+
+    /// Time between the starts of two measurements.
+    pub interval: Time,
+
+    /// Total monitoring time, `None` monitors until `on_sample` breaks.
+    pub duration: Option<Time>,
+
+    pub auto_zero: Option<AutoZeroParameters>,
+
+    pub timestamps: TimestampSource,
+
+    /// Reference of the sample times, shared to align the samples of several devices. `None` starts at the monitoring.
+    pub epoch: Option<Epoch>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoZeroParameters {
+    /// Time between two offset measurements, the first one is taken before the monitoring starts.
+    pub interval: Time,
+
+    /// Time to settle after switching the bias, before the offset measurement and before monitoring continues.
+    pub settling_time: Time,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorSample {
+    /// Time since the epoch of the monitoring.
+    pub time: Time,
+    pub voltage: Voltage,
+    pub current: Current,
+    /// Offsets of the last auto-zero, `None` without auto-zero.
+    pub offset: Option<MeasureResponse>,
+}
+
+impl MonitorSample {
+    pub fn corrected_voltage(&self) -> Voltage {
+        match self.offset {
+            Some(offset) => self.voltage - offset.voltage,
+            None => self.voltage,
+        }
+    }
+
+    pub fn corrected_current(&self) -> Current {
+        match self.offset {
+            Some(offset) => self.current - offset.current,
+            None => self.current,
+        }
+    }
+}
+
+impl MonitorParameters {
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<MonitorSample>> {
+        self.record_with(smu, |_| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called for each sample and can end the monitoring early.
+    ///
+    /// Measurements are scheduled on a fixed grid of the interval, a measurement taking longer than the
+    /// interval delays the following ones. The output is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(&MonitorSample) -> ControlFlow<()>,
+    ) -> Result<Vec<MonitorSample>> {
+        let mut samples = Vec::new();
+        self.monitor_with(smu, |sample| {
+            samples.push(*sample);
+            on_sample(sample)
+        })?;
+        Ok(samples)
+    }
+
+    /// Like [Self::record_with], but without collecting the samples, e.g. to monitor without a duration.
+    pub fn monitor_with(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(&MonitorSample) -> ControlFlow<()>,
+    ) -> Result<()> {
+        // validated before enabling, the output must not stay at the bias on a misconfiguration
+        let interval = timing::duration("interval", self.interval)?;
+        let duration = self
+            .duration
+            .map(|e| timing::duration("duration", e))
+            .transpose()?;
+        let auto_zero = self
+            .auto_zero
+            .as_ref()
+            .map(|e| -> Result<_> {
+                Ok((
+                    timing::duration("auto-zero interval", e.interval)?,
+                    timing::duration("auto-zero settling time", e.settling_time)?,
+                ))
+            })
+            .transpose()?;
+
+        smu.set_voltage(self.voltage)?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.set_over_sample_rate(self.over_sampling)?;
+        // the output is disabled also if the monitoring fails, see [guard](crate::guard)
+        let mut output = smu.enabled()?;
+        let smu = &mut *output;
+
+        let epoch = self.epoch.unwrap_or_else(Epoch::now);
+        let start = Instant::now();
+        let mut next = start;
+        let mut zeroed: Option<Instant> = None;
+        let mut offset = None;
+        while duration.is_none_or(|e| next - start < e) {
+            if let Some((interval, settling_time)) = auto_zero
+                && zeroed.is_none_or(|e| e.elapsed() >= interval)
+            {
+                zeroed = Some(Instant::now());
+                offset = Some(self.zero(smu, settling_time)?);
+            }
+
+            sleep(next.saturating_duration_since(Instant::now()));
+            let time = epoch.elapsed(self.timestamps);
+            let MeasureResponse { voltage, current } = smu.measure(self.voltage)?;
+            let sample = MonitorSample {
+                time,
+                voltage,
+                current,
+                offset,
+            };
+            if on_sample(&sample).is_break() {
+                break;
+            }
+
+            next += interval;
+            if next < Instant::now() {
+                // skip the missed slots instead of catching up with a burst of measurements
+                let behind = Instant::now() - next;
+                next += interval * (behind.as_nanos() / interval.as_nanos().max(1)) as u32;
+            }
+        }
+
+        output.disable()
+    }
+
+    /// Measure the offsets at 0 V and return to the bias.
+    fn zero(&self, smu: &mut MicroSmu, settling_time: Duration) -> Result<MeasureResponse> {
+        let zero = Voltage::new::<volt>(0.0);
+        smu.set_voltage(zero)?;
+        sleep(settling_time);
+        let offset = smu.measure(zero)?;
+        smu.set_voltage(self.voltage)?;
+        sleep(settling_time);
+        Ok(offset)
+    }
+}
+
+#[derive(Serialize)]
+struct Row {
+    time: f32,
+    voltage: f32,
+    current: f32,
+    voltage_offset: Option<f32>,
+    current_offset: Option<f32>,
+    corrected_voltage: f32,
+    corrected_current: f32,
+}
+
+impl From<&MonitorSample> for Row {
+    fn from(sample: &MonitorSample) -> Self {
+        Self {
+            time: sample.time.get::<second>(),
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+            voltage_offset: sample.offset.map(|e| e.voltage.get::<volt>()),
+            current_offset: sample.offset.map(|e| e.current.get::<ampere>()),
+            corrected_voltage: sample.corrected_voltage().get::<volt>(),
+            corrected_current: sample.corrected_current().get::<ampere>(),
+        }
+    }
+}
+
+/// Write the samples as CSV with the columns `time` (seconds), `voltage`, `current`, `voltage_offset`,
+/// `current_offset`, `corrected_voltage` and `corrected_current`.
+///
+/// The offsets are empty and the corrected values equal the raw ones without auto-zero.
+pub fn write_csv(samples: &[MonitorSample], output: impl Write) -> Result<()> {
+    let mut writer = schema::csv_writer(output)?;
+    for sample in samples {
+        writer.serialize(Row::from(sample))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct MonitorArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Bias voltage applied while monitoring.
+    #[arg(long, short = 'v', default_value = "0 V")]
+    pub voltage: Voltage,
+
+    #[arg(long, short = 'c', default_value = "20 mA")]
+    pub current_limit: Current,
+
+    /// Number of samples averaged per measurement.
+    #[arg(long, short = 'r', default_value_t = 10)]
+    pub over_sampling: u16,
+
+    /// Time between measurements.
+    #[arg(long, short = 'i', default_value = "1 s")]
+    pub interval: Time,
+
+    /// Total monitoring time, monitors until interrupted if not given.
+    #[arg(long, short = 't')]
+    pub duration: Option<Time>,
+
+    #[arg(long, default_value = "monotonic")]
+    pub timestamps: TimestampSource,
+
+    /// Unix time in seconds the sample times refer to, e.g. shared by several monitored devices to align them.
+    /// Defaults to the start of the monitoring.
+    #[arg(long)]
+    pub epoch: Option<f64>,
+
+    /// Periodically measure the offsets at 0 V in this interval and record offset-corrected values.
+    #[arg(long)]
+    pub auto_zero_interval: Option<Time>,
+
+    /// Time to settle after switching the bias for the auto-zero.
+    #[arg(long, default_value = "1 s", requires = "auto_zero_interval")]
+    pub auto_zero_settling_time: Time,
+
+    /// Only write the first and then every Nth sample.
+    #[arg(long, conflicts_with = "bucket")]
+    pub keep_every: Option<usize>,
+
+    /// Write the minimum, mean and maximum of the samples per time bucket of this width,
+    /// instead of the individual samples.
+    #[arg(long)]
+    pub bucket: Option<Time>,
+
+    /// Write the samples as CSV to this file instead of stdout, each sample as soon as it is measured.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    #[cfg(feature = "checksum")]
+    #[command(flatten)]
+    pub checksum_parameter: crate::checksum::ChecksumParameter,
+
+    #[cfg(feature = "spectrum")]
+    #[command(flatten)]
+    pub spectrum_parameter: crate::spectrum::SpectrumParameter,
+
+    #[command(flatten)]
+    pub notify_parameter: NotifyParameter,
+
+    #[command(flatten)]
+    pub budget_parameter: BudgetParameter,
+}
+
+fn write_decimated(
+    writer: &mut csv::Writer<impl Write>,
+    decimated: Option<Decimated>,
+) -> Result<()> {
+    match decimated {
+        Some(Decimated::Sample(sample)) => writer.serialize(Row::from(&sample)),
+        Some(Decimated::Bucket(bucket)) => writer.serialize(BucketRow::from(&bucket)),
+        None => return Ok(()),
+    }?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl MonitorArguments {
+    pub fn run(&self) -> Result<()> {
+        let result = self.monitor();
+        self.notify_parameter.notify(RUN, &result);
+        result
+    }
+
+    fn monitor(&self) -> Result<()> {
+        #[cfg(feature = "spectrum")]
+        if self.spectrum_parameter.spectrum.is_some() && self.duration.is_none() {
+            Err(Error::Configuration(
+                "The spectrum requires a monitoring duration.".to_string(),
+            ))?;
+        }
+
+        let decimation = match (self.keep_every, self.bucket) {
+            (Some(0), _) => Err(Error::Configuration(
+                "Cannot keep every 0th sample.".to_string(),
+            ))?,
+            (Some(n), _) => Some(Decimation::KeepEvery(n)),
+            (_, Some(width)) if width.get::<second>().is_nan() || width.get::<second>() <= 0.0 => {
+                Err(Error::Configuration(
+                    "The bucket width must be positive.".to_string(),
+                ))?
+            }
+            (_, Some(width)) => Some(Decimation::Bucket(width)),
+            (None, None) => None,
+        };
+        let mut decimator = decimation.map(Decimator::new);
+
+        let output: Box<dyn Write> = match self.output.as_ref() {
+            Some(output) => Box::new(File::create(output)?),
+            None => Box::new(std::io::stdout()),
+        };
+        let mut writer = schema::csv_writer(output)?;
+
+        let epoch = self
+            .epoch
+            .map(|e| {
+                let since_unix = Duration::try_from_secs_f64(e)
+                    .map_err(|_| Error::Configuration(format!("Invalid epoch {e}.")))?;
+                Epoch::from_system_time(SystemTime::UNIX_EPOCH + since_unix)
+            })
+            .transpose()?;
+
+        let mut smu = self.connection_parameter.connect()?;
+        let mut written = Ok(());
+        // only kept for the spectrum, monitoring without a duration would grow them indefinitely
+        #[cfg(feature = "spectrum")]
+        let mut samples = Vec::new();
+        let budget = self.budget_parameter.start();
+        let monitored = MonitorParameters {
+            voltage: self.voltage,
+            current_limit: self.current_limit,
+            over_sampling: self.over_sampling,
+            interval: self.interval,
+            duration: self.duration,
+            auto_zero: self.auto_zero_interval.map(|interval| AutoZeroParameters {
+                interval,
+                settling_time: self.auto_zero_settling_time,
+            }),
+            timestamps: self.timestamps,
+            epoch,
+        }
+        .monitor_with(&mut smu, |sample| {
+            #[cfg(feature = "spectrum")]
+            if self.spectrum_parameter.spectrum.is_some() {
+                samples.push(*sample);
+            }
+            let decimated = match decimator.as_mut() {
+                Some(decimator) => decimator.push(sample),
+                None => Some(Decimated::Sample(*sample)),
+            };
+            written = write_decimated(&mut writer, decimated);
+            match written {
+                Ok(()) => budget::check(budget.as_ref()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        });
+        self.notify_parameter
+            .protection(RUN, &smu.take_protection_events());
+        monitored?;
+        written?;
+        write_decimated(&mut writer, decimator.and_then(Decimator::finish))?;
+        #[cfg(feature = "checksum")]
+        {
+            // closes the file before sealing it
+            drop(writer);
+            self.checksum_parameter.seal(self.output.as_ref())?;
+        }
+
+        #[cfg(feature = "spectrum")]
+        self.spectrum_parameter.output(&samples)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        milliampere,
+        simulator::{DutModel, Resistor},
+        test_util::simulated_smu,
+    };
+
+    use super::*;
+
+    fn parameters() -> MonitorParameters {
+        MonitorParameters {
+            voltage: Voltage::new::<volt>(1.0),
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 1,
+            interval: Time::new::<second>(0.02),
+            duration: Some(Time::new::<second>(0.09)),
+            auto_zero: None,
+            timestamps: TimestampSource::Monotonic,
+            epoch: None,
+        }
+    }
+
+    #[test]
+    fn samples_on_the_interval_grid() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let samples = parameters().record(&mut smu).unwrap();
+
+        assert_eq!(samples.len(), 5);
+        for (previous, sample) in samples.iter().zip(&samples[1..]) {
+            let spacing = (sample.time - previous.time).get::<second>();
+            assert!((0.015..0.1).contains(&spacing), "{spacing}");
+        }
+        assert!((samples[0].current.get::<ampere>() - 1e-3).abs() < 1e-5);
+        assert!(samples.iter().all(|e| e.offset.is_none()));
+        assert!(!smu.is_enabled());
+    }
+
+    #[test]
+    fn stops_on_break() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let parameters = MonitorParameters {
+            interval: Time::new::<second>(0.0),
+            duration: None,
+            ..parameters()
+        };
+        let mut count = 0;
+        let samples = parameters
+            .record_with(&mut smu, |_| {
+                count += 1;
+                match count {
+                    3 => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+
+        assert_eq!(samples.len(), 3);
+        assert!(!smu.is_enabled());
+    }
+
+    /// A resistor with a constant leakage, measured as offset at 0 V.
+    struct Leaky;
+
+    impl DutModel for Leaky {
+        fn current(&self, voltage: Voltage) -> Current {
+            Current::new::<ampere>(voltage.get::<volt>() / 1000.0 + 5e-4)
+        }
+    }
+
+    #[test]
+    fn subtracts_the_offsets() {
+        let mut smu = simulated_smu(Leaky);
+        let parameters = MonitorParameters {
+            auto_zero: Some(AutoZeroParameters {
+                interval: Time::new::<second>(1.0),
+                settling_time: Time::new::<second>(0.0),
+            }),
+            ..parameters()
+        };
+        let samples = parameters.record(&mut smu).unwrap();
+
+        for sample in &samples {
+            let offset = sample.offset.unwrap().current.get::<ampere>();
+            assert!((offset - 5e-4).abs() < 1e-5, "{offset}");
+            let corrected = sample.corrected_current().get::<ampere>();
+            assert!((corrected - 1e-3).abs() < 1e-5, "{corrected}");
+        }
+    }
+
+    #[test]
+    fn rejects_negative_times() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        for parameters in [
+            MonitorParameters {
+                interval: Time::new::<second>(-1.0),
+                ..parameters()
+            },
+            MonitorParameters {
+                duration: Some(Time::new::<second>(-1.0)),
+                ..parameters()
+            },
+            MonitorParameters {
+                auto_zero: Some(AutoZeroParameters {
+                    interval: Time::new::<second>(-1.0),
+                    settling_time: Time::new::<second>(0.0),
+                }),
+                ..parameters()
+            },
+            MonitorParameters {
+                auto_zero: Some(AutoZeroParameters {
+                    interval: Time::new::<second>(1.0),
+                    settling_time: Time::new::<second>(-1.0),
+                }),
+                ..parameters()
+            },
+        ] {
+            let result = parameters.record(&mut smu);
+            assert!(matches!(result, Err(Error::Configuration(_))));
+            assert_eq!(smu.get_set_voltage(), None);
+        }
+    }
+}
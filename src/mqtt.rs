@@ -0,0 +1,326 @@
+//! Publishing of measurements to an MQTT broker.
+//!
+//! Every measurement is published as JSON to `<topic>/measurement`,
+//! e.g. `{"timestamp": 1755600000.12, "voltage": 0.5, "current": 0.0012}`
+//! with the timestamp in seconds since the UNIX epoch.
+//! The end of each sweep is announced on `<topic>/sweep`,
+//! e.g. `{"timestamp": 1755600003.4, "status": "finished", "samples": 50}`
+//! or `{"timestamp": 1755600003.4, "status": "failed", "error": "..."}`.
+//!
+//! Lost connections to the broker are re-established with an increasing delay,
+//! publications are queued meanwhile.
+
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use clap::Parser;
+use rumqttc::{Client, ClientError, ConnectionError, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde::Serialize;
+
+use crate::{Current, Error, Result, Voltage, ampere, volt};
+
+const DEFAULT_PORT: u16 = 1883;
+
+/// Delay before the first reconnection attempt, doubled for each further one.
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Time [MqttPublisher::finish] keeps reconnecting to send the pending publications.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Parser)]
+pub struct MqttParameter {
+    /// Publish measurements to this MQTT broker, e.g. `localhost:1883`.
+    #[arg(long)]
+    pub mqtt_broker: Option<String>,
+
+    /// Topic prefix of the published measurements and sweep events.
+    #[arg(long, default_value = "usmu")]
+    pub mqtt_topic: String,
+}
+
+impl MqttParameter {
+    /// Connect to the broker, if one is configured.
+    pub fn connect(&self) -> Result<Option<MqttPublisher>> {
+        self.mqtt_broker
+            .as_deref()
+            .map(|broker| MqttPublisher::connect(broker, &self.mqtt_topic))
+            .transpose()
+    }
+}
+
+pub struct MqttPublisher {
+    client: Client,
+    topic: String,
+    /// The connection thread, returning the last connection failure if it gave up.
+    connection: JoinHandle<Option<ConnectionError>>,
+    /// Deadline of [Self::finish], until which the connection thread keeps reconnecting.
+    finish: Sender<Instant>,
+    /// Publishing must not abort a running measurement, hence the first error is kept for [Self::finish].
+    error: Option<ClientError>,
+}
+
+#[derive(Serialize)]
+struct MeasurementPayload {
+    timestamp: f64,
+    voltage: f32,
+    current: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SweepStatus {
+    Finished,
+    Failed,
+}
+
+#[derive(Serialize)]
+struct SweepPayload {
+    timestamp: f64,
+    status: SweepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    samples: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl MqttPublisher {
+    /// Connect to `broker` given as `host` or `host:port`.
+    pub fn connect(broker: &str, topic: &str) -> Result<Self> {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| {
+                    Error::Configuration(format!("Invalid MQTT broker port in '{broker}'."))
+                })?;
+                (host, port)
+            }
+            None => (broker, DEFAULT_PORT),
+        };
+
+        let options = MqttOptions::new(format!("usmu-{}", std::process::id()), host, port);
+        let (client, mut connection) = Client::new(options, 64);
+
+        // The event loop must be driven for the publications to be transmitted,
+        // each poll after a failure reconnects.
+        let (finish, finished) = mpsc::channel();
+        let connection = thread::spawn(move || {
+            let mut failures = 0;
+            let mut deadline = None;
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => return None,
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => failures = 0,
+                    Ok(_) => {}
+                    Err(e) => {
+                        if failures == 0 {
+                            eprintln!("MQTT connection failed: {e}, reconnecting.");
+                        }
+                        failures += 1;
+                        let delay = reconnect_delay(failures);
+                        match deadline {
+                            Some(_) => thread::sleep(delay),
+                            None => match finished.recv_timeout(delay) {
+                                Ok(finish) => deadline = Some(finish),
+                                Err(RecvTimeoutError::Timeout) => {}
+                                Err(RecvTimeoutError::Disconnected) => return Some(e),
+                            },
+                        }
+                        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                            return Some(e);
+                        }
+                    }
+                }
+            }
+            None
+        });
+
+        Ok(Self {
+            client,
+            topic: topic.to_string(),
+            connection,
+            finish,
+            error: None,
+        })
+    }
+
+    pub fn publish_measurement(&mut self, voltage: Voltage, current: Current) {
+        let payload = MeasurementPayload {
+            timestamp: timestamp(),
+            voltage: voltage.get::<volt>(),
+            current: current.get::<ampere>(),
+        };
+        self.publish("measurement", &payload);
+    }
+
+    pub fn publish_sweep_finished(&mut self, samples: usize) {
+        let payload = SweepPayload {
+            timestamp: timestamp(),
+            status: SweepStatus::Finished,
+            samples: Some(samples),
+            error: None,
+        };
+        self.publish("sweep", &payload);
+    }
+
+    pub fn publish_sweep_failed(&mut self, error: &Error) {
+        let payload = SweepPayload {
+            timestamp: timestamp(),
+            status: SweepStatus::Failed,
+            samples: None,
+            error: Some(error.to_string()),
+        };
+        self.publish("sweep", &payload);
+    }
+
+    fn publish(&mut self, subtopic: &str, payload: &impl Serialize) {
+        let payload = serde_json::to_vec(payload).expect("serialization of plain data cannot fail");
+        let topic = format!("{}/{subtopic}", self.topic);
+        // without blocking the measurement, if the queue is full while the broker is unreachable
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, false, payload)
+        {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    /// Disconnect after all pending publications are sent and report the first publishing failure.
+    ///
+    /// A lost connection is re-established for some seconds at most, to not block on an unreachable broker.
+    pub fn finish(mut self) -> Result<()> {
+        // before disconnecting, which blocks while the queue is full and the broker unreachable
+        let _ = self.finish.send(Instant::now() + FINISH_TIMEOUT);
+        if let Err(e) = self.client.disconnect() {
+            self.error.get_or_insert(e);
+        }
+        let lost = self
+            .connection
+            .join()
+            .map_err(|_| Error::Internal("MQTT connection thread panicked.".to_string()))?;
+
+        match (self.error, lost) {
+            (Some(e), _) => Err(Error::External(format!(
+                "Failed to publish to MQTT broker: {e}"
+            )))?,
+            (None, Some(e)) => Err(Error::External(format!(
+                "Lost the connection to the MQTT broker: {e}"
+            )))?,
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Delay before the reconnection following `failures` failed attempts.
+fn reconnect_delay(failures: u32) -> Duration {
+    RECONNECT_DELAY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_RECONNECT_DELAY)
+}
+
+fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|e| e.as_secs_f64())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use serde_json::Value;
+
+    use crate::milliampere;
+
+    use super::*;
+
+    /// Read an MQTT packet, returning its type and body, `None` once the client closed the connection.
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0];
+        stream.read_exact(&mut header).ok()?;
+        let (mut length, mut shift) = (0, 0);
+        loop {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).ok()?;
+            length |= usize::from(byte[0] & 0x7f) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).ok()?;
+        Some((header[0] >> 4, body))
+    }
+
+    /// Broker dropping the first connection, then collecting the publications of the second one.
+    fn broker(listener: TcpListener) -> Vec<(String, Value)> {
+        let (mut dropped, _) = listener.accept().unwrap();
+        read_packet(&mut dropped).unwrap();
+        drop(dropped);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        read_packet(&mut stream).unwrap();
+        stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+        let mut publications = Vec::new();
+        while let Some((kind, body)) = read_packet(&mut stream) {
+            match kind {
+                // PUBLISH with QoS 1, acknowledged by its packet id
+                3 => {
+                    let topic_length = usize::from(u16::from_be_bytes([body[0], body[1]]));
+                    let (topic, rest) = body[2..].split_at(topic_length);
+                    stream.write_all(&[0x40, 0x02, rest[0], rest[1]]).unwrap();
+                    publications.push((
+                        String::from_utf8(topic.to_vec()).unwrap(),
+                        serde_json::from_slice(&rest[2..]).unwrap(),
+                    ));
+                }
+                // DISCONNECT
+                14 => break,
+                _ => {}
+            }
+        }
+        publications
+    }
+
+    #[test]
+    fn doubles_the_reconnect_delay() {
+        assert_eq!(reconnect_delay(1), RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(3), RECONNECT_DELAY * 4);
+        assert_eq!(reconnect_delay(100), MAX_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn reconnects_and_announces_failed_sweeps() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let broker = thread::spawn(move || broker(listener));
+
+        let mut publisher = MqttPublisher::connect(&address.to_string(), "lab").unwrap();
+        publisher.publish_measurement(Voltage::new::<volt>(1.0), Current::new::<milliampere>(1.0));
+        publisher.publish_sweep_failed(&Error::Internal("Device vanished.".to_string()));
+        publisher.finish().unwrap();
+
+        let publications = broker.join().unwrap();
+        let topics = publications
+            .iter()
+            .map(|e| e.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(topics, ["lab/measurement", "lab/sweep"]);
+        assert_eq!(publications[0].1["voltage"], 1.0);
+        assert_eq!(publications[1].1["status"], "failed");
+        assert!(
+            publications[1].1["error"]
+                .as_str()
+                .unwrap()
+                .contains("Device vanished.")
+        );
+        assert!(publications[1].1.get("samples").is_none());
+    }
+}
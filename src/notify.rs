@@ -0,0 +1,128 @@
+//! Notifications of the operator when a long run completes or fails, e.g. `record_iv_curve --notify desktop`.
+//!
+//! With the `alerts` feature, the completion, failure and protection events are also posted to webhooks
+//! and, with the `email` feature, sent by email, see [alert](crate::alert).
+
+use std::{io::Write, process::Command};
+
+use clap::{Parser, ValueEnum};
+
+use crate::{Error, Result, protection::ProtectionRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Notification {
+    /// Ring the terminal bell.
+    Bell,
+    /// Desktop notification, via `notify-send` on Linux and `osascript` on macOS.
+    Desktop,
+}
+
+impl Notification {
+    pub fn send(&self, title: &str, message: &str) -> Result<()> {
+        match self {
+            Notification::Bell => {
+                // stdout may carry the output data
+                let mut stderr = std::io::stderr();
+                stderr.write_all(b"\x07")?;
+                stderr.flush()?;
+                Ok(())
+            }
+            Notification::Desktop => desktop_notification(title, message),
+        }
+    }
+}
+
+fn desktop_notification(title: &str, message: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let quote = |e: &str| format!("\"{}\"", e.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            quote(message),
+            quote(title)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(message);
+        command
+    };
+    let status = command
+        .status()
+        .map_err(|e| Error::External(format!("Failed to run {:?}: {e}", command.get_program())))?;
+    if !status.success() {
+        Err(Error::External(format!(
+            "{:?} failed with {status}",
+            command.get_program()
+        )))?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Parser)]
+pub struct NotifyParameter {
+    /// Notify when the run completes or fails, by the terminal bell (default) or a desktop notification.
+    /// Separate several by commas.
+    #[arg(long, value_delimiter = ',', num_args = 0..=1, default_missing_value = "bell")]
+    pub notify: Vec<Notification>,
+
+    #[cfg(feature = "alerts")]
+    #[command(flatten)]
+    pub alert_parameter: crate::alert::AlertParameter,
+}
+
+impl NotifyParameter {
+    /// Notify about the `outcome` of the run `name`, failing notifications are only reported,
+    /// so they do not mask the outcome.
+    pub fn notify<T>(&self, name: &str, outcome: &Result<T>) {
+        let message = message(name, outcome);
+        for notification in &self.notify {
+            if let Err(e) = notification.send("usmu", &message) {
+                eprintln!("Notification failed: {e}");
+            }
+        }
+        #[cfg(feature = "alerts")]
+        self.alert_parameter
+            .send(&crate::alert::Alert::outcome(name, outcome));
+    }
+
+    /// Alert about the protection events of the run `name`, requires the `alerts` feature.
+    #[cfg_attr(not(feature = "alerts"), allow(unused_variables))]
+    pub fn protection(&self, name: &str, records: &[ProtectionRecord]) {
+        #[cfg(feature = "alerts")]
+        for record in records {
+            self.alert_parameter
+                .send(&crate::alert::Alert::protection(name, record));
+        }
+    }
+}
+
+fn message<T>(name: &str, outcome: &Result<T>) -> String {
+    match outcome {
+        Ok(_) => format!("{name} completed."),
+        Err(e) => format!("{name} failed: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Error;
+
+    use super::*;
+
+    #[test]
+    fn reports_outcome() {
+        assert_eq!(message("Monitoring", &Ok(())), "Monitoring completed.");
+        assert_eq!(
+            message::<()>("Monitoring", &Err(Error::Interlock("lid open".to_string()))),
+            "Monitoring failed: Interlock open: lid open"
+        );
+        let parameter = NotifyParameter::parse_from(["usmu", "--notify"]);
+        assert_eq!(parameter.notify, [Notification::Bell]);
+        let parameter = NotifyParameter::parse_from(["usmu", "--notify=bell,desktop"]);
+        assert_eq!(
+            parameter.notify,
+            [Notification::Bell, Notification::Desktop]
+        );
+    }
+}
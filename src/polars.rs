@@ -0,0 +1,104 @@
+//! Sweep results as Polars [DataFrame] for analysis without intermediate files.
+//!
+//! The frame has a `voltage` column in volt, a `current` column in ampere, a `time` column in seconds
+//! since the start of the sweep, a `set_voltage` column in volt and a boolean `compliance` column.
+
+use ::polars::prelude::{Column, DataFrame};
+
+use crate::{
+    MicroSmu, Result, ampere, record_iv_curve::IvCurveRecordingParameters, second,
+    sweep_result::SweepResult, volt,
+};
+
+pub const VOLTAGE_COLUMN: &str = "voltage";
+pub const CURRENT_COLUMN: &str = "current";
+pub const TIME_COLUMN: &str = "time";
+pub const SET_VOLTAGE_COLUMN: &str = "set_voltage";
+pub const COMPLIANCE_COLUMN: &str = "compliance";
+
+pub fn to_data_frame(result: &SweepResult) -> DataFrame {
+    let samples = &result.samples;
+    let voltage: Vec<f32> = samples.iter().map(|e| e.voltage.get::<volt>()).collect();
+    let current: Vec<f32> = samples.iter().map(|e| e.current.get::<ampere>()).collect();
+    let time: Vec<f32> = samples.iter().map(|e| e.time.get::<second>()).collect();
+    let set_voltage: Vec<f32> = samples
+        .iter()
+        .map(|e| e.set_voltage.get::<volt>())
+        .collect();
+    let compliance: Vec<bool> = samples.iter().map(|e| e.compliance).collect();
+    DataFrame::new(vec![
+        Column::new(VOLTAGE_COLUMN.into(), voltage),
+        Column::new(CURRENT_COLUMN.into(), current),
+        Column::new(TIME_COLUMN.into(), time),
+        Column::new(SET_VOLTAGE_COLUMN.into(), set_voltage),
+        Column::new(COMPLIANCE_COLUMN.into(), compliance),
+    ])
+    .expect("columns have equal length and distinct names")
+}
+
+impl IvCurveRecordingParameters {
+    /// Like [Self::record], but returns the samples as [DataFrame].
+    pub fn record_data_frame(&self, smu: &mut MicroSmu) -> Result<DataFrame> {
+        let result = self.record(smu)?;
+        Ok(to_data_frame(&result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::polars::prelude::DataType;
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    #[test]
+    fn converts_a_sweep() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let result = IvCurveRecordingParameters {
+            voltage_steps: 5,
+            over_sampling: 1,
+            ..Default::default()
+        }
+        .record(&mut smu)
+        .unwrap();
+        let frame = to_data_frame(&result);
+
+        let columns = frame
+            .get_columns()
+            .iter()
+            .map(|e| (e.name().to_string(), e.dtype().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            [
+                (VOLTAGE_COLUMN.to_string(), DataType::Float32),
+                (CURRENT_COLUMN.to_string(), DataType::Float32),
+                (TIME_COLUMN.to_string(), DataType::Float32),
+                (SET_VOLTAGE_COLUMN.to_string(), DataType::Float32),
+                (COMPLIANCE_COLUMN.to_string(), DataType::Boolean),
+            ]
+        );
+        assert_eq!(frame.height(), 5);
+
+        let column = |name| {
+            frame
+                .column(name)
+                .unwrap()
+                .f32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>()
+        };
+        let points = column(VOLTAGE_COLUMN)
+            .into_iter()
+            .zip(column(CURRENT_COLUMN))
+            .collect::<Vec<_>>();
+        let expected = result
+            .points()
+            .into_iter()
+            .map(|(v, i)| (v.get::<volt>(), i.get::<ampere>()))
+            .collect::<Vec<_>>();
+        assert_eq!(points, expected);
+    }
+}
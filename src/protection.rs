@@ -0,0 +1,131 @@
+//! Record of the protection engaged by a [MicroSmu](crate::MicroSmu), for post-mortem analysis of failed tests.
+//!
+//! The uSMU records when the current enters compliance, i.e. reaches the current limit,
+//! when a setting is rejected by the [SafetyLimits](crate::safety::SafetyLimits)
+//! and when the [Interlock](crate::interlock::Interlock) trips, e.g. because its heartbeats stopped.
+//! The events are timestamped and counted, see [MicroSmu::protection_counters](crate::MicroSmu::protection_counters)
+//! and [MicroSmu::take_protection_events](crate::MicroSmu::take_protection_events).
+//! With the `event-log` feature, `record_iv_curve --event-log` appends them to the event log of the run.
+
+use std::{fmt::Display, time::SystemTime};
+
+use crate::{Current, Voltage, milliampere, volt};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtectionEvent {
+    /// The measured current entered compliance.
+    Compliance { voltage: Voltage, current: Current },
+    /// A setting was rejected by the safety limits.
+    SafetyLimit { message: String },
+    /// The interlock opened and the output was disabled.
+    Interlock { message: String },
+}
+
+impl Display for ProtectionEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtectionEvent::Compliance { voltage, current } => write!(
+                f,
+                "compliance at {} V and {} mA",
+                voltage.get::<volt>(),
+                current.get::<milliampere>()
+            ),
+            ProtectionEvent::SafetyLimit { message } => write!(f, "safety limit: {message}"),
+            ProtectionEvent::Interlock { message } => write!(f, "interlock open: {message}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtectionRecord {
+    pub time: SystemTime,
+    pub event: ProtectionEvent,
+}
+
+/// Number of protection events since the [MicroSmu](crate::MicroSmu) was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtectionCounters {
+    pub compliance: usize,
+    pub safety_limit: usize,
+    pub interlock: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProtectionLog {
+    pub(crate) counters: ProtectionCounters,
+    pub(crate) records: Vec<ProtectionRecord>,
+    in_compliance: bool,
+}
+
+impl ProtectionLog {
+    pub(crate) fn record(&mut self, event: ProtectionEvent) {
+        match event {
+            ProtectionEvent::Compliance { .. } => self.counters.compliance += 1,
+            ProtectionEvent::SafetyLimit { .. } => self.counters.safety_limit += 1,
+            ProtectionEvent::Interlock { .. } => self.counters.interlock += 1,
+        }
+        self.records.push(ProtectionRecord {
+            time: SystemTime::now(),
+            event,
+        });
+    }
+
+    /// Record a compliance event, if the measurement enters compliance.
+    pub(crate) fn measurement(&mut self, voltage: Voltage, current: Current, compliance: bool) {
+        if compliance && !self.in_compliance {
+            self.record(ProtectionEvent::Compliance { voltage, current });
+        }
+        self.in_compliance = compliance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, MicroSmu, safety::SafetyLimits, test_util::FakeSerialPort};
+
+    use super::*;
+
+    #[test]
+    fn records_protection_events() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:CUR 10")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.00999")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.00999")
+            .expect_query("CH1:MEA:VOL 0.5", "0.5,0.005")
+            .expect_query("CH1:MEA:VOL 1", "1.0,-0.00999");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_safety_limits(SafetyLimits {
+            max_voltage: Some(Voltage::new::<volt>(2.0)),
+            ..Default::default()
+        });
+        smu.set_current_limit(Current::new::<milliampere>(10.0))
+            .unwrap();
+        for voltage in [1.0, 1.0, 0.5, 1.0] {
+            smu.measure(Voltage::new::<volt>(voltage)).unwrap();
+        }
+        let rejected = smu.set_voltage(Voltage::new::<volt>(3.0));
+        assert!(matches!(rejected, Err(Error::SafetyLimit(_))));
+        port.verify();
+
+        assert_eq!(
+            smu.protection_counters(),
+            ProtectionCounters {
+                compliance: 2,
+                safety_limit: 1,
+                interlock: 0,
+            }
+        );
+        let records = smu.take_protection_events();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[1].event,
+            ProtectionEvent::Compliance {
+                voltage: Voltage::new::<volt>(1.0),
+                current: Current::new::<milliampere>(-9.99),
+            }
+        );
+        assert!(records[0].time <= records[2].time);
+        assert!(smu.take_protection_events().is_empty());
+        assert_eq!(smu.protection_counters().compliance, 2);
+    }
+}
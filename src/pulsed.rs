@@ -0,0 +1,201 @@
+//! Pulsed IV curves, applying the bias of each point only for a short on-time
+//! and returning to a rest bias in between, to limit the self-heating of small devices.
+//!
+//! The shortest pulse is bounded by the serial communication, which takes some tens of milliseconds
+//! per command, hence the effective on-time and duty cycle are measured and reported.
+
+use std::{
+    ops::ControlFlow,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use ndarray::linspace;
+
+use crate::{
+    Current, MicroSmu, Result, Time, Voltage,
+    commands::MeasureResponse,
+    second,
+    sweep_result::{COMPLIANCE_THRESHOLD, SweepSample},
+    timestamp::{Epoch, TimestampSource},
+    timing, volt,
+};
+
+#[derive(Debug, Clone)]
+pub struct PulsedIvParameters {
+    pub start_voltage: Voltage,
+    pub end_voltage: Voltage,
+    pub voltage_steps: usize,
+    pub current_limit: Current,
+
+    /// Number of samples averaged per measurement.
+    pub over_sampling: u16,
+
+    /// Bias applied between the pulses, typically 0 V.
+    pub rest_voltage: Voltage,
+
+    /// Time the pulse is applied before the measurement starts.
+    pub on_time: Time,
+
+    /// Time at the rest bias between pulses.
+    pub off_time: Time,
+}
+
+#[derive(Debug, Clone)]
+pub struct PulsedIvCurve {
+    pub samples: Vec<SweepSample>,
+
+    /// Mean effective time the pulses were applied, including the communication and measurement.
+    pub on_time: Time,
+
+    /// Effective fraction of the recording time the pulses were applied.
+    pub duty_cycle: f32,
+}
+
+impl PulsedIvParameters {
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<PulsedIvCurve> {
+        self.record_with(smu, |_, _| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called for each sample and can end the sweep early.
+    ///
+    /// The output returns to the rest bias and is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<PulsedIvCurve> {
+        let curve = self.pulse(smu, on_sample);
+        let rested = smu
+            .set_voltage(self.rest_voltage)
+            .and_then(|_| smu.disable());
+        let curve = curve?;
+        rested?;
+        Ok(curve)
+    }
+
+    fn pulse(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<PulsedIvCurve> {
+        let on_time = timing::duration("on-time", self.on_time)?;
+        let off_time = timing::duration("off-time", self.off_time)?;
+
+        smu.set_voltage(self.rest_voltage)?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.set_over_sample_rate(self.over_sampling)?;
+        smu.enable()?;
+
+        let start = Instant::now();
+        let epoch = Epoch::now();
+        let mut applied = Duration::ZERO;
+        let mut samples = Vec::with_capacity(self.voltage_steps);
+        for set_voltage in linspace(
+            self.start_voltage.get::<volt>(),
+            self.end_voltage.get::<volt>(),
+            self.voltage_steps,
+        ) {
+            let set_voltage = Voltage::new::<volt>(set_voltage);
+
+            let pulse = Instant::now();
+            smu.set_voltage(set_voltage)?;
+            sleep(on_time);
+            let time = epoch.elapsed(TimestampSource::Monotonic);
+            let MeasureResponse { voltage, current } = smu.measure(set_voltage)?;
+            smu.set_voltage(self.rest_voltage)?;
+            applied += pulse.elapsed();
+
+            samples.push(SweepSample {
+                time,
+                set_voltage,
+                voltage,
+                current,
+                compliance: current.abs() >= self.current_limit * COMPLIANCE_THRESHOLD,
+                range: None,
+            });
+            if on_sample(voltage, current).is_break() {
+                break;
+            }
+            sleep(off_time);
+        }
+
+        let total = start.elapsed().as_secs_f32();
+        let pulses = samples.len().max(1) as f32;
+        Ok(PulsedIvCurve {
+            samples,
+            on_time: Time::new::<second>(applied.as_secs_f32() / pulses),
+            duty_cycle: if total > 0.0 {
+                applied.as_secs_f32() / total
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, ampere, milliampere, millisecond, test_util::FakeSerialPort};
+
+    use super::*;
+
+    fn parameters() -> PulsedIvParameters {
+        PulsedIvParameters {
+            start_voltage: Voltage::new::<volt>(1.0),
+            end_voltage: Voltage::new::<volt>(2.0),
+            voltage_steps: 2,
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 10,
+            rest_voltage: Voltage::new::<volt>(0.0),
+            on_time: Time::new::<second>(0.0),
+            off_time: Time::new::<second>(0.0),
+        }
+    }
+
+    #[test]
+    fn returns_to_the_rest_bias_between_pulses() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 0")
+            .expect("CH1:CUR 20")
+            .expect("CH1:OSR 10")
+            .expect("CH1:ENA")
+            .expect("CH1:VOL 1")
+            .expect_query("CH1:MEA:VOL 1", "1.000000,0.001000")
+            .expect("CH1:VOL 0")
+            .expect("CH1:VOL 2")
+            .expect_query("CH1:MEA:VOL 2", "2.000000,0.020000")
+            .expect("CH1:VOL 0")
+            .expect("CH1:VOL 0")
+            .expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+
+        let curve = parameters().record(&mut smu).unwrap();
+        port.verify();
+        let currents = curve.samples.iter().map(|e| e.current).collect::<Vec<_>>();
+        assert_eq!(
+            currents,
+            [Current::new::<ampere>(0.001), Current::new::<ampere>(0.02)]
+        );
+        assert!(!curve.samples[0].compliance);
+        assert!(curve.samples[1].compliance);
+        assert!((0.0..=1.0).contains(&curve.duty_cycle));
+    }
+
+    #[test]
+    fn rejects_negative_off_times() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 0").expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+
+        let parameters = PulsedIvParameters {
+            off_time: Time::new::<millisecond>(-1.0),
+            ..parameters()
+        };
+        let result = parameters.record(&mut smu);
+        assert!(matches!(result, Err(Error::Configuration(_))));
+        port.verify();
+    }
+}
@@ -0,0 +1,274 @@
+//! Recovery from losing the connection to the uSMU during a run, e.g. by a USB glitch.
+//!
+//! With a [ReconnectPolicy], see [MicroSmu::set_reconnect_policy](crate::MicroSmu::set_reconnect_policy),
+//! a [MicroSmu] whose port fails looks the device up again by its UID,
+//! restores the current limit, over-sampling and voltage, and re-enables the output if it was enabled.
+//! Sweeps then resume from the point that failed.
+//! Read timeouts are not considered a lost connection, the device might just be busy.
+//!
+//! [MicroSmu::reset_and_reconnect](crate::MicroSmu::reset_and_reconnect) likewise finds the device by its UID
+//! again after it was reset, see [wait_for_device].
+
+use std::{
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use serialport::{SerialPort, SerialPortInfo};
+
+use crate::{Error, MicroSmu, Result, Time, devices::identify_attached, second};
+
+/// Opens the port, or other [Transport](crate::transport::Transport), of the uSMU with the given UID.
+pub type Connector<T = Box<dyn SerialPort>> = Arc<dyn Fn(u32) -> Result<T> + Send + Sync>;
+
+/// Called before each attempt to reconnect, with the UID, the attempt counted from 1 and the error losing the connection.
+pub type AttemptHook = Arc<dyn Fn(u32, u32, &Error) + Send + Sync>;
+
+pub struct ReconnectPolicy<T = Box<dyn SerialPort>> {
+    /// Attempts to reconnect after the connection is lost.
+    pub attempts: u32,
+    /// Delay before each attempt, e.g. for the device to enumerate again.
+    pub delay: Duration,
+    /// Maximum number of reconnections in total, to give up on a persistently failing connection.
+    pub max_reconnects: u32,
+    pub connector: Connector<T>,
+    /// Reports the attempts, e.g. to the user, the library itself only reports them with the `tracing` feature.
+    pub on_attempt: Option<AttemptHook>,
+}
+
+impl<T> Clone for ReconnectPolicy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            connector: self.connector.clone(),
+            on_attempt: self.on_attempt.clone(),
+            ..*self
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Reconnect to the serial ports of attached uSMUs, see [open_by_uid].
+    pub fn new(attempts: u32, delay: Duration, max_reconnects: u32) -> Self {
+        Self {
+            attempts,
+            delay,
+            max_reconnects,
+            connector: Arc::new(open_by_uid),
+            on_attempt: None,
+        }
+    }
+}
+
+impl<T> ReconnectPolicy<T> {
+    /// Reconnect with `connector` instead, e.g. to a simulated or remote device.
+    pub fn with_connector(
+        self,
+        connector: impl Fn(u32) -> Result<T> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            connector: Arc::new(connector),
+            ..self
+        }
+    }
+
+    /// Call `on_attempt` before each attempt to reconnect, see [AttemptHook].
+    pub fn with_on_attempt(
+        self,
+        on_attempt: impl Fn(u32, u32, &Error) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            on_attempt: Some(Arc::new(on_attempt)),
+            ..self
+        }
+    }
+}
+
+/// Open the serial port of the attached uSMU with the UID `uid`, see [find_by_uid].
+pub fn open_by_uid(uid: u32) -> Result<Box<dyn SerialPort>> {
+    MicroSmu::open_port(find_by_uid(uid)?)
+}
+
+/// The serial port of the attached uSMU with the UID `uid`, e.g. to open it with [MicroSmu::builder].
+pub fn find_by_uid(uid: u32) -> Result<SerialPortInfo> {
+    identify_attached()?
+        .into_iter()
+        .find(|e| e.uid == Some(uid))
+        .map(|e| e.port)
+        .ok_or_else(|| Error::NotFound(format!("Could not find uSMU {uid}.")))
+}
+
+/// Interval of the attempts to open the device in [wait_for_device].
+pub const REENUMERATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait up to `timeout` for the device with the UID `uid` to enumerate again, e.g. after a reset,
+/// and open it with `connector`, e.g. [open_by_uid], every [REENUMERATION_POLL_INTERVAL].
+///
+/// The first attempt is made after one interval, so the device has disappeared meanwhile.
+/// Fails with [Error::Timeout] and the last failure of `connector`, if the device is not found in time.
+pub fn wait_for_device<T>(
+    uid: u32,
+    timeout: Duration,
+    connector: impl Fn(u32) -> Result<T>,
+) -> Result<T> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        sleep(REENUMERATION_POLL_INTERVAL);
+        match connector(uid) {
+            Ok(port) => return Ok(port),
+            Err(e) if Instant::now() >= deadline => Err(Error::Timeout(format!(
+                "uSMU {uid} did not enumerate again within {timeout:?}: {e}"
+            )))?,
+            Err(_) => continue,
+        }
+    }
+}
+
+impl Error {
+    /// Whether the error indicates a lost connection to the device.
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            Error::Exchange { source, .. } => source.is_disconnect(),
+            Error::Retried(attempts) => attempts.last().is_some_and(Error::is_disconnect),
+            // timeouts and garbled responses are transient, see [Error::is_transient]
+            Error::IoError(e) => !matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::InvalidData
+            ),
+            Error::Serialport(e) => matches!(
+                e.kind,
+                serialport::ErrorKind::NoDevice | serialport::ErrorKind::Io(_)
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct ReconnectParameter {
+    /// Attempts to reconnect to the device by its UID, if the connection is lost during a sweep.
+    /// The sweep resumes from the point that failed. 0 disables reconnecting.
+    #[arg(long, default_value_t = 0)]
+    pub reconnect_attempts: u32,
+
+    /// Delay before each attempt to reconnect.
+    #[arg(long, default_value = "2 s")]
+    pub reconnect_delay: Time,
+
+    /// Maximum number of reconnections during the run.
+    #[arg(long, default_value_t = 3)]
+    pub max_reconnects: u32,
+}
+
+impl Default for ReconnectParameter {
+    /// The defaults of the command line.
+    fn default() -> Self {
+        Self::parse_from(["usmu"])
+    }
+}
+
+impl ReconnectParameter {
+    /// The policy of the command line, reporting the attempts on stderr.
+    pub fn policy(&self) -> Option<ReconnectPolicy> {
+        let attempts = self.reconnect_attempts;
+        (attempts > 0).then(|| {
+            ReconnectPolicy::new(
+                attempts,
+                Duration::try_from_secs_f32(self.reconnect_delay.get::<second>())
+                    .unwrap_or_default(),
+                self.max_reconnects,
+            )
+            .with_on_attempt(move |uid, attempt, error| {
+                eprintln!(
+                    "Lost the connection to uSMU {uid} ({error}), reconnecting, attempt {attempt} of {attempts}."
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Current, Voltage, milliampere,
+        record_iv_curve::IvCurveRecordingParameters,
+        test_util::{FakeSerialPort, Fault},
+        volt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn resumes_after_reconnect() {
+        let port = FakeSerialPort::new();
+        port.expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .expect("CH1:VOL -1")
+            .expect("CH1:CUR 20")
+            .expect("CH1:ENA")
+            .expect("CH1:OSR 1")
+            .expect("CH1:VOL -1")
+            .expect_query("CH1:MEA:VOL -1", "-1.0,-0.001")
+            .expect("CH1:VOL 1")
+            .fault(Fault::Disconnect);
+        let reconnected = FakeSerialPort::new();
+        reconnected
+            .expect("CH1:CUR 20")
+            .expect("CH1:OSR 1")
+            .expect("CH1:VOL 1")
+            .expect("CH1:ENA")
+            .expect("CH1:VOL 1")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .expect("CH1:DIS");
+
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let connector = reconnected.clone();
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_attempt = reported.clone();
+        smu.set_reconnect_policy(Some(
+            ReconnectPolicy::new(1, Duration::ZERO, 1)
+                .with_connector(move |uid| {
+                    assert_eq!(uid, 42);
+                    Ok(Box::new(connector.clone()))
+                })
+                .with_on_attempt(move |uid, attempt, error| {
+                    assert!(error.is_disconnect());
+                    on_attempt.lock().unwrap().push((uid, attempt));
+                }),
+        ))
+        .unwrap();
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(-1.0),
+            end_voltage: Voltage::new::<volt>(1.0),
+            voltage_steps: 2,
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 1,
+            ..Default::default()
+        };
+        let result = parameters.record(&mut smu).unwrap();
+
+        assert_eq!(result.samples.len(), 2);
+        assert_eq!(*reported.lock().unwrap(), [(42, 1)]);
+        port.verify();
+        reconnected.verify();
+    }
+
+    #[test]
+    fn waits_for_the_device_to_enumerate() {
+        let attempts = std::cell::Cell::new(0);
+        let found = wait_for_device(42, Duration::from_secs(10), |uid| {
+            attempts.set(attempts.get() + 1);
+            match attempts.get() {
+                1 => Err(Error::NotFound(format!("Could not find uSMU {uid}."))),
+                _ => Ok(uid),
+            }
+        });
+        assert_eq!(found.unwrap(), 42);
+        assert_eq!(attempts.get(), 2);
+
+        let missing = wait_for_device(42, Duration::ZERO, |uid| {
+            Err::<(), _>(Error::NotFound(format!("Could not find uSMU {uid}.")))
+        });
+        assert!(matches!(missing, Err(Error::Timeout(_))));
+    }
+}
@@ -1,17 +1,41 @@
-use std::{io::Write, path::PathBuf, thread::sleep, time::Duration};
+use std::{io::Write, ops::ControlFlow, path::PathBuf, thread::sleep, time::Duration};
 
 use crate::{
-    Current, MicroSmu, Result, Voltage, ampere, commands::MeasureResponse, find_serial_ports, volt,
+    Current, Error, MicroSmu, Result, Voltage,
+    adaptive_over_sampling::AdaptiveOverSampling,
+    annotation::{AnnotationParameter, Stage},
+    auto_range::{AutoRange, AutoRanging},
+    budget::{self, BudgetParameter},
+    calibration_log::CalibrationAgeParameter,
+    cassette::{Cassette, RecordingPort, ReplayPort},
+    commands::MeasureResponse,
+    devices,
+    filters::FilterParameter,
+    notify::NotifyParameter,
+    reconnect::ReconnectParameter,
+    safety::SafetyParameter,
+    simulator::SimulationParameter,
+    step_refinement::StepRefinement,
+    sweep_file,
+    sweep_result::{COMPLIANCE_THRESHOLD, DeviceIdentity, SweepResult, SweepSample, SweepStatus},
+    timestamp::{Epoch, TimestampSource},
+    timing::{TimingModel, format_duration},
+    volt,
 };
-use anyhow::anyhow;
 use clap::{Parser, ValueEnum};
 use ndarray::linspace;
-use serde::Serialize;
+use serialport::SerialPort;
 use uom::si::{f32::Time, time::second};
 
+/// Name of the run in notifications.
+const RUN: &str = "IV curve recording";
+
 #[derive(Debug, Clone, ValueEnum, Parser, PartialEq, Eq)]
 pub enum OutputFormat {
     Csv,
+    /// The complete result including the sample times, requires the `json` feature.
+    #[cfg(feature = "json")]
+    Json,
 }
 
 #[derive(Debug, Parser)]
@@ -24,14 +48,72 @@ pub struct CommandlineArguments {
 
     #[command(flatten)]
     pub output_parameter: OutputParameter,
+
+    #[command(flatten)]
+    pub annotation_parameter: AnnotationParameter,
+
+    #[command(flatten)]
+    pub filter_parameter: FilterParameter,
+
+    #[cfg(feature = "mqtt")]
+    #[command(flatten)]
+    pub mqtt_parameter: crate::mqtt::MqttParameter,
+
+    #[cfg(feature = "sqlite")]
+    #[command(flatten)]
+    pub store_parameter: crate::store::StoreParameter,
+
+    #[cfg(feature = "event-log")]
+    #[command(flatten)]
+    pub event_log_parameter: crate::event_log::EventLogParameter,
+
+    #[command(flatten)]
+    pub notify_parameter: NotifyParameter,
+
+    #[command(flatten)]
+    pub budget_parameter: BudgetParameter,
+
+    /// Only print the estimated duration of the run and exit, without connecting to the device.
+    #[arg(long)]
+    pub estimate_only: bool,
 }
 
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone, Default, Parser)]
 pub struct SmuConnectionParameter {
     #[arg(long)]
     pub port: Option<PathBuf>,
     #[arg(long)]
     pub serial_number: Option<u32>,
+
+    /// Record the communication with the device to this cassette file.
+    #[arg(long)]
+    pub record_cassette: Option<PathBuf>,
+
+    /// Replay the communication from this cassette file instead of connecting to a device,
+    /// e.g. to develop without hardware.
+    #[arg(long, conflicts_with = "record_cassette")]
+    pub replay_cassette: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub simulation_parameter: SimulationParameter,
+
+    #[command(flatten)]
+    pub safety_parameter: SafetyParameter,
+
+    #[command(flatten)]
+    pub reconnect_parameter: ReconnectParameter,
+
+    #[command(flatten)]
+    pub calibration_age_parameter: CalibrationAgeParameter,
+
+    #[cfg(any(feature = "config", feature = "json"))]
+    #[command(flatten)]
+    pub calibration_override_parameter: crate::calibration_override::CalibrationOverrideParameter,
+
+    #[cfg(any(feature = "config", feature = "json"))]
+    #[command(flatten)]
+    pub temperature_compensation_parameter:
+        crate::temperature_compensation::TemperatureCompensationParameter,
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -55,6 +137,22 @@ pub struct IvCurveRecordingParameters {
     /// Time delay to wait before taking a measurement.
     #[arg(long, short = 'd', default_value = "0 ms")]
     pub delay: Time,
+
+    #[command(flatten)]
+    pub adaptive_over_sampling: AdaptiveOverSampling,
+
+    #[command(flatten)]
+    pub step_refinement: StepRefinement,
+
+    #[command(flatten)]
+    pub auto_range: AutoRange,
+}
+
+impl Default for IvCurveRecordingParameters {
+    /// The defaults of the command line.
+    fn default() -> Self {
+        Self::parse_from(["record_iv_curve"])
+    }
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -64,13 +162,90 @@ pub struct OutputParameter {
 
     #[arg(long, short = 'f', default_value = "csv")]
     pub format: OutputFormat,
+
+    #[cfg(feature = "checksum")]
+    #[command(flatten)]
+    pub checksum_parameter: crate::checksum::ChecksumParameter,
 }
 
 impl CommandlineArguments {
     pub fn run(&self) -> Result<()> {
+        let estimate = format_duration(self.estimated_duration(&TimingModel::default()));
+        if self.estimate_only {
+            println!("{estimate}");
+            return Ok(());
+        }
+        eprintln!("Estimated duration: {estimate}");
+
+        let result = self.record();
+        self.notify_parameter.notify(RUN, &result);
+        result
+    }
+
+    /// Estimated duration of the run, including the soft-start and ramp-down.
+    pub fn estimated_duration(&self, timing: &TimingModel) -> Duration {
+        let safety = &self.connection_parameter.safety_parameter;
+        let ramps = [safety.soft_start, safety.ramp_down]
+            .into_iter()
+            .flatten()
+            .map(|e| Duration::try_from_secs_f32(e.get::<second>()).unwrap_or_default())
+            .sum::<Duration>();
+        self.recording_parameter.estimated_duration(timing) + ramps
+    }
+
+    fn record(&self) -> Result<()> {
         let mut smu = self.connection_parameter.connect()?;
-        let samples = self.recording_parameter.record(&mut smu)?;
-        self.output_parameter.output(samples)?;
+        let mut hooks = self.annotation_parameter.hooks();
+        self.filter_parameter.annotate(&mut hooks);
+        hooks.run(Stage::Pre)?;
+
+        #[cfg(feature = "mqtt")]
+        let mut publisher = self.mqtt_parameter.connect()?;
+        #[cfg(feature = "event-log")]
+        let mut events = self.event_log_parameter.start()?;
+
+        let budget = self.budget_parameter.start();
+        #[cfg_attr(not(feature = "mqtt"), allow(unused_variables))]
+        let result = self
+            .recording_parameter
+            .record_with(&mut smu, |voltage, current| {
+                #[cfg(feature = "mqtt")]
+                if let Some(publisher) = publisher.as_mut() {
+                    publisher.publish_measurement(voltage, current);
+                }
+                budget::check(budget.as_ref())
+            });
+        let protection = smu.take_protection_events();
+        self.notify_parameter.protection(RUN, &protection);
+        #[cfg(feature = "event-log")]
+        let logged = events
+            .as_mut()
+            .map_or(Ok(()), |e| e.finish_sweep(&result, &protection));
+        #[cfg(feature = "mqtt")]
+        if let Some(mut publisher) = publisher {
+            match &result {
+                Ok(result) => publisher.publish_sweep_finished(result.samples.len()),
+                Err(e) => publisher.publish_sweep_failed(e),
+            }
+            let published = publisher.finish();
+            // the failure of the sweep is more relevant than a failing publication
+            if result.is_ok() {
+                published?;
+            }
+        }
+        let mut result = result?;
+        #[cfg(feature = "event-log")]
+        logged?;
+        result.device.serial_number = self.connection_parameter.serial_number;
+
+        #[cfg(feature = "sqlite")]
+        self.store_parameter.store(&result)?;
+
+        self.output_parameter
+            .output(&self.filter_parameter.apply(result)?)?;
+
+        hooks.run(Stage::Post)?;
+        self.annotation_parameter.output(&hooks)?;
 
         Ok(())
     }
@@ -78,89 +253,268 @@ impl CommandlineArguments {
 
 impl SmuConnectionParameter {
     pub fn connect(&self) -> Result<MicroSmu> {
-        let ports = find_serial_ports()?;
+        if let Some(cassette) = self.replay_cassette.as_ref() {
+            let port = ReplayPort::new(Cassette::load(cassette)?);
+            let mut smu = MicroSmu::new(Box::new(port));
+            self.safety_parameter.configure(&mut smu)?;
+            // profiles are not looked up, as identifying the device is not part of the cassette
+            #[cfg(any(feature = "config", feature = "json"))]
+            smu.set_calibration_override(self.calibration_override_parameter.load()?);
+            return Ok(smu);
+        }
 
-        let mut ports = ports
-            .into_iter()
-            .map(|port| {
-                let serial = MicroSmu::open(port.clone())
-                    .ok()
-                    .and_then(|mut e| e.get_identity().ok())
-                    .map(|e| format!("{e}"))
-                    .unwrap_or("<failed to read>".to_string());
-
-                (port, serial)
-            })
-            .collect::<Vec<_>>();
+        let mut port = match self.simulation_parameter.port() {
+            Some(port) => Box::new(port),
+            None => self.open_port()?,
+        };
+        if let Some(cassette) = self.record_cassette.as_ref() {
+            port = Box::new(RecordingPort::create(port, cassette)?);
+        }
+        let mut smu = MicroSmu::new(port);
+        self.safety_parameter.configure(&mut smu)?;
+        smu.set_reconnect_policy(self.reconnect_parameter.policy())?;
+        self.calibration_age_parameter.configure(&mut smu)?;
+        #[cfg(any(feature = "config", feature = "json"))]
+        self.calibration_override_parameter.configure(&mut smu)?;
+        #[cfg(any(feature = "config", feature = "json"))]
+        self.temperature_compensation_parameter
+            .configure(&mut smu)?;
 
-        if ports.is_empty() {
-            Err(anyhow!(
-                "Could not find uSMU. No matching serial port identified."
+        Ok(smu)
+    }
+
+    fn open_port(&self) -> Result<Box<dyn SerialPort>> {
+        let mut devices = devices::identify_attached()?;
+
+        if devices.is_empty() {
+            Err(Error::NotFound(
+                "Could not find uSMU. No matching serial port identified.".to_string(),
             ))?;
         }
 
-        if ports.len() > 1 && self.port.is_none() && self.serial_number.is_none() {
+        if devices.len() > 1 && self.port.is_none() && self.serial_number.is_none() {
             eprintln!("Available devices:");
-            for (port, serial) in ports.iter() {
-                eprintln!("{} - {}", port.port_name, serial);
+            for device in devices.iter() {
+                let serial = device
+                    .uid
+                    .map_or("<failed to read>".to_string(), |e| e.to_string());
+                eprintln!("{} - {}", device.port.port_name, serial);
             }
 
-            Err(anyhow!(
-                "Multiple uSMUs are attached, but neitherr port nor serial number are defined. Specify at least one to disambiguate the device."
-            ))?;
+            Err(Error::Ambiguous("Multiple uSMUs are attached, but neither port nor serial number are defined. Specify at least one to disambiguate the device.".to_string()))?;
         }
 
         if let Some(port) = self.port.as_ref() {
-            ports.retain(|(e, _)| {
-                e.port_name == port.to_str().expect("path to string conversion failed")
-            });
+            devices.retain(|e| Some(e.port.port_name.as_str()) == port.to_str());
         }
-        if let Some(serial_number) = self.serial_number.as_ref() {
-            ports.retain(|(_, e)| e == serial_number.to_string().as_str());
+        if let Some(serial_number) = self.serial_number {
+            devices.retain(|e| e.uid == Some(serial_number));
         }
 
-        assert_eq!(ports.len(), 1);
-        let (port, _) = ports.into_iter().next().unwrap();
-
-        let smu = MicroSmu::open(port)?;
-
-        Ok(smu)
+        let device = devices.into_iter().next().ok_or_else(|| {
+            Error::NotFound("No attached uSMU matches the port and serial number.".to_string())
+        })?;
+        MicroSmu::open_port(device.port)
     }
 }
 
 impl IvCurveRecordingParameters {
-    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<(Voltage, Current)>> {
+    /// Estimated duration of the sweep, see [TimingModel].
+    pub fn estimated_duration(&self, timing: &TimingModel) -> Duration {
+        let points = self.voltage_steps as u32;
+        let delay = Duration::try_from_secs_f32(self.delay.get::<second>()).unwrap_or_default();
+        // setting the voltage, current limit, over-sampling and enabling before, disabling after the sweep
+        timing.commands(5)
+            + (timing.commands(1) + delay + timing.measurement(self.over_sampling)) * points
+    }
+
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<SweepResult> {
+        self.record_with(smu, |_, _| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called with every recorded sample.
+    ///
+    /// Returning [ControlFlow::Break] from `on_sample` ends the sweep early,
+    /// the output is disabled and the samples recorded so far are returned with [SweepStatus::Aborted].
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<SweepResult> {
+        self.record_with_hooks(smu, || Ok(()), on_sample)
+    }
+
+    /// Like [Self::record_with], but `before_point` is called after setting the voltage of each point,
+    /// right before measuring.
+    pub(crate) fn record_with_hooks(
+        &self,
+        smu: &mut MicroSmu,
+        before_point: impl FnMut() -> Result<()>,
+        on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<SweepResult> {
+        #[cfg(feature = "opentelemetry")]
+        let span = crate::telemetry::sweep_span(self);
+
+        let result = self.sweep(smu, before_point, on_sample);
+
+        #[cfg(feature = "opentelemetry")]
+        span.finish_sweep(&result);
+
+        result
+    }
+
+    fn sweep(
+        &self,
+        smu: &mut MicroSmu,
+        before_point: impl FnMut() -> Result<()>,
+        on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<SweepResult> {
+        let ranging = self.auto_range.start(smu)?;
+        let result = self.sweep_points(smu, ranging.as_ref(), before_point, on_sample);
+        // the calibration of the firmware is restored also after a failed sweep
+        let restored = ranging.map_or(Ok(()), |e| e.finish(smu));
+        let result = result?;
+        restored?;
+        Ok(result)
+    }
+
+    fn sweep_points(
+        &self,
+        smu: &mut MicroSmu,
+        ranging: Option<&AutoRanging>,
+        mut before_point: impl FnMut() -> Result<()>,
+        mut on_sample: impl FnMut(Voltage, Current) -> ControlFlow<()>,
+    ) -> Result<SweepResult> {
+        let epoch = Epoch::now();
         smu.set_voltage(self.start_voltage)?;
         smu.set_current_limit(self.current_limit)?;
-        smu.enable()?;
+        // the output is disabled also if the sweep fails, see [guard](crate::guard)
+        let mut output = smu.enabled()?;
+        let smu = &mut *output;
         smu.set_over_sample_rate(self.over_sampling)?;
 
-        let mut samples = Vec::with_capacity(self.voltage_steps);
+        let mut samples: Vec<SweepSample> = Vec::with_capacity(self.voltage_steps);
+        let mut status = SweepStatus::Completed;
+        let mut over_sampling = self.over_sampling;
+        let mut refinement_budget = self.step_refinement.budget(self.voltage_steps);
+        let mut measure = |smu: &mut MicroSmu, set_voltage| {
+            // a point failing by a lost connection is repeated, if the smu reconnects
+            let (time, MeasureResponse { voltage, current }, range) = loop {
+                match self.measure_point(
+                    smu,
+                    ranging,
+                    set_voltage,
+                    &mut before_point,
+                    &epoch,
+                    &mut over_sampling,
+                ) {
+                    Ok(measured) => break measured,
+                    Err(e) => smu.recover(e)?,
+                }
+            };
+            Ok::<_, Error>(SweepSample {
+                time,
+                set_voltage,
+                voltage,
+                current,
+                compliance: current.abs() >= self.current_limit * COMPLIANCE_THRESHOLD,
+                range,
+            })
+        };
 
-        for set_voltage in linspace(
+        'sweep: for set_voltage in linspace(
             self.start_voltage.get::<volt>(),
             self.end_voltage.get::<volt>(),
             self.voltage_steps,
         ) {
             // unfortunately, we need to unpack and repack the voltage here to use the linspace iterator :'(
             let set_voltage = Voltage::new::<volt>(set_voltage);
-            smu.set_voltage(set_voltage)?;
-            sleep(Duration::from_secs_f32(self.delay.get::<second>()));
-            let MeasureResponse { voltage, current } = smu.measure(set_voltage)?;
-            samples.push((voltage, current));
+            // the upcoming samples, nearest last, refined against the last recorded sample, see [StepRefinement]
+            let mut pending = vec![measure(smu, set_voltage)?];
+            while let Some(sample) = pending.pop() {
+                let midpoint = samples
+                    .last()
+                    .filter(|_| refinement_budget > 0)
+                    .and_then(|last| self.step_refinement.midpoint(last, &sample));
+                if let Some(midpoint) = midpoint {
+                    refinement_budget -= 1;
+                    pending.push(sample);
+                    pending.push(measure(smu, midpoint)?);
+                    continue;
+                }
+                samples.push(sample);
+                if on_sample(sample.voltage, sample.current).is_break() {
+                    status = SweepStatus::Aborted;
+                    break 'sweep;
+                }
+            }
         }
 
-        smu.disable()?;
+        let device = DeviceIdentity {
+            port: smu.port_name(),
+            serial_number: None,
+        };
+        output.disable()?;
+
+        Ok(SweepResult {
+            parameters: self.clone(),
+            device,
+            started_at: Some(epoch.system_time()),
+            status,
+            samples,
+        })
+    }
 
-        Ok(samples)
+    /// Measure at `set_voltage`, in the range of the current with `ranging`,
+    /// measuring again if the current calls for another `over_sampling`, see [AdaptiveOverSampling].
+    fn measure_point(
+        &self,
+        smu: &mut MicroSmu,
+        ranging: Option<&AutoRanging>,
+        set_voltage: Voltage,
+        before_point: &mut impl FnMut() -> Result<()>,
+        epoch: &Epoch,
+        over_sampling: &mut u16,
+    ) -> Result<(Time, MeasureResponse, Option<u8>)> {
+        let measure = |smu: &mut MicroSmu| match ranging {
+            Some(ranging) => {
+                let (measured, range) = ranging.measure(smu, set_voltage)?;
+                Ok((measured, Some(range.value())))
+            }
+            None => Ok::<_, Error>((smu.measure(set_voltage)?, None)),
+        };
+        smu.set_voltage(set_voltage)?;
+        sleep(Duration::from_secs_f32(self.delay.get::<second>()));
+        before_point()?;
+        let time = epoch.elapsed(TimestampSource::Monotonic);
+        let (measured, range) = measure(smu)?;
+
+        let rate = self
+            .adaptive_over_sampling
+            .rate(measured.current, self.over_sampling);
+        if rate == *over_sampling {
+            return Ok((time, measured, range));
+        }
+        smu.set_over_sample_rate(rate)?;
+        *over_sampling = rate;
+        let time = epoch.elapsed(TimestampSource::Monotonic);
+        let (measured, range) = measure(smu)?;
+        Ok((time, measured, range))
     }
 }
 
 impl OutputParameter {
-    pub fn output(&self, samples: Vec<(Voltage, Current)>) -> Result<()> {
+    pub fn output(&self, result: &SweepResult) -> Result<()> {
+        let output = self.output_writer()?;
         match self.format {
-            OutputFormat::Csv => self.write_csv(samples),
+            OutputFormat::Csv => sweep_file::write_csv(result, output)?,
+            #[cfg(feature = "json")]
+            OutputFormat::Json => sweep_file::json::write_json(result, output)?,
         }
+        #[cfg(feature = "checksum")]
+        self.checksum_parameter.seal(self.output.as_ref())?;
+
+        Ok(())
     }
 
     fn output_writer(&self) -> Result<Box<dyn Write>> {
@@ -171,26 +525,4 @@ impl OutputParameter {
             Ok(Box::new(std::io::stdout()))
         }
     }
-
-    fn write_csv(&self, samples: Vec<(Voltage, Current)>) -> Result<()> {
-        #[derive(Serialize)]
-        struct Sample {
-            voltage: f32,
-            current: f32,
-        }
-
-        let output = self.output_writer()?;
-        let mut writer = csv::WriterBuilder::new().from_writer(output);
-        for (v, i) in samples {
-            writer
-                .serialize(Sample {
-                    voltage: v.get::<volt>(),
-                    current: i.get::<ampere>(),
-                })
-                .map_err(|e| anyhow::anyhow!(e))?;
-        }
-        writer.flush()?;
-
-        Ok(())
-    }
 }
@@ -0,0 +1,352 @@
+//! Software regulation of the output, e.g. a constant current, power or resistive load,
+//! by repeatedly measuring and adjusting the voltage setpoint.
+//!
+//! The uSMU only sources voltage, hence any other operating mode is emulated on the host.
+//! Each [Regulator::step] measures once and moves the setpoint towards the target,
+//! using a secant estimate of the device under test's slope.
+//! The loop is as fast as the serial communication, i.e. a few steps per second,
+//! so it is suited for slowly changing devices like batteries, but not for fast transients.
+
+use std::{io::Write, ops::ControlFlow, thread::sleep, time::Instant};
+
+use serde::Serialize;
+use uom::si::power::watt;
+
+use crate::{
+    Current, Error, MicroSmu, Power, Resistance, Result, Time, Voltage, ampere,
+    commands::MeasureResponse, schema, second, timing, volt,
+};
+
+/// The quantity held constant by a [Regulator].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegulationTarget {
+    /// Hold the output current, negative values sink current from the device under test.
+    Current(Current),
+    /// Hold the power dissipated in the device under test, for either polarity.
+    Power(Power),
+    /// Emulate a resistive load, i.e. sink the current `V / R` from a device under test sourcing current,
+    /// e.g. an energy harvester or a sensor output.
+    Resistance(Resistance),
+}
+
+impl RegulationTarget {
+    /// Deviation of a measurement from the target, zero if the target is met.
+    ///
+    /// The deviation rises with the output voltage for a passive device under test.
+    fn error(&self, voltage: Voltage, current: Current) -> f32 {
+        match self {
+            RegulationTarget::Current(target) => (current - *target).get::<ampere>(),
+            RegulationTarget::Power(target) => {
+                let voltage = voltage.get::<volt>();
+                let power = voltage * current.get::<ampere>();
+                // the dissipation rises with the magnitude of the voltage, hence the sign
+                (power - target.get::<watt>()) * voltage.signum()
+            }
+            RegulationTarget::Resistance(target) => (current + voltage / *target).get::<ampere>(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regulator {
+    target: RegulationTarget,
+    minimum_voltage: Voltage,
+    maximum_voltage: Voltage,
+    maximum_step: Voltage,
+    setpoint: Voltage,
+    /// Setpoint and error of the previous step, for the secant estimate.
+    previous: Option<(f32, f32)>,
+    /// Estimated change of the error per volt.
+    slope: Option<f32>,
+}
+
+/// Setpoint changes below this are dominated by noise and not used to estimate the slope, in volt.
+const MINIMUM_SECANT: f32 = 1e-3;
+
+impl Regulator {
+    /// A regulator starting at the `initial` setpoint, within the full output range of ±5 V
+    /// and with at most 100 mV change per step.
+    pub fn new(target: RegulationTarget, initial: Voltage) -> Self {
+        Self {
+            target,
+            minimum_voltage: Voltage::new::<volt>(-5.0),
+            maximum_voltage: Voltage::new::<volt>(5.0),
+            maximum_step: Voltage::new::<volt>(0.1),
+            setpoint: initial,
+            previous: None,
+            slope: None,
+        }
+    }
+
+    /// Restrict the setpoint to `minimum..=maximum`.
+    pub fn with_bounds(mut self, minimum: Voltage, maximum: Voltage) -> Self {
+        self.minimum_voltage = minimum;
+        self.maximum_voltage = maximum;
+        self.setpoint = self.clamp(self.setpoint.get::<volt>());
+        self
+    }
+
+    /// Limit the change of the setpoint per step.
+    pub fn with_maximum_step(mut self, step: Voltage) -> Self {
+        self.maximum_step = step;
+        self
+    }
+
+    pub fn target(&self) -> RegulationTarget {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: RegulationTarget) {
+        self.target = target;
+        self.previous = None;
+        self.slope = None;
+    }
+
+    pub fn setpoint(&self) -> Voltage {
+        self.setpoint
+    }
+
+    /// Measure at the current setpoint and adjust the setpoint for the next step.
+    ///
+    /// The output must be enabled.
+    pub fn step(&mut self, smu: &mut MicroSmu) -> Result<MeasureResponse> {
+        let response = smu.measure(self.setpoint)?;
+        let error = self.target.error(response.voltage, response.current);
+        let setpoint = self.setpoint.get::<volt>();
+        let maximum_step = self.maximum_step.get::<volt>();
+
+        if let Some((previous_setpoint, previous_error)) = self.previous
+            && (setpoint - previous_setpoint).abs() >= MINIMUM_SECANT
+        {
+            let slope = (error - previous_error) / (setpoint - previous_setpoint);
+            // a flat or inverted slope, e.g. in compliance, keeps the previous estimate
+            if slope.is_finite() && slope > 0.0 {
+                self.slope = Some(slope);
+            }
+        }
+        let step = match self.slope {
+            Some(slope) => (-error / slope).clamp(-maximum_step, maximum_step),
+            // without a slope estimate, move at full pace
+            None if error != 0.0 => -maximum_step.copysign(error),
+            None => 0.0,
+        };
+
+        self.previous = Some((setpoint, error));
+        self.setpoint = self.clamp(setpoint + step);
+        Ok(response)
+    }
+
+    fn clamp(&self, setpoint: f32) -> Voltage {
+        Voltage::new::<volt>(setpoint.clamp(
+            self.minimum_voltage.get::<volt>(),
+            self.maximum_voltage.get::<volt>(),
+        ))
+    }
+}
+
+/// Hold a [RegulationTarget] for some time, e.g. to stress a component at a defined dissipation.
+#[derive(Debug, Clone)]
+pub struct HoldParameters {
+    pub target: RegulationTarget,
+
+    /// Initial setpoint, preferably close to the expected operating point.
+    pub start_voltage: Voltage,
+
+    /// The setpoint is kept within `minimum_voltage..=maximum_voltage`.
+    pub minimum_voltage: Voltage,
+    pub maximum_voltage: Voltage,
+
+    pub current_limit: Current,
+
+    pub duration: Time,
+
+    /// Time between regulation steps.
+    pub interval: Time,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HoldSample {
+    /// Time since the start of the hold.
+    pub time: Time,
+    pub voltage: Voltage,
+    pub current: Current,
+}
+
+impl HoldParameters {
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<HoldSample>> {
+        self.record_with(smu, |_| ControlFlow::Continue(()))
+    }
+
+    /// Like [Self::record], but `on_sample` is called for each sample and can end the hold early.
+    ///
+    /// The output is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        on_sample: impl FnMut(&HoldSample) -> ControlFlow<()>,
+    ) -> Result<Vec<HoldSample>> {
+        let samples = self.hold(smu, on_sample);
+        let disabled = smu.disable();
+        let samples = samples?;
+        disabled?;
+        Ok(samples)
+    }
+
+    fn hold(
+        &self,
+        smu: &mut MicroSmu,
+        mut on_sample: impl FnMut(&HoldSample) -> ControlFlow<()>,
+    ) -> Result<Vec<HoldSample>> {
+        if self.minimum_voltage > self.maximum_voltage {
+            Err(Error::Configuration(format!(
+                "Minimum voltage {} V exceeds the maximum voltage {} V.",
+                self.minimum_voltage.get::<volt>(),
+                self.maximum_voltage.get::<volt>()
+            )))?;
+        }
+        let duration = timing::duration("duration", self.duration)?;
+        let interval = timing::duration("interval", self.interval)?;
+        let mut regulator = Regulator::new(self.target, self.start_voltage)
+            .with_bounds(self.minimum_voltage, self.maximum_voltage);
+
+        smu.set_voltage(regulator.setpoint())?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.enable()?;
+
+        let start = Instant::now();
+        let mut samples = Vec::new();
+        loop {
+            let MeasureResponse { voltage, current } = regulator.step(smu)?;
+            let elapsed = start.elapsed();
+            let sample = HoldSample {
+                time: Time::new::<second>(elapsed.as_secs_f32()),
+                voltage,
+                current,
+            };
+            samples.push(sample);
+
+            if on_sample(&sample).is_break() || elapsed >= duration {
+                break;
+            }
+            sleep(interval);
+        }
+
+        Ok(samples)
+    }
+}
+
+/// Write the samples as CSV with the columns `time` (seconds), `voltage` and `current`.
+pub fn write_csv(samples: &[HoldSample], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        time: f32,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for sample in samples {
+        writer.serialize(Row {
+            time: sample.time.get::<second>(),
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uom::si::{electrical_resistance::ohm, power::milliwatt};
+
+    use crate::{
+        Current, Error, MicroSmu, Power, Resistance, Time, Voltage, ampere, milliampere,
+        regulation::{HoldParameters, RegulationTarget, Regulator},
+        second,
+        simulator::{Resistor, SimulatedSmu, SolarCell},
+        volt,
+    };
+
+    #[test]
+    fn holds_constant_current() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        smu.enable().unwrap();
+        let target = Current::new::<milliampere>(2.0);
+        let mut regulator =
+            Regulator::new(RegulationTarget::Current(target), Voltage::new::<volt>(0.0));
+
+        let mut current = Current::new::<ampere>(0.0);
+        for _ in 0..30 {
+            current = regulator.step(&mut smu).unwrap().current;
+        }
+
+        assert!((current - target).abs().get::<milliampere>() < 1e-3);
+        assert!((regulator.setpoint().get::<volt>() - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn holds_constant_power() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        smu.enable().unwrap();
+        let mut regulator = Regulator::new(
+            RegulationTarget::Power(Power::new::<milliwatt>(10.0)),
+            Voltage::new::<volt>(0.0),
+        )
+        .with_maximum_step(Voltage::new::<volt>(1.0));
+
+        for _ in 0..15 {
+            regulator.step(&mut smu).unwrap();
+        }
+
+        // 10 mW in 1 kOhm
+        assert!((regulator.setpoint().get::<volt>() - 10f32.sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn emulates_resistive_load() {
+        let cell = SolarCell::new(Current::new::<milliampere>(10.0), Voltage::new::<volt>(0.6));
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(cell)));
+        smu.enable().unwrap();
+        let mut regulator = Regulator::new(
+            RegulationTarget::Resistance(Resistance::new::<ohm>(10.0)),
+            Voltage::new::<volt>(0.0),
+        );
+
+        let mut response = regulator.step(&mut smu).unwrap();
+        for _ in 0..10 {
+            response = regulator.step(&mut smu).unwrap();
+        }
+
+        let load = response.voltage / -response.current;
+        assert!((load.get::<ohm>() - 10.0).abs() < 0.1);
+    }
+    #[test]
+    fn rejects_negative_times() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let parameters = HoldParameters {
+            target: RegulationTarget::Current(Current::new::<milliampere>(1.0)),
+            start_voltage: Voltage::new::<volt>(0.0),
+            minimum_voltage: Voltage::new::<volt>(-1.0),
+            maximum_voltage: Voltage::new::<volt>(1.0),
+            current_limit: Current::new::<milliampere>(20.0),
+            duration: Time::new::<second>(1.0),
+            interval: Time::new::<second>(0.1),
+        };
+        for parameters in [
+            HoldParameters {
+                duration: Time::new::<second>(-1.0),
+                ..parameters.clone()
+            },
+            HoldParameters {
+                interval: Time::new::<second>(f32::NAN),
+                ..parameters
+            },
+        ] {
+            let result = parameters.record(&mut smu);
+            assert!(matches!(result, Err(Error::Configuration(_))));
+            assert_eq!(smu.get_set_voltage(), None);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Resampling of IV curves onto a common voltage grid,
+//! e.g. to diff, average or compare the envelopes of runs recorded with different steps,
+//! or with measured voltages deviating from the set voltages.
+
+use crate::{Current, Voltage, ampere, volt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Straight lines between adjacent samples.
+    #[default]
+    Linear,
+    /// Natural cubic spline through the samples, with continuous first and second derivatives.
+    Spline,
+}
+
+/// `steps` equally spaced voltages from `start` to `end`, both included.
+pub fn voltage_grid(start: Voltage, end: Voltage, steps: usize) -> Vec<Voltage> {
+    match steps {
+        0 => Vec::new(),
+        1 => vec![start],
+        _ => (0..steps)
+            .map(|e| start + (end - start) * (e as f32 / (steps - 1) as f32))
+            .collect(),
+    }
+}
+
+/// Resample the curve at the voltages of `grid`.
+///
+/// The samples may be in any order, samples of equal voltage are averaged.
+/// There is no extrapolation, the current at grid voltages outside the range of the samples is `None`.
+pub fn resample(
+    samples: &[(Voltage, Current)],
+    grid: &[Voltage],
+    interpolation: Interpolation,
+) -> Vec<(Voltage, Option<Current>)> {
+    let knots = knots(samples);
+    let curvatures = match interpolation {
+        Interpolation::Linear => vec![0.0; knots.len()],
+        Interpolation::Spline => spline_curvatures(&knots),
+    };
+
+    grid.iter()
+        .map(|&voltage| {
+            let x = f64::from(voltage.get::<volt>());
+            let current =
+                evaluate(&knots, &curvatures, x).map(|i| Current::new::<ampere>(i as f32));
+            (voltage, current)
+        })
+        .collect()
+}
+
+/// The finite samples sorted by voltage, with samples of equal voltage averaged.
+fn knots(samples: &[(Voltage, Current)]) -> Vec<(f64, f64)> {
+    let mut points: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|(v, i)| (f64::from(v.get::<volt>()), f64::from(i.get::<ampere>())))
+        .filter(|(v, i)| v.is_finite() && i.is_finite())
+        .collect();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut knots: Vec<(f64, f64, usize)> = Vec::with_capacity(points.len());
+    for (v, i) in points {
+        match knots.last_mut() {
+            Some((voltage, current, count)) if *voltage == v => {
+                *current += i;
+                *count += 1;
+            }
+            _ => knots.push((v, i, 1)),
+        }
+    }
+    knots
+        .into_iter()
+        .map(|(v, i, count)| (v, i / count as f64))
+        .collect()
+}
+
+/// Second derivatives at the knots of the natural cubic spline, zero at both ends.
+fn spline_curvatures(knots: &[(f64, f64)]) -> Vec<f64> {
+    let n = knots.len();
+    let mut curvatures = vec![0.0; n];
+    if n < 3 {
+        return curvatures;
+    }
+    let h: Vec<f64> = knots.windows(2).map(|e| e[1].0 - e[0].0).collect();
+    let slope: Vec<f64> = knots
+        .windows(2)
+        .zip(&h)
+        .map(|(e, h)| (e[1].1 - e[0].1) / h)
+        .collect();
+
+    // tridiagonal system of the inner knots, solved by the Thomas algorithm
+    let mut diagonal: Vec<f64> = (1..n - 1).map(|k| 2.0 * (h[k - 1] + h[k])).collect();
+    let mut rhs: Vec<f64> = (1..n - 1)
+        .map(|k| 6.0 * (slope[k] - slope[k - 1]))
+        .collect();
+    for row in 1..diagonal.len() {
+        // the sub- and superdiagonal entries between inner knots `row` and `row + 1` are `h[row]`
+        let factor = h[row] / diagonal[row - 1];
+        diagonal[row] -= factor * h[row];
+        rhs[row] -= factor * rhs[row - 1];
+    }
+    for row in (0..diagonal.len()).rev() {
+        let next = curvatures[row + 2];
+        curvatures[row + 1] = (rhs[row] - h[row + 1] * next) / diagonal[row];
+    }
+    curvatures
+}
+
+fn evaluate(knots: &[(f64, f64)], curvatures: &[f64], x: f64) -> Option<f64> {
+    let (first, last) = (knots.first()?, knots.last()?);
+    if !(first.0..=last.0).contains(&x) {
+        return None;
+    }
+    if knots.len() == 1 {
+        return Some(first.1);
+    }
+    let k = knots
+        .partition_point(|e| e.0 <= x)
+        .clamp(1, knots.len() - 1)
+        - 1;
+    let ((x0, y0), (x1, y1)) = (knots[k], knots[k + 1]);
+    let (m0, m1) = (curvatures[k], curvatures[k + 1]);
+    let h = x1 - x0;
+    let (a, b) = (x1 - x, x - x0);
+    Some(
+        (m0 * a.powi(3) + m1 * b.powi(3)) / (6.0 * h)
+            + (y0 / h - m0 * h / 6.0) * a
+            + (y1 / h - m1 * h / 6.0) * b,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(
+        f: impl Fn(f32) -> f32,
+        voltages: impl Iterator<Item = f32>,
+    ) -> Vec<(Voltage, Current)> {
+        voltages
+            .map(|v| (Voltage::new::<volt>(v), Current::new::<ampere>(f(v))))
+            .collect()
+    }
+
+    #[test]
+    fn resamples_onto_grid() {
+        let grid = voltage_grid(Voltage::new::<volt>(-1.0), Voltage::new::<volt>(2.0), 7);
+        assert_eq!(grid.len(), 7);
+        assert_eq!(grid[1], Voltage::new::<volt>(-0.5));
+
+        // unsorted with a repeated voltage, averaging to the line
+        let mut samples = curve(|v| 0.01 * v, [0.0, 1.0, 0.3, 2.0].into_iter());
+        samples.push((Voltage::new::<volt>(1.0), Current::new::<ampere>(0.0)));
+        samples.push((Voltage::new::<volt>(1.0), Current::new::<ampere>(0.02)));
+        let resampled = resample(&samples, &grid, Interpolation::Linear);
+        assert_eq!(resampled[0], (grid[0], None));
+        assert_eq!(resampled[1].1, None);
+        for (v, i) in &resampled[2..] {
+            let i = i.unwrap().get::<ampere>();
+            assert!((i - 0.01 * v.get::<volt>()).abs() < 1e-6, "{v:?} {i}");
+        }
+    }
+
+    #[test]
+    fn spline_follows_curvature() {
+        let samples = curve(|v| v.sin(), (0..=10).map(|e| e as f32 * 0.3));
+        let grid = voltage_grid(Voltage::new::<volt>(0.15), Voltage::new::<volt>(2.85), 10);
+        let error = |interpolation| {
+            resample(&samples, &grid, interpolation)
+                .into_iter()
+                .map(|(v, i)| (i.unwrap().get::<ampere>() - v.get::<volt>().sin()).abs())
+                .fold(0.0, f32::max)
+        };
+        let (linear, spline) = (error(Interpolation::Linear), error(Interpolation::Spline));
+        assert!(spline < 1e-3, "{spline}");
+        assert!(spline < linear / 5.0, "{spline} {linear}");
+
+        // the samples themselves are reproduced
+        let at_samples: Vec<Voltage> = samples.iter().map(|e| e.0).collect();
+        for ((_, i), (_, expected)) in resample(&samples, &at_samples, Interpolation::Spline)
+            .into_iter()
+            .zip(&samples)
+        {
+            assert!((i.unwrap() - *expected).abs().get::<ampere>() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Retrying queries failing transiently, e.g. on flaky USB links.
+//!
+//! With a [RetryPolicy], see [MicroSmu::set_retry_policy](crate::MicroSmu::set_retry_policy),
+//! queries whose response timed out or could not be parsed are sent again, after discarding any pending input.
+//! All queries of the uSMU are idempotent, e.g. a measurement sets the same voltage again,
+//! while commands without response are never retried, as their failures are not transient.
+//! If all attempts fail, [Error::Retried] reports the failure of each,
+//! a query failing without being retried, e.g. on a lost connection, reports its failure as is.
+
+use std::time::Duration;
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts of each query in total, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for each further one.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(attempts: u32, backoff: Duration) -> Self {
+        Self { attempts, backoff }
+    }
+
+    /// Delay before the retry following `failures` failed attempts.
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+    }
+}
+
+impl Error {
+    /// Whether the error may not recur when trying again, i.e. a timeout or a garbled response,
+    /// including invalid UTF-8.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Exchange { source, .. } => source.is_transient(),
+            Error::IoError(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::InvalidData
+            ),
+            Error::ScpiClient(_) | Error::InvalidResponse { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MicroSmu, Voltage,
+        test_util::{FakeSerialPort, Fault},
+        volt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn doubles_the_backoff() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(10));
+        assert_eq!(policy.delay(1), Duration::from_millis(10));
+        assert_eq!(policy.delay(3), Duration::from_millis(40));
+    }
+
+    #[test]
+    fn retries_garbled_responses() {
+        let port = FakeSerialPort::new();
+        port.expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .fault(Fault::Garbage(b"#".to_vec()))
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Truncate(4))
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Garbage(b"#".to_vec()))
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Garbage(b"\xff".to_vec()))
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Disconnect);
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+        smu.set_timeout(Duration::from_millis(50)).unwrap();
+        smu.set_retry_policy(Some(RetryPolicy::new(2, Duration::ZERO)));
+
+        let measured = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert_eq!(measured.voltage, Voltage::new::<volt>(1.0));
+        let error = smu.get_identity().unwrap_err();
+        let Error::Retried(attempts) = &error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts.iter().all(Error::is_transient));
+
+        // invalid UTF-8 is garbled as well
+        assert_eq!(smu.get_identity().unwrap(), 42);
+        let error = smu.get_identity().unwrap_err();
+        assert!(!matches!(error, Error::Retried(_)), "{error}");
+        assert!(error.is_disconnect());
+        port.verify();
+    }
+}
@@ -0,0 +1,163 @@
+//! Rolling buffer continuously sampling a device in the background, a "soft oscilloscope".
+//!
+//! The buffer keeps the most recent samples in a ring of fixed capacity, so TUI and GUI frontends
+//! can poll [RollingBuffer::snapshot] to render strip charts without managing the acquisition themselves.
+
+use std::{
+    collections::VecDeque,
+    ops::ControlFlow,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    Error, MicroSmu, Result,
+    monitor::{MonitorParameters, MonitorSample},
+};
+
+#[derive(Debug)]
+struct Ring {
+    samples: VecDeque<MonitorSample>,
+    capacity: usize,
+    total: u64,
+}
+
+pub struct RollingBuffer {
+    ring: Arc<Mutex<Ring>>,
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<(MicroSmu, Result<()>)>>,
+}
+
+impl RollingBuffer {
+    /// Start monitoring with `parameters` in a background thread, keeping the last `capacity` samples.
+    ///
+    /// Sampling continues until [Self::stop] is called, the duration of the parameters elapsed or a measurement failed.
+    ///
+    /// Panics, if `capacity` is zero.
+    pub fn start(mut smu: MicroSmu, parameters: MonitorParameters, capacity: usize) -> Self {
+        assert!(capacity > 0, "The capacity must be positive.");
+        let ring = Arc::new(Mutex::new(Ring {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            total: 0,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let sampler = {
+            let ring = ring.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let result = parameters.monitor_with(&mut smu, |sample| {
+                    {
+                        let mut ring = ring.lock().unwrap_or_else(PoisonError::into_inner);
+                        if ring.samples.len() == ring.capacity {
+                            ring.samples.pop_front();
+                        }
+                        ring.samples.push_back(*sample);
+                        ring.total += 1;
+                    }
+                    if stop.load(Ordering::Relaxed) {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                });
+                (smu, result)
+            })
+        };
+
+        Self {
+            ring,
+            stop,
+            sampler: Some(sampler),
+        }
+    }
+
+    fn ring(&self) -> std::sync::MutexGuard<'_, Ring> {
+        self.ring.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The buffered samples, oldest first.
+    pub fn snapshot(&self) -> Vec<MonitorSample> {
+        self.ring().samples.iter().copied().collect()
+    }
+
+    pub fn latest(&self) -> Option<MonitorSample> {
+        self.ring().samples.back().copied()
+    }
+
+    /// Number of samples taken since the start, including the ones dropped from the ring.
+    pub fn total(&self) -> u64 {
+        self.ring().total
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.sampler.as_ref().is_some_and(|e| !e.is_finished())
+    }
+
+    /// Stop sampling, the output is disabled.
+    ///
+    /// Returns the device, or the error that ended the sampling early.
+    pub fn stop(mut self) -> Result<MicroSmu> {
+        self.stop.store(true, Ordering::Relaxed);
+        let sampler = self.sampler.take().expect("sampler is only taken once");
+        let (smu, result) = sampler
+            .join()
+            .map_err(|_| Error::Internal("The sampling thread panicked.".to_string()))?;
+        result?;
+        Ok(smu)
+    }
+}
+
+impl Drop for RollingBuffer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(sampler) = self.sampler.take() {
+            let _ = sampler.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::sleep, time::Duration};
+
+    use super::*;
+    use crate::{
+        Current, Time, Voltage, ampere, second,
+        simulator::{Resistor, SimulatedSmu},
+        timestamp::TimestampSource,
+        volt,
+    };
+
+    #[test]
+    fn keeps_the_most_recent_samples() {
+        let smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let parameters = MonitorParameters {
+            voltage: Voltage::new::<volt>(1.0),
+            current_limit: Current::new::<ampere>(0.01),
+            over_sampling: 1,
+            interval: Time::new::<second>(0.001),
+            duration: None,
+            auto_zero: None,
+            timestamps: TimestampSource::default(),
+            epoch: None,
+        };
+        let buffer = RollingBuffer::start(smu, parameters, 3);
+        while buffer.total() < 5 {
+            assert!(buffer.is_running());
+            sleep(Duration::from_millis(1));
+        }
+
+        let snapshot = buffer.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert!(snapshot.windows(2).all(|e| e[0].time < e[1].time));
+        let current = buffer.latest().unwrap().current.get::<ampere>();
+        assert!((current - 0.001).abs() < 1e-5);
+
+        buffer.stop().unwrap();
+    }
+}
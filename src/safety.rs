@@ -0,0 +1,270 @@
+//! Safety limits enforced by the host, independent of the hardware limits of the uSMU.
+//!
+//! [MicroSmu](crate::MicroSmu) validates every voltage and current limit against its [SafetyLimits]
+//! before sending it, and fails with [Error::SafetyLimit] instead, e.g. to protect a device under test
+//! rated for lower voltages than the uSMU provides.
+//! The raw DAC levels of the calibration commands are not checked.
+//!
+//! With a soft-start, see [MicroSmu::set_soft_start](crate::MicroSmu::set_soft_start), enabling the output
+//! ramps the voltage up from 0 V instead of switching it on at once,
+//! and with a ramp-down, see [MicroSmu::set_ramp_down](crate::MicroSmu::set_ramp_down),
+//! disabling it, e.g. at the end of a sweep, ramps the voltage down to 0 V first.
+
+#[cfg(feature = "config")]
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use uom::si::power::milliwatt;
+
+use crate::{
+    Current, Error, MicroSmu, Power, Result, Time, Voltage, interlock::InterlockSpec, milliampere,
+    timing, volt,
+};
+
+/// Unset limits are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SafetyLimits {
+    /// Maximum magnitude of the voltage.
+    pub max_voltage: Option<Voltage>,
+    pub max_current_limit: Option<Current>,
+    /// Maximum of the worst case power, i.e. the magnitude of the voltage times the current limit.
+    pub max_power: Option<Power>,
+}
+
+impl SafetyLimits {
+    /// Check the settings of the uSMU, unknown settings are not checked.
+    pub fn check(&self, voltage: Option<Voltage>, current_limit: Option<Current>) -> Result<()> {
+        // NaN exceeds any limit
+        let exceeds = |value: f32, max: f32| value.is_nan() || value > max;
+
+        if let (Some(voltage), Some(max)) = (voltage, self.max_voltage)
+            && exceeds(voltage.abs().value, max.value)
+        {
+            return Err(Error::SafetyLimit(format!(
+                "the voltage of {} V exceeds the maximum of ±{} V",
+                voltage.get::<volt>(),
+                max.get::<volt>()
+            )));
+        }
+        if let (Some(limit), Some(max)) = (current_limit, self.max_current_limit)
+            && exceeds(limit.value, max.value)
+        {
+            return Err(Error::SafetyLimit(format!(
+                "the current limit of {} mA exceeds the maximum of {} mA",
+                limit.get::<milliampere>(),
+                max.get::<milliampere>()
+            )));
+        }
+        if let (Some(voltage), Some(limit), Some(max)) = (voltage, current_limit, self.max_power) {
+            let power = voltage.abs() * limit;
+            if exceeds(power.value, max.value) {
+                return Err(Error::SafetyLimit(format!(
+                    "the worst case power of {} mW at {} V and a current limit of {} mA exceeds the maximum of {} mW",
+                    power.get::<milliwatt>(),
+                    voltage.get::<volt>(),
+                    limit.get::<milliampere>(),
+                    max.get::<milliwatt>()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the limits from a TOML file with the quantities as strings, missing limits are unset, e.g.
+    ///
+    /// ```toml
+    /// max_voltage = "3.3 V"
+    /// max_current_limit = "10 mA"
+    /// max_power = "20 mW"
+    /// ```
+    #[cfg(feature = "config")]
+    pub fn load(path: &Path) -> Result<Self> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct File {
+            max_voltage: Option<String>,
+            max_current_limit: Option<String>,
+            max_power: Option<String>,
+        }
+
+        fn parse<Q: std::str::FromStr>(value: Option<String>, name: &str) -> Result<Option<Q>>
+        where
+            Q::Err: std::fmt::Debug,
+        {
+            value
+                .map(|value| {
+                    value.parse().map_err(|e| {
+                        Error::Configuration(format!("Invalid {name} '{value}': {e:?}"))
+                    })
+                })
+                .transpose()
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        let file: File = toml::from_str(&content).map_err(|e| {
+            Error::Configuration(format!("Invalid safety limits {}: {e}", path.display()))
+        })?;
+        Ok(Self {
+            max_voltage: parse(file.max_voltage, "max_voltage")?,
+            max_current_limit: parse(file.max_current_limit, "max_current_limit")?,
+            max_power: parse(file.max_power, "max_power")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Parser)]
+pub struct SafetyParameter {
+    /// Reject voltages of a larger magnitude.
+    #[arg(long)]
+    pub max_voltage: Option<Voltage>,
+
+    /// Reject larger current limits.
+    #[arg(long)]
+    pub max_current_limit: Option<Current>,
+
+    /// Reject settings whose worst case power, the magnitude of the voltage times the current limit, is larger.
+    #[arg(long)]
+    pub max_power: Option<Power>,
+
+    /// Ramp the voltage up from 0 V within this time when enabling the output, e.g. "500 ms".
+    #[arg(long)]
+    pub soft_start: Option<Time>,
+
+    /// Ramp the voltage down to 0 V within this time before disabling the output, e.g. "500 ms".
+    #[arg(long)]
+    pub ramp_down: Option<Time>,
+
+    /// Interlock permitting the operation, `file:PATH` (closed while the file exists),
+    /// `tcp:ADDRESS` (closed while heartbeats are received) or `gpio:CHIP:LINE` (closed while high).
+    #[arg(long)]
+    pub interlock: Option<InterlockSpec>,
+
+    /// Maximum interval of the heartbeats of a TCP interlock.
+    #[arg(long, default_value = "1 s")]
+    pub heartbeat_timeout: Time,
+
+    /// Read the safety limits from this TOML file, e.g. `max_voltage = "3.3 V"`.
+    /// Limits given on the command line take precedence.
+    #[cfg(feature = "config")]
+    #[arg(long)]
+    pub safety_limits: Option<PathBuf>,
+}
+
+impl SafetyParameter {
+    /// Apply the safety limits, the soft-start, the ramp-down and the interlock to `smu`.
+    pub fn configure(&self, smu: &mut MicroSmu) -> Result<()> {
+        smu.set_safety_limits(self.limits()?);
+        smu.set_soft_start(self.soft_start);
+        smu.set_ramp_down(self.ramp_down);
+        if let Some(interlock) = self.interlock.as_ref() {
+            let timeout = timing::duration("heartbeat timeout", self.heartbeat_timeout)?;
+            smu.set_interlock(Some(interlock.open(timeout)?));
+        }
+        Ok(())
+    }
+
+    pub fn limits(&self) -> Result<SafetyLimits> {
+        #[cfg(feature = "config")]
+        let file = match self.safety_limits.as_ref() {
+            Some(path) => SafetyLimits::load(path)?,
+            None => SafetyLimits::default(),
+        };
+        #[cfg(not(feature = "config"))]
+        let file = SafetyLimits::default();
+        Ok(SafetyLimits {
+            max_voltage: self.max_voltage.or(file.max_voltage),
+            max_current_limit: self.max_current_limit.or(file.max_current_limit),
+            max_power: self.max_power.or(file.max_power),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{millisecond, test_util::FakeSerialPort};
+
+    use super::*;
+
+    #[test]
+    fn rejects_negative_heartbeat_timeouts() {
+        let mut smu = MicroSmu::new(Box::new(FakeSerialPort::new()));
+        let parameter = SafetyParameter {
+            interlock: Some(InterlockSpec::Tcp("127.0.0.1:0".to_string())),
+            heartbeat_timeout: Time::new::<millisecond>(-1.0),
+            ..Default::default()
+        };
+        let result = parameter.configure(&mut smu);
+        assert!(matches!(result, Err(Error::Configuration(_))), "{result:?}");
+    }
+
+    #[test]
+    fn rejects_settings_beyond_limits() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:CUR 10").expect("CH1:VOL 1.5");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_safety_limits(SafetyLimits {
+            max_voltage: Some(Voltage::new::<volt>(2.0)),
+            max_current_limit: Some(Current::new::<milliampere>(20.0)),
+            max_power: Some(Power::new::<milliwatt>(15.0)),
+        });
+
+        smu.set_current_limit(Current::new::<milliampere>(10.0))
+            .unwrap();
+        smu.set_voltage(Voltage::new::<volt>(1.5)).unwrap();
+        for result in [
+            smu.set_voltage(Voltage::new::<volt>(-2.5)),
+            smu.set_voltage(Voltage::new::<volt>(f32::NAN)),
+            smu.measure(Voltage::new::<volt>(1.8)).map(|_| ()),
+            smu.set_current_limit(Current::new::<milliampere>(25.0)),
+            smu.set_current_limit(Current::new::<milliampere>(15.0)),
+        ] {
+            assert!(matches!(result, Err(Error::SafetyLimit(_))), "{result:?}");
+        }
+        port.verify();
+    }
+
+    #[test]
+    fn ramps_down() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL -1")
+            .expect("CH1:DIS")
+            .expect("CH1:ENA")
+            .expect("CH1:VOL -0.5")
+            .expect("CH1:VOL 0")
+            .expect("CH1:DIS")
+            .expect("CH1:VOL -1");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_ramp_down(Some(Time::new::<millisecond>(200.0)));
+        smu.set_voltage(Voltage::new::<volt>(-1.0)).unwrap();
+        // not enabled by this instance
+        smu.disable().unwrap();
+
+        smu.enable().unwrap();
+        smu.disable().unwrap();
+        port.verify();
+    }
+
+    #[test]
+    fn soft_starts() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 2")
+            .expect("CH1:VOL 0")
+            .expect("CH1:ENA")
+            .expect("CH1:VOL 0.5")
+            .expect("CH1:VOL 1")
+            .expect("CH1:VOL 1.5")
+            .expect("CH1:VOL 2");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_soft_start(Some(Time::new::<millisecond>(400.0)));
+        smu.set_voltage(Voltage::new::<volt>(2.0)).unwrap();
+
+        let start = Instant::now();
+        smu.enable().unwrap();
+        assert!(start.elapsed().as_millis() >= 400);
+        port.verify();
+    }
+}
@@ -0,0 +1,151 @@
+//! Versioning of the output files, so files written by older versions of the crate keep loading.
+//!
+//! CSV files start with the comment line `# usmu-schema: VERSION`, files without it predate the versioning
+//! and are of version 0. The version is incremented whenever a format changes, and the readers convert
+//! all known versions to the current representation.
+//!
+//! | Version | Changes |
+//! |---------|---------|
+//! | 0 | Sweeps with the columns `voltage` and `current`. |
+//! | 1 | Version comment, sweeps additionally with the columns `set_voltage` and `compliance`. |
+//! | 2 | Sweeps with their parameters and device as comment lines, see [crate::sweep_file]. |
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+use serde::Deserialize;
+
+use crate::{Current, Error, Result, Voltage, ampere, volt};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+const CSV_COMMENT: &str = "# usmu-schema:";
+
+/// CSV writer stamping `output` with the current schema version.
+pub fn csv_writer<W: Write>(output: W) -> Result<csv::Writer<W>> {
+    csv_writer_with_metadata(output, &[])
+}
+
+/// Like [csv_writer], followed by the comment lines `# KEY: VALUE` of `metadata`.
+pub fn csv_writer_with_metadata<W: Write>(
+    mut output: W,
+    metadata: &[(&str, String)],
+) -> Result<csv::Writer<W>> {
+    writeln!(output, "{CSV_COMMENT} {SCHEMA_VERSION}")?;
+    for (key, value) in metadata {
+        writeln!(output, "# {key}: {value}")?;
+    }
+    Ok(csv::WriterBuilder::new().from_writer(output))
+}
+
+/// CSV reader for a file written by [csv_writer] or an older version, together with its schema version.
+///
+/// Comment lines are skipped. Fails for versions newer than [SCHEMA_VERSION].
+pub fn csv_reader<R: Read>(input: R) -> Result<(u32, csv::Reader<BufReader<R>>)> {
+    let mut input = BufReader::new(input);
+    let mut version = 0;
+    if input.fill_buf()?.starts_with(b"#") {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        if let Some(value) = line.trim().strip_prefix(CSV_COMMENT) {
+            version = value.trim().parse().map_err(|e| {
+                Error::Data(format!("Invalid schema version '{}': {e}", value.trim()))
+            })?;
+        }
+    }
+    if version > SCHEMA_VERSION {
+        Err(Error::Data(format!(
+            "The file has schema version {version}, but only versions up to {SCHEMA_VERSION} are supported. It was written by a newer version of usmu."
+        )))?;
+    }
+    let reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(input);
+    Ok((version, reader))
+}
+
+/// A sample of the sweep output of `record_iv_curve`, with the columns missing in older versions as `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepRecord {
+    pub voltage: Voltage,
+    pub current: Current,
+    pub set_voltage: Option<Voltage>,
+    pub compliance: Option<bool>,
+}
+
+/// Read the sweep output of `record_iv_curve` of any schema version.
+pub fn read_sweep_csv(input: impl Read) -> Result<Vec<SweepRecord>> {
+    #[derive(Deserialize)]
+    struct RowV0 {
+        voltage: f32,
+        current: f32,
+    }
+
+    #[derive(Deserialize)]
+    struct RowV1 {
+        voltage: f32,
+        current: f32,
+        set_voltage: f32,
+        compliance: bool,
+    }
+
+    let (version, mut reader) = csv_reader(input)?;
+    match version {
+        0 => reader
+            .deserialize()
+            .map(|row| {
+                let RowV0 { voltage, current } = row?;
+                Ok(SweepRecord {
+                    voltage: Voltage::new::<volt>(voltage),
+                    current: Current::new::<ampere>(current),
+                    set_voltage: None,
+                    compliance: None,
+                })
+            })
+            .collect(),
+        _ => reader
+            .deserialize()
+            .map(|row| {
+                let RowV1 {
+                    voltage,
+                    current,
+                    set_voltage,
+                    compliance,
+                } = row?;
+                Ok(SweepRecord {
+                    voltage: Voltage::new::<volt>(voltage),
+                    current: Current::new::<ampere>(current),
+                    set_voltage: Some(Voltage::new::<volt>(set_voltage)),
+                    compliance: Some(compliance),
+                })
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_all_versions() {
+        let v0 = "voltage,current\n1.0,0.001\n";
+        let records = read_sweep_csv(v0.as_bytes()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].current.get::<ampere>(), 0.001);
+        assert_eq!(records[0].set_voltage, None);
+
+        let mut v1 = Vec::new();
+        let mut writer = csv_writer(&mut v1).unwrap();
+        writer
+            .write_record(["voltage", "current", "set_voltage", "compliance"])
+            .unwrap();
+        writer.write_record(["0.9", "0.02", "1.0", "true"]).unwrap();
+        drop(writer);
+        let records = read_sweep_csv(v1.as_slice()).unwrap();
+        assert_eq!(records[0].set_voltage, Some(Voltage::new::<volt>(1.0)));
+        assert_eq!(records[0].compliance, Some(true));
+
+        let future = format!("{CSV_COMMENT} {}\nvoltage,current\n", SCHEMA_VERSION + 1);
+        assert!(read_sweep_csv(future.as_bytes()).is_err());
+    }
+}
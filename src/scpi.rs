@@ -0,0 +1,9 @@
+//! The SCPI traits of the requests and responses in [commands](crate::commands), re-exported from `scpi-client`.
+//!
+//! Downstream code should name them through this module, so it is not tied to the version of `scpi-client`
+//! this crate depends on, e.g. to send custom requests with [MicroSmu::query](crate::MicroSmu::query).
+
+pub use scpi_client::{
+    EmptyResponse, Error, Result, ScpiDeserialize, ScpiRequest, ScpiSerialize, check_empty,
+    match_literal,
+};
@@ -0,0 +1,301 @@
+#[cfg(any(feature = "grpc", feature = "http"))]
+use std::net::SocketAddr;
+#[cfg(feature = "json-rpc")]
+use std::path::PathBuf;
+use std::{
+    sync::{Arc, Mutex, MutexGuard, mpsc},
+    thread,
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Current, Error, MicroSmu, Result, Time, Voltage, ampere,
+    commands::MeasureResponse,
+    devices::{AttachedDevice, identify_attached},
+    milliampere,
+    record_iv_curve::{IvCurveRecordingParameters, SmuConnectionParameter},
+    second, volt,
+};
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "json-rpc")]
+pub mod json_rpc;
+
+#[derive(Debug, Parser)]
+pub struct ServeArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Serve the gRPC interface on this address, e.g. `127.0.0.1:50051`.
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    pub grpc: Option<SocketAddr>,
+
+    /// Serve the REST API on this address, e.g. `127.0.0.1:8080`.
+    #[cfg(feature = "http")]
+    #[arg(long)]
+    pub http: Option<SocketAddr>,
+
+    /// Serve JSON-RPC on this Unix domain socket, or named pipe on Windows (e.g. `\\.\pipe\usmu`).
+    #[cfg(feature = "json-rpc")]
+    #[arg(long)]
+    pub json_rpc: Option<PathBuf>,
+}
+
+enum Server {
+    #[cfg(feature = "grpc")]
+    Grpc(SocketAddr),
+    #[cfg(feature = "http")]
+    Http(SocketAddr),
+    #[cfg(feature = "json-rpc")]
+    JsonRpc(PathBuf),
+}
+
+impl ServeArguments {
+    pub fn run(&self) -> Result<()> {
+        let servers = self.servers();
+        if servers.is_empty() {
+            Err(Error::Configuration("No server selected. Pass an address to one of the server protocols enabled at build time, e.g. `--grpc`.".to_string()))?;
+        }
+
+        let smu = Arc::new(Mutex::new(self.connection_parameter.connect()?));
+
+        // Servers only return on failure, hence the first result terminates all of them.
+        let (sender, receiver) = mpsc::channel();
+        for server in servers {
+            let smu = smu.clone();
+            let sender = sender.clone();
+            thread::spawn(move || sender.send(server.serve(smu)));
+        }
+        drop(sender);
+
+        receiver
+            .recv()
+            .map_err(|_| Error::Internal("All servers terminated unexpectedly.".to_string()))?
+    }
+
+    fn servers(&self) -> Vec<Server> {
+        let servers = std::iter::empty();
+        #[cfg(feature = "grpc")]
+        let servers = servers.chain(self.grpc.map(Server::Grpc));
+        #[cfg(feature = "http")]
+        let servers = servers.chain(self.http.map(Server::Http));
+        #[cfg(feature = "json-rpc")]
+        let servers = servers.chain(self.json_rpc.clone().map(Server::JsonRpc));
+        servers.collect()
+    }
+}
+
+impl Server {
+    fn serve(self, smu: Arc<Mutex<MicroSmu>>) -> Result<()> {
+        match self {
+            #[cfg(feature = "grpc")]
+            Server::Grpc(address) => {
+                let runtime = tokio::runtime::Runtime::new()?;
+                runtime.block_on(grpc::serve(address, smu))
+            }
+            #[cfg(feature = "http")]
+            Server::Http(address) => {
+                let runtime = tokio::runtime::Runtime::new()?;
+                runtime.block_on(http::serve(address, smu))
+            }
+            #[cfg(feature = "json-rpc")]
+            Server::JsonRpc(path) => json_rpc::serve(&path, smu),
+        }
+    }
+}
+
+fn lock(smu: &Mutex<MicroSmu>) -> Result<MutexGuard<'_, MicroSmu>> {
+    let smu = smu.lock().map_err(|_| {
+        Error::Internal("Device handle is poisoned by a previous failure.".to_string())
+    })?;
+    Ok(smu)
+}
+
+/// Run the blocking device communication outside of the async runtime.
+#[cfg(any(feature = "grpc", feature = "http"))]
+async fn with_smu<T: Send + 'static>(
+    smu: &Arc<Mutex<MicroSmu>>,
+    f: impl FnOnce(&mut MicroSmu) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let smu = smu.clone();
+    tokio::task::spawn_blocking(move || f(&mut *lock(&smu)?))
+        .await
+        .map_err(|e| Error::Internal(e.to_string()))?
+}
+
+/// An attached uSMU as reported to remote clients.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub port_name: String,
+    /// Identity of the device, absent if it could not be read.
+    pub uid: Option<u32>,
+    /// Whether this is the device controlled by the server.
+    pub served: bool,
+}
+
+/// List all attached uSMUs.
+///
+/// The served port is already opened and cannot be identified again, hence its identity is passed in.
+fn list_devices(served_port: Option<&str>, served_uid: u32) -> Result<Vec<DeviceInfo>> {
+    let devices = identify_attached()?
+        .into_iter()
+        .map(|AttachedDevice { port, uid }| {
+            let served = served_port == Some(port.port_name.as_str());
+            DeviceInfo {
+                port_name: port.port_name,
+                uid: if served { Some(served_uid) } else { uid },
+                served,
+            }
+        })
+        .collect();
+    Ok(devices)
+}
+
+/// A measurement in SI units, as reported to remote clients.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Measurement {
+    pub voltage: f32,
+    pub current: f32,
+}
+
+impl From<MeasureResponse> for Measurement {
+    fn from(value: MeasureResponse) -> Self {
+        (value.voltage, value.current).into()
+    }
+}
+
+impl From<(Voltage, Current)> for Measurement {
+    fn from((voltage, current): (Voltage, Current)) -> Self {
+        Self {
+            voltage: voltage.get::<volt>(),
+            current: current.get::<ampere>(),
+        }
+    }
+}
+
+#[cfg(any(feature = "http", feature = "json-rpc"))]
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct VoltageParams {
+    voltage: f32,
+}
+
+#[cfg(any(feature = "http", feature = "json-rpc"))]
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct CurrentLimitParams {
+    limit: f32,
+}
+
+#[cfg(any(feature = "http", feature = "json-rpc"))]
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct OverSampleRateParams {
+    samples: u32,
+}
+
+/// Sweep parameters in SI units, as received from remote clients.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SweepParameters {
+    pub start_voltage: f32,
+    pub end_voltage: f32,
+    pub voltage_steps: usize,
+    pub current_limit: f32,
+    pub over_sampling: u32,
+    #[serde(default)]
+    pub delay: f32,
+}
+
+impl TryFrom<SweepParameters> for IvCurveRecordingParameters {
+    type Error = InvalidArgument;
+
+    fn try_from(value: SweepParameters) -> std::result::Result<Self, Self::Error> {
+        Ok(Self {
+            start_voltage: Voltage::new::<volt>(value.start_voltage),
+            end_voltage: Voltage::new::<volt>(value.end_voltage),
+            voltage_steps: voltage_steps(value.voltage_steps)?,
+            current_limit: current_limit(value.current_limit)?,
+            over_sampling: over_sample_rate(value.over_sampling)?,
+            delay: delay(value.delay)?,
+            adaptive_over_sampling: Default::default(),
+            step_refinement: Default::default(),
+            auto_range: Default::default(),
+        })
+    }
+}
+
+/// A parameter received from a remote client is invalid.
+///
+/// Remote input is validated upfront, to report invalid values with a message for the client.
+#[derive(Debug)]
+pub struct InvalidArgument(pub &'static str);
+
+/// Upper bound of the voltage steps of a remote sweep, as all samples are kept in memory.
+pub const MAX_VOLTAGE_STEPS: usize = 10_000;
+
+fn voltage_steps(steps: usize) -> std::result::Result<usize, InvalidArgument> {
+    if (1..=MAX_VOLTAGE_STEPS).contains(&steps) {
+        Ok(steps)
+    } else {
+        Err(InvalidArgument(
+            "The voltage steps must be between 1 and 10000.",
+        ))
+    }
+}
+
+fn current_limit(limit: f32) -> std::result::Result<Current, InvalidArgument> {
+    let limit = Current::new::<ampere>(limit);
+    if limit.is_sign_positive() && limit.get::<milliampere>() <= 40.0 {
+        Ok(limit)
+    } else {
+        Err(InvalidArgument(
+            "The current limit must be between 0 mA and 40 mA.",
+        ))
+    }
+}
+
+fn over_sample_rate(samples: u32) -> std::result::Result<u16, InvalidArgument> {
+    u16::try_from(samples).map_err(|_| InvalidArgument("The oversample rate exceeds 16 bit."))
+}
+
+fn delay(delay: f32) -> std::result::Result<Time, InvalidArgument> {
+    if delay.is_finite() && delay >= 0.0 {
+        Ok(Time::new::<second>(delay))
+    } else {
+        Err(InvalidArgument("The delay must not be negative."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(voltage_steps: usize) -> SweepParameters {
+        SweepParameters {
+            start_voltage: -1.0,
+            end_voltage: 1.0,
+            voltage_steps,
+            current_limit: 0.02,
+            over_sampling: 10,
+            delay: 0.0,
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_sweeps() {
+        let parameters = IvCurveRecordingParameters::try_from(sweep(11)).unwrap();
+        assert_eq!(parameters.voltage_steps, 11);
+        assert!(IvCurveRecordingParameters::try_from(sweep(0)).is_err());
+        assert!(IvCurveRecordingParameters::try_from(sweep(MAX_VOLTAGE_STEPS + 1)).is_err());
+        assert!(
+            IvCurveRecordingParameters::try_from(SweepParameters {
+                delay: -1.0,
+                ..sweep(11)
+            })
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! gRPC interface of a [MicroSmu], see `proto/usmu.proto` for the service definition.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tonic::{Request, Response, Status, transport::Server};
+
+use super::{
+    InvalidArgument, SweepParameters, current_limit, list_devices, over_sample_rate, with_smu,
+};
+use crate::{Error, MicroSmu, Result, Voltage, record_iv_curve::IvCurveRecordingParameters, volt};
+
+pub mod proto {
+    tonic::include_proto!("usmu.v1");
+}
+
+use proto::{
+    DisableRequest, DisableResponse, EnableRequest, EnableResponse, ListDevicesRequest,
+    ListDevicesResponse, MeasureRequest, Measurement, SetCurrentLimitRequest,
+    SetCurrentLimitResponse, SetOverSampleRateRequest, SetOverSampleRateResponse,
+    SetVoltageRequest, SetVoltageResponse, SweepRequest, SweepResponse,
+    usmu_server::{Usmu, UsmuServer},
+};
+
+/// Serve the gRPC interface for `smu` on `address` until the server fails.
+pub async fn serve(address: SocketAddr, smu: Arc<Mutex<MicroSmu>>) -> Result<()> {
+    let service = UsmuService::new(smu).await?;
+    Server::builder()
+        .add_service(UsmuServer::new(service))
+        .serve(address)
+        .await
+        .map_err(|e| Error::External(e.to_string()))?;
+    Ok(())
+}
+
+struct UsmuService {
+    smu: Arc<Mutex<MicroSmu>>,
+    port_name: Option<String>,
+    uid: u32,
+}
+
+impl UsmuService {
+    async fn new(smu: Arc<Mutex<MicroSmu>>) -> Result<Self> {
+        let (port_name, uid) =
+            with_smu(&smu, |smu| Ok((smu.port_name(), smu.get_identity()?))).await?;
+        Ok(Self {
+            smu,
+            port_name,
+            uid,
+        })
+    }
+
+    async fn with_smu<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut MicroSmu) -> Result<T> + Send + 'static,
+    ) -> std::result::Result<T, Status> {
+        with_smu(&self.smu, f).await.map_err(status)
+    }
+}
+
+fn status(error: Error) -> Status {
+    Status::internal(error.to_string())
+}
+
+impl From<InvalidArgument> for Status {
+    fn from(value: InvalidArgument) -> Self {
+        Status::invalid_argument(value.0)
+    }
+}
+
+impl From<super::Measurement> for Measurement {
+    fn from(value: super::Measurement) -> Self {
+        Self {
+            voltage: value.voltage,
+            current: value.current,
+        }
+    }
+}
+
+impl From<super::DeviceInfo> for proto::Device {
+    fn from(value: super::DeviceInfo) -> Self {
+        Self {
+            port_name: value.port_name,
+            uid: value.uid,
+            served: value.served,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Usmu for UsmuService {
+    async fn list_devices(
+        &self,
+        _request: Request<ListDevicesRequest>,
+    ) -> std::result::Result<Response<ListDevicesResponse>, Status> {
+        let port_name = self.port_name.clone();
+        let uid = self.uid;
+        let devices = tokio::task::spawn_blocking(move || list_devices(port_name.as_deref(), uid))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(Response::new(ListDevicesResponse { devices }))
+    }
+
+    async fn enable(
+        &self,
+        _request: Request<EnableRequest>,
+    ) -> std::result::Result<Response<EnableResponse>, Status> {
+        self.with_smu(|smu| smu.enable()).await?;
+        Ok(Response::new(EnableResponse {}))
+    }
+
+    async fn disable(
+        &self,
+        _request: Request<DisableRequest>,
+    ) -> std::result::Result<Response<DisableResponse>, Status> {
+        self.with_smu(|smu| smu.disable()).await?;
+        Ok(Response::new(DisableResponse {}))
+    }
+
+    async fn set_voltage(
+        &self,
+        request: Request<SetVoltageRequest>,
+    ) -> std::result::Result<Response<SetVoltageResponse>, Status> {
+        let voltage = Voltage::new::<volt>(request.into_inner().voltage);
+        self.with_smu(move |smu| smu.set_voltage(voltage)).await?;
+        Ok(Response::new(SetVoltageResponse {}))
+    }
+
+    async fn set_current_limit(
+        &self,
+        request: Request<SetCurrentLimitRequest>,
+    ) -> std::result::Result<Response<SetCurrentLimitResponse>, Status> {
+        let limit = current_limit(request.into_inner().limit)?;
+        self.with_smu(move |smu| smu.set_current_limit(limit))
+            .await?;
+        Ok(Response::new(SetCurrentLimitResponse {}))
+    }
+
+    async fn set_over_sample_rate(
+        &self,
+        request: Request<SetOverSampleRateRequest>,
+    ) -> std::result::Result<Response<SetOverSampleRateResponse>, Status> {
+        let samples = over_sample_rate(request.into_inner().samples)?;
+        self.with_smu(move |smu| smu.set_over_sample_rate(samples))
+            .await?;
+        Ok(Response::new(SetOverSampleRateResponse {}))
+    }
+
+    async fn measure(
+        &self,
+        request: Request<MeasureRequest>,
+    ) -> std::result::Result<Response<Measurement>, Status> {
+        let voltage = Voltage::new::<volt>(request.into_inner().voltage);
+        let measurement = self.with_smu(move |smu| smu.measure(voltage)).await?;
+        Ok(Response::new(super::Measurement::from(measurement).into()))
+    }
+
+    async fn sweep(
+        &self,
+        request: Request<SweepRequest>,
+    ) -> std::result::Result<Response<SweepResponse>, Status> {
+        let request = request.into_inner();
+        let parameters = IvCurveRecordingParameters::try_from(SweepParameters {
+            start_voltage: request.start_voltage,
+            end_voltage: request.end_voltage,
+            voltage_steps: request.voltage_steps as usize,
+            current_limit: request.current_limit,
+            over_sampling: request.over_sampling,
+            delay: request.delay,
+        })?;
+
+        let samples = self
+            .with_smu(move |smu| parameters.record(smu))
+            .await?
+            .samples
+            .into_iter()
+            .map(|sample| super::Measurement::from(sample.point()).into())
+            .collect();
+
+        Ok(Response::new(SweepResponse { samples }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tonic::Code;
+
+    use crate::{
+        simulator::{Resistor, SimulatedSmu},
+        test_util::simulated_smu,
+    };
+
+    use super::*;
+
+    fn sweep(voltage_steps: u32) -> Request<SweepRequest> {
+        Request::new(SweepRequest {
+            start_voltage: 0.0,
+            end_voltage: 1.0,
+            voltage_steps,
+            current_limit: 0.02,
+            over_sampling: 1,
+            delay: 0.0,
+        })
+    }
+
+    #[test]
+    fn serves_the_simulated_device() {
+        let mut smu = MicroSmu::new(Box::new(
+            SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(42),
+        ));
+        smu.set_inter_command_delay(Duration::ZERO);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let service = UsmuService::new(Arc::new(Mutex::new(smu))).await.unwrap();
+            assert_eq!(service.uid, 42);
+
+            service
+                .set_current_limit(Request::new(SetCurrentLimitRequest { limit: 0.02 }))
+                .await
+                .unwrap();
+            service
+                .enable(Request::new(EnableRequest {}))
+                .await
+                .unwrap();
+            let measurement = service
+                .measure(Request::new(MeasureRequest { voltage: 1.0 }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!((measurement.current - 1e-3).abs() < 1e-5);
+
+            let samples = service.sweep(sweep(3)).await.unwrap().into_inner().samples;
+            assert_eq!(samples.len(), 3);
+            assert!((samples[2].current - 1e-3).abs() < 1e-5);
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let smu = simulated_smu(Resistor::ohm(1000.0));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let service = UsmuService::new(Arc::new(Mutex::new(smu))).await.unwrap();
+
+            let limit = Request::new(SetCurrentLimitRequest { limit: 0.05 });
+            let status = service.set_current_limit(limit).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+            let status = service.sweep(sweep(0)).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        });
+    }
+}
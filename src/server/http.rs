@@ -0,0 +1,492 @@
+//! REST interface of a [MicroSmu] over HTTP.
+//!
+//! Request and response bodies are JSON, all quantities are floats in SI units.
+//!
+//! | Endpoint                  | Description                                                  |
+//! |---------------------------|--------------------------------------------------------------|
+//! | `GET /`                   | Built-in dashboard with live readout, plot and controls      |
+//! | `GET /status`             | Served device and sweep progress                             |
+//! | `GET /devices`            | All attached uSMUs                                           |
+//! | `POST /enable`            | Enable SMU output                                            |
+//! | `POST /disable`           | Disable SMU output                                           |
+//! | `PUT /voltage`            | Set the voltage, e.g. `{"voltage": 1.0}`                     |
+//! | `PUT /current-limit`      | Set the current limit, e.g. `{"limit": 0.02}`                |
+//! | `PUT /over-sample-rate`   | Set the oversample rate, e.g. `{"samples": 10}`              |
+//! | `POST /measure`           | Set the voltage and measure, e.g. `{"voltage": 1.0}`         |
+//! | `POST /sweep`             | Start a sweep in the background, see [SweepParameters]       |
+//! | `POST /sweep/stop`        | Stop the running sweep                                       |
+//! | `GET /sweep`              | State and samples of the current or last sweep               |
+//! | `GET /events`             | WebSocket streaming live measurements and sweep progress     |
+//!
+//! Every WebSocket text message is a JSON [Event], tagged by its `type`,
+//! e.g. `{"type": "sweep_sample", "completed": 3, "total": 50, "voltage": -0.9, "current": 0.0001}`.
+//! Clients too slow to keep up miss events instead of stalling the server.
+//!
+//! The device is occupied while a sweep runs, hence other device requests are rejected with `409 Conflict`.
+
+use std::{
+    net::SocketAddr,
+    ops::ControlFlow,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use axum::{
+    Json, Router,
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post, put},
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use super::{
+    CurrentLimitParams, DeviceInfo, InvalidArgument, Measurement, OverSampleRateParams,
+    SweepParameters, VoltageParams, current_limit, list_devices, lock, over_sample_rate, with_smu,
+};
+use crate::{Error, MicroSmu, Result, Voltage, record_iv_curve::IvCurveRecordingParameters, volt};
+
+/// Serve the REST API for `smu` on `address` until the server fails.
+pub async fn serve(address: SocketAddr, smu: Arc<Mutex<MicroSmu>>) -> Result<()> {
+    let router = router(smu).await?;
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+/// The endpoints of the REST API for `smu`, see the [module documentation](self).
+async fn router(smu: Arc<Mutex<MicroSmu>>) -> Result<Router> {
+    let device = with_smu(&smu, |smu| {
+        Ok(ServedDevice {
+            port_name: smu.port_name(),
+            uid: smu.get_identity()?,
+        })
+    })
+    .await?;
+    let state = AppState {
+        smu,
+        device,
+        sweep: Default::default(),
+        events: broadcast::channel(EVENT_CAPACITY).0,
+    };
+
+    let router = Router::new()
+        .route("/", get(dashboard))
+        .route("/status", get(status))
+        .route("/devices", get(devices))
+        .route("/enable", post(enable))
+        .route("/disable", post(disable))
+        .route("/voltage", put(set_voltage))
+        .route("/current-limit", put(set_current_limit))
+        .route("/over-sample-rate", put(set_over_sample_rate))
+        .route("/measure", post(measure))
+        .route("/sweep", post(start_sweep).get(sweep))
+        .route("/sweep/stop", post(stop_sweep))
+        .route("/events", get(events))
+        .with_state(state);
+    Ok(router)
+}
+
+#[derive(Clone)]
+struct AppState {
+    smu: Arc<Mutex<MicroSmu>>,
+    device: ServedDevice,
+    sweep: Arc<Mutex<Sweep>>,
+    events: broadcast::Sender<Event>,
+}
+
+/// Number of events buffered per WebSocket client.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Live update streamed to WebSocket clients.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Result of a single measurement request.
+    Measurement(Measurement),
+    SweepStarted {
+        total: usize,
+    },
+    SweepSample {
+        completed: usize,
+        total: usize,
+        #[serde(flatten)]
+        sample: Measurement,
+    },
+    SweepEnded {
+        state: SweepState,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ServedDevice {
+    port_name: Option<String>,
+    uid: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct Sweep {
+    state: SweepState,
+    /// Number of voltage steps of the sweep.
+    total: usize,
+    samples: Vec<Measurement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip)]
+    stop_requested: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepState {
+    #[default]
+    Idle,
+    Running,
+    Stopped,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    device: ServedDevice,
+    sweep: SweepProgress,
+}
+
+#[derive(Debug, Serialize)]
+struct SweepProgress {
+    state: SweepState,
+    completed: usize,
+    total: usize,
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(value: Error) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, value)
+    }
+}
+
+impl From<InvalidArgument> for ApiError {
+    fn from(value: InvalidArgument) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, value.0)
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// The sweep record is plain data, which stays consistent even if a holder panicked.
+fn lock_sweep(sweep: &Mutex<Sweep>) -> MutexGuard<'_, Sweep> {
+    sweep.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl AppState {
+    /// Run `f` on the device, unless it is occupied by a sweep.
+    async fn with_smu<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut MicroSmu) -> Result<T> + Send + 'static,
+    ) -> ApiResult<T> {
+        if lock_sweep(&self.sweep).state == SweepState::Running {
+            return Err(ApiError::new(StatusCode::CONFLICT, "A sweep is running."));
+        }
+        Ok(with_smu(&self.smu, f).await?)
+    }
+
+    fn publish(&self, event: Event) {
+        publish(&self.events, event);
+    }
+}
+
+fn publish(events: &broadcast::Sender<Event>, event: Event) {
+    // Sending only fails without any subscribed client, which is fine.
+    let _ = events.send(event);
+}
+
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+async fn status(State(state): State<AppState>) -> Json<Status> {
+    let sweep = lock_sweep(&state.sweep);
+    Json(Status {
+        device: state.device.clone(),
+        sweep: SweepProgress {
+            state: sweep.state,
+            completed: sweep.samples.len(),
+            total: sweep.total,
+        },
+    })
+}
+
+async fn devices(State(state): State<AppState>) -> ApiResult<Json<Vec<DeviceInfo>>> {
+    let ServedDevice { port_name, uid } = state.device.clone();
+    let devices = state
+        .with_smu(move |_| list_devices(port_name.as_deref(), uid))
+        .await?;
+    Ok(Json(devices))
+}
+
+async fn enable(State(state): State<AppState>) -> ApiResult<StatusCode> {
+    state.with_smu(|smu| smu.enable()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn disable(State(state): State<AppState>) -> ApiResult<StatusCode> {
+    state.with_smu(|smu| smu.disable()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_voltage(
+    State(state): State<AppState>,
+    Json(VoltageParams { voltage }): Json<VoltageParams>,
+) -> ApiResult<StatusCode> {
+    let voltage = Voltage::new::<volt>(voltage);
+    state.with_smu(move |smu| smu.set_voltage(voltage)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_current_limit(
+    State(state): State<AppState>,
+    Json(CurrentLimitParams { limit }): Json<CurrentLimitParams>,
+) -> ApiResult<StatusCode> {
+    let limit = current_limit(limit)?;
+    state
+        .with_smu(move |smu| smu.set_current_limit(limit))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_over_sample_rate(
+    State(state): State<AppState>,
+    Json(OverSampleRateParams { samples }): Json<OverSampleRateParams>,
+) -> ApiResult<StatusCode> {
+    let samples = over_sample_rate(samples)?;
+    state
+        .with_smu(move |smu| smu.set_over_sample_rate(samples))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn measure(
+    State(state): State<AppState>,
+    Json(VoltageParams { voltage }): Json<VoltageParams>,
+) -> ApiResult<Json<Measurement>> {
+    let voltage = Voltage::new::<volt>(voltage);
+    let measurement = Measurement::from(state.with_smu(move |smu| smu.measure(voltage)).await?);
+    state.publish(Event::Measurement(measurement));
+    Ok(Json(measurement))
+}
+
+async fn start_sweep(
+    State(state): State<AppState>,
+    Json(parameters): Json<SweepParameters>,
+) -> ApiResult<StatusCode> {
+    let parameters = IvCurveRecordingParameters::try_from(parameters)?;
+
+    {
+        let mut sweep = lock_sweep(&state.sweep);
+        if sweep.state == SweepState::Running {
+            return Err(ApiError::new(StatusCode::CONFLICT, "A sweep is running."));
+        }
+        *sweep = Sweep {
+            state: SweepState::Running,
+            total: parameters.voltage_steps,
+            ..Default::default()
+        };
+    }
+    let total = parameters.voltage_steps;
+    state.publish(Event::SweepStarted { total });
+
+    let AppState {
+        smu, sweep, events, ..
+    } = state;
+    tokio::task::spawn_blocking(move || {
+        let result = lock(&smu).and_then(|mut smu| {
+            parameters.record_with(&mut smu, |voltage, current| {
+                let mut sweep = lock_sweep(&sweep);
+                let sample = Measurement::from((voltage, current));
+                sweep.samples.push(sample);
+                publish(
+                    &events,
+                    Event::SweepSample {
+                        completed: sweep.samples.len(),
+                        total,
+                        sample,
+                    },
+                );
+                if sweep.stop_requested {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+        });
+
+        let mut sweep = lock_sweep(&sweep);
+        match result {
+            Ok(_) if sweep.stop_requested => sweep.state = SweepState::Stopped,
+            Ok(_) => sweep.state = SweepState::Finished,
+            Err(e) => {
+                sweep.state = SweepState::Failed;
+                sweep.error = Some(e.to_string());
+            }
+        }
+        publish(
+            &events,
+            Event::SweepEnded {
+                state: sweep.state,
+                error: sweep.error.clone(),
+            },
+        );
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn stop_sweep(State(state): State<AppState>) -> ApiResult<StatusCode> {
+    let mut sweep = lock_sweep(&state.sweep);
+    if sweep.state != SweepState::Running {
+        return Err(ApiError::new(StatusCode::CONFLICT, "No sweep is running."));
+    }
+    sweep.stop_requested = true;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn sweep(State(state): State<AppState>) -> Json<Sweep> {
+    Json(lock_sweep(&state.sweep).clone())
+}
+
+async fn events(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> Response {
+    let events = state.events.subscribe();
+    upgrade.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<Event>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let message =
+            serde_json::to_string(&event).expect("serialization of plain data cannot fail");
+        if socket.send(Message::Text(message.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    use serde_json::Value;
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    /// Serve a simulated 1 kΩ resistor on a free local port, as long as the runtime is kept.
+    fn serve_simulator() -> (tokio::runtime::Runtime, SocketAddr) {
+        let smu = simulated_smu(Resistor::ohm(1000.0));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let address = runtime.block_on(async {
+            let router = router(Arc::new(Mutex::new(smu))).await.unwrap();
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(async { axum::serve(listener, router).await });
+            address
+        });
+        (runtime, address)
+    }
+
+    /// Send a request with the JSON `body`, returning the status code and the parsed response body.
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        let body = serde_json::from_str(body).unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    #[test]
+    fn serves_the_simulated_device() {
+        let (_runtime, address) = serve_simulator();
+
+        let (status, _) = request(address, "PUT", "/current-limit", r#"{"limit": 0.02}"#);
+        assert_eq!(status, 204);
+        assert_eq!(request(address, "POST", "/enable", "").0, 204);
+        let (status, measurement) = request(address, "POST", "/measure", r#"{"voltage": 1.0}"#);
+        assert_eq!(status, 200);
+        assert!((measurement["current"].as_f64().unwrap() - 1e-3).abs() < 1e-5);
+
+        let sweep = r#"{"start_voltage": 0.0, "end_voltage": 1.0, "voltage_steps": 3, "current_limit": 0.02, "over_sampling": 1}"#;
+        assert_eq!(request(address, "POST", "/sweep", sweep).0, 202);
+        let sweep = (0..100)
+            .map(|_| {
+                std::thread::sleep(Duration::from_millis(10));
+                request(address, "GET", "/sweep", "").1
+            })
+            .find(|e| e["state"] != "running")
+            .unwrap();
+        assert_eq!(sweep["state"], "finished");
+        assert_eq!(sweep["samples"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let (_runtime, address) = serve_simulator();
+
+        let (status, body) = request(address, "PUT", "/current-limit", r#"{"limit": 0.05}"#);
+        assert_eq!(status, 400);
+        assert!(body["error"].is_string());
+        let sweep = r#"{"start_voltage": 0.0, "end_voltage": 1.0, "voltage_steps": 0, "current_limit": 0.02, "over_sampling": 1}"#;
+        assert_eq!(request(address, "POST", "/sweep", sweep).0, 400);
+    }
+}
@@ -0,0 +1,318 @@
+//! [JSON-RPC 2.0](https://www.jsonrpc.org/specification) interface of a [MicroSmu] on a local socket.
+//!
+//! Requests and responses are newline delimited JSON objects.
+//! The methods mirror the gRPC interface and all quantities are floats in SI units, e.g.
+//! `{"jsonrpc": "2.0", "id": 1, "method": "measure", "params": {"voltage": 0.5}}`.
+//!
+//! Available methods are `list_devices`, `enable`, `disable`, `set_voltage` (`voltage`),
+//! `set_current_limit` (`limit`), `set_over_sample_rate` (`samples`), `measure` (`voltage`)
+//! and `sweep` (see [SweepParameters]).
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use interprocess::local_socket::{GenericFilePath, ListenerOptions, Stream, prelude::*};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use super::{
+    CurrentLimitParams, InvalidArgument, Measurement, OverSampleRateParams, SweepParameters,
+    VoltageParams, current_limit, list_devices, lock, over_sample_rate,
+};
+use crate::{Error, MicroSmu, Result, Voltage, record_iv_curve::IvCurveRecordingParameters, volt};
+
+/// Serve JSON-RPC for `smu` on the local socket `path` until the listener fails.
+///
+/// A socket file left over by a crashed server is replaced, while a running server is kept.
+///
+/// Every connection is handled in a separate thread, while access to the device is serialized.
+pub fn serve(path: &Path, smu: Arc<Mutex<MicroSmu>>) -> Result<()> {
+    let (port_name, uid) = {
+        let mut smu = lock(&smu)?;
+        (smu.port_name(), smu.get_identity()?)
+    };
+    let service = Arc::new(JsonRpcService {
+        smu,
+        port_name,
+        uid,
+    });
+
+    let name = path.to_fs_name::<GenericFilePath>()?;
+    let listener = match ListenerOptions::new().name(name.clone()).create_sync() {
+        // nobody listens on the socket file, it is left over by a server which crashed
+        Err(e) if e.kind() == ErrorKind::AddrInUse && Stream::connect(name.clone()).is_err() => {
+            fs::remove_file(path)?;
+            ListenerOptions::new().name(name).create_sync()?
+        }
+        listener => listener?,
+    };
+
+    for connection in listener.incoming() {
+        let connection = match connection {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Incoming connection failed: {e}");
+                continue;
+            }
+        };
+
+        let service = service.clone();
+        thread::spawn(move || {
+            if let Err(e) = service.handle(connection) {
+                eprintln!("Connection failed: {e}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+struct JsonRpcService {
+    smu: Arc<Mutex<MicroSmu>>,
+    port_name: Option<String>,
+    uid: u32,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Requests without id are notifications and are not answered.
+    id: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    const PARSE_ERROR: i32 = -32700;
+    const INVALID_REQUEST: i32 = -32600;
+    const METHOD_NOT_FOUND: i32 = -32601;
+    const INVALID_PARAMS: i32 = -32602;
+    /// Implementation defined server error, used for all device failures.
+    const DEVICE_ERROR: i32 = -32000;
+
+    fn new(code: i32, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(value: Error) -> Self {
+        Self::new(Self::DEVICE_ERROR, value)
+    }
+}
+
+impl From<InvalidArgument> for RpcError {
+    fn from(value: InvalidArgument) -> Self {
+        Self::new(Self::INVALID_PARAMS, value.0)
+    }
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e))
+}
+
+impl JsonRpcService {
+    fn handle(&self, connection: Stream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(&connection);
+        let mut writer = &connection;
+
+        let mut line = String::new();
+        while reader.read_line(&mut line)? != 0 {
+            if !line.trim().is_empty()
+                && let Some(response) = self.respond(&line)
+            {
+                serde_json::to_writer(&mut writer, &response)?;
+                writer.write_all(b"\n")?;
+            }
+            line.clear();
+        }
+
+        Ok(())
+    }
+
+    fn respond(&self, line: &str) -> Option<Response> {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(request) => request,
+            Err(e) => return Some(error(Value::Null, RpcError::new(RpcError::PARSE_ERROR, e))),
+        };
+        let request = match serde_json::from_value::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                let e = RpcError::new(RpcError::INVALID_REQUEST, "Only JSON-RPC 2.0 is supported.");
+                return Some(error(Value::Null, e));
+            }
+            Err(e) => {
+                let e = RpcError::new(RpcError::INVALID_REQUEST, e);
+                return Some(error(Value::Null, e));
+            }
+        };
+
+        let result = self.dispatch(&request.method, request.params);
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => Response {
+                jsonrpc: "2.0",
+                result: Some(result),
+                error: None,
+                id,
+            },
+            Err(e) => error(id, e),
+        })
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        let result = match method {
+            "list_devices" => to_value(list_devices(self.port_name.as_deref(), self.uid)?),
+            "enable" => {
+                lock(&self.smu)?.enable()?;
+                Value::Null
+            }
+            "disable" => {
+                lock(&self.smu)?.disable()?;
+                Value::Null
+            }
+            "set_voltage" => {
+                let VoltageParams { voltage } = self::params(params)?;
+                lock(&self.smu)?.set_voltage(Voltage::new::<volt>(voltage))?;
+                Value::Null
+            }
+            "set_current_limit" => {
+                let CurrentLimitParams { limit } = self::params(params)?;
+                let limit = current_limit(limit)?;
+                lock(&self.smu)?.set_current_limit(limit)?;
+                Value::Null
+            }
+            "set_over_sample_rate" => {
+                let OverSampleRateParams { samples } = self::params(params)?;
+                let samples = over_sample_rate(samples)?;
+                lock(&self.smu)?.set_over_sample_rate(samples)?;
+                Value::Null
+            }
+            "measure" => {
+                let VoltageParams { voltage } = self::params(params)?;
+                let measurement = lock(&self.smu)?.measure(Voltage::new::<volt>(voltage))?;
+                to_value(Measurement::from(measurement))
+            }
+            "sweep" => {
+                let parameters = self::params::<SweepParameters>(params)?;
+                let parameters = IvCurveRecordingParameters::try_from(parameters)?;
+                let result = parameters.record(&mut *lock(&self.smu)?)?;
+                let samples = result
+                    .samples
+                    .iter()
+                    .map(|e| Measurement::from(e.point()))
+                    .collect::<Vec<_>>();
+                to_value(samples)
+            }
+            _ => {
+                return Err(RpcError::new(
+                    RpcError::METHOD_NOT_FOUND,
+                    format!("Unknown method '{method}'."),
+                ));
+            }
+        };
+        Ok(result)
+    }
+}
+
+fn to_value(value: impl Serialize) -> Value {
+    serde_json::to_value(value).expect("serialization of plain data cannot fail")
+}
+
+fn error(id: Value, error: RpcError) -> Response {
+    Response {
+        jsonrpc: "2.0",
+        result: None,
+        error: Some(error),
+        id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use crate::{simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    /// Serve the simulated device on `path` and connect to it.
+    fn connect(path: PathBuf) -> Stream {
+        let smu = simulated_smu(Resistor::ohm(1000.0));
+        let server = path.clone();
+        thread::spawn(move || serve(&server, Arc::new(Mutex::new(smu))));
+
+        let name = path.to_fs_name::<GenericFilePath>().unwrap();
+        for _ in 0..100 {
+            if let Ok(connection) = Stream::connect(name.clone()) {
+                return connection;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("server did not start listening");
+    }
+
+    /// Send `request` and wait for the response, only one is pending at a time.
+    fn call(mut connection: &Stream, request: &str) -> Value {
+        writeln!(connection, "{request}").unwrap();
+        let mut line = String::new();
+        BufReader::new(connection).read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn serves_the_simulated_device() {
+        let path = std::env::temp_dir().join(format!("usmu-json-rpc-{}.sock", std::process::id()));
+        // socket file of a crashed server, which nobody listens on
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let connection = connect(path);
+
+        let limit = r#"{"jsonrpc": "2.0", "id": 1, "method": "set_current_limit", "params": {"limit": 0.02}}"#;
+        assert_eq!(call(&connection, limit)["result"], Value::Null);
+        let enable = r#"{"jsonrpc": "2.0", "id": 2, "method": "enable"}"#;
+        assert_eq!(call(&connection, enable)["id"], 2);
+        let measure =
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "measure", "params": {"voltage": 1.0}}"#;
+        let current = call(&connection, measure)["result"]["current"]
+            .as_f64()
+            .unwrap();
+        assert!((current - 1e-3).abs() < 1e-5);
+
+        let sweep = r#"{"jsonrpc": "2.0", "id": 4, "method": "sweep", "params": {"start_voltage": 0.0, "end_voltage": 1.0, "voltage_steps": 0, "current_limit": 0.02, "over_sampling": 1}}"#;
+        assert_eq!(
+            call(&connection, sweep)["error"]["code"],
+            RpcError::INVALID_PARAMS
+        );
+        let unknown = r#"{"jsonrpc": "2.0", "id": 5, "method": "calibrate"}"#;
+        assert_eq!(
+            call(&connection, unknown)["error"]["code"],
+            RpcError::METHOD_NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! [SharedMicroSmu], a handle of one uSMU shared by several threads.
+//!
+//! The device communicates strictly request by request, hence the handle serializes the accesses:
+//! each call waits for the calls of other threads to complete, e.g. a GUI thread setting voltages
+//! while a logger thread measures. Sequences which must not be interleaved, e.g. setting a voltage
+//! and measuring right after, run as one call of [SharedMicroSmu::with].
+//!
+//! ```no_run
+//! # fn example(smu: usmu::MicroSmu) -> usmu::Result<()> {
+//! use std::thread;
+//!
+//! use usmu::{Voltage, shared::SharedMicroSmu, volt};
+//!
+//! let smu = SharedMicroSmu::new(smu);
+//! let logger = {
+//!     let smu = smu.clone();
+//!     thread::spawn(move || smu.measure_here())
+//! };
+//! smu.set_voltage(Voltage::new::<volt>(1.0))?;
+//! let measured = logger.join().unwrap()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use serialport::SerialPort;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Voltage, commands::MeasureResponse, transport::Transport,
+};
+
+/// See the [module documentation](self), clones share the device.
+pub struct SharedMicroSmu<T = Box<dyn SerialPort>> {
+    smu: Arc<Mutex<MicroSmu<T>>>,
+}
+
+impl<T> Clone for SharedMicroSmu<T> {
+    fn clone(&self) -> Self {
+        Self {
+            smu: self.smu.clone(),
+        }
+    }
+}
+
+impl<T: Transport> SharedMicroSmu<T> {
+    pub fn new(smu: MicroSmu<T>) -> Self {
+        Self {
+            smu: Arc::new(Mutex::new(smu)),
+        }
+    }
+
+    /// Exclusive access to the device until the guard is dropped.
+    ///
+    /// Fails with [Error::Internal], if a thread panicked while accessing the device,
+    /// as its state is unknown.
+    pub fn lock(&self) -> Result<MutexGuard<'_, MicroSmu<T>>> {
+        self.smu.lock().map_err(|_| {
+            Error::Internal("Device handle is poisoned by a previous failure.".to_string())
+        })
+    }
+
+    /// Run `f` with exclusive access to the device, e.g. a sequence of commands.
+    pub fn with<R>(&self, f: impl FnOnce(&mut MicroSmu<T>) -> Result<R>) -> Result<R> {
+        f(&mut *self.lock()?)
+    }
+
+    /// The device, if no other handle shares it anymore.
+    pub fn try_into_inner(self) -> std::result::Result<MicroSmu<T>, Self> {
+        match Arc::try_unwrap(self.smu) {
+            Ok(smu) => Ok(smu.into_inner().unwrap_or_else(PoisonError::into_inner)),
+            Err(smu) => Err(Self { smu }),
+        }
+    }
+
+    /// See [MicroSmu::enable].
+    pub fn enable(&self) -> Result<()> {
+        self.with(|smu| smu.enable())
+    }
+
+    /// See [MicroSmu::disable].
+    pub fn disable(&self) -> Result<()> {
+        self.with(|smu| smu.disable())
+    }
+
+    /// See [MicroSmu::set_voltage].
+    pub fn set_voltage(&self, voltage: Voltage) -> Result<()> {
+        self.with(|smu| smu.set_voltage(voltage))
+    }
+
+    /// See [MicroSmu::set_current_limit].
+    pub fn set_current_limit(&self, limit: Current) -> Result<()> {
+        self.with(|smu| smu.set_current_limit(limit))
+    }
+
+    /// See [MicroSmu::measure].
+    pub fn measure(&self, voltage: Voltage) -> Result<MeasureResponse> {
+        self.with(|smu| smu.measure(voltage))
+    }
+
+    /// See [MicroSmu::measure_here].
+    pub fn measure_here(&self) -> Result<MeasureResponse> {
+        self.with(|smu| smu.measure_here())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{ampere, milliampere, simulator::Resistor, test_util::simulated_smu, volt};
+
+    use super::*;
+
+    #[test]
+    fn serializes_the_threads() {
+        let smu = simulated_smu(Resistor::ohm(1000.0));
+        let smu = SharedMicroSmu::new(smu);
+        smu.set_current_limit(Current::new::<milliampere>(20.0))
+            .unwrap();
+        smu.set_voltage(Voltage::new::<volt>(1.0)).unwrap();
+        smu.enable().unwrap();
+
+        let loggers = (0..4)
+            .map(|_| {
+                let smu = smu.clone();
+                thread::spawn(move || {
+                    (0..10)
+                        .map(|_| smu.measure_here())
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for logger in loggers {
+            for measured in logger.join().unwrap().unwrap() {
+                assert!((measured.current.get::<ampere>() - 1e-3).abs() < 1e-5);
+            }
+        }
+
+        let mut smu = smu.try_into_inner().ok().unwrap();
+        assert_eq!(smu.get_set_voltage(), Some(Voltage::new::<volt>(1.0)));
+        smu.disable().unwrap();
+    }
+}
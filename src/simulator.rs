@@ -0,0 +1,605 @@
+//! Simulation of a uSMU with a device under test attached, to run sweeps and analysis without hardware.
+//!
+//! [SimulatedSmu] emulates the firmware behind the serial port interface,
+//! hence it exercises the complete driver:
+//!
+//! ```
+//! # use usmu::{MicroSmu, Voltage, volt, simulator::{Resistor, SimulatedSmu}};
+//! let port = SimulatedSmu::new(Resistor::ohm(1000.0));
+//! let mut smu = MicroSmu::new(Box::new(port));
+//! smu.enable().unwrap();
+//! let measurement = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+//! ```
+//!
+//! Measurements are ideal, unless a [NoiseModel] is configured,
+//! whose noise is reproducible for a given seed.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::{
+    Current, Voltage, ampere,
+    auto_range::RANGE_FULL_SCALE,
+    calibration::{Calibration, Coefficients, EEPROM_SIZE},
+    milliampere, volt,
+};
+
+/// Thermal voltage at room temperature (300 K).
+pub(crate) const THERMAL_VOLTAGE: f32 = 0.025852;
+/// Output voltages are searched within this range when the current limit is reached.
+const MAX_VOLTAGE: f32 = 10.0;
+const MAX_CURRENT_LIMIT: f32 = 0.04;
+/// Code of the current limit DAC for [MAX_CURRENT_LIMIT], the nominal transfer is linear.
+const ILIM_FULL_SCALE: f32 = 4095.0;
+
+/// The electrical behavior of a device under test.
+///
+/// The current must be monotonically non-decreasing with the voltage,
+/// positive currents flow into the device.
+pub trait DutModel: Send {
+    fn current(&self, voltage: Voltage) -> Current;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Resistor {
+    /// Resistance in ohm.
+    pub resistance: f32,
+}
+
+impl Resistor {
+    pub fn ohm(resistance: f32) -> Self {
+        Self { resistance }
+    }
+}
+
+impl DutModel for Resistor {
+    fn current(&self, voltage: Voltage) -> Current {
+        Current::new::<ampere>(voltage.get::<volt>() / self.resistance)
+    }
+}
+
+/// Diode following the Shockley equation.
+#[derive(Debug, Clone, Copy)]
+pub struct Diode {
+    /// Saturation current in ampere.
+    pub saturation_current: f32,
+    pub ideality_factor: f32,
+}
+
+impl Default for Diode {
+    fn default() -> Self {
+        Self {
+            saturation_current: 1e-12,
+            ideality_factor: 1.0,
+        }
+    }
+}
+
+impl DutModel for Diode {
+    fn current(&self, voltage: Voltage) -> Current {
+        let exponent = voltage.get::<volt>() / (self.ideality_factor * THERMAL_VOLTAGE);
+        Current::new::<ampere>(self.saturation_current * exponent.exp_m1())
+    }
+}
+
+/// Illuminated photovoltaic cell, i.e. a diode with a photo current, sourcing current between 0 V and Voc.
+#[derive(Debug, Clone, Copy)]
+pub struct SolarCell {
+    /// Short circuit current in ampere.
+    pub short_circuit_current: f32,
+    /// Open circuit voltage in volt.
+    pub open_circuit_voltage: f32,
+    pub ideality_factor: f32,
+}
+
+impl SolarCell {
+    pub fn new(short_circuit_current: Current, open_circuit_voltage: Voltage) -> Self {
+        Self {
+            short_circuit_current: short_circuit_current.get::<ampere>(),
+            open_circuit_voltage: open_circuit_voltage.get::<volt>(),
+            ideality_factor: 1.5,
+        }
+    }
+}
+
+impl DutModel for SolarCell {
+    fn current(&self, voltage: Voltage) -> Current {
+        let thermal_voltage = self.ideality_factor * THERMAL_VOLTAGE;
+        let saturation_current =
+            self.short_circuit_current / (self.open_circuit_voltage / thermal_voltage).exp_m1();
+        let diode = saturation_current * (voltage.get::<volt>() / thermal_voltage).exp_m1();
+        Current::new::<ampere>(diode - self.short_circuit_current)
+    }
+}
+
+/// No device attached.
+#[derive(Debug, Clone, Copy)]
+pub struct Open;
+
+impl DutModel for Open {
+    fn current(&self, _voltage: Voltage) -> Current {
+        Current::new::<ampere>(0.0)
+    }
+}
+
+/// Output shorted, the current is only bounded by the current limit.
+#[derive(Debug, Clone, Copy)]
+pub struct Short;
+
+impl DutModel for Short {
+    fn current(&self, voltage: Voltage) -> Current {
+        let voltage = voltage.get::<volt>();
+        let current = if voltage == 0.0 {
+            0.0
+        } else {
+            f32::INFINITY.copysign(voltage)
+        };
+        Current::new::<ampere>(current)
+    }
+}
+
+/// Gaussian measurement noise of a single sample, which is reduced by over sampling.
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseModel {
+    /// Standard deviation of the voltage in volt.
+    pub voltage: f32,
+    /// Standard deviation of the current in ampere.
+    pub current: f32,
+    /// Standard deviation of the current relative to its value.
+    pub relative_current: f32,
+}
+
+impl Default for NoiseModel {
+    fn default() -> Self {
+        Self {
+            voltage: 1e-3,
+            current: 1e-7,
+            relative_current: 1e-3,
+        }
+    }
+}
+
+/// SplitMix64 generator, which is tiny and stable, hence the noise of a seed never changes.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in (0, 1].
+    fn uniform(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal distributed, using the Box-Muller transform.
+    fn normal(&mut self) -> f32 {
+        let radius = (-2.0 * self.uniform().ln()).sqrt();
+        let angle = 2.0 * std::f64::consts::PI * self.uniform();
+        (radius * angle.cos()) as f32
+    }
+}
+
+/// A simulated uSMU implementing the serial port interface, clones share the same device.
+#[derive(Clone)]
+pub struct SimulatedSmu {
+    device: Arc<Mutex<Device>>,
+}
+
+struct Device {
+    dut: Box<dyn DutModel>,
+    uid: u32,
+    enabled: bool,
+    voltage: f32,
+    current_limit: f32,
+    over_sampling: u16,
+    /// The current range locked, measurements clip at its full scale.
+    range: Option<u8>,
+    /// The nominal calibration initially, i.e. slopes of 1 and intercepts of 0.
+    eeprom: [f32; EEPROM_SIZE as usize],
+    noise: Option<(NoiseModel, Rng)>,
+    /// The incomplete line received so far.
+    line: Vec<u8>,
+    readable: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl SimulatedSmu {
+    pub fn new(dut: impl DutModel + 'static) -> Self {
+        let device = Device {
+            dut: Box::new(dut),
+            uid: 0,
+            enabled: false,
+            voltage: 0.0,
+            current_limit: MAX_CURRENT_LIMIT,
+            over_sampling: 1,
+            range: None,
+            eeprom: std::array::from_fn(|address| if address % 2 == 0 { 1.0 } else { 0.0 }),
+            noise: None,
+            line: Vec::new(),
+            readable: VecDeque::new(),
+            timeout: Duration::from_millis(1000),
+        };
+        Self {
+            device: Arc::new(Mutex::new(device)),
+        }
+    }
+
+    /// Unique id reported by the identity query, 0 by default.
+    pub fn with_uid(self, uid: u32) -> Self {
+        self.device().uid = uid;
+        self
+    }
+
+    /// Add `noise` to the measurements, generated reproducibly from `seed`.
+    pub fn with_noise(self, noise: NoiseModel, seed: u64) -> Self {
+        self.device().noise = Some((noise, Rng(seed)));
+        self
+    }
+
+    /// Replace the attached device under test.
+    pub fn attach(&self, dut: impl DutModel + 'static) {
+        self.device().dut = Box::new(dut);
+    }
+
+    fn device(&self) -> MutexGuard<'_, Device> {
+        self.device.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Device {
+    fn current(&self, voltage: f32) -> f32 {
+        self.dut
+            .current(Voltage::new::<volt>(voltage))
+            .get::<ampere>()
+    }
+
+    /// Output voltage and current, reducing the voltage if the current limit is reached.
+    fn operating_point(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (self.voltage, 0.0);
+        }
+        let current = self.current(self.voltage);
+        if current.abs() <= self.current_limit {
+            return (self.voltage, current);
+        }
+
+        // bisect the voltage at which the current equals the limit, the model is monotonic
+        let limit = self.current_limit.copysign(current);
+        let (mut low, mut high) = (-MAX_VOLTAGE, MAX_VOLTAGE);
+        for _ in 0..64 {
+            let middle = (low + high) / 2.0;
+            if self.current(middle) < limit {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        let voltage = (low + high) / 2.0;
+        (voltage, limit)
+    }
+
+    fn add_noise(&mut self, voltage: f32, current: f32) -> (f32, f32) {
+        let over_sampling = f32::from(self.over_sampling.max(1));
+        let Some((noise, rng)) = self.noise.as_mut() else {
+            return (voltage, current);
+        };
+        let scale = over_sampling.sqrt().recip();
+        let current_deviation = noise.current + noise.relative_current * current.abs();
+        (
+            voltage + rng.normal() * noise.voltage * scale,
+            current + rng.normal() * current_deviation * scale,
+        )
+    }
+
+    /// Store the slope and intercept of the calibration command `argument`, the calibration is not applied.
+    fn calibrate(&mut self, coefficients: Coefficients, argument: &str) -> Option<()> {
+        let (slope, intercept) = argument.trim().split_once(' ')?;
+        let address = usize::from(Calibration::address(coefficients).value);
+        self.eeprom[address] = slope.parse().ok()?;
+        self.eeprom[address + 1] = intercept.parse().ok()?;
+        Some(())
+    }
+
+    fn respond(&mut self, command: &str) -> Option<String> {
+        let (header, argument) = command.split_once(' ').unwrap_or((command, ""));
+        let number = || argument.trim().parse::<f32>().ok();
+        match header {
+            "*IDN?" => return Some(format!("uSMU version 1.0 ID:{}", self.uid)),
+            "CH1:ENA" => self.enabled = true,
+            "CH1:DIS" => self.enabled = false,
+            "CH1:VOL" => self.voltage = number()?,
+            "CH1:CUR" => self.current_limit = number()? / 1000.0,
+            "ILIM" => self.current_limit = number()? / ILIM_FULL_SCALE * MAX_CURRENT_LIMIT,
+            "CH1:OSR" => self.over_sampling = number()? as u16,
+            "CH1:MEA:VOL" => {
+                self.voltage = number()?;
+                let (voltage, current) = self.operating_point();
+                let (voltage, current) = self.add_noise(voltage, current);
+                let current = match self.range {
+                    Some(range) => {
+                        let full_scale = RANGE_FULL_SCALE[usize::from(range) - 1];
+                        current.clamp(-full_scale, full_scale)
+                    }
+                    None => current,
+                };
+                return Some(format!("{voltage},{current}"));
+            }
+            "*READ" => {
+                let address = argument.trim().parse::<usize>().ok()?;
+                return Some(self.eeprom.get(address)?.to_string());
+            }
+            "CAL:DAC" => self.calibrate(Coefficients::VoltageDac, argument)?,
+            "CAL:VOL" => self.calibrate(Coefficients::VoltageAdc, argument)?,
+            "CAL:ILIM" => self.calibrate(Coefficients::CurrentLimitDac, argument)?,
+            "CAL:CUR:RANGE" => {
+                let (range, argument) = argument.split_once(' ')?;
+                self.calibrate(Coefficients::CurrentAdc(range.parse().ok()?), argument)?
+            }
+            "ADC" => return Some("0".to_string()),
+            header if header.starts_with("CH1:RANGE") => {
+                self.range = Some(header["CH1:RANGE".len()..].parse().ok()?)
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Read for SimulatedSmu {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut device = self.device();
+        if device.readable.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no response"));
+        }
+        let count = buf.len().min(device.readable.len());
+        for (target, source) in buf.iter_mut().zip(device.readable.drain(..count)) {
+            *target = source;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for SimulatedSmu {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut device = self.device();
+        for &byte in buf {
+            if byte != b'\n' {
+                device.line.push(byte);
+                continue;
+            }
+            let line = std::mem::take(&mut device.line);
+            if let Some(response) = device.respond(&String::from_utf8_lossy(&line)) {
+                device.readable.extend(response.bytes());
+                device.readable.push_back(b'\n');
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for SimulatedSmu {
+    fn name(&self) -> Option<String> {
+        Some("simulated".to_string())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(9600)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.device().timeout
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.device().timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.device().readable.len() as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        let mut device = self.device();
+        if matches!(buffer_to_clear, ClearBuffer::Input | ClearBuffer::All) {
+            device.readable.clear();
+        }
+        if matches!(buffer_to_clear, ClearBuffer::Output | ClearBuffer::All) {
+            device.line.clear();
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Devices under test available for simulation on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimulatedDut {
+    /// 1 kOhm resistor.
+    Resistor,
+    Diode,
+    /// Solar cell with 10 mA short circuit current and 0.6 V open circuit voltage.
+    SolarCell,
+    Open,
+    Short,
+}
+
+#[derive(Debug, Clone, Default, Parser)]
+pub struct SimulationParameter {
+    /// Simulate a uSMU with this device under test attached, instead of connecting to a device.
+    #[arg(long, num_args = 0..=1, default_missing_value = "diode")]
+    pub simulate: Option<SimulatedDut>,
+
+    /// Seed of the simulated measurement noise, to reproduce a simulation. Random if not given.
+    #[arg(long, requires = "simulate")]
+    pub seed: Option<u64>,
+}
+
+impl SimulationParameter {
+    /// The simulated device, if simulation is requested.
+    pub fn port(&self) -> Option<SimulatedSmu> {
+        let port = match self.simulate? {
+            SimulatedDut::Resistor => SimulatedSmu::new(Resistor::ohm(1000.0)),
+            SimulatedDut::Diode => SimulatedSmu::new(Diode::default()),
+            SimulatedDut::SolarCell => SimulatedSmu::new(SolarCell::new(
+                Current::new::<milliampere>(10.0),
+                Voltage::new::<volt>(0.6),
+            )),
+            SimulatedDut::Open => SimulatedSmu::new(Open),
+            SimulatedDut::Short => SimulatedSmu::new(Short),
+        };
+        let seed = self.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .map(|e| e.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        Some(port.with_noise(NoiseModel::default(), seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Current, MicroSmu, Voltage, ampere, milliampere,
+        simulator::{NoiseModel, Resistor, SimulatedSmu, SolarCell},
+        volt,
+    };
+
+    fn measure(smu: &mut MicroSmu, voltage: f32) -> (f32, f32) {
+        let measurement = smu.measure(Voltage::new::<volt>(voltage)).unwrap();
+        (
+            measurement.voltage.get::<volt>(),
+            measurement.current.get::<ampere>(),
+        )
+    }
+
+    #[test]
+    fn solar_cell_short_and_open_circuit() {
+        let cell = SolarCell::new(Current::new::<milliampere>(10.0), Voltage::new::<volt>(0.6));
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(cell)));
+        smu.enable().unwrap();
+
+        let (_, short_circuit) = measure(&mut smu, 0.0);
+        let (_, open_circuit) = measure(&mut smu, 0.6);
+        assert!((short_circuit + 0.01).abs() < 1e-6);
+        assert!(open_circuit.abs() < 1e-6);
+    }
+
+    #[test]
+    fn current_limit_reduces_voltage() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(crate::simulator::Short)));
+        smu.set_current_limit(Current::new::<milliampere>(10.0))
+            .unwrap();
+        smu.enable().unwrap();
+
+        let (voltage, current) = measure(&mut smu, 1.0);
+        assert!(voltage.abs() < 1e-3);
+        assert_eq!(current, 0.01);
+    }
+
+    #[test]
+    fn noise_is_reproducible_for_a_seed() {
+        let sample = |seed| {
+            let port =
+                SimulatedSmu::new(Resistor::ohm(1000.0)).with_noise(NoiseModel::default(), seed);
+            let mut smu = MicroSmu::new(Box::new(port));
+            smu.enable().unwrap();
+            (0..3).map(|_| measure(&mut smu, 1.0)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(sample(42), sample(42));
+        assert_ne!(sample(42), sample(43));
+    }
+}
@@ -0,0 +1,216 @@
+//! Power spectral density estimates of uniformly sampled data, e.g. to identify mains pickup
+//! or 1/f noise in a measurement setup.
+//!
+//! The estimate follows Welch's method: the data is split into segments overlapping by half,
+//! each segment is detrended by its mean, windowed and transformed, and the periodograms are averaged.
+//! The one-sided density is scaled such that its integral equals the variance of the data.
+
+use std::{f64::consts::PI, fs::File, io::Write, path::PathBuf};
+
+use clap::{Parser, ValueEnum};
+use rustfft::{FftPlanner, num_complex::Complex};
+use serde::Serialize;
+
+use crate::{Error, Result, ampere, monitor::MonitorSample, schema, second};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Window {
+    Rectangular,
+    /// Periodic Hann window, suppressing the leakage of strong lines, e.g. mains pickup, into their neighborhood.
+    #[default]
+    Hann,
+}
+
+impl Window {
+    fn coefficients(self, length: usize) -> Vec<f64> {
+        match self {
+            Window::Rectangular => vec![1.0; length],
+            Window::Hann => (0..length)
+                .map(|k| 0.5 * (1.0 - (2.0 * PI * k as f64 / length as f64).cos()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Frequencies in Hz, from zero to the Nyquist frequency.
+    pub frequencies: Vec<f64>,
+    /// One-sided power spectral density in squared units of the data per Hz.
+    pub density: Vec<f64>,
+    /// Number of averaged segments.
+    pub segments: usize,
+}
+
+/// Estimate the power spectral density of `values` sampled at `sample_rate` in Hz.
+///
+/// The frequency resolution is `sample_rate / segment_length`, shorter segments average more
+/// periodograms and reduce the variance of the estimate.
+pub fn welch(
+    values: &[f64],
+    sample_rate: f64,
+    segment_length: usize,
+    window: Window,
+) -> Result<Spectrum> {
+    if segment_length < 2 {
+        Err(Error::Configuration(
+            "A segment needs at least two samples.".to_string(),
+        ))?;
+    }
+    if values.len() < segment_length {
+        Err(Error::Configuration(format!(
+            "{} samples are too few for a segment length of {segment_length}.",
+            values.len()
+        )))?;
+    }
+    if !(sample_rate > 0.0 && sample_rate.is_finite()) {
+        Err(Error::Configuration(format!(
+            "Invalid sample rate {sample_rate} Hz."
+        )))?;
+    }
+
+    let window = window.coefficients(segment_length);
+    let power: f64 = window.iter().map(|e| e * e).sum();
+    let fft = FftPlanner::new().plan_fft_forward(segment_length);
+
+    let bins = segment_length / 2 + 1;
+    let mut density = vec![0.0; bins];
+    let step = (segment_length / 2).max(1);
+    let mut segments = 0;
+    let mut buffer = Vec::with_capacity(segment_length);
+    for start in (0..=values.len() - segment_length).step_by(step) {
+        let segment = &values[start..start + segment_length];
+        let mean = segment.iter().sum::<f64>() / segment_length as f64;
+        buffer.clear();
+        buffer.extend(
+            segment
+                .iter()
+                .zip(&window)
+                .map(|(value, w)| Complex::new((value - mean) * w, 0.0)),
+        );
+        fft.process(&mut buffer);
+        for (density, bin) in density.iter_mut().zip(&buffer) {
+            *density += bin.norm_sqr();
+        }
+        segments += 1;
+    }
+
+    let scale = 1.0 / (sample_rate * power * segments as f64);
+    for (k, density) in density.iter_mut().enumerate() {
+        // fold the negative frequencies, except for DC and the Nyquist frequency of even lengths
+        let one_sided = if k == 0 || 2 * k == segment_length {
+            1.0
+        } else {
+            2.0
+        };
+        *density *= scale * one_sided;
+    }
+
+    Ok(Spectrum {
+        frequencies: (0..bins)
+            .map(|k| k as f64 * sample_rate / segment_length as f64)
+            .collect(),
+        density,
+        segments,
+    })
+}
+
+/// Mean sample rate of the monitored samples in Hz, `None` for less than two samples.
+pub fn sample_rate(samples: &[MonitorSample]) -> Option<f64> {
+    let (first, last) = (samples.first()?, samples.last()?);
+    let span = f64::from((last.time - first.time).get::<second>());
+    (samples.len() > 1 && span > 0.0).then(|| (samples.len() - 1) as f64 / span)
+}
+
+/// Write the spectrum as CSV with the columns `frequency` (Hz) and `density`.
+pub fn write_csv(spectrum: &Spectrum, output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        frequency: f64,
+        density: f64,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for (&frequency, &density) in spectrum.frequencies.iter().zip(&spectrum.density) {
+        writer.serialize(Row { frequency, density })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct SpectrumParameter {
+    /// Write the power spectral density of the current in A²/Hz as CSV to this file.
+    #[arg(long)]
+    pub spectrum: Option<PathBuf>,
+
+    /// Number of samples per averaged segment of the spectrum.
+    #[arg(long, default_value_t = 256)]
+    pub segment_length: usize,
+
+    #[arg(long, default_value = "hann")]
+    pub window: Window,
+}
+
+impl SpectrumParameter {
+    pub fn output(&self, samples: &[MonitorSample]) -> Result<()> {
+        let Some(path) = self.spectrum.as_ref() else {
+            return Ok(());
+        };
+        let sample_rate = sample_rate(samples)
+            .ok_or_else(|| Error::Data("Too few samples for a spectrum.".to_string()))?;
+        let currents: Vec<f64> = samples
+            .iter()
+            .map(|e| e.current.get::<ampere>().into())
+            .collect();
+        let spectrum = welch(&currents, sample_rate, self.segment_length, self.window)?;
+        write_csv(&spectrum, File::create(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_a_line() {
+        let sample_rate = 1000.0;
+        let values: Vec<f64> = (0..4096)
+            .map(|k| (2.0 * PI * 50.0 * k as f64 / sample_rate).sin())
+            .collect();
+        let spectrum = welch(&values, sample_rate, 500, Window::Hann).unwrap();
+
+        let peak = (0..spectrum.density.len())
+            .max_by(|&a, &b| spectrum.density[a].total_cmp(&spectrum.density[b]))
+            .unwrap();
+        assert_eq!(spectrum.frequencies[peak], 50.0);
+        assert_eq!(spectrum.segments, 15);
+    }
+
+    #[test]
+    fn integrates_to_the_variance() {
+        // deterministic pseudo random values, uniform in [-0.5, 0.5)
+        let mut state = 1u64;
+        let values: Vec<f64> = (0..8192)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+            })
+            .collect();
+        let sample_rate = 10.0;
+        for window in [Window::Rectangular, Window::Hann] {
+            let spectrum = welch(&values, sample_rate, 256, window).unwrap();
+            let resolution = sample_rate / 256.0;
+            let power: f64 = spectrum.density.iter().sum::<f64>() * resolution;
+            assert!((power - 1.0 / 12.0).abs() < 0.01, "{window:?}: {power}");
+        }
+    }
+
+    #[test]
+    fn rejects_short_data() {
+        assert!(welch(&[0.0; 10], 1.0, 16, Window::Hann).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+//! Repeated readings at a fixed bias with histograms and summary statistics,
+//! to characterize the measurement repeatability and the noise of the device under test.
+
+use std::{fmt::Display, io::Write, path::PathBuf};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::{
+    Current, Error, MicroSmu, RawMeasurement, Result, Voltage, ampere, commands::MeasureResponse,
+    record_iv_curve::SmuConnectionParameter, schema, volt,
+};
+
+/// Summary statistics of a set of values.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Statistics {
+    pub count: usize,
+    pub mean: f64,
+    /// Sample standard deviation.
+    pub standard_deviation: f64,
+    /// Sample skewness, zero for a symmetric distribution.
+    pub skewness: f64,
+    pub minimum: f64,
+    pub maximum: f64,
+}
+
+impl Statistics {
+    /// `None` for less than two values.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        let count = values.len();
+        if count < 2 {
+            return None;
+        }
+        let n = count as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let moment = |k| values.iter().map(|e| (e - mean).powi(k)).sum::<f64>() / n;
+        let (m2, m3) = (moment(2), moment(3));
+        let skewness = if m2 > 0.0 {
+            // adjusted Fisher-Pearson coefficient
+            m3 / m2.powf(1.5) * (n * (n - 1.0)).sqrt() / (n - 2.0).max(1.0)
+        } else {
+            0.0
+        };
+        Some(Self {
+            count,
+            mean,
+            standard_deviation: (m2 * n / (n - 1.0)).sqrt(),
+            skewness,
+            minimum: values.iter().copied().fold(f64::INFINITY, f64::min),
+            maximum: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// Counts of values in equally wide bins between the minimum and maximum value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub minimum: f64,
+    pub bin_width: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Panics, if `bins` is zero.
+    pub fn new(values: &[f64], bins: usize) -> Self {
+        assert!(bins > 0, "A histogram needs at least one bin.");
+        let minimum = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let maximum = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let bin_width = if maximum > minimum {
+            (maximum - minimum) / bins as f64
+        } else {
+            // all values are equal or there are none
+            1.0
+        };
+        let minimum = if minimum.is_finite() { minimum } else { 0.0 };
+
+        let mut counts = vec![0; bins];
+        for value in values {
+            let bin = ((value - minimum) / bin_width) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self {
+            minimum,
+            bin_width,
+            counts,
+        }
+    }
+
+    /// Lower edge of `bin`.
+    pub fn lower_edge(&self, bin: usize) -> f64 {
+        self.minimum + bin as f64 * self.bin_width
+    }
+}
+
+impl Display for Histogram {
+    /// One line per bin with its range, count and a bar.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const WIDTH: usize = 50;
+        let largest = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (bin, count) in self.counts.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>14.6e} .. {:>14.6e} {:>6} {}",
+                self.lower_edge(bin),
+                self.lower_edge(bin + 1),
+                count,
+                "#".repeat(count * WIDTH / largest)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RepeatabilityParameters {
+    /// Bias applied for all readings.
+    pub voltage: Voltage,
+    pub current_limit: Current,
+
+    /// Number of samples averaged per reading.
+    pub over_sampling: u16,
+
+    /// Number of readings.
+    pub count: usize,
+}
+
+impl RepeatabilityParameters {
+    /// Take the readings at the fixed bias.
+    ///
+    /// The output is disabled afterwards, also on failure.
+    pub fn record(&self, smu: &mut MicroSmu) -> Result<Vec<(Voltage, Current)>> {
+        self.record_with(smu, |smu, voltage| {
+            let MeasureResponse { voltage, current } = smu.measure(voltage)?;
+            Ok((voltage, current))
+        })
+    }
+
+    /// Take the readings with their raw ADC counts, see [MicroSmu::measure_raw].
+    pub fn record_raw(&self, smu: &mut MicroSmu) -> Result<Vec<RawMeasurement>> {
+        self.record_with(smu, MicroSmu::measure_raw)
+    }
+
+    fn record_with<T>(
+        &self,
+        smu: &mut MicroSmu,
+        measure: impl FnMut(&mut MicroSmu, Voltage) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let samples = self.repeat(smu, measure);
+        let disabled = smu.disable();
+        let samples = samples?;
+        disabled?;
+        Ok(samples)
+    }
+
+    fn repeat<T>(
+        &self,
+        smu: &mut MicroSmu,
+        mut measure: impl FnMut(&mut MicroSmu, Voltage) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        smu.set_voltage(self.voltage)?;
+        smu.set_current_limit(self.current_limit)?;
+        smu.set_over_sample_rate(self.over_sampling)?;
+        smu.enable()?;
+
+        (0..self.count)
+            .map(|_| measure(smu, self.voltage))
+            .collect()
+    }
+}
+
+/// Summary statistics of the voltage and current of the samples, `None` for less than two samples.
+pub fn summarize(samples: &[(Voltage, Current)]) -> Option<(Statistics, Statistics)> {
+    let (voltages, currents) = split(samples);
+    Some((Statistics::new(&voltages)?, Statistics::new(&currents)?))
+}
+
+fn split(samples: &[(Voltage, Current)]) -> (Vec<f64>, Vec<f64>) {
+    samples
+        .iter()
+        .map(|(v, i)| (f64::from(v.get::<volt>()), f64::from(i.get::<ampere>())))
+        .unzip()
+}
+
+/// Write the samples as CSV with the columns `voltage` and `current`.
+pub fn write_csv(samples: &[(Voltage, Current)], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for (v, i) in samples {
+        writer.serialize(Row {
+            voltage: v.get::<volt>(),
+            current: i.get::<ampere>(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write the measurements as CSV with the columns `voltage`, `current`, `voltage_counts` and `current_counts`.
+pub fn write_raw_csv(measurements: &[RawMeasurement], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        voltage: f32,
+        current: f32,
+        voltage_counts: u16,
+        current_counts: u16,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for measurement in measurements {
+        writer.serialize(Row {
+            voltage: measurement.calibrated.voltage.get::<volt>(),
+            current: measurement.calibrated.current.get::<ampere>(),
+            voltage_counts: measurement.voltage_counts,
+            current_counts: measurement.current_counts,
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Parser)]
+pub struct HistogramArguments {
+    #[command(flatten)]
+    pub connection_parameter: SmuConnectionParameter,
+
+    /// Bias voltage applied for all readings.
+    #[arg(long, short = 'v', default_value = "0 V")]
+    pub voltage: Voltage,
+
+    #[arg(long, short = 'c', default_value = "20 mA")]
+    pub current_limit: Current,
+
+    /// Number of samples averaged per reading.
+    #[arg(long, short = 'r', default_value_t = 10)]
+    pub over_sampling: u16,
+
+    /// Number of readings.
+    #[arg(long, short = 'n', default_value_t = 100)]
+    pub count: usize,
+
+    /// Number of histogram bins.
+    #[arg(long, default_value_t = 20)]
+    pub bins: usize,
+
+    /// Write the individual readings as CSV to this file.
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+
+    /// Also read the raw ADC counts after each reading, to separate the ADC from the calibration.
+    #[arg(long)]
+    pub raw_counts: bool,
+
+    #[cfg(feature = "checksum")]
+    #[command(flatten)]
+    pub checksum_parameter: crate::checksum::ChecksumParameter,
+}
+
+impl HistogramArguments {
+    pub fn run(&self) -> Result<()> {
+        if self.bins == 0 {
+            Err(Error::Configuration(
+                "At least one histogram bin is required.".to_string(),
+            ))?;
+        }
+        let mut smu = self.connection_parameter.connect()?;
+        let parameters = RepeatabilityParameters {
+            voltage: self.voltage,
+            current_limit: self.current_limit,
+            over_sampling: self.over_sampling,
+            count: self.count,
+        };
+        let (samples, raw) = if self.raw_counts {
+            let raw = parameters.record_raw(&mut smu)?;
+            let samples = raw
+                .iter()
+                .map(|e| (e.calibrated.voltage, e.calibrated.current))
+                .collect();
+            (samples, raw)
+        } else {
+            (parameters.record(&mut smu)?, Vec::new())
+        };
+
+        let (voltages, currents) = split(&samples);
+        let counts = |count: fn(&RawMeasurement) -> u16| -> Vec<f64> {
+            raw.iter().map(|e| f64::from(count(e))).collect()
+        };
+        let (voltage_counts, current_counts) =
+            (counts(|e| e.voltage_counts), counts(|e| e.current_counts));
+        let mut quantities = vec![("Voltage [V]", &voltages), ("Current [A]", &currents)];
+        if self.raw_counts {
+            quantities.push(("Voltage ADC [counts]", &voltage_counts));
+            quantities.push(("Current ADC [counts]", &current_counts));
+        }
+        for (name, values) in quantities {
+            println!("{name}");
+            if let Some(Statistics {
+                count,
+                mean,
+                standard_deviation,
+                skewness,
+                minimum,
+                maximum,
+            }) = Statistics::new(values)
+            {
+                println!(
+                    "n = {count}, mean = {mean:.6e}, σ = {standard_deviation:.3e}, skew = {skewness:.3}, min = {minimum:.6e}, max = {maximum:.6e}"
+                );
+            }
+            println!("{}", Histogram::new(values, self.bins));
+        }
+
+        if let Some(output) = self.output.as_ref() {
+            let output = std::fs::File::create(output)?;
+            if self.raw_counts {
+                write_raw_csv(&raw, output)?;
+            } else {
+                write_csv(&samples, output)?;
+            }
+        }
+        #[cfg(feature = "checksum")]
+        self.checksum_parameter.seal(self.output.as_ref())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{milliampere, test_util::FakeSerialPort};
+
+    use super::*;
+
+    #[test]
+    fn summarizes_values() {
+        let statistics = Statistics::new(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(statistics.mean, 2.5);
+        assert!((statistics.standard_deviation - 1.290_994).abs() < 1e-6);
+        assert_eq!(statistics.skewness, 0.0);
+        assert_eq!((statistics.minimum, statistics.maximum), (1.0, 4.0));
+
+        let skewed = Statistics::new(&[0.0, 0.0, 0.0, 1.0]).unwrap();
+        assert!(skewed.skewness > 0.0);
+        assert_eq!(Statistics::new(&[1.0]), None);
+    }
+
+    #[test]
+    fn records_raw_counts() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 0")
+            .expect("CH1:CUR 20")
+            .expect("CH1:OSR 1")
+            .expect("CH1:ENA")
+            .expect_query("CH1:MEA:VOL 0", "0.001,0.002")
+            .expect_query("ADC 0", "100")
+            .expect_query("ADC 2", "200")
+            .expect("CH1:DIS");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let measurements = RepeatabilityParameters {
+            voltage: Voltage::new::<volt>(0.0),
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 1,
+            count: 1,
+        }
+        .record_raw(&mut smu)
+        .unwrap();
+        port.verify();
+
+        let mut csv = Vec::new();
+        write_raw_csv(&measurements, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(
+            csv.ends_with("voltage,current,voltage_counts,current_counts\n0.001,0.002,100,200\n")
+        );
+    }
+
+    #[test]
+    fn bins_values() {
+        let histogram = Histogram::new(&[0.0, 0.1, 0.5, 0.9, 1.0], 2);
+        assert_eq!(histogram.counts, vec![2, 3]);
+        assert_eq!(histogram.lower_edge(1), 0.5);
+
+        let constant = Histogram::new(&[1.0, 1.0], 3);
+        assert_eq!(constant.counts, vec![2, 0, 0]);
+    }
+}
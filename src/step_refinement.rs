@@ -0,0 +1,104 @@
+//! Extra sweep points where the current changes quickly, e.g. at the knee of a diode or a breakdown.
+//!
+//! With [StepRefinement::current_step], a point is inserted halfway between two neighbouring points
+//! whose currents differ by more than the step, and the halves are refined again in turn,
+//! until the currents of all neighbours are within the step, the voltage step reaches [StepRefinement::min_voltage_step]
+//! or [StepRefinement::max_points] were inserted.
+//!
+//! The neighbours are refined during the sweep, right after measuring each regular point,
+//! so the voltage only steps back within the last step and the samples stay ordered by their set voltage.
+
+use clap::Parser;
+
+use crate::{Current, Voltage, sweep_result::SweepSample};
+
+#[derive(Debug, Clone, PartialEq, Parser)]
+pub struct StepRefinement {
+    /// Insert points between neighbouring points whose currents differ by more than this, e.g. `1 mA`.
+    #[arg(long = "refine-current-step", value_name = "CURRENT")]
+    pub current_step: Option<Current>,
+
+    /// Do not refine voltage steps below this.
+    #[arg(long = "refine-min-voltage-step", default_value = "1 mV")]
+    pub min_voltage_step: Voltage,
+
+    /// Maximum number of inserted points, the number of voltage steps by default.
+    #[arg(long = "refine-max-points")]
+    pub max_points: Option<usize>,
+}
+
+impl Default for StepRefinement {
+    /// The defaults of the command line, without refinement.
+    fn default() -> Self {
+        Self::parse_from(["step_refinement"])
+    }
+}
+
+impl StepRefinement {
+    /// The number of points that may be inserted into a sweep of `voltage_steps`.
+    pub fn budget(&self, voltage_steps: usize) -> usize {
+        match self.current_step {
+            Some(_) => self.max_points.unwrap_or(voltage_steps),
+            None => 0,
+        }
+    }
+
+    /// The set voltage of a point inserted between the neighbours `a` and `b`, if they need to be refined.
+    pub fn midpoint(&self, a: &SweepSample, b: &SweepSample) -> Option<Voltage> {
+        let current_step = self.current_step?;
+        let voltage_step = (b.set_voltage - a.set_voltage).abs();
+        ((b.current - a.current).abs() > current_step
+            && voltage_step >= self.min_voltage_step * 2.0)
+            .then(|| (a.set_voltage + b.set_voltage) / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        MicroSmu, Time, milliampere,
+        record_iv_curve::IvCurveRecordingParameters,
+        second,
+        simulator::{Diode, SimulatedSmu},
+        volt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn refines_the_knee_of_a_diode() {
+        let mut parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(-1.0),
+            end_voltage: Voltage::new::<volt>(1.0),
+            voltage_steps: 11,
+            current_limit: Current::new::<milliampere>(20.0),
+            over_sampling: 1,
+            delay: Time::new::<second>(0.0),
+            ..Default::default()
+        };
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Diode::default())));
+        let regular = parameters.record(&mut smu).unwrap();
+        assert_eq!(regular.samples.len(), 11);
+
+        parameters.step_refinement.current_step = Some(Current::new::<milliampere>(1.0));
+        parameters.step_refinement.max_points = Some(20);
+        let refined = parameters.record(&mut smu).unwrap();
+        let inserted = refined.samples.len() - regular.samples.len();
+        assert!(inserted > 0 && inserted <= 20);
+        assert!(
+            refined
+                .samples
+                .windows(2)
+                .all(|e| e[0].set_voltage < e[1].set_voltage)
+        );
+        // only the forward biased part is refined
+        assert!(
+            refined
+                .samples
+                .iter()
+                .filter(|e| e.set_voltage.get::<volt>() < 0.0)
+                .count()
+                == 5
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! SQLite store collecting the results of many runs, e.g. to track the degradation of a device under test.
+//!
+//! Runs are identified by the id of the device under test, chosen by the user,
+//! and stored with their start time and samples.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use clap::Parser;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use uom::si::electrical_resistance::ohm;
+
+use crate::{
+    Current, Error, Resistance, Result, Voltage, ampere, analysis, schema,
+    sweep_result::SweepResult, volt,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        dut_id TEXT NOT NULL,
+        -- seconds since the unix epoch
+        started_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_dut_id ON runs (dut_id);
+    CREATE TABLE IF NOT EXISTS samples (
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        voltage REAL NOT NULL,
+        current REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS samples_run_id ON samples (run_id);
+";
+
+fn map_err(e: rusqlite::Error) -> Error {
+    Error::External(e.to_string())
+}
+
+pub struct ResultsStore {
+    connection: Connection,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredRun {
+    pub id: i64,
+    pub dut_id: String,
+    pub started_at: SystemTime,
+    pub samples: Vec<(Voltage, Current)>,
+}
+
+impl ResultsStore {
+    /// Open the store at `path`, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let connection = Connection::open(path).map_err(map_err)?;
+        connection.execute_batch(SCHEMA).map_err(map_err)?;
+        Ok(Self { connection })
+    }
+
+    /// Store the samples of a run and return its id.
+    pub fn insert(
+        &mut self,
+        dut_id: &str,
+        started_at: SystemTime,
+        samples: &[(Voltage, Current)],
+    ) -> Result<i64> {
+        let started_at = started_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|e| Error::Data(e.to_string()))?
+            .as_secs() as i64;
+
+        let transaction = self.connection.transaction().map_err(map_err)?;
+        transaction
+            .execute(
+                "INSERT INTO runs (dut_id, started_at) VALUES (?1, ?2)",
+                params![dut_id, started_at],
+            )
+            .map_err(map_err)?;
+        let id = transaction.last_insert_rowid();
+        {
+            let mut statement = transaction
+                .prepare("INSERT INTO samples (run_id, voltage, current) VALUES (?1, ?2, ?3)")
+                .map_err(map_err)?;
+            for (voltage, current) in samples {
+                statement
+                    .execute(params![id, voltage.get::<volt>(), current.get::<ampere>()])
+                    .map_err(map_err)?;
+            }
+        }
+        transaction.commit().map_err(map_err)?;
+        Ok(id)
+    }
+
+    /// All runs of `dut_id`, oldest first.
+    pub fn runs(&self, dut_id: &str) -> Result<Vec<StoredRun>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, started_at FROM runs WHERE dut_id = ?1 ORDER BY started_at, id")
+            .map_err(map_err)?;
+        let runs = statement
+            .query_map(params![dut_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })
+            .map_err(map_err)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(map_err)?;
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT voltage, current FROM samples WHERE run_id = ?1 ORDER BY rowid")
+            .map_err(map_err)?;
+        runs.into_iter()
+            .map(|(id, started_at)| {
+                let samples = statement
+                    .query_map(params![id], |row| {
+                        Ok((
+                            Voltage::new::<volt>(row.get(0)?),
+                            Current::new::<ampere>(row.get(1)?),
+                        ))
+                    })
+                    .map_err(map_err)?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                    .map_err(map_err)?;
+                Ok(StoredRun {
+                    id,
+                    dut_id: dut_id.to_string(),
+                    started_at: SystemTime::UNIX_EPOCH
+                        + Duration::from_secs(started_at.max(0) as u64),
+                    samples,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct StoreParameter {
+    /// Store the recorded samples in this SQLite results store.
+    #[arg(long, requires = "dut_id")]
+    pub store: Option<PathBuf>,
+
+    /// Id of the device under test, to identify its runs in the results store.
+    #[arg(long)]
+    pub dut_id: Option<String>,
+}
+
+impl StoreParameter {
+    pub fn store(&self, result: &SweepResult) -> Result<()> {
+        if let (Some(store), Some(dut_id)) = (self.store.as_ref(), self.dut_id.as_ref()) {
+            let started_at = result.started_at.unwrap_or_else(SystemTime::now);
+            ResultsStore::open(store)?.insert(dut_id, started_at, &result.points())?;
+        }
+        Ok(())
+    }
+}
+
+/// Key parameters of a run.
+#[derive(Debug, Clone)]
+pub struct TrendPoint {
+    pub run: i64,
+    pub started_at: SystemTime,
+    pub short_circuit_current: Option<Current>,
+    pub forward_voltage: Option<Voltage>,
+    pub resistance: Option<Resistance>,
+}
+
+impl TrendPoint {
+    pub fn new(run: &StoredRun, forward_current: Current) -> Self {
+        Self {
+            run: run.id,
+            started_at: run.started_at,
+            short_circuit_current: analysis::short_circuit_current(&run.samples),
+            forward_voltage: analysis::forward_voltage(&run.samples, forward_current),
+            resistance: analysis::resistance(&run.samples),
+        }
+    }
+
+    fn values(&self) -> [(&'static str, Option<f32>); 3] {
+        [
+            ("isc", self.short_circuit_current.map(|e| e.get::<ampere>())),
+            ("vf", self.forward_voltage.map(|e| e.get::<volt>())),
+            ("resistance", self.resistance.map(|e| e.get::<ohm>())),
+        ]
+    }
+
+    /// Names of the parameters deviating by more than the relative `threshold` from `reference`.
+    pub fn drift(&self, reference: &TrendPoint, threshold: f32) -> Vec<&'static str> {
+        self.values()
+            .into_iter()
+            .zip(reference.values())
+            .filter_map(|((name, value), (_, reference))| {
+                let (value, reference) = (value?, reference?);
+                ((value - reference).abs() > threshold * reference.abs()).then_some(name)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct TrendArguments {
+    /// SQLite results store to read the runs from.
+    #[arg(long)]
+    pub store: PathBuf,
+
+    /// Id of the device under test to track.
+    #[arg(long)]
+    pub dut_id: String,
+
+    /// Current at which the forward voltage is extracted.
+    #[arg(long, default_value = "1 mA")]
+    pub forward_current: Current,
+
+    /// Flag parameters deviating from the first run by more than this fraction.
+    #[arg(long, default_value_t = 0.1)]
+    pub threshold: f32,
+
+    #[arg(long, short = 'o')]
+    pub output: Option<PathBuf>,
+}
+
+impl TrendArguments {
+    pub fn run(&self) -> Result<()> {
+        let runs = ResultsStore::open(&self.store)?.runs(&self.dut_id)?;
+        let points: Vec<_> = runs
+            .iter()
+            .map(|e| TrendPoint::new(e, self.forward_current))
+            .collect();
+        let Some(reference) = points.first() else {
+            Err(Error::NotFound(format!(
+                "No runs of '{}' in the store.",
+                self.dut_id
+            )))?
+        };
+
+        for point in &points {
+            let drift = point.drift(reference, self.threshold);
+            if !drift.is_empty() {
+                eprintln!("Run {} drifted: {}", point.run, drift.join(", "));
+            }
+        }
+
+        match self.output.as_ref() {
+            Some(output) => write_csv(
+                &points,
+                reference,
+                self.threshold,
+                std::fs::File::create(output)?,
+            ),
+            None => write_csv(&points, reference, self.threshold, std::io::stdout()),
+        }
+    }
+}
+
+/// Write the points as CSV with the columns `run`, `started_at` (unix seconds), `isc`, `vf`, `resistance`
+/// and `drift`, listing the parameters deviating from `reference` by more than the relative `threshold`.
+pub fn write_csv(
+    points: &[TrendPoint],
+    reference: &TrendPoint,
+    threshold: f32,
+    output: impl Write,
+) -> Result<()> {
+    #[derive(Serialize)]
+    struct Row {
+        run: i64,
+        started_at: u64,
+        isc: Option<f32>,
+        vf: Option<f32>,
+        resistance: Option<f32>,
+        drift: String,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for point in points {
+        let [(_, isc), (_, vf), (_, resistance)] = point.values();
+        writer.serialize(Row {
+            run: point.run,
+            started_at: point
+                .started_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|e| e.as_secs())
+                .unwrap_or_default(),
+            isc,
+            vf,
+            resistance,
+            drift: point.drift(reference, threshold).join(";"),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of a resistor of `resistance` ohm from -1 V to 2 V.
+    fn resistor(resistance: f32) -> Vec<(Voltage, Current)> {
+        (-2..=4)
+            .map(|step| {
+                let voltage = step as f32 * 0.5;
+                (
+                    Voltage::new::<volt>(voltage),
+                    Current::new::<ampere>(voltage / resistance),
+                )
+            })
+            .collect()
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn returns_the_runs_of_a_dut() {
+        let mut store = ResultsStore::open(":memory:").unwrap();
+        let later = store
+            .insert("diode-7", at(2000), &resistor(1500.0))
+            .unwrap();
+        store.insert("diode-8", at(1500), &resistor(500.0)).unwrap();
+        let earlier = store
+            .insert("diode-7", at(1000), &resistor(1000.0))
+            .unwrap();
+
+        let runs = store.runs("diode-7").unwrap();
+        let ids = runs.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids, [earlier, later]);
+        assert_eq!(runs[0].started_at, at(1000));
+        assert_eq!(runs[0].dut_id, "diode-7");
+        assert_eq!(runs[0].samples, resistor(1000.0));
+        assert!(store.runs("diode-9").unwrap().is_empty());
+    }
+
+    #[test]
+    fn flags_drifted_parameters() {
+        let mut store = ResultsStore::open(":memory:").unwrap();
+        store.insert("r1", at(1000), &resistor(1000.0)).unwrap();
+        store.insert("r1", at(2000), &resistor(1050.0)).unwrap();
+        store.insert("r1", at(3000), &resistor(1500.0)).unwrap();
+
+        let forward_current = Current::new::<ampere>(1e-3);
+        let points = store
+            .runs("r1")
+            .unwrap()
+            .iter()
+            .map(|e| TrendPoint::new(e, forward_current))
+            .collect::<Vec<_>>();
+        let resistance = points[2].resistance.unwrap().get::<ohm>();
+        assert!((resistance - 1500.0).abs() < 0.1);
+        assert!(points[1].drift(&points[0], 0.1).is_empty());
+        assert_eq!(points[2].drift(&points[0], 0.1), ["vf", "resistance"]);
+
+        let mut csv = Vec::new();
+        write_csv(&points, &points[0], 0.1, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv
+            .lines()
+            .filter(|e| !e.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(rows[0], "run,started_at,isc,vf,resistance,drift");
+        assert!(rows[3].starts_with("3,3000,"));
+        assert!(rows[3].ends_with(",vf;resistance"));
+    }
+}
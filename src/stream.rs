@@ -0,0 +1,117 @@
+//! Continuous measurements at a fixed cadence, e.g. for data-logging applications.
+//!
+//! [MicroSmu::stream_measurements] returns a [MeasurementStream], an endless iterator measuring
+//! at the voltage set before, see [MicroSmu::measure_here], with the time since the start of the stream.
+//! Measurements are scheduled on a fixed grid of the interval like in [monitor](crate::monitor),
+//! slots missed by slow measurements or consumers are skipped.
+//!
+//! ```no_run
+//! # fn example(smu: &mut usmu::MicroSmu) -> usmu::Result<()> {
+//! use std::time::Duration;
+//!
+//! use usmu::{Voltage, volt};
+//!
+//! smu.set_voltage(Voltage::new::<volt>(1.0))?;
+//! let mut output = smu.enabled()?;
+//! for measurement in output.stream_measurements(Duration::from_millis(100)).take(100) {
+//!     let measurement = measurement?;
+//!     println!("{:?}: {:?}", measurement.time, measurement.current);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Current, MicroSmu, Result, Time, Voltage,
+    commands::MeasureResponse,
+    timestamp::{Epoch, TimestampSource},
+    transport::Transport,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedMeasurement {
+    /// Time since the start of the stream.
+    pub time: Time,
+    pub voltage: Voltage,
+    pub current: Current,
+}
+
+/// See the [module documentation](self), created by [MicroSmu::stream_measurements].
+pub struct MeasurementStream<'a, T: Transport> {
+    smu: &'a mut MicroSmu<T>,
+    interval: Duration,
+    epoch: Epoch,
+    next: Instant,
+}
+
+impl<'a, T: Transport> MeasurementStream<'a, T> {
+    pub(crate) fn new(smu: &'a mut MicroSmu<T>, interval: Duration) -> Self {
+        Self {
+            smu,
+            interval,
+            epoch: Epoch::now(),
+            next: Instant::now(),
+        }
+    }
+}
+
+impl<T: Transport> Iterator for MeasurementStream<'_, T> {
+    type Item = Result<TimedMeasurement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        sleep(self.next.saturating_duration_since(Instant::now()));
+        let time = self.epoch.elapsed(TimestampSource::Monotonic);
+        let measured = self.smu.measure_here();
+
+        self.next += self.interval;
+        if self.next < Instant::now() {
+            // skip the missed slots instead of catching up with a burst of measurements
+            let behind = Instant::now() - self.next;
+            self.next +=
+                self.interval * (behind.as_nanos() / self.interval.as_nanos().max(1)) as u32;
+        }
+
+        Some(
+            measured.map(|MeasureResponse { voltage, current }| TimedMeasurement {
+                time,
+                voltage,
+                current,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, ampere, test_util::FakeSerialPort, volt};
+
+    use super::*;
+
+    #[test]
+    fn measures_at_the_voltage_set() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:VOL 1")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.001")
+            .expect_query("CH1:MEA:VOL 1", "1.0,0.002");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+
+        let unset = smu.stream_measurements(Duration::ZERO).next().unwrap();
+        assert!(matches!(unset, Err(Error::InvalidState(_))));
+
+        smu.set_voltage(Voltage::new::<volt>(1.0)).unwrap();
+        let measurements = smu
+            .stream_measurements(Duration::from_millis(10))
+            .take(2)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(measurements.len(), 2);
+        assert!(measurements[1].time > measurements[0].time);
+        assert_eq!(measurements[1].current, Current::new::<ampere>(0.002));
+        port.verify();
+    }
+}
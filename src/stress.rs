@@ -0,0 +1,210 @@
+//! Bias stress tests, holding a stress bias and periodically interrupting it for a quick
+//! characterization sweep, e.g. for NBTI-style reliability studies at micro scale.
+//!
+//! The result is a time-resolved series of IV curves over the accumulated stress time.
+
+use std::{
+    io::Write,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Time, Voltage, ampere,
+    record_iv_curve::IvCurveRecordingParameters, schema, second, sweep_result::SweepResult, timing,
+    volt,
+};
+
+#[derive(Debug, Clone)]
+pub struct BiasStressParameters {
+    pub stress_voltage: Voltage,
+    pub stress_current_limit: Current,
+
+    /// Total time the stress bias is applied, excluding the characterizations.
+    pub duration: Time,
+
+    /// Time the stress bias is applied between two characterizations.
+    pub characterization_interval: Time,
+}
+
+/// A characterization after some accumulated stress.
+#[derive(Debug, Clone)]
+pub struct StressPoint {
+    /// Time the stress bias was applied before this characterization.
+    pub stress_time: Time,
+    /// Current measured at the end of the preceding stress period, `None` for the initial characterization.
+    pub stress_current: Option<Current>,
+    pub sweep: SweepResult,
+}
+
+impl BiasStressParameters {
+    pub fn record(
+        &self,
+        smu: &mut MicroSmu,
+        characterization: &IvCurveRecordingParameters,
+    ) -> Result<Vec<StressPoint>> {
+        self.record_with(smu, characterization, |_| {})
+    }
+
+    /// Like [Self::record], but `on_point` is called as soon as a characterization is recorded.
+    ///
+    /// The device is characterized once before the stress and after each stress period.
+    /// The output is disabled afterwards, also on failure.
+    pub fn record_with(
+        &self,
+        smu: &mut MicroSmu,
+        characterization: &IvCurveRecordingParameters,
+        on_point: impl FnMut(&StressPoint),
+    ) -> Result<Vec<StressPoint>> {
+        let points = self.stress(smu, characterization, on_point);
+        if points.is_err() {
+            // the error of the stress is more relevant than a failing disable
+            let _ = smu.disable();
+        }
+        points
+    }
+
+    fn stress(
+        &self,
+        smu: &mut MicroSmu,
+        characterization: &IvCurveRecordingParameters,
+        mut on_point: impl FnMut(&StressPoint),
+    ) -> Result<Vec<StressPoint>> {
+        let duration = timing::duration("duration", self.duration)?;
+        let interval =
+            timing::duration("characterization interval", self.characterization_interval)?;
+        if interval.is_zero() {
+            Err(Error::Configuration(
+                "The characterization interval must be positive.".to_string(),
+            ))?;
+        }
+
+        let mut points = Vec::new();
+        let mut point = StressPoint {
+            stress_time: Time::new::<second>(0.0),
+            stress_current: None,
+            sweep: characterization.record(smu)?,
+        };
+        let mut stressed = Duration::ZERO;
+        loop {
+            on_point(&point);
+            points.push(point);
+            if stressed >= duration {
+                break;
+            }
+
+            let period = interval.min(duration - stressed);
+            let start = Instant::now();
+            smu.set_voltage(self.stress_voltage)?;
+            smu.set_current_limit(self.stress_current_limit)?;
+            smu.enable()?;
+            sleep(period.saturating_sub(start.elapsed()));
+            let stress_current = smu.measure(self.stress_voltage)?.current;
+            stressed += start.elapsed();
+
+            // the characterization disables the output afterwards
+            point = StressPoint {
+                stress_time: Time::new::<second>(stressed.as_secs_f32()),
+                stress_current: Some(stress_current),
+                sweep: characterization.record(smu)?,
+            };
+        }
+
+        Ok(points)
+    }
+}
+
+/// Write the points as CSV with the columns `stress_time` (seconds), `stress_current`, `voltage` and `current`.
+///
+/// The stress current is empty for the initial characterization.
+pub fn write_csv(points: &[StressPoint], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Sample {
+        stress_time: f32,
+        stress_current: Option<f32>,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for point in points {
+        for sample in &point.sweep.samples {
+            writer.serialize(Sample {
+                stress_time: point.stress_time.get::<second>(),
+                stress_current: point.stress_current.map(|e| e.get::<ampere>()),
+                voltage: sample.voltage.get::<volt>(),
+                current: sample.current.get::<ampere>(),
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{milliampere, millisecond, simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    fn characterization() -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    fn parameters(duration: f32, interval: f32) -> BiasStressParameters {
+        BiasStressParameters {
+            stress_voltage: Voltage::new::<volt>(1.0),
+            stress_current_limit: Current::new::<milliampere>(20.0),
+            duration: Time::new::<millisecond>(duration),
+            characterization_interval: Time::new::<millisecond>(interval),
+        }
+    }
+
+    #[test]
+    fn characterizes_after_each_stress_period() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let points = parameters(20.0, 10.0)
+            .record(&mut smu, &characterization())
+            .unwrap();
+
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].stress_current, None);
+        assert!(
+            points
+                .windows(2)
+                .all(|e| e[0].stress_time < e[1].stress_time)
+        );
+        assert!(points[2].stress_time >= Time::new::<millisecond>(20.0));
+        for point in &points[1..] {
+            let current = point.stress_current.unwrap().get::<milliampere>();
+            assert!((current - 1.0).abs() < 0.01, "stress current {current} mA");
+        }
+        assert!(points.iter().all(|e| e.sweep.samples.len() == 3));
+        assert!(!smu.is_enabled());
+
+        let mut csv = Vec::new();
+        write_csv(&points, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv.lines().filter(|e| !e.starts_with('#')).count();
+        assert_eq!(rows, 1 + 3 * 3);
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for (duration, interval) in [(-1.0, 10.0), (20.0, -1.0), (20.0, 0.0)] {
+            let result = parameters(duration, interval).record(
+                &mut simulated_smu(Resistor::ohm(1000.0)),
+                &characterization(),
+            );
+            assert!(matches!(result, Err(Error::Configuration(_))));
+        }
+    }
+}
@@ -0,0 +1,535 @@
+//! Sweep results as files, written by `record_iv_curve` and loaded back as [SweepResult].
+//!
+//! CSV files carry the sweep parameters and the device as comment lines `# KEY: VALUE` before the samples,
+//! e.g. `# current_limit: 0.02 A`. The sample times and the start of the sweep are omitted,
+//! so replayed sweeps reproduce the file byte for byte. JSON files, with the `json` feature, hold the complete result.
+//!
+//! Files of older schema versions lack the metadata, the loaded parameters are then derived from the samples
+//! where possible and the defaults of `record_iv_curve` otherwise.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use crate::{
+    Current, Error, Result, Time, Voltage,
+    adaptive_over_sampling::AdaptiveOverSampling,
+    ampere,
+    auto_range::AutoRange,
+    record_iv_curve::IvCurveRecordingParameters,
+    schema, second,
+    step_refinement::StepRefinement,
+    sweep_result::{COMPLIANCE_THRESHOLD, DeviceIdentity, SweepResult, SweepSample, SweepStatus},
+    volt,
+};
+
+/// Write the result as CSV with the metadata comments and the columns `voltage`, `current`, `set_voltage`
+/// and `compliance`.
+pub fn write_csv(result: &SweepResult, output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Sample {
+        voltage: f32,
+        current: f32,
+        set_voltage: f32,
+        compliance: bool,
+    }
+
+    let mut writer = schema::csv_writer_with_metadata(output, &metadata(result))?;
+    for sample in &result.samples {
+        writer.serialize(Sample {
+            voltage: sample.voltage.get::<volt>(),
+            current: sample.current.get::<ampere>(),
+            set_voltage: sample.set_voltage.get::<volt>(),
+            compliance: sample.compliance,
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn metadata(result: &SweepResult) -> Vec<(&'static str, String)> {
+    let parameters = &result.parameters;
+    let mut metadata = vec![
+        (
+            "start_voltage",
+            format!("{} V", parameters.start_voltage.get::<volt>()),
+        ),
+        (
+            "end_voltage",
+            format!("{} V", parameters.end_voltage.get::<volt>()),
+        ),
+        ("voltage_steps", parameters.voltage_steps.to_string()),
+        (
+            "current_limit",
+            format!("{} A", parameters.current_limit.get::<ampere>()),
+        ),
+        ("over_sampling", parameters.over_sampling.to_string()),
+        ("delay", format!("{} s", parameters.delay.get::<second>())),
+        ("status", status_name(result.status).to_string()),
+    ];
+    let adaptive = &parameters.adaptive_over_sampling;
+    if let Some(below) = adaptive.over_sampling_below {
+        metadata.push(("over_sampling_below", below.to_string()));
+    }
+    if let Some(above) = adaptive.over_sampling_above {
+        metadata.push(("over_sampling_above", above.to_string()));
+    }
+    let refinement = &parameters.step_refinement;
+    if let Some(current_step) = refinement.current_step {
+        metadata.extend([
+            (
+                "refine_current_step",
+                format!("{} A", current_step.get::<ampere>()),
+            ),
+            (
+                "refine_min_voltage_step",
+                format!("{} V", refinement.min_voltage_step.get::<volt>()),
+            ),
+        ]);
+    }
+    if let Some(max_points) = refinement.max_points {
+        metadata.push(("refine_max_points", max_points.to_string()));
+    }
+    let auto_range = &parameters.auto_range;
+    if auto_range.auto_range {
+        metadata.extend([
+            ("auto_range", true.to_string()),
+            (
+                "range_settling",
+                format!("{} s", auto_range.settling.get::<second>()),
+            ),
+            ("range_discard", auto_range.discard.to_string()),
+        ]);
+    }
+    if let Some(port) = result.device.port.as_ref() {
+        metadata.push(("port", port.clone()));
+    }
+    if let Some(serial_number) = result.device.serial_number {
+        metadata.push(("serial_number", serial_number.to_string()));
+    }
+    metadata
+}
+
+fn status_name(status: SweepStatus) -> &'static str {
+    match status {
+        SweepStatus::Completed => "completed",
+        SweepStatus::Aborted => "aborted",
+    }
+}
+
+fn parse_status(status: &str) -> Result<SweepStatus> {
+    match status {
+        "completed" => Ok(SweepStatus::Completed),
+        "aborted" => Ok(SweepStatus::Aborted),
+        status => Err(Error::Data(format!("Unknown sweep status '{status}'."))),
+    }
+}
+
+/// Read a CSV file written by [write_csv] or an older version of `record_iv_curve`.
+pub fn read_csv(mut input: impl Read) -> Result<SweepResult> {
+    let mut content = String::new();
+    input.read_to_string(&mut content)?;
+    let records = schema::read_sweep_csv(content.as_bytes())?;
+
+    let metadata: Vec<(&str, &str)> = content
+        .lines()
+        .map_while(|e| e.strip_prefix('#'))
+        .filter_map(|e| e.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+    let value = |key: &str| metadata.iter().find(|(e, _)| *e == key).map(|(_, e)| *e);
+    fn parse<T: FromStr>(key: &str, value: Option<&str>) -> Result<Option<T>>
+    where
+        T::Err: std::fmt::Display,
+    {
+        value
+            .map(|e| {
+                e.parse()
+                    .map_err(|error| Error::Data(format!("Invalid {key} '{e}': {error}")))
+            })
+            .transpose()
+    }
+
+    let set_voltages: Vec<Voltage> = records
+        .iter()
+        .map(|e| e.set_voltage.unwrap_or(e.voltage))
+        .collect();
+    let defaults = IvCurveRecordingParameters::default();
+    let parameters = IvCurveRecordingParameters {
+        start_voltage: parse("start_voltage", value("start_voltage"))?
+            .or(set_voltages.first().copied())
+            .unwrap_or(defaults.start_voltage),
+        end_voltage: parse("end_voltage", value("end_voltage"))?
+            .or(set_voltages.last().copied())
+            .unwrap_or(defaults.end_voltage),
+        voltage_steps: parse("voltage_steps", value("voltage_steps"))?.unwrap_or(records.len()),
+        current_limit: parse::<Current>("current_limit", value("current_limit"))?
+            .unwrap_or(defaults.current_limit),
+        over_sampling: parse("over_sampling", value("over_sampling"))?
+            .unwrap_or(defaults.over_sampling),
+        delay: parse::<Time>("delay", value("delay"))?.unwrap_or(defaults.delay),
+        adaptive_over_sampling: AdaptiveOverSampling {
+            over_sampling_below: parse("over_sampling_below", value("over_sampling_below"))?,
+            over_sampling_above: parse("over_sampling_above", value("over_sampling_above"))?,
+        },
+        step_refinement: StepRefinement {
+            current_step: parse("refine_current_step", value("refine_current_step"))?,
+            min_voltage_step: parse("refine_min_voltage_step", value("refine_min_voltage_step"))?
+                .unwrap_or(defaults.step_refinement.min_voltage_step),
+            max_points: parse("refine_max_points", value("refine_max_points"))?,
+        },
+        auto_range: AutoRange {
+            auto_range: parse("auto_range", value("auto_range"))?.unwrap_or(false),
+            settling: parse::<Time>("range_settling", value("range_settling"))?
+                .unwrap_or(defaults.auto_range.settling),
+            discard: parse("range_discard", value("range_discard"))?
+                .unwrap_or(defaults.auto_range.discard),
+        },
+    };
+    let status = value("status")
+        .map(parse_status)
+        .transpose()?
+        .unwrap_or(SweepStatus::Completed);
+    let device = DeviceIdentity {
+        port: value("port").map(str::to_string),
+        serial_number: parse("serial_number", value("serial_number"))?,
+    };
+
+    let samples = records
+        .iter()
+        .zip(set_voltages)
+        .map(|(record, set_voltage)| SweepSample {
+            time: Time::new::<second>(0.0),
+            set_voltage,
+            voltage: record.voltage,
+            current: record.current,
+            compliance: record.compliance.unwrap_or_else(|| {
+                record.current.abs() >= parameters.current_limit * COMPLIANCE_THRESHOLD
+            }),
+            range: None,
+        })
+        .collect();
+
+    Ok(SweepResult {
+        parameters,
+        device,
+        started_at: None,
+        status,
+        samples,
+    })
+}
+
+/// Load a sweep result from a CSV file, or a JSON file with the `json` feature, chosen by the file extension.
+pub fn load(path: &Path) -> Result<SweepResult> {
+    let file = File::open(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "json")]
+        Some("json") => json::read_json(file),
+        _ => read_csv(file),
+    }
+}
+
+#[cfg(feature = "json")]
+pub mod json {
+    //! The complete sweep result as JSON document with SI values, e.g.
+    //! `{"schema_version": 2, "parameters": {...}, "device": {...}, "started_at": 1755600000.12, "status": "completed", "samples": [...]}`.
+
+    use std::{
+        io::{Read, Write},
+        time::{Duration, SystemTime},
+    };
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{parse_status, status_name};
+    use crate::{
+        Current, Error, Result, Time, Voltage,
+        adaptive_over_sampling::{AdaptiveOverSampling, OverSamplingThreshold},
+        ampere,
+        auto_range::AutoRange,
+        record_iv_curve::IvCurveRecordingParameters,
+        schema::SCHEMA_VERSION,
+        second,
+        step_refinement::StepRefinement,
+        sweep_result::{DeviceIdentity, SweepResult, SweepSample},
+        volt,
+    };
+
+    #[derive(Serialize, Deserialize)]
+    struct Parameters {
+        start_voltage: f32,
+        end_voltage: f32,
+        voltage_steps: usize,
+        current_limit: f32,
+        over_sampling: u16,
+        delay: f32,
+        /// See [AdaptiveOverSampling], as `CURRENT:RATE`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        over_sampling_below: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        over_sampling_above: Option<String>,
+        /// See [StepRefinement], in SI units.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        refine_current_step: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        refine_min_voltage_step: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        refine_max_points: Option<usize>,
+        /// See [AutoRange], only with [AutoRange::auto_range].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range_settling: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range_discard: Option<usize>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Device {
+        port: Option<String>,
+        serial_number: Option<u32>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Sample {
+        time: f32,
+        set_voltage: f32,
+        voltage: f32,
+        current: f32,
+        compliance: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        range: Option<u8>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Document {
+        schema_version: u32,
+        parameters: Parameters,
+        device: Device,
+        /// Seconds since the UNIX epoch.
+        started_at: Option<f64>,
+        status: String,
+        samples: Vec<Sample>,
+    }
+
+    pub fn write_json(result: &SweepResult, output: impl Write) -> Result<()> {
+        let parameters = &result.parameters;
+        let adaptive = &parameters.adaptive_over_sampling;
+        let refinement = &parameters.step_refinement;
+        let auto_range = &parameters.auto_range;
+        let document = Document {
+            schema_version: SCHEMA_VERSION,
+            parameters: Parameters {
+                start_voltage: parameters.start_voltage.get::<volt>(),
+                end_voltage: parameters.end_voltage.get::<volt>(),
+                voltage_steps: parameters.voltage_steps,
+                current_limit: parameters.current_limit.get::<ampere>(),
+                over_sampling: parameters.over_sampling,
+                delay: parameters.delay.get::<second>(),
+                over_sampling_below: adaptive.over_sampling_below.map(|e| e.to_string()),
+                over_sampling_above: adaptive.over_sampling_above.map(|e| e.to_string()),
+                refine_current_step: refinement.current_step.map(|e| e.get::<ampere>()),
+                refine_min_voltage_step: refinement
+                    .current_step
+                    .map(|_| refinement.min_voltage_step.get::<volt>()),
+                refine_max_points: refinement.max_points,
+                range_settling: auto_range
+                    .auto_range
+                    .then(|| auto_range.settling.get::<second>()),
+                range_discard: auto_range.auto_range.then_some(auto_range.discard),
+            },
+            device: Device {
+                port: result.device.port.clone(),
+                serial_number: result.device.serial_number,
+            },
+            started_at: result.started_at.map(|e| {
+                e.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64()
+            }),
+            status: status_name(result.status).to_string(),
+            samples: result
+                .samples
+                .iter()
+                .map(|e| Sample {
+                    time: e.time.get::<second>(),
+                    set_voltage: e.set_voltage.get::<volt>(),
+                    voltage: e.voltage.get::<volt>(),
+                    current: e.current.get::<ampere>(),
+                    compliance: e.compliance,
+                    range: e.range,
+                })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(output, &document).map_err(|e| Error::Data(e.to_string()))?;
+        Ok(())
+    }
+
+    fn threshold(threshold: Option<String>) -> Result<Option<OverSamplingThreshold>> {
+        threshold
+            .map(|e| {
+                e.parse()
+                    .map_err(|error| Error::Data(format!("Invalid threshold '{e}': {error}")))
+            })
+            .transpose()
+    }
+
+    pub fn read_json(input: impl Read) -> Result<SweepResult> {
+        let document: Document =
+            serde_json::from_reader(input).map_err(|e| Error::Data(e.to_string()))?;
+        if document.schema_version > SCHEMA_VERSION {
+            Err(Error::Data(format!(
+                "The file has schema version {}, but only versions up to {SCHEMA_VERSION} are supported. It was written by a newer version of usmu.",
+                document.schema_version
+            )))?;
+        }
+        let parameters = document.parameters;
+        Ok(SweepResult {
+            parameters: IvCurveRecordingParameters {
+                start_voltage: Voltage::new::<volt>(parameters.start_voltage),
+                end_voltage: Voltage::new::<volt>(parameters.end_voltage),
+                voltage_steps: parameters.voltage_steps,
+                current_limit: Current::new::<ampere>(parameters.current_limit),
+                over_sampling: parameters.over_sampling,
+                delay: Time::new::<second>(parameters.delay),
+                adaptive_over_sampling: AdaptiveOverSampling {
+                    over_sampling_below: threshold(parameters.over_sampling_below)?,
+                    over_sampling_above: threshold(parameters.over_sampling_above)?,
+                },
+                step_refinement: StepRefinement {
+                    current_step: parameters.refine_current_step.map(Current::new::<ampere>),
+                    min_voltage_step: parameters
+                        .refine_min_voltage_step
+                        .map(Voltage::new::<volt>)
+                        .unwrap_or(StepRefinement::default().min_voltage_step),
+                    max_points: parameters.refine_max_points,
+                },
+                auto_range: AutoRange {
+                    auto_range: parameters.range_settling.is_some(),
+                    settling: parameters
+                        .range_settling
+                        .map(Time::new::<second>)
+                        .unwrap_or(AutoRange::default().settling),
+                    discard: parameters
+                        .range_discard
+                        .unwrap_or(AutoRange::default().discard),
+                },
+            },
+            device: DeviceIdentity {
+                port: document.device.port,
+                serial_number: document.device.serial_number,
+            },
+            started_at: document
+                .started_at
+                .and_then(|e| Duration::try_from_secs_f64(e).ok())
+                .map(|e| SystemTime::UNIX_EPOCH + e),
+            status: parse_status(&document.status)?,
+            samples: document
+                .samples
+                .into_iter()
+                .map(|e| SweepSample {
+                    time: Time::new::<second>(e.time),
+                    set_voltage: Voltage::new::<volt>(e.set_voltage),
+                    voltage: Voltage::new::<volt>(e.voltage),
+                    current: Current::new::<ampere>(e.current),
+                    compliance: e.compliance,
+                    range: e.range,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::milliampere;
+
+    use super::*;
+
+    fn result() -> SweepResult {
+        let parameters = IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(-1.0),
+            end_voltage: Voltage::new::<volt>(1.0),
+            voltage_steps: 2,
+            current_limit: Current::new::<milliampere>(10.0),
+            over_sampling: 4,
+            delay: Time::new::<second>(0.5),
+            adaptive_over_sampling: AdaptiveOverSampling {
+                over_sampling_below: Some("0.001 mA:100".parse().unwrap()),
+                over_sampling_above: None,
+            },
+            step_refinement: StepRefinement {
+                current_step: Some(Current::new::<milliampere>(1.0)),
+                max_points: Some(8),
+                ..Default::default()
+            },
+            auto_range: AutoRange {
+                auto_range: true,
+                ..Default::default()
+            },
+        };
+        let sample = |time, voltage, current, compliance| SweepSample {
+            time: Time::new::<second>(time),
+            set_voltage: Voltage::new::<volt>(voltage),
+            voltage: Voltage::new::<volt>(voltage),
+            current: Current::new::<milliampere>(current),
+            compliance,
+            range: None,
+        };
+        SweepResult {
+            parameters,
+            device: DeviceIdentity {
+                port: Some("/dev/ttyACM0".to_string()),
+                serial_number: Some(1234),
+            },
+            started_at: None,
+            status: SweepStatus::Aborted,
+            samples: vec![sample(0.0, -1.0, -1.0, false), sample(0.5, 1.0, 10.0, true)],
+        }
+    }
+
+    #[test]
+    fn reads_written_csv() {
+        let expected = result();
+        let mut csv = Vec::new();
+        write_csv(&expected, &mut csv).unwrap();
+        let loaded = read_csv(csv.as_slice()).unwrap();
+
+        assert_eq!(
+            format!("{:?}", loaded.parameters),
+            format!("{:?}", expected.parameters)
+        );
+        assert_eq!(loaded.device, expected.device);
+        assert_eq!(loaded.status, SweepStatus::Aborted);
+        assert_eq!(loaded.points(), expected.points());
+        assert!(loaded.samples[1].compliance);
+    }
+
+    #[test]
+    fn derives_parameters_of_legacy_csv() {
+        let loaded = read_csv("voltage,current\n-1.0,-0.001\n1.0,0.02\n".as_bytes()).unwrap();
+        assert_eq!(loaded.parameters.voltage_steps, 2);
+        assert_eq!(loaded.parameters.end_voltage.get::<volt>(), 1.0);
+        assert!(loaded.samples[1].compliance);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn reads_written_json() {
+        let expected = SweepResult {
+            started_at: Some(std::time::SystemTime::UNIX_EPOCH),
+            ..result()
+        };
+        let mut json = Vec::new();
+        json::write_json(&expected, &mut json).unwrap();
+        let loaded = json::read_json(json.as_slice()).unwrap();
+
+        assert_eq!(loaded.samples, expected.samples);
+        assert_eq!(loaded.started_at, expected.started_at);
+        assert_eq!(loaded.device, expected.device);
+    }
+}
@@ -0,0 +1,117 @@
+//! Result of an IV sweep together with the context of its recording.
+
+use std::time::SystemTime;
+
+use crate::{Current, Time, Voltage, record_iv_curve::IvCurveRecordingParameters};
+
+/// The device reports no compliance state, hence currents within this fraction of the limit count as compliance.
+pub const COMPLIANCE_THRESHOLD: f32 = 0.99;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepSample {
+    /// Time since the start of the sweep, when the measurement was requested.
+    pub time: Time,
+    /// Voltage set for this point.
+    pub set_voltage: Voltage,
+    pub voltage: Voltage,
+    pub current: Current,
+    /// The current reached the current limit, see [COMPLIANCE_THRESHOLD].
+    pub compliance: bool,
+    /// The current range locked while measuring, see [crate::auto_range], `None` if ranged by the device.
+    pub range: Option<u8>,
+}
+
+impl SweepSample {
+    pub fn point(&self) -> (Voltage, Current) {
+        (self.voltage, self.current)
+    }
+}
+
+/// Identity of the device that recorded a sweep, as far as known without querying it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub port: Option<String>,
+    pub serial_number: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SweepStatus {
+    Completed,
+    /// Ended early by the sample callback, e.g. on a stop request.
+    Aborted,
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    pub parameters: IvCurveRecordingParameters,
+    pub device: DeviceIdentity,
+    /// `None` for results loaded from files without it, see [crate::sweep_file].
+    pub started_at: Option<SystemTime>,
+    pub status: SweepStatus,
+    pub samples: Vec<SweepSample>,
+}
+
+impl SweepResult {
+    /// Measured voltage and current of each sample, e.g. for [crate::analysis].
+    pub fn points(&self) -> Vec<(Voltage, Current)> {
+        self.samples.iter().map(SweepSample::point).collect()
+    }
+
+    /// Whether any sample reached the current limit.
+    pub fn compliance(&self) -> bool {
+        self.samples.iter().any(|e| e.compliance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use crate::{
+        MicroSmu, ampere, milliampere,
+        simulator::{Resistor, SimulatedSmu},
+        volt,
+    };
+
+    use super::*;
+
+    fn parameters(current_limit: f32) -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            end_voltage: Voltage::new::<volt>(2.0),
+            voltage_steps: 5,
+            current_limit: Current::new::<milliampere>(current_limit),
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_sample_context() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let result = parameters(1.0).record(&mut smu).unwrap();
+
+        assert_eq!(result.status, SweepStatus::Completed);
+        assert_eq!(result.samples.len(), 5);
+        assert!(result.samples.windows(2).all(|e| e[0].time <= e[1].time));
+        assert_eq!(result.samples[2].set_voltage.get::<volt>(), 1.0);
+        // 2 V across 1 kOhm exceed the limit of 1 mA
+        assert!(!result.samples[0].compliance);
+        assert!(result.samples[4].compliance);
+        assert!(result.compliance());
+        assert_eq!(result.points().len(), 5);
+        assert!(result.points()[0].1.get::<ampere>().abs() < 1e-6);
+    }
+
+    #[test]
+    fn marks_aborted_sweeps() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let result = parameters(20.0)
+            .record_with(&mut smu, |_, _| ControlFlow::Break(()))
+            .unwrap();
+
+        assert_eq!(result.status, SweepStatus::Aborted);
+        assert_eq!(result.samples.len(), 1);
+        assert!(!result.compliance());
+    }
+}
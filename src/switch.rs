@@ -0,0 +1,188 @@
+//! Measuring multiple devices under test through a switch matrix or multiplexer,
+//! controlled by a user implemented [DutSwitch] backend.
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{
+    MicroSmu, Result, ampere, record_iv_curve::IvCurveRecordingParameters, schema,
+    sweep_result::SweepResult, volt,
+};
+
+pub trait DutSwitch {
+    /// Connect the SMU to `channel`, disconnecting all other channels.
+    fn select(&mut self, channel: usize) -> Result<()>;
+
+    /// Disconnect all channels.
+    fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The IV curve recorded on one channel.
+#[derive(Debug, Clone)]
+pub struct ChannelSweep {
+    pub channel: usize,
+    pub result: SweepResult,
+}
+
+impl IvCurveRecordingParameters {
+    /// Record an IV curve on each of `channels` in sequence.
+    ///
+    /// Channels are only switched while the output is disabled.
+    /// All channels are disconnected afterwards, even if recording fails.
+    pub fn record_channels(
+        &self,
+        smu: &mut MicroSmu,
+        switch: &mut impl DutSwitch,
+        channels: impl IntoIterator<Item = usize>,
+    ) -> Result<Vec<ChannelSweep>> {
+        self.record_channels_with(smu, switch, channels, |_| {})
+    }
+
+    /// Like [Self::record_channels], but `on_channel` is called as soon as the curve of a channel is recorded.
+    pub fn record_channels_with(
+        &self,
+        smu: &mut MicroSmu,
+        switch: &mut impl DutSwitch,
+        channels: impl IntoIterator<Item = usize>,
+        on_channel: impl FnMut(&ChannelSweep),
+    ) -> Result<Vec<ChannelSweep>> {
+        let sweeps = self.sweep_channels(smu, switch, channels, on_channel);
+        let disconnected = switch.disconnect();
+        let sweeps = sweeps?;
+        disconnected?;
+        Ok(sweeps)
+    }
+
+    fn sweep_channels(
+        &self,
+        smu: &mut MicroSmu,
+        switch: &mut impl DutSwitch,
+        channels: impl IntoIterator<Item = usize>,
+        mut on_channel: impl FnMut(&ChannelSweep),
+    ) -> Result<Vec<ChannelSweep>> {
+        let mut sweeps = Vec::new();
+        for channel in channels {
+            smu.disable()?;
+            switch.select(channel)?;
+            let result = self.record(smu)?;
+            let sweep = ChannelSweep { channel, result };
+            on_channel(&sweep);
+            sweeps.push(sweep);
+        }
+        Ok(sweeps)
+    }
+}
+
+/// Write the sweeps as CSV with the columns `channel`, `voltage` and `current`.
+pub fn write_csv(sweeps: &[ChannelSweep], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Sample {
+        channel: usize,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for sweep in sweeps {
+        for sample in &sweep.result.samples {
+            writer.serialize(Sample {
+                channel: sweep.channel,
+                voltage: sample.voltage.get::<volt>(),
+                current: sample.current.get::<ampere>(),
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        Error, Voltage, milliampere,
+        simulator::{Open, Resistor, SimulatedSmu},
+    };
+
+    use super::*;
+
+    /// Multiplexer of resistors of 1 kΩ times the channel number, failing for channels above 3.
+    struct ResistorMux {
+        port: SimulatedSmu,
+        log: Vec<String>,
+    }
+
+    impl DutSwitch for ResistorMux {
+        fn select(&mut self, channel: usize) -> Result<()> {
+            self.log.push(format!("select {channel}"));
+            if channel > 3 {
+                Err(Error::NotFound(format!("No channel {channel}.")))?;
+            }
+            self.port.attach(Resistor::ohm(1000.0 * channel as f32));
+            Ok(())
+        }
+
+        fn disconnect(&mut self) -> Result<()> {
+            self.log.push("disconnect".to_string());
+            self.port.attach(Open);
+            Ok(())
+        }
+    }
+
+    fn setup() -> (MicroSmu, ResistorMux) {
+        let port = SimulatedSmu::new(Open);
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+        let switch = ResistorMux {
+            port,
+            log: Vec::new(),
+        };
+        (smu, switch)
+    }
+
+    fn parameters() -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_each_channel() {
+        let (mut smu, mut switch) = setup();
+        let sweeps = parameters()
+            .record_channels(&mut smu, &mut switch, [1, 2])
+            .unwrap();
+
+        assert_eq!(switch.log, ["select 1", "select 2", "disconnect"]);
+        for (sweep, channel) in sweeps.iter().zip([1, 2]) {
+            assert_eq!(sweep.channel, channel);
+            let current = sweep.result.samples[2].current.get::<milliampere>();
+            assert!((current - 1.0 / channel as f32).abs() < 0.01);
+        }
+        assert!(!smu.is_enabled());
+
+        let mut csv = Vec::new();
+        write_csv(&sweeps, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv.lines().filter(|e| !e.starts_with('#')).count();
+        assert_eq!(rows, 1 + 2 * 3);
+    }
+
+    #[test]
+    fn disconnects_after_a_failure() {
+        let (mut smu, mut switch) = setup();
+        let result = parameters().record_channels(&mut smu, &mut switch, [1, 4, 2]);
+
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        assert_eq!(switch.log, ["select 1", "select 4", "disconnect"]);
+        assert!(!smu.is_enabled());
+    }
+}
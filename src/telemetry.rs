@@ -0,0 +1,221 @@
+//! OpenTelemetry spans for sweeps and individual SCPI exchanges.
+//!
+//! The spans are emitted through the global tracer provider,
+//! hence the application has to install an SDK with an exporter to collect them.
+//! SCPI exchanges running during a sweep are recorded as its child spans.
+//! With a [RetryPolicy](crate::retry::RetryPolicy), each query is a span with the exchange of each attempt as child,
+//! recording the number of attempts and retries.
+
+use opentelemetry::{
+    Context, ContextGuard, KeyValue, global,
+    trace::{Status, TraceContextExt, Tracer},
+};
+
+use crate::{
+    Result, ampere, record_iv_curve::IvCurveRecordingParameters, second, sweep_result::SweepResult,
+    volt,
+};
+
+const TRACER: &str = "usmu";
+
+/// An active span, which ends when dropped.
+pub(crate) struct SpanGuard {
+    _context: ContextGuard,
+}
+
+impl SpanGuard {
+    fn start(name: &'static str, attributes: Vec<KeyValue>) -> Self {
+        let tracer = global::tracer(TRACER);
+        let span = tracer
+            .span_builder(name)
+            .with_attributes(attributes)
+            .start(&tracer);
+        let context = Context::current_with_span(span).attach();
+        Self { _context: context }
+    }
+
+    fn set_attribute(&self, attribute: KeyValue) {
+        Context::current().span().set_attribute(attribute);
+    }
+
+    fn set_result<T>(&self, result: &Result<T>) {
+        if let Err(e) = result {
+            Context::current()
+                .span()
+                .set_status(Status::error(e.to_string()));
+        }
+    }
+
+    /// Mark the span as failed, if the exchange failed.
+    pub(crate) fn finish_exchange<T>(self, result: &Result<T>) {
+        self.set_result(result);
+    }
+
+    /// Record the number of attempts and the failure of the query.
+    pub(crate) fn finish_query<T>(self, attempts: u32, result: &Result<T>) {
+        self.set_attribute(KeyValue::new("usmu.scpi.attempts", i64::from(attempts)));
+        self.set_attribute(KeyValue::new(
+            "usmu.scpi.retries",
+            i64::from(attempts.saturating_sub(1)),
+        ));
+        self.set_result(result);
+    }
+
+    /// Record the number of samples or the failure of the sweep.
+    pub(crate) fn finish_sweep(self, result: &Result<SweepResult>) {
+        if let Ok(sweep) = result {
+            self.set_attribute(KeyValue::new(
+                "usmu.sweep.samples",
+                sweep.samples.len() as i64,
+            ));
+        }
+        self.set_result(result);
+    }
+}
+
+/// Span of a single SCPI exchange, i.e. the transmitted command and its response, if any.
+pub(crate) fn scpi_span(command: &str) -> SpanGuard {
+    SpanGuard::start(
+        "scpi",
+        vec![KeyValue::new(
+            "scpi.command",
+            command.trim_end().to_string(),
+        )],
+    )
+}
+
+/// Span of a query with retries, which includes the SCPI exchange of each attempt.
+pub(crate) fn query_span(command: &str) -> SpanGuard {
+    SpanGuard::start(
+        "scpi.query",
+        vec![KeyValue::new(
+            "scpi.command",
+            command.trim_end().to_string(),
+        )],
+    )
+}
+
+/// Span of a complete sweep, which includes all SCPI exchanges of the sweep.
+pub(crate) fn sweep_span(parameters: &IvCurveRecordingParameters) -> SpanGuard {
+    SpanGuard::start(
+        "sweep",
+        vec![
+            KeyValue::new(
+                "usmu.sweep.start_voltage",
+                f64::from(parameters.start_voltage.get::<volt>()),
+            ),
+            KeyValue::new(
+                "usmu.sweep.end_voltage",
+                f64::from(parameters.end_voltage.get::<volt>()),
+            ),
+            KeyValue::new("usmu.sweep.voltage_steps", parameters.voltage_steps as i64),
+            KeyValue::new(
+                "usmu.sweep.current_limit",
+                f64::from(parameters.current_limit.get::<ampere>()),
+            ),
+            KeyValue::new(
+                "usmu.sweep.over_sampling",
+                i64::from(parameters.over_sampling),
+            ),
+            KeyValue::new(
+                "usmu.sweep.delay",
+                f64::from(parameters.delay.get::<second>()),
+            ),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use opentelemetry::Value;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+
+    use crate::{
+        MicroSmu, Voltage,
+        retry::RetryPolicy,
+        simulator::Resistor,
+        test_util::simulated_smu,
+        test_util::{FakeSerialPort, Fault},
+    };
+
+    use super::*;
+
+    fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+        span.attributes
+            .iter()
+            .find(|e| e.key.as_str() == key)
+            .map(|e| &e.value)
+    }
+
+    fn children<'a>(spans: &'a [SpanData], parent: &SpanData) -> Vec<&'a SpanData> {
+        spans
+            .iter()
+            .filter(|e| e.parent_span_id == parent.span_context.span_id())
+            .collect()
+    }
+
+    #[test]
+    fn records_sweeps_and_retries() {
+        let exporter = InMemorySpanExporter::default();
+        global::set_tracer_provider(
+            SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build(),
+        );
+
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        IvCurveRecordingParameters {
+            // distinguishes the sweep from those of other tests
+            start_voltage: Voltage::new::<volt>(0.125),
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+        .record(&mut smu)
+        .unwrap();
+
+        let port = FakeSerialPort::new();
+        port.expect_query("CH1:MEA:VOL 0.375", "0.375,0.001")
+            .fault(Fault::Garbage(b"#".to_vec()))
+            .expect_query("CH1:MEA:VOL 0.375", "0.375,0.001");
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.set_inter_command_delay(Duration::ZERO);
+        smu.set_retry_policy(Some(RetryPolicy::new(2, Duration::ZERO)));
+        smu.measure(Voltage::new::<volt>(0.375)).unwrap();
+        port.verify();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let sweep = spans
+            .iter()
+            .find(|e| {
+                e.name == "sweep"
+                    && attribute(e, "usmu.sweep.start_voltage") == Some(&Value::F64(0.125))
+            })
+            .unwrap();
+        assert_eq!(attribute(sweep, "usmu.sweep.samples"), Some(&Value::I64(3)));
+        let exchanges = children(&spans, sweep);
+        assert!(exchanges.iter().all(|e| e.name == "scpi"));
+        let measurements = exchanges
+            .iter()
+            .filter_map(|e| attribute(e, "scpi.command"))
+            .filter(|e| e.as_str().starts_with("CH1:MEA:VOL"))
+            .count();
+        assert_eq!(measurements, 3);
+
+        let query = spans
+            .iter()
+            .find(|e| {
+                e.name == "scpi.query"
+                    && attribute(e, "scpi.command") == Some(&Value::from("CH1:MEA:VOL 0.375"))
+            })
+            .unwrap();
+        assert_eq!(attribute(query, "usmu.scpi.attempts"), Some(&Value::I64(2)));
+        assert_eq!(attribute(query, "usmu.scpi.retries"), Some(&Value::I64(1)));
+        let attempts = children(&spans, query);
+        assert_eq!(attempts.len(), 2);
+        assert!(matches!(attempts[0].status, Status::Error { .. }));
+        assert_eq!(attempts[1].status, Status::Unset);
+    }
+}
@@ -0,0 +1,170 @@
+//! Compensation of the thermal drift of the analog front end by an external temperature sensor.
+//!
+//! The measured values are modelled as drifting linearly with the temperature difference `ΔT` to a reference,
+//! `measured = (1 + gain × ΔT) × value + offset × ΔT`, with user supplied gain and offset coefficients per quantity.
+//! With a [TemperatureCompensation], see [MicroSmu::set_temperature_compensation](crate::MicroSmu::set_temperature_compensation),
+//! the sensor is read with each measurement and the drift is removed, e.g. during day-long runs in a changing environment.
+//! The coefficients are given in a TOML or JSON file, in volt, ampere and kelvin:
+//!
+//! ```toml
+//! # °C
+//! reference_temperature = 25.0
+//! voltage_gain = 20e-6
+//! voltage_offset = 5e-6
+//! current_gain = 50e-6
+//! current_offset = 1e-9
+//! ```
+
+#[cfg(any(feature = "config", feature = "json"))]
+use std::path::{Path, PathBuf};
+
+#[cfg(any(feature = "config", feature = "json"))]
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Current, Result, Voltage, ampere, auxiliary::AuxiliarySensor, commands::MeasureResponse, volt,
+};
+#[cfg(any(feature = "config", feature = "json"))]
+use crate::{MicroSmu, auxiliary::FeedSensor, calibration::load_file};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureCoefficients {
+    /// Temperature without drift, in the unit of the sensor.
+    pub reference_temperature: f64,
+    /// Relative change of the voltage per kelvin.
+    #[serde(default)]
+    pub voltage_gain: f64,
+    /// Voltage offset per kelvin.
+    #[serde(default)]
+    pub voltage_offset: f64,
+    /// Relative change of the current per kelvin.
+    #[serde(default)]
+    pub current_gain: f64,
+    /// Current offset in ampere per kelvin.
+    #[serde(default)]
+    pub current_offset: f64,
+}
+
+impl TemperatureCoefficients {
+    #[cfg(any(feature = "config", feature = "json"))]
+    pub fn load(path: &Path) -> Result<Self> {
+        load_file(path, "temperature coefficients")
+    }
+
+    /// Remove the drift at `temperature` from `response`.
+    pub fn compensate(&self, response: MeasureResponse, temperature: f64) -> MeasureResponse {
+        let delta = temperature - self.reference_temperature;
+        let compensate = |value: f32, gain: f64, offset: f64| {
+            ((f64::from(value) - offset * delta) / (1.0 + gain * delta)) as f32
+        };
+        MeasureResponse {
+            voltage: Voltage::new::<volt>(compensate(
+                response.voltage.get::<volt>(),
+                self.voltage_gain,
+                self.voltage_offset,
+            )),
+            current: Current::new::<ampere>(compensate(
+                response.current.get::<ampere>(),
+                self.current_gain,
+                self.current_offset,
+            )),
+        }
+    }
+}
+
+/// The coefficients with the sensor of the temperature.
+pub struct TemperatureCompensation {
+    pub coefficients: TemperatureCoefficients,
+    pub sensor: Box<dyn AuxiliarySensor + Send>,
+}
+
+impl TemperatureCompensation {
+    pub fn new(
+        coefficients: TemperatureCoefficients,
+        sensor: impl AuxiliarySensor + Send + 'static,
+    ) -> Self {
+        Self {
+            coefficients,
+            sensor: Box::new(sensor),
+        }
+    }
+
+    /// Read the sensor and remove the drift from `response`, fails if the sensor fails.
+    pub fn apply(&mut self, response: MeasureResponse) -> Result<MeasureResponse> {
+        let temperature = self.sensor.read()?;
+        Ok(self.coefficients.compensate(response, temperature))
+    }
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+#[derive(Debug, Clone, Default, Parser)]
+pub struct TemperatureCompensationParameter {
+    /// Compensate the thermal drift with the temperature coefficients in this TOML or JSON file.
+    #[arg(long, requires = "temperature_feed")]
+    pub temperature_coefficients: Option<PathBuf>,
+
+    /// Address of a TCP server reporting the temperature as CSV lines, e.g. "localhost:5000".
+    #[arg(long, requires = "temperature_coefficients")]
+    pub temperature_feed: Option<String>,
+
+    /// Column of the temperature in the lines of the feed.
+    #[arg(long, default_value_t = 0)]
+    pub temperature_column: usize,
+}
+
+#[cfg(any(feature = "config", feature = "json"))]
+impl TemperatureCompensationParameter {
+    pub fn configure(&self, smu: &mut MicroSmu) -> Result<()> {
+        let (Some(coefficients), Some(feed)) = (
+            self.temperature_coefficients.as_ref(),
+            self.temperature_feed.as_ref(),
+        ) else {
+            return Ok(());
+        };
+        let coefficients = TemperatureCoefficients::load(coefficients)?;
+        let sensor = FeedSensor::connect("temperature", feed.as_str(), self.temperature_column)?;
+        smu.set_temperature_compensation(Some(TemperatureCompensation::new(coefficients, sensor)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        MicroSmu,
+        auxiliary::FnSensor,
+        simulator::{Resistor, SimulatedSmu},
+    };
+
+    use super::*;
+
+    #[test]
+    fn compensates_drift() {
+        let coefficients = TemperatureCoefficients {
+            reference_temperature: 25.0,
+            voltage_gain: 0.01,
+            voltage_offset: 0.0,
+            current_gain: 0.0,
+            current_offset: 1e-4,
+        };
+        let temperature = Arc::new(Mutex::new(25.0));
+        let sensor_temperature = temperature.clone();
+        let sensor = FnSensor::new("temperature", move || {
+            Ok(*sensor_temperature.lock().unwrap())
+        });
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        smu.set_temperature_compensation(Some(TemperatureCompensation::new(coefficients, sensor)));
+        smu.enable().unwrap();
+
+        let at_reference = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert_eq!(at_reference.voltage, Voltage::new::<volt>(1.0));
+
+        *temperature.lock().unwrap() = 35.0;
+        let drifted = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert!((drifted.voltage.get::<volt>() - 1.0 / 1.1).abs() < 1e-6);
+        assert!(drifted.current.get::<ampere>().abs() < 1e-6);
+    }
+}
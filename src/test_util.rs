@@ -0,0 +1,487 @@
+//! Test utilities to validate command sequences without hardware.
+//!
+//! [FakeSerialPort] is scripted with the expected commands and their responses,
+//! and passed to [MicroSmu::new](crate::MicroSmu::new) in place of a real port:
+//!
+//! ```
+//! # use usmu::{MicroSmu, test_util::FakeSerialPort};
+//! let port = FakeSerialPort::new();
+//! port.expect("CH1:ENA")
+//!     .expect_query("*IDN?", "uSMU version 1.0 ID:42");
+//!
+//! let mut smu = MicroSmu::new(Box::new(port.clone()));
+//! smu.enable().unwrap();
+//! assert_eq!(smu.get_identity().unwrap(), 42);
+//! port.verify();
+//! ```
+//!
+//! Responses can be disturbed with a [Fault] to exercise the error handling deterministically:
+//!
+//! ```
+//! # use usmu::{MicroSmu, test_util::{Fault, FakeSerialPort}};
+//! let port = FakeSerialPort::new();
+//! port.expect_query("*IDN?", "uSMU version 1.0 ID:42")
+//!     .fault(Fault::Truncate(8));
+//!
+//! let mut smu = MicroSmu::new(Box::new(port.clone()));
+//! assert!(smu.get_identity().is_err());
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::{
+    MicroSmu,
+    simulator::{DutModel, SimulatedSmu},
+};
+
+/// A device simulating `dut`, without the delay between commands to keep tests fast.
+pub fn simulated_smu(dut: impl DutModel + 'static) -> MicroSmu {
+    let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(dut)));
+    smu.set_inter_command_delay(Duration::ZERO);
+    smu
+}
+
+/// A scripted serial port, clones share the same script.
+///
+/// Commands are compared line by line without the terminating newline,
+/// unexpected commands panic.
+/// Reading without pending response data fails with [io::ErrorKind::TimedOut], like a real port.
+#[derive(Clone)]
+pub struct FakeSerialPort {
+    state: Arc<Mutex<State>>,
+}
+
+/// A disturbance of the communication, applied when the scripted command is received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The response becomes readable only after this delay,
+    /// reads time out if it exceeds the port timeout.
+    Delay(Duration),
+    /// Only the first bytes of the response are transmitted, without the terminating newline.
+    Truncate(usize),
+    /// These bytes are transmitted before the response.
+    Garbage(Vec<u8>),
+    /// The device disconnects instead of answering, all further reads and writes fail.
+    Disconnect,
+}
+
+struct State {
+    expectations: VecDeque<Expectation>,
+    /// The incomplete line written so far.
+    line: Vec<u8>,
+    readable: VecDeque<u8>,
+    /// Delayed response data and the instant it becomes readable.
+    delayed: Option<(Instant, Vec<u8>)>,
+    connected: bool,
+    received: Vec<String>,
+    baud_rate: u32,
+    timeout: Duration,
+}
+
+struct Expectation {
+    command: String,
+    response: Option<String>,
+    faults: Vec<Fault>,
+}
+
+impl Default for FakeSerialPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeSerialPort {
+    pub fn new() -> Self {
+        let state = State {
+            expectations: VecDeque::new(),
+            line: Vec::new(),
+            readable: VecDeque::new(),
+            delayed: None,
+            connected: true,
+            received: Vec::new(),
+            baud_rate: 9600,
+            timeout: Duration::from_millis(1000),
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Expect `command`, which is not answered.
+    pub fn expect(&self, command: &str) -> &Self {
+        self.state().expectations.push_back(Expectation {
+            command: command.to_string(),
+            response: None,
+            faults: Vec::new(),
+        });
+        self
+    }
+
+    /// Expect `command`, which is answered with the `response` line.
+    pub fn expect_query(&self, command: &str, response: &str) -> &Self {
+        self.state().expectations.push_back(Expectation {
+            command: command.to_string(),
+            response: Some(format!("{response}\n")),
+            faults: Vec::new(),
+        });
+        self
+    }
+
+    /// Apply `fault` to the most recently scripted command.
+    ///
+    /// Panics, if no command is scripted.
+    pub fn fault(&self, fault: Fault) -> &Self {
+        self.state()
+            .expectations
+            .back_mut()
+            .expect("no command scripted to apply the fault to")
+            .faults
+            .push(fault);
+        self
+    }
+
+    /// Disconnect the port immediately, all further reads and writes fail.
+    pub fn disconnect(&self) -> &Self {
+        self.state().connected = false;
+        self
+    }
+
+    /// Reconnect a disconnected port, discarding any pending data.
+    pub fn reconnect(&self) -> &Self {
+        let mut state = self.state();
+        state.connected = true;
+        state.line.clear();
+        state.readable.clear();
+        state.delayed = None;
+        drop(state);
+        self
+    }
+
+    /// Make `data` readable immediately, e.g. to emulate unsolicited output.
+    pub fn push_readable(&self, data: &str) -> &Self {
+        self.state().readable.extend(data.bytes());
+        self
+    }
+
+    /// All commands received so far.
+    pub fn received(&self) -> Vec<String> {
+        self.state().received.clone()
+    }
+
+    /// Panics, if not all expected commands were received.
+    pub fn verify(&self) {
+        let state = self.state();
+        let missing: Vec<_> = state.expectations.iter().map(|e| &e.command).collect();
+        assert!(
+            missing.is_empty(),
+            "expected commands not received: {missing:?}"
+        );
+    }
+}
+
+impl State {
+    fn receive(&mut self, command: String) {
+        let expectation = self
+            .expectations
+            .pop_front()
+            .unwrap_or_else(|| panic!("unexpected command '{command}', no command expected"));
+        assert_eq!(
+            command, expectation.command,
+            "unexpected command '{command}', expected '{}'",
+            expectation.command
+        );
+        self.received.push(command);
+
+        let mut response = expectation.response.unwrap_or_default().into_bytes();
+        let mut delay = None;
+        for fault in expectation.faults {
+            match fault {
+                Fault::Delay(duration) => delay = Some(duration),
+                Fault::Truncate(length) => response.truncate(length),
+                Fault::Garbage(garbage) => {
+                    response.splice(0..0, garbage);
+                }
+                Fault::Disconnect => {
+                    self.connected = false;
+                    return;
+                }
+            }
+        }
+        match delay {
+            Some(delay) => self.delayed = Some((Instant::now() + delay, response)),
+            None => self.readable.extend(response),
+        }
+    }
+
+    fn check_connected(&self) -> io::Result<()> {
+        if self.connected {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "disconnected"))
+        }
+    }
+}
+
+impl Read for FakeSerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        state.check_connected()?;
+        if state.readable.is_empty()
+            && let Some((at, _)) = state.delayed
+        {
+            let wait = at.saturating_duration_since(Instant::now());
+            if wait > state.timeout {
+                sleep(state.timeout);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "delayed response"));
+            }
+            sleep(wait);
+            let (_, response) = state.delayed.take().unwrap();
+            state.readable.extend(response);
+        }
+        if state.readable.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no response"));
+        }
+        let count = buf.len().min(state.readable.len());
+        for (target, source) in buf.iter_mut().zip(state.readable.drain(..count)) {
+            *target = source;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for FakeSerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state();
+        state.check_connected()?;
+        for &byte in buf {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut state.line);
+                state.receive(String::from_utf8_lossy(&line).into_owned());
+            } else {
+                state.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for FakeSerialPort {
+    fn name(&self) -> Option<String> {
+        Some("fake".to_string())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.state().baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.state().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.state().timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.state().readable.len() as u32)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        let mut state = self.state();
+        if matches!(buffer_to_clear, ClearBuffer::Input | ClearBuffer::All) {
+            state.readable.clear();
+        }
+        if matches!(buffer_to_clear, ClearBuffer::Output | ClearBuffer::All) {
+            state.line.clear();
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serialport::SerialPort;
+
+    use crate::{
+        Error, MicroSmu, ampere,
+        test_util::{FakeSerialPort, Fault},
+        volt,
+    };
+
+    #[test]
+    fn measure_parses_response() {
+        let port = FakeSerialPort::new();
+        port.expect_query("CH1:MEA:VOL 0.5", "0.4998,0.00123");
+
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        let measurement = smu.measure(crate::Voltage::new::<volt>(0.5)).unwrap();
+
+        assert_eq!(measurement.voltage.get::<volt>(), 0.4998);
+        assert_eq!(measurement.current.get::<ampere>(), 0.00123);
+        port.verify();
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected command")]
+    fn unexpected_command_panics() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:DIS");
+
+        MicroSmu::new(Box::new(port)).enable().unwrap();
+    }
+
+    #[test]
+    fn garbage_fails_parsing() {
+        let port = FakeSerialPort::new();
+        port.expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Garbage(b"\xff#".to_vec()));
+
+        assert!(MicroSmu::new(Box::new(port)).get_identity().is_err());
+    }
+
+    #[test]
+    fn errors_report_the_exchange() {
+        let port = FakeSerialPort::new();
+        port.expect_query("*IDN?", "uSMU firmware 2.0");
+
+        let error = MicroSmu::new(Box::new(port)).get_identity().unwrap_err();
+        let Error::Exchange {
+            command, response, ..
+        } = &error
+        else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(command, "*IDN?");
+        assert_eq!(response, "uSMU firmware 2.0\n");
+        assert!(
+            error
+                .to_string()
+                .contains(r#"(command "*IDN?", response "uSMU firmware 2.0\n")"#)
+        );
+    }
+
+    #[test]
+    fn delay_beyond_timeout_times_out() {
+        let mut port = FakeSerialPort::new();
+        port.set_timeout(Duration::from_millis(100)).unwrap();
+        port.expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Delay(Duration::from_millis(20)))
+            .expect_query("*IDN?", "uSMU version 1.0 ID:42")
+            .fault(Fault::Delay(Duration::from_secs(1)));
+
+        let mut smu = MicroSmu::new(Box::new(port));
+        assert_eq!(smu.get_identity().unwrap(), 42);
+        assert!(smu.get_identity().is_err());
+    }
+
+    #[test]
+    fn disconnect_fails_until_reconnected() {
+        let port = FakeSerialPort::new();
+        port.expect("CH1:ENA")
+            .fault(Fault::Disconnect)
+            .expect("CH1:ENA");
+
+        let mut smu = MicroSmu::new(Box::new(port.clone()));
+        smu.enable().unwrap();
+        assert!(smu.enable().is_err());
+        port.reconnect();
+        smu.enable().unwrap();
+        port.verify();
+    }
+}
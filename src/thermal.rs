@@ -0,0 +1,249 @@
+//! Thermal sweeps, recording an IV curve at each of a series of temperature setpoints,
+//! e.g. for activation energy (Arrhenius) studies.
+//!
+//! The temperature is controlled by a user implemented [TemperatureController] backend.
+
+use std::{io::Write, thread::sleep, time::Instant};
+
+use serde::Serialize;
+use uom::si::{temperature_interval, thermodynamic_temperature::kelvin};
+
+use crate::{
+    Error, MicroSmu, Result, Temperature, TemperatureInterval, Time, ampere,
+    record_iv_curve::IvCurveRecordingParameters, schema, sweep_result::SweepResult, timing, volt,
+};
+
+pub trait TemperatureController {
+    /// Set the target temperature, without waiting for it to be reached.
+    fn set_temperature(&mut self, setpoint: Temperature) -> Result<()>;
+
+    /// Read the current temperature of the device under test.
+    fn temperature(&mut self) -> Result<Temperature>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ThermalSweepParameters {
+    pub setpoints: Vec<Temperature>,
+
+    /// Maximum deviation from the setpoint considered stable.
+    pub tolerance: TemperatureInterval,
+
+    /// Time the temperature must stay within the tolerance before recording.
+    pub settling_time: Time,
+
+    /// Fail, if the temperature does not stabilize within this time after changing the setpoint.
+    pub timeout: Time,
+
+    /// Time between temperature readings while waiting for stability.
+    pub poll_interval: Time,
+}
+
+/// The IV curve recorded at one setpoint.
+#[derive(Debug, Clone)]
+pub struct ThermalSweepPoint {
+    pub setpoint: Temperature,
+    /// Temperature read before the IV curve was recorded.
+    pub temperature_before: Temperature,
+    /// Temperature read after the IV curve was recorded.
+    pub temperature_after: Temperature,
+    pub sweep: SweepResult,
+}
+
+impl ThermalSweepParameters {
+    pub fn record(
+        &self,
+        controller: &mut impl TemperatureController,
+        smu: &mut MicroSmu,
+        sweep: &IvCurveRecordingParameters,
+    ) -> Result<Vec<ThermalSweepPoint>> {
+        self.record_with(controller, smu, sweep, |_| {})
+    }
+
+    /// Like [Self::record], but `on_point` is called as soon as the curve of a setpoint is recorded.
+    pub fn record_with(
+        &self,
+        controller: &mut impl TemperatureController,
+        smu: &mut MicroSmu,
+        sweep: &IvCurveRecordingParameters,
+        mut on_point: impl FnMut(&ThermalSweepPoint),
+    ) -> Result<Vec<ThermalSweepPoint>> {
+        let mut points = Vec::with_capacity(self.setpoints.len());
+        for &setpoint in &self.setpoints {
+            controller.set_temperature(setpoint)?;
+            let temperature_before = self.wait_for_stability(controller, setpoint)?;
+            let result = sweep.record(smu)?;
+            let temperature_after = controller.temperature()?;
+
+            let point = ThermalSweepPoint {
+                setpoint,
+                temperature_before,
+                temperature_after,
+                sweep: result,
+            };
+            on_point(&point);
+            points.push(point);
+        }
+        Ok(points)
+    }
+
+    /// Wait until the temperature stayed within the tolerance for the settling time and return the last reading.
+    fn wait_for_stability(
+        &self,
+        controller: &mut impl TemperatureController,
+        setpoint: Temperature,
+    ) -> Result<Temperature> {
+        let tolerance = self.tolerance.get::<temperature_interval::kelvin>();
+        let settling_time = timing::duration("settling time", self.settling_time)?;
+        let timeout = timing::duration("timeout", self.timeout)?;
+        let poll_interval = timing::duration("poll interval", self.poll_interval)?;
+
+        let start = Instant::now();
+        let mut stable_since = None;
+        loop {
+            let temperature = controller.temperature()?;
+            let now = Instant::now();
+            if (temperature.get::<kelvin>() - setpoint.get::<kelvin>()).abs() <= tolerance {
+                let stable_since = *stable_since.get_or_insert(now);
+                if now - stable_since >= settling_time {
+                    return Ok(temperature);
+                }
+            } else {
+                stable_since = None;
+            }
+
+            if now - start > timeout {
+                Err(Error::Timeout(format!(
+                    "Temperature did not stabilize at {} K within {} s, last reading {} K.",
+                    setpoint.get::<kelvin>(),
+                    timeout.as_secs_f32(),
+                    temperature.get::<kelvin>()
+                )))?;
+            }
+            sleep(poll_interval);
+        }
+    }
+}
+
+/// Write the points as CSV with the columns `setpoint`, `temperature` (kelvin), `voltage` and `current`.
+///
+/// The temperature of each sample is the mean of the readings before and after its curve.
+pub fn write_csv(points: &[ThermalSweepPoint], output: impl Write) -> Result<()> {
+    #[derive(Serialize)]
+    struct Sample {
+        setpoint: f32,
+        temperature: f32,
+        voltage: f32,
+        current: f32,
+    }
+
+    let mut writer = schema::csv_writer(output)?;
+    for point in points {
+        let temperature = (point.temperature_before.get::<kelvin>()
+            + point.temperature_after.get::<kelvin>())
+            / 2.0;
+        for sample in &point.sweep.samples {
+            writer.serialize(Sample {
+                setpoint: point.setpoint.get::<kelvin>(),
+                temperature,
+                voltage: sample.voltage.get::<volt>(),
+                current: sample.current.get::<ampere>(),
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Voltage, millisecond, simulator::Resistor, test_util::simulated_smu};
+
+    use super::*;
+
+    /// Controller approaching the setpoint by half of the remaining difference per reading.
+    struct FakeController {
+        setpoint: f32,
+        temperature: f32,
+    }
+
+    impl TemperatureController for FakeController {
+        fn set_temperature(&mut self, setpoint: Temperature) -> Result<()> {
+            self.setpoint = setpoint.get::<kelvin>();
+            Ok(())
+        }
+
+        fn temperature(&mut self) -> Result<Temperature> {
+            self.temperature += (self.setpoint - self.temperature) / 2.0;
+            Ok(Temperature::new::<kelvin>(self.temperature))
+        }
+    }
+
+    fn sweep() -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            start_voltage: Voltage::new::<volt>(0.0),
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    fn parameters(setpoints: &[f32], timeout: f32) -> ThermalSweepParameters {
+        ThermalSweepParameters {
+            setpoints: setpoints
+                .iter()
+                .map(|&e| Temperature::new::<kelvin>(e))
+                .collect(),
+            tolerance: TemperatureInterval::new::<temperature_interval::kelvin>(0.5),
+            settling_time: Time::new::<millisecond>(0.0),
+            timeout: Time::new::<millisecond>(timeout),
+            poll_interval: Time::new::<millisecond>(0.0),
+        }
+    }
+
+    #[test]
+    fn records_a_curve_per_setpoint() {
+        let mut controller = FakeController {
+            setpoint: 300.0,
+            temperature: 300.0,
+        };
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let points = parameters(&[310.0, 320.0], 1000.0)
+            .record(&mut controller, &mut smu, &sweep())
+            .unwrap();
+
+        assert_eq!(points.len(), 2);
+        for (point, setpoint) in points.iter().zip([310.0, 320.0]) {
+            assert_eq!(point.setpoint.get::<kelvin>(), setpoint);
+            assert!((point.temperature_before.get::<kelvin>() - setpoint).abs() <= 0.5);
+            assert_eq!(point.sweep.samples.len(), 3);
+        }
+
+        let mut csv = Vec::new();
+        write_csv(&points, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv.lines().filter(|e| !e.starts_with('#')).count();
+        assert_eq!(rows, 1 + 2 * 3);
+    }
+
+    #[test]
+    fn fails_if_the_temperature_does_not_stabilize() {
+        // the readings oscillate around the setpoint beyond the tolerance
+        struct Oscillating(f32);
+        impl TemperatureController for Oscillating {
+            fn set_temperature(&mut self, _setpoint: Temperature) -> Result<()> {
+                Ok(())
+            }
+            fn temperature(&mut self) -> Result<Temperature> {
+                self.0 = -self.0;
+                Ok(Temperature::new::<kelvin>(310.0 + self.0))
+            }
+        }
+
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        let result = parameters(&[310.0], 10.0).record(&mut Oscillating(1.0), &mut smu, &sweep());
+        assert!(matches!(result, Err(Error::Timeout(_))));
+        let result = parameters(&[310.0], -1.0).record(&mut Oscillating(1.0), &mut smu, &sweep());
+        assert!(matches!(result, Err(Error::Configuration(_))));
+    }
+}
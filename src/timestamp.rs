@@ -0,0 +1,132 @@
+//! Timestamps of acquisitions, relative to a shared [Epoch].
+//!
+//! Samples carry the time since the epoch of their acquisition. Sharing one epoch between the acquisitions
+//! of several devices puts their samples on a common timeline, so the datasets can be merged.
+//! Processes align via the wall-clock time of the epoch, see [Epoch::from_system_time].
+
+use std::time::{Instant, SystemTime};
+
+use clap::ValueEnum;
+
+use crate::{Error, Result, Time, second};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TimestampSource {
+    /// Monotonic clock, unaffected by adjustments of the system time, e.g. by NTP.
+    #[default]
+    Monotonic,
+    /// System time, following its adjustments, e.g. to correlate with other logs over long runs.
+    WallClock,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Epoch {
+    instant: Instant,
+    system_time: SystemTime,
+}
+
+impl Epoch {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            system_time: SystemTime::now(),
+        }
+    }
+
+    /// The epoch at the wall-clock time `system_time`, e.g. agreed on by several processes.
+    ///
+    /// Fails with [Error::Configuration] if the monotonic clock cannot represent it,
+    /// depending on the platform e.g. for an epoch before the boot of the system.
+    pub fn from_system_time(system_time: SystemTime) -> Result<Self> {
+        let now = Self::now();
+        let instant = match now.system_time.duration_since(system_time) {
+            Ok(past) => now.instant.checked_sub(past),
+            Err(e) => now.instant.checked_add(e.duration()),
+        };
+        let instant = instant.ok_or_else(|| {
+            Error::Configuration(
+                "The epoch is out of the range of the monotonic clock, e.g. before the system booted."
+                    .to_string(),
+            )
+        })?;
+        Ok(Self {
+            instant,
+            system_time,
+        })
+    }
+
+    pub fn system_time(&self) -> SystemTime {
+        self.system_time
+    }
+
+    /// Time since the epoch, negative before it.
+    ///
+    /// [Time] is single precision, which resolves about 0.5 ms an hour after the epoch
+    /// and 8 ms a day after it, see [Self::elapsed_seconds] for long runs.
+    pub fn elapsed(&self, source: TimestampSource) -> Time {
+        Time::new::<second>(self.elapsed_seconds(source) as f32)
+    }
+
+    /// Seconds since the epoch in double precision, negative before it.
+    pub fn elapsed_seconds(&self, source: TimestampSource) -> f64 {
+        match source {
+            TimestampSource::Monotonic => {
+                let now = Instant::now();
+                match now.checked_duration_since(self.instant) {
+                    Some(elapsed) => elapsed.as_secs_f64(),
+                    None => -(self.instant - now).as_secs_f64(),
+                }
+            }
+            TimestampSource::WallClock => {
+                match SystemTime::now().duration_since(self.system_time) {
+                    Ok(elapsed) => elapsed.as_secs_f64(),
+                    Err(e) => -e.duration().as_secs_f64(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn aligns_to_a_system_time() {
+        let epoch = Epoch::from_system_time(SystemTime::now() - Duration::from_secs(10)).unwrap();
+        for source in [TimestampSource::Monotonic, TimestampSource::WallClock] {
+            let elapsed = epoch.elapsed(source).get::<second>();
+            assert!((elapsed - 10.0).abs() < 0.5, "{source:?}: {elapsed}");
+        }
+
+        let future = Epoch::from_system_time(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        let elapsed = future.elapsed(TimestampSource::Monotonic).get::<second>();
+        assert!((elapsed + 10.0).abs() < 0.5, "{elapsed}");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn rejects_epochs_out_of_the_monotonic_clock() {
+        // the monotonic clock counts seconds since the boot in 64 bit
+        let ancient = SystemTime::UNIX_EPOCH - Duration::from_secs(i64::MAX as u64);
+        let epoch = Epoch::from_system_time(ancient);
+        assert!(matches!(epoch, Err(Error::Configuration(_))));
+    }
+
+    #[test]
+    fn resolves_milliseconds_far_from_the_epoch() {
+        // in single precision, the seconds resolve only 62.5 ms ten days from the epoch
+        let far = SystemTime::now() + Duration::from_secs(10 * 86400);
+        let epoch = Epoch::from_system_time(far).unwrap();
+        let before = epoch.elapsed_seconds(TimestampSource::Monotonic);
+        std::thread::sleep(Duration::from_millis(2));
+        let after = epoch.elapsed_seconds(TimestampSource::Monotonic);
+        assert!(
+            (0.002..0.05).contains(&(after - before)),
+            "{}",
+            after - before
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Model of the time the uSMU takes for commands and measurements, to estimate the duration of runs before starting them.
+//!
+//! Each command costs a fixed round trip over the serial port, and each measurement additionally
+//! the conversion of its over-sampled ADC samples.
+//! The defaults are rough values of a uSMU attached by USB, estimates are meant for planning,
+//! not for scheduling.
+
+use std::time::Duration;
+
+use crate::{Error, Result, Time, second};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingModel {
+    /// Round trip of a command, i.e. transmission, the pause the device needs after it, parsing and the response.
+    pub command_time: Duration,
+    /// Conversion time of a single ADC sample, measurements take one per over-sampling.
+    pub sample_time: Duration,
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        Self {
+            command_time: Duration::from_millis(60),
+            sample_time: Duration::from_millis(1),
+        }
+    }
+}
+
+impl TimingModel {
+    /// Duration of `count` commands without measurement.
+    pub fn commands(&self, count: u32) -> Duration {
+        self.command_time * count
+    }
+
+    /// Duration of a measurement averaging `over_sampling` samples.
+    pub fn measurement(&self, over_sampling: u16) -> Duration {
+        self.command_time + self.sample_time * u32::from(over_sampling)
+    }
+}
+
+/// `time` of the parameter `name` as [Duration],
+/// failing with [Error::Configuration] if it is negative or not finite.
+pub fn duration(name: &str, time: Time) -> Result<Duration> {
+    let seconds = time.get::<second>();
+    Duration::try_from_secs_f32(seconds).map_err(|_| {
+        Error::Configuration(format!(
+            "The {name} must be a non-negative time, got {seconds} s."
+        ))
+    })
+}
+
+/// Human readable duration, e.g. `12.3 s`, `4 min 05 s` or `2 h 03 min`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{:.1} s", duration.as_secs_f32()),
+        60..3600 => format!("{} min {:02} s", seconds / 60, seconds % 60),
+        _ => format!("{} h {:02} min", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record_iv_curve::IvCurveRecordingParameters;
+
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_millis(12345)), "12.3 s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4 min 05 s");
+        assert_eq!(format_duration(Duration::from_secs(7380)), "2 h 03 min");
+        let model = TimingModel::default();
+        assert_eq!(model.measurement(10), Duration::from_millis(70));
+        let sweep = IvCurveRecordingParameters::default();
+        assert_eq!(
+            sweep.estimated_duration(&model),
+            Duration::from_millis(6800)
+        );
+    }
+}
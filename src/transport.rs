@@ -0,0 +1,218 @@
+//! The connection carrying the SCPI lines between the host and the uSMU.
+//!
+//! [MicroSmu](crate::MicroSmu) talks to the device through a [Transport], by default the serial port of the device.
+//! Other connections, e.g. a TCP bridge to a remote serial port, a PTY or an in-memory mock,
+//! implement [Transport] and are passed to [MicroSmu::with_transport](crate::MicroSmu::with_transport).
+//! [TcpStream] is a [Transport], e.g. for `ser2net` bridges.
+//!
+//! The utilities of this crate, e.g. the sweeps, take the serial port device `MicroSmu<Box<dyn SerialPort>>`,
+//! only the device itself is generic over its [Transport].
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use serialport::{ClearBuffer, SerialPort};
+
+use crate::Result;
+
+pub trait Transport: Send {
+    /// Transmit `line`, including its line terminator.
+    fn write_line(&mut self, line: &str) -> Result<()>;
+
+    /// Receive a line into `line`, including its line terminator.
+    /// Fails, if the line is not complete within the [Self::timeout].
+    /// The data received so far is kept in `line` on failure.
+    fn read_line(&mut self, line: &mut String) -> Result<()>;
+
+    /// Time to wait for a line, `Duration::MAX` if reads block indefinitely.
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()>;
+
+    /// Name of the connection, e.g. the serial port, if available.
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// Drop data received but not read yet, e.g. a late response to a query that timed out.
+    fn discard_input(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Read a line from `reader`, see [Transport::read_line].
+fn read_line(reader: impl Read, line: &mut String) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    match reader.read_line(line) {
+        // sockets report timeouts as `WouldBlock`, which is no lost connection, see `Error::is_disconnect`
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Err(io::Error::from(ErrorKind::TimedOut))?,
+        read => read?,
+    };
+    Ok(())
+}
+
+impl<P: SerialPort + ?Sized> Transport for Box<P> {
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        self.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_line(&mut self, line: &mut String) -> Result<()> {
+        read_line(self, line)
+    }
+
+    fn timeout(&self) -> Duration {
+        SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout)?;
+        Ok(())
+    }
+
+    fn name(&self) -> Option<String> {
+        SerialPort::name(self.as_ref())
+    }
+
+    fn discard_input(&mut self) -> Result<()> {
+        self.clear(ClearBuffer::Input)?;
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        self.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_line(&mut self, line: &mut String) -> Result<()> {
+        read_line(self, line)
+    }
+
+    fn timeout(&self) -> Duration {
+        // without a timeout, reads block indefinitely
+        self.read_timeout().ok().flatten().unwrap_or(Duration::MAX)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    fn name(&self) -> Option<String> {
+        self.peer_addr().ok().map(|e| e.to_string())
+    }
+
+    fn discard_input(&mut self) -> Result<()> {
+        self.set_nonblocking(true)?;
+        let mut buffer = [0; 256];
+        let discarded = loop {
+            match self.read(&mut buffer) {
+                // a closed connection is reported by the next read
+                Ok(0) => break Ok(()),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        discarded?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use scpi_client::{impl_scpi_request, impl_scpi_serialize};
+
+    use crate::{
+        Current, MicroSmu, Voltage,
+        commands::IdentityResponse,
+        milliampere,
+        retry::RetryPolicy,
+        simulator::{Resistor, SimulatedSmu},
+        volt,
+    };
+
+    use super::*;
+
+    /// Answer the requests received on `stream` by `device`.
+    fn serve(mut stream: TcpStream, mut device: SimulatedSmu) {
+        let mut buffer = [0; 256];
+        while let Ok(count @ 1..) = stream.read(&mut buffer) {
+            device.write_all(&buffer[..count]).unwrap();
+            let mut response = Vec::new();
+            // the simulated device times out once all responses are read
+            let _ = device.read_to_end(&mut response);
+            stream.write_all(&response).unwrap();
+        }
+    }
+
+    /// Enabling the output expecting a response, which the device never sends.
+    struct UnansweredRequest;
+    impl_scpi_serialize!(UnansweredRequest, ["CH1:ENA"]);
+    impl_scpi_request!(UnansweredRequest, IdentityResponse);
+
+    #[test]
+    fn measures_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve(
+                stream,
+                SimulatedSmu::new(Resistor::ohm(1000.0)).with_uid(42),
+            );
+        });
+
+        let mut smu = MicroSmu::with_transport(TcpStream::connect(address).unwrap());
+        smu.set_timeout(Duration::from_millis(1000)).unwrap();
+        assert_eq!(smu.port_name(), Some(address.to_string()));
+        assert_eq!(smu.get_identity().unwrap(), 42);
+        smu.set_current_limit(Current::new::<milliampere>(20.0))
+            .unwrap();
+        smu.enable().unwrap();
+        let measured = smu.measure(Voltage::new::<volt>(1.0)).unwrap();
+        assert!((measured.current.get::<milliampere>() - 1.0).abs() < 1e-3);
+
+        // the device does not answer commands
+        smu.set_timeout(Duration::from_millis(100)).unwrap();
+        let Err(error) = smu.query(UnansweredRequest) else {
+            panic!("the command was answered");
+        };
+        assert!(!error.is_disconnect());
+        drop(smu);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn discards_late_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut requests = BufReader::new(stream.try_clone().unwrap()).lines();
+            for (uid, delay) in [(1, 100), (2, 0)] {
+                assert_eq!(requests.next().unwrap().unwrap(), "*IDN?");
+                thread::sleep(Duration::from_millis(delay));
+                writeln!(stream, "uSMU version 1.0 ID:{uid}").unwrap();
+            }
+        });
+
+        let mut smu = MicroSmu::with_transport(TcpStream::connect(address).unwrap());
+        smu.set_inter_command_delay(Duration::ZERO);
+        smu.set_timeout(Duration::from_millis(50)).unwrap();
+        smu.set_retry_policy(Some(RetryPolicy::new(2, Duration::from_millis(200))));
+        // the late response to the timed out attempt is not taken for the response to the retry
+        assert_eq!(smu.get_identity().unwrap(), 2);
+        drop(smu);
+        server.join().unwrap();
+    }
+}
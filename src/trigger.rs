@@ -0,0 +1,492 @@
+//! Synchronization of sweeps with external light sources, shutters or other instruments.
+//!
+//! A [Trigger] is either awaited or emitted before each sweep or each point of a sweep,
+//! see [IvCurveRecordingParameters::record_triggered].
+//! Triggers are available via the handshake lines of a serial port ([SerialLineTrigger]),
+//! TCP messages ([TcpTrigger]) and, with the `gpio` feature, Linux GPIO character devices ([gpio::GpioTrigger]).
+//!
+//! For orchestration by external software, an [Acquisition] splits a measurement into an explicit
+//! arm, trigger and fetch step.
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+pub mod gpio;
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    ops::ControlFlow,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use serialport::SerialPort;
+
+use crate::{
+    Current, Error, MicroSmu, Result, Time, Voltage, capture::TriggerCondition,
+    commands::MeasureResponse, monitor::MonitorSample, record_iv_curve::IvCurveRecordingParameters,
+    second, sweep_result::SweepResult, volt,
+};
+
+const DEFAULT_PULSE_WIDTH: Duration = Duration::from_millis(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+pub trait Trigger {
+    /// Block until the trigger is received.
+    fn wait(&mut self) -> Result<()>;
+
+    /// Send a trigger.
+    fn emit(&mut self) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerScope {
+    /// Trigger once before the sweep.
+    Sweep,
+    /// Trigger before measuring each point, after the voltage is set and the delay passed.
+    Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerDirection {
+    /// Wait for an external trigger.
+    Wait,
+    /// Emit a trigger to an external instrument.
+    Emit,
+}
+
+fn timed_out(timeout: Duration) -> Error {
+    Error::Timeout(format!("No trigger received within {timeout:?}."))
+}
+
+impl IvCurveRecordingParameters {
+    /// Like [Self::record], but synchronized with `trigger`.
+    pub fn record_triggered(
+        &self,
+        smu: &mut MicroSmu,
+        trigger: &mut (impl Trigger + ?Sized),
+        scope: TriggerScope,
+        direction: TriggerDirection,
+    ) -> Result<SweepResult> {
+        let mut fire = || match direction {
+            TriggerDirection::Wait => trigger.wait(),
+            TriggerDirection::Emit => trigger.emit(),
+        };
+        match scope {
+            TriggerScope::Sweep => {
+                fire()?;
+                self.record(smu)
+            }
+            TriggerScope::Point => {
+                self.record_with_hooks(smu, fire, |_, _| ControlFlow::Continue(()))
+            }
+        }
+    }
+}
+
+/// Trigger on the handshake lines of a serial port,
+/// received on a rising edge of CTS (clear to send) and emitted as pulse on RTS (request to send).
+pub struct SerialLineTrigger {
+    port: Box<dyn SerialPort>,
+    pulse_width: Duration,
+    timeout: Option<Duration>,
+}
+
+impl SerialLineTrigger {
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        Self {
+            port,
+            pulse_width: DEFAULT_PULSE_WIDTH,
+            timeout: None,
+        }
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        let port = serialport::new(path, 9600).open()?;
+        Ok(Self::new(port))
+    }
+
+    /// Duration of emitted pulses, 1ms by default.
+    pub fn with_pulse_width(mut self, pulse_width: Duration) -> Self {
+        self.pulse_width = pulse_width;
+        self
+    }
+
+    /// Fail waiting with [Error::Timeout] if no trigger is received within `timeout`,
+    /// waits indefinitely by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Trigger for SerialLineTrigger {
+    fn wait(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let mut previous = self.port.read_clear_to_send()?;
+        loop {
+            let current = self.port.read_clear_to_send()?;
+            if current && !previous {
+                return Ok(());
+            }
+            if let Some(timeout) = self.timeout
+                && start.elapsed() >= timeout
+            {
+                Err(timed_out(timeout))?;
+            }
+            previous = current;
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn emit(&mut self) -> Result<()> {
+        self.port.write_request_to_send(true)?;
+        sleep(self.pulse_width);
+        self.port.write_request_to_send(false)?;
+        Ok(())
+    }
+}
+
+/// Trigger via line based TCP messages, any received line is a trigger.
+pub struct TcpTrigger {
+    stream: BufReader<TcpStream>,
+    message: String,
+    timeout: Option<Duration>,
+}
+
+impl TcpTrigger {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            message: "TRIGGER".to_string(),
+            timeout: None,
+        }
+    }
+
+    /// Connect to an instrument listening on `address`.
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self::new(TcpStream::connect(address)?))
+    }
+
+    /// Wait for an instrument connecting to `address`.
+    pub fn accept(address: impl ToSocketAddrs) -> Result<Self> {
+        let (stream, _) = TcpListener::bind(address)?.accept()?;
+        Ok(Self::new(stream))
+    }
+
+    /// Line sent when emitting a trigger, `TRIGGER` by default.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    /// Fail waiting with [Error::Timeout] if no trigger is received within `timeout`,
+    /// waits indefinitely by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Trigger for TcpTrigger {
+    fn wait(&mut self) -> Result<()> {
+        self.stream.get_ref().set_read_timeout(self.timeout)?;
+        let mut line = String::new();
+        let read = self
+            .stream
+            .read_line(&mut line)
+            .map_err(|e| match e.kind() {
+                // the kind of a timed out read depends on the platform
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                    timed_out(self.timeout.unwrap_or_default())
+                }
+                _ => e.into(),
+            })?;
+        if read == 0 {
+            Err(Error::External("Trigger connection closed.".to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self) -> Result<()> {
+        let stream = self.stream.get_mut();
+        writeln!(stream, "{}", self.message)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+/// A triggered measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fetched {
+    pub response: MeasureResponse,
+    /// When the measurement was requested from the device.
+    pub requested_at: Instant,
+    /// When the response was received.
+    pub completed_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AcquisitionState {
+    Idle,
+    Armed,
+    Triggered(Fetched),
+}
+
+/// Arm → trigger → fetch workflow, to control precisely when the measurement is taken relative to other instruments.
+///
+/// Arming applies the bias and enables the output ahead of time, so triggering only takes the measurement.
+/// The trigger is either a software trigger ([Self::trigger]), an external [Trigger] ([Self::trigger_external])
+/// or a [TriggerCondition] on continuously taken measurements ([Self::trigger_on]).
+/// After fetching the measurement, the acquisition is armed again.
+pub struct Acquisition<'a> {
+    smu: &'a mut MicroSmu,
+    voltage: Voltage,
+    state: AcquisitionState,
+}
+
+impl<'a> Acquisition<'a> {
+    pub fn new(smu: &'a mut MicroSmu) -> Self {
+        Self {
+            smu,
+            voltage: Voltage::new::<volt>(0.0),
+            state: AcquisitionState::Idle,
+        }
+    }
+
+    pub fn arm(
+        &mut self,
+        voltage: Voltage,
+        current_limit: Current,
+        over_sampling: u16,
+    ) -> Result<()> {
+        self.smu.set_voltage(voltage)?;
+        self.smu.set_current_limit(current_limit)?;
+        self.smu.set_over_sample_rate(over_sampling)?;
+        self.smu.enable()?;
+        self.voltage = voltage;
+        self.state = AcquisitionState::Armed;
+        Ok(())
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.state == AcquisitionState::Armed
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        matches!(self.state, AcquisitionState::Triggered(_))
+    }
+
+    fn ensure_armed(&self) -> Result<()> {
+        match self.state {
+            AcquisitionState::Armed => Ok(()),
+            AcquisitionState::Idle => Err(Error::InvalidState(
+                "The acquisition is not armed.".to_string(),
+            ))?,
+            AcquisitionState::Triggered(_) => Err(Error::InvalidState(
+                "The acquisition was triggered and not fetched yet.".to_string(),
+            ))?,
+        }
+    }
+
+    fn measure(&mut self) -> Result<Fetched> {
+        let requested_at = Instant::now();
+        let response = self.smu.measure(self.voltage)?;
+        Ok(Fetched {
+            response,
+            requested_at,
+            completed_at: Instant::now(),
+        })
+    }
+
+    /// Software trigger, take the measurement now.
+    pub fn trigger(&mut self) -> Result<()> {
+        self.ensure_armed()?;
+        self.state = AcquisitionState::Triggered(self.measure()?);
+        Ok(())
+    }
+
+    /// Wait for the external `trigger`, then take the measurement.
+    pub fn trigger_external(&mut self, trigger: &mut (impl Trigger + ?Sized)) -> Result<()> {
+        self.ensure_armed()?;
+        trigger.wait()?;
+        self.trigger()
+    }
+
+    /// Measure continuously until `condition` is met, the measurement meeting it is the triggered one.
+    ///
+    /// Fails, if the condition is not met within `timeout`, the acquisition stays armed.
+    pub fn trigger_on(&mut self, condition: TriggerCondition, timeout: Duration) -> Result<()> {
+        self.ensure_armed()?;
+        let start = Instant::now();
+        let sample = |fetched: &Fetched| MonitorSample {
+            time: Time::new::<second>((fetched.requested_at - start).as_secs_f32()),
+            voltage: fetched.response.voltage,
+            current: fetched.response.current,
+            offset: None,
+        };
+
+        let mut previous = None;
+        loop {
+            let fetched = self.measure()?;
+            let current = sample(&fetched);
+            if condition.is_met(previous.as_ref(), &current) {
+                self.state = AcquisitionState::Triggered(fetched);
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                Err(Error::Timeout(format!(
+                    "The trigger condition was not met within {timeout:?}."
+                )))?;
+            }
+            previous = Some(current);
+        }
+    }
+
+    /// Return the triggered measurement and re-arm.
+    pub fn fetch(&mut self) -> Result<Fetched> {
+        match self.state {
+            AcquisitionState::Triggered(fetched) => {
+                self.state = AcquisitionState::Armed;
+                Ok(fetched)
+            }
+            _ => Err(Error::InvalidState(
+                "The acquisition was not triggered.".to_string(),
+            ))?,
+        }
+    }
+
+    /// Disable the output, discarding a triggered but not fetched measurement.
+    pub fn abort(&mut self) -> Result<()> {
+        self.state = AcquisitionState::Idle;
+        self.smu.disable()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        ampere,
+        simulator::{Resistor, SimulatedSmu},
+        test_util::{FakeSerialPort, simulated_smu},
+    };
+
+    fn sweep() -> IvCurveRecordingParameters {
+        IvCurveRecordingParameters {
+            voltage_steps: 3,
+            over_sampling: 1,
+            ..Default::default()
+        }
+    }
+
+    /// A trigger connected to an instrument on localhost, and the instrument side.
+    fn connected() -> (TcpTrigger, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let trigger = TcpTrigger::connect(listener.local_addr().unwrap())
+            .unwrap()
+            .with_timeout(Duration::from_millis(200));
+        let (instrument, _) = listener.accept().unwrap();
+        (trigger, instrument)
+    }
+
+    #[test]
+    fn waits_per_scope() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        for (scope, triggers) in [(TriggerScope::Point, 3), (TriggerScope::Sweep, 1)] {
+            let (mut trigger, mut instrument) = connected();
+            instrument
+                .write_all("TRIGGER\n".repeat(triggers).as_bytes())
+                .unwrap();
+            let result = sweep()
+                .record_triggered(&mut smu, &mut trigger, scope, TriggerDirection::Wait)
+                .unwrap();
+            assert_eq!(result.samples.len(), 3);
+            // all triggers are consumed
+            assert!(matches!(trigger.wait(), Err(Error::Timeout(_))));
+
+            let (mut trigger, mut instrument) = connected();
+            instrument
+                .write_all("TRIGGER\n".repeat(triggers - 1).as_bytes())
+                .unwrap();
+            let result =
+                sweep().record_triggered(&mut smu, &mut trigger, scope, TriggerDirection::Wait);
+            assert!(matches!(result, Err(Error::Timeout(_))));
+            assert!(!smu.is_enabled());
+        }
+    }
+
+    #[test]
+    fn emits_per_scope() {
+        let mut smu = simulated_smu(Resistor::ohm(1000.0));
+        for (scope, triggers) in [(TriggerScope::Point, 3), (TriggerScope::Sweep, 1)] {
+            let (trigger, mut instrument) = connected();
+            let mut trigger = trigger.with_message("GO");
+            sweep()
+                .record_triggered(&mut smu, &mut trigger, scope, TriggerDirection::Emit)
+                .unwrap();
+            drop(trigger);
+
+            let mut received = String::new();
+            instrument.read_to_string(&mut received).unwrap();
+            assert_eq!(received, "GO\n".repeat(triggers));
+        }
+    }
+
+    #[test]
+    fn serial_line_trigger_times_out() {
+        let mut trigger = SerialLineTrigger::new(Box::new(FakeSerialPort::new()))
+            .with_timeout(Duration::from_millis(10));
+        assert!(matches!(trigger.wait(), Err(Error::Timeout(_))));
+        trigger.emit().unwrap();
+    }
+
+    #[test]
+    fn arms_triggers_and_fetches() {
+        let mut smu = MicroSmu::new(Box::new(SimulatedSmu::new(Resistor::ohm(1000.0))));
+        let mut acquisition = Acquisition::new(&mut smu);
+        assert!(acquisition.trigger().is_err());
+
+        acquisition
+            .arm(Voltage::new::<volt>(1.0), Current::new::<ampere>(0.01), 1)
+            .unwrap();
+        assert!(acquisition.is_armed());
+        assert!(acquisition.fetch().is_err());
+
+        acquisition.trigger().unwrap();
+        assert!(acquisition.trigger().is_err());
+        let fetched = acquisition.fetch().unwrap();
+        assert!((fetched.response.current.get::<ampere>() - 0.001).abs() < 1e-5);
+        assert!(fetched.requested_at <= fetched.completed_at);
+
+        acquisition
+            .trigger_on(
+                TriggerCondition::CurrentAbove(Current::new::<ampere>(0.0005)),
+                Duration::from_secs(1),
+            )
+            .unwrap();
+        assert!(acquisition.is_triggered());
+        assert!(
+            acquisition
+                .trigger_on(
+                    TriggerCondition::CurrentAbove(Current::new::<ampere>(0.0005)),
+                    Duration::ZERO,
+                )
+                .is_err()
+        );
+        acquisition.fetch().unwrap();
+        assert!(
+            acquisition
+                .trigger_on(
+                    TriggerCondition::CurrentBelow(Current::new::<ampere>(0.0)),
+                    Duration::ZERO,
+                )
+                .is_err()
+        );
+        assert!(acquisition.is_armed());
+
+        acquisition.abort().unwrap();
+        assert!(!acquisition.is_armed());
+    }
+}
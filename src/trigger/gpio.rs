@@ -0,0 +1,92 @@
+//! Triggers on GPIO lines of the Linux character device interface (`/dev/gpiochipN`).
+
+use std::{path::Path, thread::sleep, time::Duration};
+
+use gpio_cdev::{Chip, EventRequestFlags, LineEventHandle, LineHandle, LineRequestFlags};
+
+use crate::{Error, Result, trigger::DEFAULT_PULSE_WIDTH, trigger::Trigger};
+
+const CONSUMER: &str = "usmu";
+
+/// A GPIO line, which is either an input receiving triggers on rising edges
+/// or an output emitting triggers as high pulses.
+pub struct GpioTrigger {
+    line: Line,
+    pulse_width: Duration,
+}
+
+enum Line {
+    Input(LineEventHandle),
+    Output(LineHandle),
+}
+
+impl GpioTrigger {
+    pub fn input(chip: impl AsRef<Path>, line: u32) -> Result<Self> {
+        let handle = Chip::new(chip)
+            .and_then(|mut e| e.get_line(line))
+            .and_then(|e| {
+                e.events(
+                    LineRequestFlags::INPUT,
+                    EventRequestFlags::RISING_EDGE,
+                    CONSUMER,
+                )
+            })
+            .map_err(|e| {
+                Error::External(format!("Failed to request GPIO input line {line}: {e}"))
+            })?;
+        Ok(Self {
+            line: Line::Input(handle),
+            pulse_width: DEFAULT_PULSE_WIDTH,
+        })
+    }
+
+    pub fn output(chip: impl AsRef<Path>, line: u32) -> Result<Self> {
+        let handle = Chip::new(chip)
+            .and_then(|mut e| e.get_line(line))
+            .and_then(|e| e.request(LineRequestFlags::OUTPUT, 0, CONSUMER))
+            .map_err(|e| {
+                Error::External(format!("Failed to request GPIO output line {line}: {e}"))
+            })?;
+        Ok(Self {
+            line: Line::Output(handle),
+            pulse_width: DEFAULT_PULSE_WIDTH,
+        })
+    }
+
+    /// Duration of emitted pulses, 1ms by default.
+    pub fn with_pulse_width(mut self, pulse_width: Duration) -> Self {
+        self.pulse_width = pulse_width;
+        self
+    }
+}
+
+impl Trigger for GpioTrigger {
+    fn wait(&mut self) -> Result<()> {
+        let Line::Input(handle) = &mut self.line else {
+            Err(Error::Configuration(
+                "Cannot wait for a trigger on a GPIO output line.".to_string(),
+            ))?
+        };
+        handle
+            .get_event()
+            .map_err(|e| Error::External(format!("Failed to wait for GPIO trigger: {e}")))?;
+        Ok(())
+    }
+
+    fn emit(&mut self) -> Result<()> {
+        let Line::Output(handle) = &self.line else {
+            Err(Error::Configuration(
+                "Cannot emit a trigger on a GPIO input line.".to_string(),
+            ))?
+        };
+        let set = |value| {
+            handle
+                .set_value(value)
+                .map_err(|e| Error::External(format!("Failed to emit GPIO trigger: {e}")))
+        };
+        set(1)?;
+        sleep(self.pulse_width);
+        set(0)?;
+        Ok(())
+    }
+}
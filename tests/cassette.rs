@@ -0,0 +1,47 @@
+//! Cassette tests, replaying recorded device communication through the CLI
+//! and asserting the output is byte-identical to the expected output.
+//!
+//! Cassettes are recorded with the `--record-cassette <file>` option of the CLI.
+//! `record_iv_curve.cassette` is a synthetic capture of the CLI against the simulated diode
+//! (`--simulate diode`), not a hardware reference: it pins the replay of the protocol,
+//! not the behaviour of a real device.
+//! Set `UPDATE_GOLDEN=1` to rewrite the expected outputs after intended changes.
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+fn cassette(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/cassettes")
+        .join(name)
+}
+
+fn assert_golden(output: &Path, golden: &Path) {
+    let output = std::fs::read_to_string(output).unwrap();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(golden, &output).unwrap();
+    }
+    let golden = std::fs::read_to_string(golden).unwrap();
+    assert_eq!(output, golden);
+}
+
+#[test]
+fn record_iv_curve() {
+    let output = std::env::temp_dir().join(format!("usmu-cassette-{}.csv", std::process::id()));
+    let cassette_path = cassette("record_iv_curve.cassette");
+    let arguments = usmu::record_iv_curve::CommandlineArguments::parse_from([
+        "record_iv_curve".as_ref(),
+        "--replay-cassette".as_ref(),
+        cassette_path.as_os_str(),
+        "-n".as_ref(),
+        "11".as_ref(),
+        "-o".as_ref(),
+        output.as_os_str(),
+    ]);
+
+    arguments.run().unwrap();
+
+    assert_golden(&output, &cassette("record_iv_curve.csv"));
+    std::fs::remove_file(output).unwrap();
+}
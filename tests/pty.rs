@@ -0,0 +1,154 @@
+//! Loopback tests running the complete `MicroSmu` stack against an emulated firmware
+//! on the other end of a pseudo-terminal, covering the timing and buffering of the serial port.
+#![cfg(unix)]
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle, sleep},
+    time::Duration,
+};
+
+use serialport::{SerialPort, SerialPortInfo, SerialPortType, TTYPort};
+use usmu::{
+    Current, MicroSmu, Time, Voltage, ampere, milliampere,
+    record_iv_curve::IvCurveRecordingParameters, second, sweep_result::SweepStatus, volt,
+};
+
+const UID: u32 = 1234;
+const RESISTANCE: f32 = 1000.0;
+
+/// Emulation of the uSMU firmware with a resistor connected to the output.
+struct Firmware {
+    port: TTYPort,
+    /// Answer in two chunks with a pause in between, like a slow device.
+    fragmented: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl Firmware {
+    fn respond(&mut self, command: &str) -> Option<String> {
+        if command == "*IDN?" {
+            return Some(format!("uSMU version 1.0 ID:{UID}"));
+        }
+        let voltage: f32 = command.strip_prefix("CH1:MEA:VOL ")?.parse().ok()?;
+        Some(format!("{voltage},{}", voltage / RESISTANCE))
+    }
+
+    fn run(mut self) {
+        let mut line = Vec::new();
+        let mut buffer = [0; 64];
+        while !self.stop.load(Ordering::Relaxed) {
+            let count = match self.port.read(&mut buffer) {
+                Ok(count) => count,
+                Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                Err(_) => break,
+            };
+            for &byte in &buffer[..count] {
+                if byte != b'\n' {
+                    line.push(byte);
+                    continue;
+                }
+                let command = String::from_utf8(std::mem::take(&mut line)).unwrap();
+                if let Some(response) = self.respond(&command) {
+                    let response = format!("{response}\n");
+                    if self.fragmented {
+                        let (head, tail) = response.split_at(response.len() / 2);
+                        self.port.write_all(head.as_bytes()).unwrap();
+                        sleep(Duration::from_millis(20));
+                        self.port.write_all(tail.as_bytes()).unwrap();
+                    } else {
+                        self.port.write_all(response.as_bytes()).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Loopback {
+    smu: MicroSmu,
+    stop: Arc<AtomicBool>,
+    firmware: Option<JoinHandle<()>>,
+    _device: TTYPort,
+}
+
+impl Loopback {
+    fn start(fragmented: bool) -> Self {
+        let (host, device) = TTYPort::pair().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let firmware = Firmware {
+            port: host,
+            fragmented,
+            stop: stop.clone(),
+        };
+        let firmware = thread::spawn(move || firmware.run());
+
+        let port = SerialPortInfo {
+            port_name: device.name().unwrap(),
+            port_type: SerialPortType::Unknown,
+        };
+        let smu = MicroSmu::open(port).unwrap();
+
+        Self {
+            smu,
+            stop,
+            firmware: Some(firmware),
+            _device: device,
+        }
+    }
+}
+
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(firmware) = self.firmware.take() {
+            firmware.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn identity() {
+    let mut loopback = Loopback::start(false);
+    assert_eq!(loopback.smu.get_identity().unwrap(), UID);
+}
+
+#[test]
+fn fragmented_response() {
+    let mut loopback = Loopback::start(true);
+    let measurement = loopback.smu.measure(Voltage::new::<volt>(0.5)).unwrap();
+    assert_eq!(measurement.voltage.get::<volt>(), 0.5);
+    assert_eq!(measurement.current.get::<ampere>(), 0.5 / RESISTANCE);
+}
+
+#[test]
+fn sweep() {
+    let mut loopback = Loopback::start(false);
+    let parameters = IvCurveRecordingParameters {
+        start_voltage: Voltage::new::<volt>(-1.0),
+        end_voltage: Voltage::new::<volt>(1.0),
+        voltage_steps: 5,
+        current_limit: Current::new::<milliampere>(20.0),
+        over_sampling: 1,
+        delay: Time::new::<second>(0.0),
+        adaptive_over_sampling: Default::default(),
+        step_refinement: Default::default(),
+        auto_range: Default::default(),
+    };
+
+    let result = parameters.record(&mut loopback.smu).unwrap();
+
+    assert_eq!(result.samples.len(), 5);
+    assert_eq!(result.status, SweepStatus::Completed);
+    for sample in result.samples {
+        assert_eq!(
+            sample.current.get::<ampere>(),
+            sample.voltage.get::<volt>() / RESISTANCE
+        );
+        assert!(!sample.compliance);
+    }
+}
@@ -0,0 +1,163 @@
+//! Golden transcripts of the wire protocol.
+//!
+//! Every request is checked against the exact line transmitted to the firmware
+//! and the responses are checked against lines as sent by the firmware (hardware version 10),
+//! so that any protocol change is caught immediately.
+
+use usmu::{
+    Current, Voltage, ampere,
+    commands::{
+        CurrentRange, DifferentialConversionRequest, DifferentialConversionResponse,
+        DisableRequest, EepromAddress, EnableRequest, EnableVoltageCalibrationModeRequest,
+        IdentityRequest, IdentityResponse, LockCurrentRangeAndClearCalibrationRequest,
+        MeasureRequest, MeasureResponse, ReadEepromRequest, ReadEepromResponse, ResetRequest,
+        SetCurrentLimitDacRequest, SetCurrentLimitRequest, SetOverSampleRateRequest,
+        SetVoltageDacRequest, SetVoltageRequest, WriteCurrentLimitCalibrationRequest,
+        WriteCurrentLimitDacCalibrationRequest, WriteEepromRequest,
+        WriteVoltageAdcCalibrationRequest, WriteVoltageDacCalibrationRequest, from_wire, to_wire,
+    },
+    milliampere,
+    scpi::ScpiSerialize,
+    volt,
+};
+
+fn assert_wire(request: impl ScpiSerialize, expected: &str) {
+    assert_eq!(to_wire(&request), expected);
+}
+
+#[test]
+fn output_commands() {
+    assert_wire(EnableRequest, "CH1:ENA\n");
+    assert_wire(DisableRequest, "CH1:DIS\n");
+    assert_wire(
+        SetVoltageRequest {
+            voltage: Voltage::new::<volt>(-1.25),
+        },
+        "CH1:VOL -1.25\n",
+    );
+    assert_wire(
+        SetCurrentLimitRequest::new(Current::new::<milliampere>(20.0)),
+        "CH1:CUR 20\n",
+    );
+    assert_wire(
+        SetCurrentLimitRequest::new(Current::new::<milliampere>(0.5)),
+        "CH1:CUR 0.5\n",
+    );
+    assert_wire(SetOverSampleRateRequest { samples: 25 }, "CH1:OSR 25\n");
+}
+
+#[test]
+fn measurement_commands() {
+    assert_wire(
+        MeasureRequest {
+            voltage: Voltage::new::<volt>(0.5),
+        },
+        "CH1:MEA:VOL 0.5\n",
+    );
+    assert_wire(
+        MeasureRequest {
+            voltage: Voltage::new::<volt>(0.0),
+        },
+        "CH1:MEA:VOL 0\n",
+    );
+    assert_wire(DifferentialConversionRequest::channel_zero(), "ADC 0\n");
+    assert_wire(DifferentialConversionRequest::channel_two(), "ADC 2\n");
+}
+
+#[test]
+fn system_commands() {
+    assert_wire(IdentityRequest, "*IDN?\n");
+    assert_wire(ResetRequest, "*RST\n");
+    assert_wire(
+        ReadEepromRequest {
+            address: EepromAddress { value: 3 },
+        },
+        "*READ 3\n",
+    );
+    assert_wire(
+        WriteEepromRequest {
+            address: EepromAddress { value: 3 },
+            value: 1.5,
+        },
+        "WRITE 3 1.5\n",
+    );
+}
+
+#[test]
+fn calibration_commands() {
+    assert_wire(SetVoltageDacRequest { level: 32768 }, "DAC 32768\n");
+    assert_wire(SetCurrentLimitDacRequest::new(4095), "ILIM 4095\n");
+    assert_wire(EnableVoltageCalibrationModeRequest, "CH1:VCAL\n");
+    assert_wire(
+        LockCurrentRangeAndClearCalibrationRequest {
+            range: CurrentRange::new(2),
+        },
+        "CH1:RANGE2\n",
+    );
+    assert_wire(
+        WriteVoltageDacCalibrationRequest {
+            slope: 0.5,
+            intercept: -2.25,
+        },
+        "CAL:DAC 0.5 -2.25\n",
+    );
+    assert_wire(
+        WriteVoltageAdcCalibrationRequest {
+            slope: 1.5,
+            intercept: 0.25,
+        },
+        "CAL:VOL 1.5 0.25\n",
+    );
+    assert_wire(
+        WriteCurrentLimitCalibrationRequest {
+            range: CurrentRange::new(4),
+            slope: 2.5,
+            intercept: -0.125,
+        },
+        "CAL:CUR:RANGE 4 2.5 -0.125\n",
+    );
+    assert_wire(
+        WriteCurrentLimitDacCalibrationRequest {
+            slope: 0.75,
+            intercept: 12.5,
+        },
+        "CAL:ILIM 0.75 12.5\n",
+    );
+}
+
+#[test]
+fn measure_response() {
+    let response: MeasureResponse = from_wire("0.499870,0.000123\n").unwrap();
+    assert_eq!(response.voltage, Voltage::new::<volt>(0.49987));
+    assert_eq!(response.current, Current::new::<ampere>(0.000123));
+
+    let response: MeasureResponse = from_wire("-1.000112,-0.020001\n").unwrap();
+    assert_eq!(response.voltage, Voltage::new::<volt>(-1.000112));
+    assert_eq!(response.current, Current::new::<ampere>(-0.020001));
+}
+
+#[test]
+fn identity_response() {
+    let response: IdentityResponse = from_wire("uSMU version 1.0 ID:3473457\n").unwrap();
+    assert_eq!(response.uid, 3473457);
+}
+
+#[test]
+fn read_eeprom_response() {
+    let response: ReadEepromResponse = from_wire("1.023400\n").unwrap();
+    assert_eq!(response.value, 1.0234);
+}
+
+#[test]
+fn differential_conversion_response() {
+    let response: DifferentialConversionResponse = from_wire("2047\n").unwrap();
+    assert_eq!(response.value, 2047);
+}
+
+#[test]
+fn incomplete_responses_are_rejected() {
+    assert!(from_wire::<MeasureResponse>("0.499870,0.000123").is_err());
+    assert!(from_wire::<MeasureResponse>("0.499870\n").is_err());
+    assert!(from_wire::<IdentityResponse>("uSMU version 1.0 ID:\n").is_err());
+    assert!(from_wire::<DifferentialConversionResponse>("2047 \n").is_err());
+}